@@ -4,6 +4,7 @@ mod notifications;
 mod pubsub_client;
 mod tak_board;
 pub mod tak_board_state;
+mod tak_chat;
 mod tak_clock;
 mod tak_engine;
 mod tak_flats_counter;
@@ -13,14 +14,17 @@ mod tak_piece;
 mod tak_tile;
 mod tak_websocket;
 mod tak_win_modal;
+mod token_renewer;
 
 pub use color_applier::*;
 pub use nav_button::*;
 pub use notifications::*;
 pub use pubsub_client::*;
 pub use tak_board::*;
+pub use tak_chat::*;
 pub use tak_clock::*;
 pub use tak_engine::*;
 pub use tak_game_actions::*;
 pub use tak_websocket::*;
 pub use tak_win_modal::*;
+pub use token_renewer::*;
@@ -4,7 +4,7 @@ use ws_pubsub::use_ws_topic_receive_dynamic;
 use crate::{
     Route,
     server::{
-        NOTIFICATION_TOPIC, SEEK_ACCEPTED_SUBTOPIC,
+        MATCH_CREATED_SUBTOPIC, MatchUpdate, NOTIFICATION_TOPIC, SEEK_ACCEPTED_SUBTOPIC,
         api::{MyServerFunctions, get_user_id},
     },
 };
@@ -29,5 +29,20 @@ pub fn Notifications() -> Element {
         },
     );
 
+    use_ws_topic_receive_dynamic::<_, MyServerFunctions, _>(
+        move || match player_id.read().as_ref() {
+            Some(Ok(Ok(x))) => Some(format!(
+                "{}/{}/{}",
+                NOTIFICATION_TOPIC, x, MATCH_CREATED_SUBTOPIC
+            )),
+            _ => None,
+        },
+        move |update: MatchUpdate| async move {
+            if let MatchUpdate::Created { match_id, .. } = update {
+                nav.push(Route::PlayOnline { match_id });
+            }
+        },
+    );
+
     rsx! {}
 }
@@ -20,23 +20,20 @@ pub fn TakBoard() -> Element {
             .with_game(|game| {
                 let mut piece_ids = game.pieces.iter().map(|(id, _)| *id).collect::<Vec<_>>();
                 piece_ids.sort_unstable();
-                (
-                    game.game().current_player,
-                    game.game().board.size,
-                    piece_ids,
-                )
+                let size = game.game().board.size;
+                // Tiles are laid out row by row from the top of the shown board.
+                let tile_coords = (0..size)
+                    .rev()
+                    .flat_map(|j| (0..size).map(move |i| TakCoord::new(i as i32, j as i32)))
+                    .map(|shown| game.orient(shown))
+                    .map(|pos| (format!("{},{}", pos.x, pos.y), pos))
+                    .collect::<Vec<_>>();
+                (game.game().current_player, size, piece_ids, tile_coords)
             })
             .expect("Game should exist to get board data")
     });
 
-    let (player, size, piece_ids) = data.read().clone();
-
-    let tile_coords = (0..size)
-        .rev()
-        .flat_map(|j| {
-            (0..size).map(move |i| (format!("{},{}", i, j), TakCoord::new(i as i32, j as i32)))
-        })
-        .collect::<Vec<_>>();
+    let (player, size, piece_ids, tile_coords) = data.read().clone();
 
     let state_clone = state.clone();
     let player_names = use_memo(move || {
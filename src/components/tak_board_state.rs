@@ -1,3 +1,4 @@
+use crate::server::MatchId;
 use crate::server::api::get_match_info;
 use crate::views::ClientGameMessage;
 use dioxus::logger::tracing;
@@ -17,6 +18,19 @@ pub struct TakBoardState {
     pub selected_piece_type: Signal<TakPieceVariant>,
     pub player_info: Signal<HashMap<TakPlayer, PlayerInfo>>,
     pub message_queue: Signal<Vec<ClientGameMessage>>,
+    /// The computer's view of each move it played in this game.
+    pub engine_evals: Signal<Vec<EngineEval>>,
+    /// The version of the remote match the board shows, which local moves are sent with.
+    pub match_version: Signal<u64>,
+}
+
+/// What the computer thought of one of its moves, written into the exported PTN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineEval {
+    pub ply: usize,
+    pub depth: usize,
+    /// In pieces on the board, positive values favor white.
+    pub eval: f64,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -58,6 +72,8 @@ impl TakBoardState {
             player_info: Signal::new(player_info),
             selected_piece_type: Signal::new(TakPieceVariant::Flat),
             message_queue: Signal::new(Vec::new()),
+            engine_evals: Signal::new(Vec::new()),
+            match_version: Signal::new(0),
         }
     }
 
@@ -74,7 +90,7 @@ impl TakBoardState {
     pub fn try_set_from_ptn(&mut self, ptn: String) -> Option<()> {
         tracing::info!("from ptn str: {:?}", ptn);
         let ptn = TakPtn::try_from_str(&ptn)?;
-        self.set_from_game(TakGame::try_from_ptn(ptn)?);
+        self.set_from_game(TakGame::try_from_ptn(ptn).ok()?);
         Some(())
     }
 
@@ -88,6 +104,7 @@ impl TakBoardState {
         let mut game_lock = self.game.lock().unwrap();
         *game_lock = Some(new_game);
         drop(game_lock);
+        self.engine_evals.set(Vec::new());
         tracing::info!("Game set from game, {}", self.has_game());
         self.trigger_change();
     }
@@ -101,6 +118,23 @@ impl TakBoardState {
         Ok(f(game))
     }
 
+    /// The PTN of the game, tagged with the engine's depth and evaluations if it played.
+    pub fn to_ptn(&self) -> Option<TakPtn> {
+        let mut ptn = self.with_game(|game| game.game().to_ptn()).ok()?;
+        let plies = self
+            .with_game(|game| game.game().action_history.len())
+            .ok()?;
+        let evals = self.engine_evals.peek();
+        let evals = evals.iter().filter(|x| x.ply < plies).collect::<Vec<_>>();
+        if let Some(depth) = evals.iter().map(|x| x.depth).max() {
+            ptn.set_engine_info("takumi", depth);
+            for eval in evals {
+                ptn.set_eval(eval.ply, eval.eval);
+            }
+        }
+        Some(ptn)
+    }
+
     pub fn with_game_mut<F, R>(&mut self, f: F) -> Result<R, ()>
     where
         F: FnOnce(&mut TakUIState) -> R,
@@ -110,6 +144,16 @@ impl TakBoardState {
         Ok(f(game))
     }
 
+    /// Turns the board around, for players sharing a device from opposite sides.
+    pub fn toggle_orientation(&mut self) {
+        if self
+            .with_game_mut(|game| game.set_orientation(!game.is_flipped()))
+            .is_ok()
+        {
+            self.trigger_change();
+        }
+    }
+
     pub fn get_active_local_player(&self) -> TakPlayer {
         let current_player = self
             .with_game(|game| game.game().current_player)
@@ -129,6 +173,7 @@ impl TakBoardState {
 
     pub fn reset(&mut self) {
         self.selected_piece_type.set(TakPieceVariant::Flat);
+        self.engine_evals.set(Vec::new());
         let mut game_lock = self.game.lock().unwrap();
         game_lock.as_mut().map(|x| x.reset());
     }
@@ -140,8 +185,15 @@ impl TakBoardState {
         .expect("Game should exist to set time remaining");
     }
 
-    pub async fn update_from_remote(&mut self) {
-        let Ok(res) = get_match_info().await else {
+    pub fn sync_time_remaining(&mut self, player: TakPlayer, time_remaining: u64) {
+        self.with_game_mut(|game| {
+            game.sync_time_remaining(player, time_remaining);
+        })
+        .expect("Game should exist to sync time remaining");
+    }
+
+    pub async fn update_from_remote(&mut self, match_id: &MatchId) {
+        let Ok(res) = get_match_info(match_id.clone()).await else {
             tracing::error!("Failed to fetch player info");
             return;
         };
@@ -167,6 +219,7 @@ impl TakBoardState {
                     );
                 }
                 drop(map);
+                self.match_version.set(match_data.version);
                 self.set_from_game(match_data.game);
             }
             _ => {}
@@ -227,8 +280,9 @@ impl TakBoardState {
 
     fn send_move_message(&mut self, action: TakActionRecord) {
         println!("local move: {:?}", action);
+        let version = *self.match_version.peek();
         self.message_queue
-            .push(ClientGameMessage::Move(action.to_ptn()));
+            .push(ClientGameMessage::Move(version, action.to_ptn()));
     }
 
     pub fn correct_selected_piece_type(&mut self) {
@@ -0,0 +1,60 @@
+use dioxus::prelude::*;
+use ws_pubsub::use_ws_topic_receive;
+
+use crate::{
+    components::tak_board_state::TakBoardState,
+    server::{
+        ChatMessage,
+        api::{CHAT_SUBTOPIC, MATCHES_TOPIC, MyServerFunctions},
+    },
+    views::ClientGameMessage,
+};
+
+#[component]
+pub fn TakChat(match_id: String) -> Element {
+    let mut state = use_context::<TakBoardState>();
+    let mut messages = use_signal(Vec::<ChatMessage>::new);
+    let mut draft = use_signal(String::new);
+
+    use_ws_topic_receive::<_, MyServerFunctions, _>(
+        format!("{}/{}/{}", MATCHES_TOPIC, match_id, CHAT_SUBTOPIC),
+        move |msg: ChatMessage| async move {
+            messages.push(msg);
+        },
+    );
+
+    let mut on_send = move || {
+        let text = draft.peek().trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+        state.message_queue.push(ClientGameMessage::Chat(text));
+        draft.set(String::new());
+    };
+
+    rsx! {
+        div { class: "chat",
+            div { class: "chat-messages",
+                for msg in messages.read().iter() {
+                    div { class: "chat-message",
+                        span { class: "chat-author", "{msg.from.username}" }
+                        span { class: "chat-text", "{msg.text}" }
+                    }
+                }
+            }
+            input {
+                class: "chat-input",
+                r#type: "text",
+                placeholder: "Say something",
+                maxlength: "300",
+                value: "{draft}",
+                oninput: move |e| draft.set(e.value()),
+                onkeydown: move |e: KeyboardEvent| {
+                    if e.key() == Key::Enter {
+                        on_send();
+                    }
+                },
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::components::tak_board_state::TakBoardState;
 use dioxus::core_macro::component;
 use dioxus::prelude::*;
-use tak_core::TakPlayer;
+use tak_core::{TakPlayer, format_time_remaining};
 
 #[component]
 pub fn TakClock(player: TakPlayer) -> Element {
@@ -31,13 +31,7 @@ pub fn TakClock(player: TakPlayer) -> Element {
     let time_remaining_str = time_remaining
         .read()
         .as_ref()
-        .map_or("-:--".to_string(), |&t| {
-            if t >= 20000 {
-                format!("{}:{:02}", (t / 1000) / 60, (t / 1000) % 60)
-            } else {
-                format!("0:{:02}.{}", t / 1000, (t / 100) % 10)
-            }
-        });
+        .map_or("-:--".to_string(), |&t| format_time_remaining(t));
 
     rsx! {
         div { class: "clock clock-{class_name}",
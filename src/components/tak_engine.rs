@@ -1,14 +1,18 @@
 use dioxus::prelude::*;
 use futures_util::{SinkExt, StreamExt};
 use gloo_worker::Spawnable;
-use tak_core::{TakAction, TakCoord, TakPieceVariant};
-use takumi::{TakumiWorker, TakumiWorkerInput};
+use tak_core::{TakAction, TakCoord, TakPieceVariant, TakPlayer};
+use takumi::{AnalysisUpdate, TakumiWorker, TakumiWorkerInput, TakumiWorkerOutput};
 
-use crate::components::tak_board_state::{PlayerType, TakBoardState};
+use crate::{
+    components::tak_board_state::{EngineEval, PlayerType, TakBoardState},
+    views::LOCAL_SETTINGS,
+};
 
 #[component]
 pub fn TakEngine() -> Element {
     let mut state = use_context::<TakBoardState>();
+    let mut analysis = use_signal(|| None::<AnalysisUpdate>);
 
     let state_clone = state.clone();
     let sx = use_coroutine(move |mut rx| {
@@ -28,18 +32,49 @@ pub fn TakEngine() -> Element {
                     })
                     .expect("Game should exist to get current player");
                 bridge
-                    .send(TakumiWorkerInput::new(
-                        tps,
-                        12,
-                        takumi::Settings::new(4),
-                        time_remaining,
-                        increment,
-                    ))
+                    .send(
+                        TakumiWorkerInput::new(
+                            tps,
+                            12,
+                            takumi::Settings::new(4),
+                            time_remaining,
+                            increment,
+                        )
+                        .with_ponder(true)
+                        .with_level(LOCAL_SETTINGS.peek().engine_level),
+                    )
                     .await
                     .unwrap();
-                let action = bridge.next().await.unwrap();
+                let mut last_update = None;
+                let action = loop {
+                    match bridge.next().await.unwrap() {
+                        TakumiWorkerOutput::AnalysisUpdate(update) => {
+                            last_update = Some(update.clone());
+                            analysis.set(Some(update));
+                        }
+                        TakumiWorkerOutput::Done(action) => break action,
+                    }
+                };
                 let action = map_action(size, action);
                 dioxus::logger::tracing::info!("received action: {:?}", action);
+                if let Some(update) = last_update {
+                    let (ply, player) = state
+                        .with_game(|game| {
+                            let game = game.game();
+                            (game.action_history.len(), game.current_player)
+                        })
+                        .expect("Game should exist to record evaluation");
+                    // Scores are from the mover's side, with a piece on the board worth 100.
+                    let score = match player {
+                        TakPlayer::White => update.score,
+                        TakPlayer::Black => -update.score,
+                    };
+                    state.engine_evals.push(EngineEval {
+                        ply,
+                        depth: update.depth,
+                        eval: score as f64 / 100.0,
+                    });
+                }
                 state
                     .with_game_mut(|game| {
                         game.try_do_action(action)
@@ -64,7 +99,13 @@ pub fn TakEngine() -> Element {
         sx.send((tps, size));
     });
 
-    rsx! {}
+    rsx! {
+        if let Some(update) = analysis.read().as_ref() {
+            div { class: "engine-analysis",
+                {format!("Depth {}, eval {}", update.depth, update.score)}
+            }
+        }
+    }
 }
 
 fn map_action(size: usize, best_move: takumi::Action) -> TakAction {
@@ -1,4 +1,5 @@
 use dioxus::prelude::*;
+use tak_core::{TakGameState, TakPlayer};
 
 use crate::components::tak_board_state::TakBoardState;
 
@@ -11,7 +12,12 @@ pub fn TakFlatsCounter() -> Element {
         state
             .with_game(|game| {
                 let komi = &game.game().settings.komi;
+                let leader = match game.game().projected_result() {
+                    TakGameState::Win(player, _) => Some(player),
+                    _ => None,
+                };
                 (
+                    leader,
                     game.flat_counts[0],
                     game.flat_counts[1],
                     if komi.tiebreak {
@@ -24,17 +30,26 @@ pub fn TakFlatsCounter() -> Element {
             .expect("Game should exist to get flats counter data")
     });
 
-    let (white_flats, black_flats, komi_flats) = data.read().clone();
+    let (leader, white_flats, black_flats, komi_flats) = data.read().clone();
+    let leading_class = |player: TakPlayer| {
+        if leader == Some(player) {
+            " flats-bar-leading"
+        } else {
+            ""
+        }
+    };
+    let white_class = leading_class(TakPlayer::White);
+    let black_class = leading_class(TakPlayer::Black);
 
     rsx! {
         div { class: "flats-counter",
             div {
-                class: "flats-bar flats-bar-light",
+                class: "flats-bar flats-bar-light{white_class}",
                 style: "flex-grow: {white_flats + 1};",
                 p { "{white_flats}" }
             }
             div {
-                class: "flats-bar flats-bar-dark",
+                class: "flats-bar flats-bar-dark{black_class}",
                 style: "flex-grow: {black_flats + 1};",
                 p { "{black_flats}" }
             }
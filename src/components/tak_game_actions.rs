@@ -1,3 +1,5 @@
+use crate::components::tak_board_state::TakBoardState;
+use crate::views::ClientGameMessage;
 use dioxus::prelude::*;
 use dioxus_free_icons::{
     Icon,
@@ -6,10 +8,16 @@ use dioxus_free_icons::{
 
 #[component]
 pub fn GameActionsOnline() -> Element {
+    let mut state = use_context::<TakBoardState>();
+    let on_resign = move || {
+        if state.check_ongoing_game() {
+            state.message_queue.push(ClientGameMessage::Resign);
+        }
+    };
     rsx! {
         div {
             class: "game-actions",
-            GameActionButton { icon: GameActionIcon::Resign, onclick: move || {} }
+            GameActionButton { icon: GameActionIcon::Resign, onclick: on_resign }
             GameActionButton { icon: GameActionIcon::OfferDraw, onclick: move || {} }
         }
     }
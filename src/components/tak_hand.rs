@@ -10,11 +10,12 @@ pub fn TakHand(player: TakPlayer) -> Element {
         let _ = state.on_change.read();
         state
             .with_game(|game| {
-                let hand = &game.preview_game().hands[player.index()];
+                let summary = game.preview_game().material_summary();
+                let material = summary.get(player);
                 (
                     game.get_visible_active_player() == player,
-                    hand.stones,
-                    hand.capstones,
+                    material.stones_in_hand,
+                    material.capstones_in_hand,
                 )
             })
             .expect("Game should exist to get hand data")
@@ -90,12 +90,12 @@ pub fn TakTile(pos: TakCoord) -> Element {
             class: if (pos.x + pos.y) % 2 == 1 { "tak-tile tak-tile-light" } else { "tak-tile tak-tile-dark" },
             class: if tile.highlighted || tile.last_action { "tak-tile-highlight" } else { "" },
             class: if tile.selectable { "tak-tile-selected" } else { "" },
-            if pos.y == 0 {
+            if tile.display_pos.y == 0 {
                 div { class: "tak-tile-label tak-tile-label-rank",
                     {format!("{}", ('A' as u8 + pos.x as u8) as char)}
                 }
             }
-            if pos.x == 0 {
+            if tile.display_pos.x == 0 {
                 div { class: "tak-tile-label tak-tile-label-file", {format!("{}", pos.y + 1)} }
             }
             {rendered_bridges.iter()}
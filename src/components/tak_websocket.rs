@@ -1,4 +1,5 @@
 use crate::components::tak_board_state::TakBoardState;
+use crate::server::MatchId;
 use crate::server::api::{MATCHES_TOPIC, MyServerFunctions};
 use dioxus::core_macro::component;
 use dioxus::prelude::*;
@@ -8,8 +9,17 @@ use ws_pubsub::{use_ws_topic_receive, use_ws_topic_send};
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum ServerGameMessage {
     StartGame,
-    Move(usize, Vec<(TakPlayer, u64)>, String),
+    /// A move in PTN played at the given ply, with the time left of both players and the
+    /// version of the match after it.
+    Move(usize, Vec<(TakPlayer, u64)>, String, u64),
     GameOver(TakGameState),
+    /// A takeback was accepted and the game now continues from this ply.
+    Takeback(usize),
+    /// The time left of both players as measured by the server, sent periodically so that
+    /// clients can correct their drift.
+    ClockSync(Vec<(TakPlayer, u64)>),
+    /// The number of spectators changed.
+    Spectators(usize),
 }
 
 #[component]
@@ -18,52 +28,69 @@ pub fn TakWebSocket(match_id: String) -> Element {
 
     let board_clone = board.clone();
 
-    let handle_game_message = async move |board: &mut TakBoardState, msg: ServerGameMessage| {
-        match msg {
-            ServerGameMessage::StartGame => {
-                dioxus::logger::tracing::info!("[WebSocket] Starting game");
-                board.reset();
-                board.update_from_remote().await;
-            }
-            ServerGameMessage::Move(move_index, time_remaining, action) => {
-                dioxus::logger::tracing::info!("[WebSocket] Processing move action: {action}");
-                let Some(action) = TakAction::from_ptn(&action) else {
-                    dioxus::logger::tracing::error!(
-                        "[WebSocket] Invalid action received: {action}"
-                    );
-                    return;
-                };
-                let should_resync = board
-                    .maybe_try_do_remote_action(move_index, action)
-                    .is_err();
-                for (player, duration) in time_remaining {
-                    board.set_time_remaining(player, duration);
+    let handle_game_message =
+        async move |board: &mut TakBoardState, match_id: &MatchId, msg: ServerGameMessage| {
+            match msg {
+                ServerGameMessage::StartGame => {
+                    dioxus::logger::tracing::info!("[WebSocket] Starting game");
+                    board.reset();
+                    board.update_from_remote(match_id).await;
                 }
-                if should_resync {
-                    dioxus::logger::tracing::info!(
-                        "[WebSocket] Resyncing game state after message"
-                    );
-                    board.update_from_remote().await;
+                ServerGameMessage::Move(move_index, time_remaining, action, version) => {
+                    dioxus::logger::tracing::info!("[WebSocket] Processing move action: {action}");
+                    let Some(action) = TakAction::from_ptn(&action) else {
+                        dioxus::logger::tracing::error!(
+                            "[WebSocket] Invalid action received: {action}"
+                        );
+                        return;
+                    };
+                    let should_resync = board
+                        .maybe_try_do_remote_action(move_index, action)
+                        .is_err();
+                    for (player, duration) in time_remaining {
+                        board.set_time_remaining(player, duration);
+                    }
+                    if should_resync {
+                        dioxus::logger::tracing::info!(
+                            "[WebSocket] Resyncing game state after message"
+                        );
+                        board.update_from_remote(match_id).await;
+                    } else {
+                        board.match_version.set(version);
+                    }
                 }
-            }
-            ServerGameMessage::GameOver(game_state) => {
-                dioxus::logger::tracing::info!("[WebSocket] Game over: {game_state:?}");
-                if board
-                    .with_game(|game| game.game().game_state != game_state)
-                    .unwrap_or(true)
-                {
-                    board.update_from_remote().await;
+                ServerGameMessage::Takeback(ply_index) => {
+                    dioxus::logger::tracing::info!("[WebSocket] Takeback to ply {ply_index}");
+                    board.update_from_remote(match_id).await;
                 }
-            }
+                ServerGameMessage::ClockSync(time_remaining) => {
+                    for (player, duration) in time_remaining {
+                        board.sync_time_remaining(player, duration);
+                    }
+                }
+                ServerGameMessage::Spectators(count) => {
+                    dioxus::logger::tracing::info!("[WebSocket] {count} spectators");
+                }
+                ServerGameMessage::GameOver(game_state) => {
+                    dioxus::logger::tracing::info!("[WebSocket] Game over: {game_state:?}");
+                    if board
+                        .with_game(|game| game.game().game_state != game_state)
+                        .unwrap_or(true)
+                    {
+                        board.update_from_remote(match_id).await;
+                    }
+                }
+            };
         };
-    };
 
+    let match_id_clone = match_id.clone();
     use_ws_topic_receive::<_, MyServerFunctions, _>(
         format!("{}/{}", MATCHES_TOPIC, match_id),
         move |msg| {
             let mut board = board_clone.clone();
+            let match_id = match_id_clone.clone();
             async move {
-                handle_game_message(&mut board, msg).await;
+                handle_game_message(&mut board, &match_id, msg).await;
             }
         },
     );
@@ -81,19 +108,14 @@ pub fn TakWebSocket(match_id: String) -> Element {
             spawn(async move {
                 for message in msg_queue.write().drain(..) {
                     dioxus::logger::tracing::info!("[WebSocket] Sending message: {:?}", message);
-                    match send_service.send(message).await {
-                        None => {
-                            dioxus::logger::tracing::error!(
-                                "[WebSocket] Send service is not running, skipping message"
-                            );
-                        }
-                        Some(Err(e)) => {
+                    match send_service.send(message).await.and_then(|res| res) {
+                        Err(e) => {
                             dioxus::logger::tracing::error!(
-                                "[WebSocket] Failed to send message: {}",
+                                "[WebSocket] Failed to send message, skipping it: {}",
                                 e
                             );
                         }
-                        Some(Ok(())) => {
+                        Ok(()) => {
                             dioxus::logger::tracing::info!("[WebSocket] Message sent successfully");
                         }
                     }
@@ -20,7 +20,8 @@ pub fn TakWinModal(match_id: String) -> Element {
     let state = use_context::<TakBoardState>();
     let nav = use_navigator();
 
-    let mut match_data = use_resource(|| get_match_info());
+    let match_id_clone = match_id.clone();
+    let mut match_data = use_resource(move || get_match_info(match_id_clone.clone()));
 
     let state_clone = state.clone();
 
@@ -75,6 +76,9 @@ pub fn TakWinModal(match_id: String) -> Element {
                 TakWinReason::Resignation => {
                     format!("{} wins by resignation!", player_str)
                 }
+                TakWinReason::Abandonment => {
+                    format!("{} wins by abandonment!", player_str)
+                }
             }
         }
         TakGameState::Draw(_) => "It's a draw!".to_string(),
@@ -82,13 +86,15 @@ pub fn TakWinModal(match_id: String) -> Element {
         TakGameState::Ongoing => unreachable!(),
     };
 
+    let match_id_clone = match_id.clone();
     let on_click_leave = move |_| {
         if let Some(Ok(Err(ServerError::NotFound))) = match_data.read().as_ref() {
             nav.push(Route::Home {});
             return;
         }
+        let match_id = match_id_clone.clone();
         spawn(async move {
-            let res = leave_match().await;
+            let res = leave_match(match_id).await;
             match res {
                 Ok(Err(ServerError::Unauthorized)) => {
                     nav.push(Route::Auth {});
@@ -121,13 +127,14 @@ pub fn TakWinModal(match_id: String) -> Element {
     let show_rematch_button = use_memo(move || state_clone.get_game_type() != GameType::Spectated);
 
     let on_click_rematch = move |_| {
+        let match_id = match_id.clone();
         spawn(async move {
             let res = if let Some(Ok(Ok((player_id, _, _, _, data)))) = match_data.read().as_ref()
                 && data.rematch_agree.contains(player_id)
             {
-                retract_rematch().await
+                retract_rematch(match_id).await
             } else {
-                agree_rematch().await
+                agree_rematch(match_id).await
             };
             match res {
                 Ok(Err(ServerError::Unauthorized)) => {
@@ -192,6 +199,7 @@ pub fn TakWinModalLocal() -> Element {
                 TakWinReason::Road => format!("{} wins by road!", player_str),
                 TakWinReason::Timeout => format!("{} wins by timeout!", player_str),
                 TakWinReason::Resignation => format!("{} wins by resignation!", player_str),
+                TakWinReason::Abandonment => format!("{} wins by abandonment!", player_str),
             }
         }
         TakGameState::Draw(_) => "It's a draw!".to_string(),
@@ -205,13 +213,12 @@ pub fn TakWinModalLocal() -> Element {
 
     let state_clone = state.clone();
     let on_click_copy_ptn = move |_| {
-        state_clone
-            .with_game(|game| {
-                let ptn = game.game().to_ptn().to_str();
-                copy_to_clipboard(&ptn);
-                dioxus::logger::tracing::info!("PTN copied to clipboard: {}", ptn);
-            })
-            .expect("Should be able to copy PTN");
+        let ptn = state_clone
+            .to_ptn()
+            .expect("Should be able to copy PTN")
+            .to_str();
+        copy_to_clipboard(&ptn);
+        dioxus::logger::tracing::info!("PTN copied to clipboard: {}", ptn);
     };
 
     let state_clone = state.clone();
@@ -0,0 +1,60 @@
+use dioxus::prelude::*;
+use ws_pubsub::use_ws_connection;
+
+use crate::{
+    Route,
+    server::api::{post_renew_token, renewal_delay, token_expiry},
+    views::{AUTH_CHANGED, AUTH_TOKEN_KEY},
+};
+
+/// Renews the auth token shortly before it expires, so that long sessions and WebSocket
+/// reconnects keep working. If renewal fails, the user has to log in again.
+#[component]
+pub fn TokenRenewer() -> Element {
+    let nav = use_navigator();
+    let connector = use_ws_connection();
+
+    let _ = use_resource(move || {
+        let mut connector = connector.clone();
+        async move {
+            let _ = AUTH_CHANGED.read();
+            loop {
+                let Some(token) = crate::storage::get(AUTH_TOKEN_KEY).unwrap_or(None::<String>)
+                else {
+                    return;
+                };
+                let Some(expiry) = token_expiry(&token) else {
+                    dioxus::logger::tracing::warn!("[TokenRenewer] Token has no expiry");
+                    return;
+                };
+                let delay = renewal_delay(expiry, chrono::Utc::now().timestamp());
+                crate::future::sleep(delay).await;
+                match post_renew_token().await {
+                    Ok(Ok(token)) => {
+                        if let Err(e) = crate::storage::set(AUTH_TOKEN_KEY, token.clone()) {
+                            dioxus::logger::tracing::error!(
+                                "[TokenRenewer] Failed to store token: {}",
+                                e
+                            );
+                        }
+                        connector.token.set(Some(token));
+                        dioxus::logger::tracing::info!("[TokenRenewer] Token renewed");
+                    }
+                    res => {
+                        dioxus::logger::tracing::error!("[TokenRenewer] Renewal failed: {:?}", res);
+                        if let Err(e) = crate::storage::set(AUTH_TOKEN_KEY, None::<String>) {
+                            dioxus::logger::tracing::error!(
+                                "[TokenRenewer] Failed to clear token: {}",
+                                e
+                            );
+                        }
+                        nav.replace(Route::Auth {});
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    rsx! {}
+}
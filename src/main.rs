@@ -10,7 +10,8 @@ use flexi_logger::{
 };
 use views::{
     Colors, CreateRoomComputer, CreateRoomLocal, CreateRoomOnline, History, Home, More, Navbar,
-    PlayComputer, PlayLocal, PlayOnline, Puzzles, ReviewBoard, Rules, Seeks, Settings, Stats,
+    PlayComputer, PlayLocal, PlayOnline, Puzzles, ReviewBoard, ReviewBoardAt, Rules, Seeks,
+    Settings, Stats,
 };
 
 mod components;
@@ -43,6 +44,8 @@ enum Route {
 
     #[route("/review/:game_id")]
     ReviewBoard { game_id: String },
+    #[route("/review/:game_id/:ply")]
+    ReviewBoardAt { game_id: String, ply: usize },
 
     #[route("/play/computer")]
     PlayComputer {},
@@ -139,6 +142,35 @@ async fn main() {
     let db_user = std::env::var("SURREALDB_USER").expect("SURREALDB_USER not set");
     let db_pass = std::env::var("SURREALDB_PASS").expect("SURREALDB_PASS not set");
 
+    server::internal::pub_sub::setup_handlers();
+    ws_pubsub::spawn_heartbeat();
+
+    let first_move_deadline = std::env::var("FIRST_MOVE_DEADLINE_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(30);
+    server::internal::matches::set_first_move_deadline(std::time::Duration::from_secs(
+        first_move_deadline,
+    ));
+    server::internal::matches::set_move_audit(
+        std::env::var("AUDIT_MOVES").is_ok_and(|value| value == "1" || value == "true"),
+    );
+
+    // Comma separated `user_id=limit` pairs of accounts that may play several matches at once.
+    if let Ok(limits) = std::env::var("CONCURRENT_MATCH_LIMITS") {
+        for (user_id, limit) in limits.split(',').filter_map(|x| x.split_once('=')) {
+            match limit.trim().parse() {
+                Ok(limit) => server::internal::matches::set_max_concurrent_matches(
+                    user_id.trim().to_string(),
+                    limit,
+                ),
+                Err(_) => log::warn!("Invalid match limit for {user_id}: {limit}"),
+            }
+        }
+    }
+
+    // Restoring matches checks the match limits and starts the first move deadlines, so both
+    // need to be configured first.
     spawn(async move {
         if let Err(e) = server::internal::db::connect_db(&db_url, &db_user, &db_pass).await {
             log::error!("Failed to connect to database: {}", e);
@@ -148,9 +180,30 @@ async fn main() {
             log::error!("Failed to set up database: {}", e);
             return;
         }
+        if let Err(e) = server::internal::matches::restore_matches().await {
+            log::error!("Failed to restore matches: {}", e);
+        }
+        match server::internal::puzzle::generate_puzzles(server::internal::puzzle::PUZZLE_MAX_DEPTH)
+            .await
+        {
+            Ok(created) => log::info!("Generated {} new puzzles", created),
+            Err(e) => log::error!("Failed to generate puzzles: {}", e),
+        }
     });
 
-    server::internal::pub_sub::setup_handlers();
+    server::internal::analysis::set_featured_min_rating(
+        std::env::var("FEATURED_MIN_RATING")
+            .ok()
+            .and_then(|rating| rating.parse().ok()),
+    );
+
+    let disconnect_grace = std::env::var("DISCONNECT_GRACE_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(30);
+    server::internal::presence::setup_presence_handler(std::time::Duration::from_secs(
+        disconnect_grace,
+    ));
 
     let ip =
         dioxus::cli_config::server_ip().unwrap_or_else(|| IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
@@ -1,3 +1,4 @@
+use base64::Engine;
 use dioxus_fullstack::client::Client;
 use dioxus_fullstack::server_fn::error::FromServerFnError;
 
@@ -5,6 +6,31 @@ use crate::views::AUTH_TOKEN_KEY;
 
 pub struct AuthClient;
 
+/// How long before its expiry a token gets renewed.
+pub const TOKEN_RENEW_MARGIN_SECS: i64 = 60;
+
+/// Reads the expiry timestamp from the claims of a JWT. The signature isn't checked, since
+/// only the server can do that.
+pub fn token_expiry(token: &str) -> Option<i64> {
+    #[derive(serde::Deserialize)]
+    struct ExpiryClaim {
+        exp: i64,
+    }
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()?;
+    let claim = serde_json::from_slice::<ExpiryClaim>(&payload).ok()?;
+    Some(claim.exp)
+}
+
+/// Time to wait before renewing a token that expires at `expiry`, both in Unix seconds.
+/// Tokens that are about to expire get renewed right away.
+pub fn renewal_delay(expiry: i64, now: i64) -> std::time::Duration {
+    let delay = expiry - TOKEN_RENEW_MARGIN_SECS - now;
+    std::time::Duration::from_secs(delay.max(0) as u64)
+}
+
 #[cfg(feature = "web")]
 impl<Error: FromServerFnError> Client<Error> for AuthClient {
     type Request = dioxus::prelude::server_fn::request::browser::BrowserRequest;
@@ -82,3 +108,42 @@ impl<Error: FromServerFnError> Client<Error> for AuthClient {
         <dioxus::prelude::server_fn::client::reqwest::ReqwestClient as Client<Error>>::spawn(future)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_with_claims(claims: &str) -> String {
+        let encode = |part: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(part);
+        format!(
+            "{}.{}.signature",
+            encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+            encode(claims)
+        )
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        let token = token_with_claims(r#"{"sub":"user","exp":1700003600}"#);
+        assert_eq!(token_expiry(&token), Some(1_700_003_600));
+        assert_eq!(token_expiry("not a token"), None);
+        assert_eq!(token_expiry(&token_with_claims(r#"{"sub":"user"}"#)), None);
+    }
+
+    #[test]
+    fn test_renewal_delay() {
+        let now = 1_700_000_000;
+        assert_eq!(
+            renewal_delay(now + 3600, now),
+            std::time::Duration::from_secs(3600 - 60)
+        );
+        assert_eq!(
+            renewal_delay(now + 30, now),
+            std::time::Duration::from_secs(0)
+        );
+        assert_eq!(
+            renewal_delay(now - 10, now),
+            std::time::Duration::from_secs(0)
+        );
+    }
+}
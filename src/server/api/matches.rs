@@ -2,7 +2,10 @@ use dioxus::prelude::*;
 
 use crate::{
     bail_api,
-    server::{MatchData, MatchId, MatchInstance, PlayerInformation, UserId, api::AuthClient},
+    server::{
+        LiveMatchInfo, MatchData, MatchId, MatchInstance, PlayerInformation, UserId,
+        api::AuthClient,
+    },
 };
 
 use crate::server::error::ServerResult;
@@ -10,6 +13,9 @@ use crate::server::error::ServerResult;
 pub const MATCHES_TOPIC: &str = "matches";
 pub const REMATCH_SUBTOPIC: &str = "rematch";
 pub const DRAW_SUBTOPIC: &str = "draw";
+pub const TAKEBACK_SUBTOPIC: &str = "takeback";
+pub const CHAT_SUBTOPIC: &str = "chat";
+pub const ANALYSIS_SUBTOPIC: &str = "analysis";
 
 #[cfg(feature = "server")]
 use crate::server::api::authorize;
@@ -26,7 +32,13 @@ pub async fn get_matches() -> Result<
 }
 
 #[server(client=AuthClient)]
-pub async fn get_match_id() -> Result<ServerResult<MatchId>, ServerFnError> {
+pub async fn get_live_matches() -> Result<ServerResult<Vec<LiveMatchInfo>>, ServerFnError> {
+    let _ = bail_api!(authorize().await);
+    Ok(matches::get_live_matches().await)
+}
+
+#[server(client=AuthClient)]
+pub async fn get_match_id() -> Result<ServerResult<Vec<MatchId>>, ServerFnError> {
     let user_id = bail_api!(authorize().await);
     Ok(matches::get_match_id(&user_id).await)
 }
@@ -38,7 +50,9 @@ pub async fn get_match(match_id: String) -> Result<ServerResult<MatchInstance>,
 }
 
 #[server(client=AuthClient)]
-pub async fn get_match_info() -> Result<
+pub async fn get_match_info(
+    match_id: MatchId,
+) -> Result<
     ServerResult<(
         UserId,
         PlayerInformation,
@@ -49,7 +63,9 @@ pub async fn get_match_info() -> Result<
     ServerFnError,
 > {
     let user_id = bail_api!(authorize().await);
-    let match_id = bail_api!(matches::get_match_id(&user_id).await);
+    if !bail_api!(matches::get_match_id(&user_id).await).contains(&match_id) {
+        return Ok(Err(crate::server::ServerError::NotFound));
+    }
     let instance = bail_api!(matches::get_match(&match_id).await);
     let player_info = bail_api!(cache::get_or_retrieve_player_info(&instance.player_id).await);
     let opponent_info = bail_api!(cache::get_or_retrieve_player_info(&instance.opponent_id).await);
@@ -64,20 +80,49 @@ pub async fn get_match_info() -> Result<
 }
 
 #[server(client=AuthClient)]
-pub async fn agree_rematch() -> Result<ServerResult<()>, ServerFnError> {
+pub async fn agree_rematch(match_id: MatchId) -> Result<ServerResult<()>, ServerFnError> {
+    let player_id = bail_api!(authorize().await);
+    Ok(matches::agree_rematch(&match_id, &player_id).await)
+}
+
+#[server(client=AuthClient)]
+pub async fn retract_rematch(match_id: MatchId) -> Result<ServerResult<()>, ServerFnError> {
+    let player_id = bail_api!(authorize().await);
+    Ok(matches::retract_rematch(&match_id, &player_id).await)
+}
+
+#[server(client=AuthClient)]
+pub async fn request_takeback(match_id: MatchId) -> Result<ServerResult<()>, ServerFnError> {
     let player_id = bail_api!(authorize().await);
-    Ok(matches::agree_rematch(&player_id).await)
+    Ok(matches::request_takeback(&match_id, &player_id).await)
 }
 
 #[server(client=AuthClient)]
-pub async fn retract_rematch() -> Result<ServerResult<()>, ServerFnError> {
+pub async fn answer_takeback(
+    match_id: MatchId,
+    accept: bool,
+) -> Result<ServerResult<()>, ServerFnError> {
     let player_id = bail_api!(authorize().await);
-    Ok(matches::retract_rematch(&player_id).await)
+    Ok(matches::answer_takeback(&match_id, &player_id, accept).await)
+}
+
+#[server(client=AuthClient)]
+pub async fn join_match_as_spectator(match_id: MatchId) -> Result<ServerResult<()>, ServerFnError> {
+    let user_id = bail_api!(authorize().await);
+    Ok(matches::join_match_as_spectator(&match_id, &user_id).await)
+}
+
+#[server(client=AuthClient)]
+pub async fn leave_match_as_spectator(
+    match_id: MatchId,
+) -> Result<ServerResult<()>, ServerFnError> {
+    let user_id = bail_api!(authorize().await);
+    Ok(matches::leave_match_as_spectator(&match_id, &user_id).await)
 }
 
 #[server(client=AuthClient)]
-pub async fn leave_match() -> Result<ServerResult<()>, ServerFnError> {
+pub async fn leave_match(match_id: MatchId) -> Result<ServerResult<()>, ServerFnError> {
     let player_id = bail_api!(authorize().await);
-    bail_api!(matches::leave_match(&player_id).await);
+    bail_api!(matches::leave_match(&match_id, &player_id).await);
     Ok(Ok(()))
 }
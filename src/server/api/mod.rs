@@ -11,7 +11,14 @@ pub use seek::*;
 
 use crate::server::GameId;
 use crate::server::GameInformation;
+use crate::server::GameSummary;
+use crate::server::H2HStats;
+use crate::server::HistoryCursor;
+use crate::server::HistoryPage;
 use crate::server::JWTToken;
+use crate::server::PtnImportSummary;
+use crate::server::PuzzleId;
+use crate::server::PuzzleVerdict;
 use crate::server::UserId;
 use crate::server::error::{ServerError, ServerResult};
 
@@ -23,6 +30,7 @@ pub async fn authorize() -> ServerResult<UserId> {
     let Some(auth::Claims { sub: user_id, .. }) = extract().await.ok() else {
         return Err(ServerError::Unauthorized);
     };
+    auth::check_account(&user_id).await?;
     Ok(user_id)
 }
 
@@ -71,6 +79,20 @@ pub async fn get_stats() -> Result<ServerResult<StatsData>, ServerFnError> {
     }))
 }
 
+#[server(client=AuthClient)]
+pub async fn get_head_to_head(
+    opponent_id: UserId,
+) -> Result<ServerResult<H2HStats>, ServerFnError> {
+    let user_id = bail_api!(authorize().await);
+    if opponent_id == user_id {
+        return Ok(Err(ServerError::BadRequest(
+            "Cannot compare a player with themselves".to_string(),
+        )));
+    }
+    let stats = bail_api!(player::get_head_to_head(&user_id, &opponent_id).await);
+    Ok(Ok(stats))
+}
+
 #[server]
 pub async fn post_register(
     username: String,
@@ -92,7 +114,7 @@ pub async fn post_login(
 #[server(client=AuthClient)]
 pub async fn post_renew_token() -> Result<ServerResult<JWTToken>, ServerFnError> {
     let user_id = bail_api!(authorize().await);
-    let token = bail_api!(auth::renew_token(&user_id));
+    let token = bail_api!(auth::renew_token(&user_id).await);
     Ok(Ok(token))
 }
 
@@ -106,6 +128,13 @@ pub async fn post_change_password(
     Ok(Ok(()))
 }
 
+#[server(client=AuthClient)]
+pub async fn post_delete_account() -> Result<ServerResult<()>, ServerFnError> {
+    let user_id = bail_api!(authorize().await);
+    bail_api!(auth::try_delete_account(&user_id).await);
+    Ok(Ok(()))
+}
+
 #[server(client=AuthClient)]
 pub async fn get_user_id() -> Result<ServerResult<UserId>, ServerFnError> {
     let user_id = bail_api!(authorize().await);
@@ -125,13 +154,24 @@ pub async fn get_game(game_id: GameId) -> Result<ServerResult<GameInformation>,
     Ok(Ok(game))
 }
 
+/// The final position and result of a game, for link previews and thumbnails.
+#[server]
+pub async fn get_game_summary(game_id: GameId) -> Result<ServerResult<GameSummary>, ServerFnError> {
+    Ok(player::get_game_summary(&game_id).await)
+}
+
+/// Largest page of the game history served at once.
+const MAX_HISTORY_PAGE: usize = 100;
+
 #[server(client=AuthClient)]
 pub async fn get_history(
-    pagination: Option<(usize, usize)>,
-) -> Result<ServerResult<(UserId, Vec<GameInformation>)>, ServerFnError> {
+    before: Option<HistoryCursor>,
+    limit: usize,
+) -> Result<ServerResult<(UserId, HistoryPage)>, ServerFnError> {
     let user_id = bail_api!(authorize().await);
-    let games = bail_api!(player::get_games_of_player(&user_id, pagination).await);
-    let game_info: Vec<GameInformation> = games
+    let (games, next_cursor) =
+        bail_api!(player::get_history_page(&user_id, before, limit.min(MAX_HISTORY_PAGE)).await);
+    let games = games
         .into_iter()
         .map(|game_record| GameInformation {
             game_id: game_record.game_id,
@@ -141,15 +181,60 @@ pub async fn get_history(
             timestamp: game_record.timestamp.into(),
         })
         .collect();
-    Ok(Ok((user_id, game_info)))
+    Ok(Ok((user_id, HistoryPage { games, next_cursor })))
 }
 
 #[server(client=AuthClient)]
-pub async fn post_pubsub_subscribe(topic: String) -> Result<ServerResult<String>, ServerFnError> {
+pub async fn post_import_games(
+    ptn: String,
+) -> Result<ServerResult<PtnImportSummary>, ServerFnError> {
     let user_id = bail_api!(authorize().await);
-    Ok(ws_pubsub::client_subscribe(&topic, &user_id)
-        .await
-        .ok_or(ServerError::NotFound))
+    let mut summary = PtnImportSummary {
+        imported: Vec::new(),
+        failed: Vec::new(),
+    };
+    for (index, game) in tak_core::parse_ptn_collection(&ptn).into_iter().enumerate() {
+        let result = match game {
+            Ok(game) => player::import_game(&game, &user_id).await,
+            Err(e) => Err(ServerError::BadRequest(format!("{:?}", e))),
+        };
+        match result {
+            Ok(game_id) => summary.imported.push(game_id),
+            Err(e) => {
+                log::warn!("Failed to import game {} for {}: {:?}", index, user_id, e);
+                summary.failed.push(index);
+            }
+        }
+    }
+    Ok(Ok(summary))
+}
+
+#[server(client=AuthClient)]
+pub async fn post_submit_puzzle_solution(
+    puzzle_id: PuzzleId,
+    moves: Vec<String>,
+) -> Result<ServerResult<PuzzleVerdict>, ServerFnError> {
+    let _ = bail_api!(authorize().await);
+    Ok(puzzle::submit_solution(&puzzle_id, moves).await)
+}
+
+#[server(client=AuthClient)]
+pub async fn post_pubsub_subscribe(
+    topic: String,
+    connection_id: String,
+) -> Result<ServerResult<String>, ServerFnError> {
+    let user_id = bail_api!(authorize().await);
+    if !analysis::may_subscribe(&topic, &user_id) {
+        return Ok(Err(ServerError::Unauthorized));
+    }
+    Ok(
+        ws_pubsub::client_subscribe(&topic, &user_id, &connection_id)
+            .await
+            .map_err(|e| match e {
+                ws_pubsub::SubscribeError::NotConnected => ServerError::NotFound,
+                e => ServerError::NotAllowed(e.to_string()),
+            }),
+    )
 }
 
 #[server(client=AuthClient)]
@@ -169,8 +254,8 @@ pub struct MyServerFunctions;
 #[async_trait::async_trait]
 impl ws_pubsub::ServerFunctions for MyServerFunctions {
     type Error = Result<ServerError, ServerFnError>;
-    async fn subscribe(topic: String) -> Result<String, Self::Error> {
-        match post_pubsub_subscribe(topic).await {
+    async fn subscribe(topic: String, connection_id: String) -> Result<String, Self::Error> {
+        match post_pubsub_subscribe(topic, connection_id).await {
             Ok(Ok(subscription_id)) => Ok(subscription_id),
             Ok(Err(e)) => Err(Ok(e)),
             Err(e) => Err(Err(e)),
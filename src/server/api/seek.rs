@@ -2,7 +2,9 @@ use dioxus::prelude::*;
 
 use crate::{
     bail_api,
-    server::{MatchId, PlayerInformation, SeekSettings, UserId, api::AuthClient},
+    server::{
+        MatchId, PlayerInformation, SeekSettings, UserId, ValidatedPosition, api::AuthClient,
+    },
 };
 
 use crate::server::error::ServerResult;
@@ -35,8 +37,8 @@ pub async fn get_seek() -> Result<ServerResult<SeekSettings>, ServerFnError> {
 #[server(client=AuthClient)]
 pub async fn get_seeks()
 -> Result<ServerResult<Vec<(PlayerInformation, SeekSettings)>>, ServerFnError> {
-    let _ = bail_api!(authorize().await);
-    Ok(seek::get_seeks().await)
+    let user_id = bail_api!(authorize().await);
+    Ok(seek::get_seeks(&user_id).await)
 }
 
 #[server(client=AuthClient)]
@@ -44,3 +46,13 @@ pub async fn accept_seek(seek_owner: UserId) -> Result<ServerResult<MatchId>, Se
     let user_id = bail_api!(authorize().await);
     Ok(seek::accept_seek(&user_id, &seek_owner).await)
 }
+
+/// Normalizes and checks a start position from the position editor.
+#[server(client=AuthClient)]
+pub async fn post_validate_position(
+    tps: String,
+    size: usize,
+) -> Result<ServerResult<ValidatedPosition>, ServerFnError> {
+    let _ = bail_api!(authorize().await);
+    Ok(seek::validate_position(&tps, size))
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use tak_core::{TakGame, TakGameState, TakPlayer};
+
+use crate::server::{
+    MatchAnalysis, MatchId, UserId,
+    api::{ANALYSIS_SUBTOPIC, MATCHES_TOPIC},
+    internal::matches,
+};
+
+/// Deepest search run for a featured match. Together with the time limit this bounds the cost
+/// of a move, since every move of a featured match gets analyzed.
+pub const ANALYSIS_MAX_DEPTH: usize = 8;
+pub const ANALYSIS_MAX_MILLIS: u64 = 2_000;
+
+/// Matches whose positions get analyzed for spectators, with the last ply analyzed in each.
+pub struct FeaturedMatches {
+    analyzed: Mutex<HashMap<MatchId, Option<usize>>>,
+}
+
+impl FeaturedMatches {
+    pub fn new() -> Self {
+        Self {
+            analyzed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn feature(&self, match_id: &MatchId) {
+        self.analyzed
+            .lock()
+            .unwrap()
+            .entry(match_id.clone())
+            .or_default();
+    }
+
+    pub fn unfeature(&self, match_id: &MatchId) {
+        self.analyzed.lock().unwrap().remove(match_id);
+    }
+
+    pub fn is_featured(&self, match_id: &MatchId) -> bool {
+        self.analyzed.lock().unwrap().contains_key(match_id)
+    }
+
+    /// Reserves the analysis of the position after `ply_index` plies. Returns false if the
+    /// match isn't featured or that position was just analyzed.
+    pub fn claim(&self, match_id: &MatchId, ply_index: usize) -> bool {
+        let mut analyzed = self.analyzed.lock().unwrap();
+        let Some(last) = analyzed.get_mut(match_id) else {
+            return false;
+        };
+        if *last == Some(ply_index) {
+            return false;
+        }
+        *last = Some(ply_index);
+        true
+    }
+}
+
+pub static FEATURED: LazyLock<FeaturedMatches> = LazyLock::new(FeaturedMatches::new);
+
+static FEATURED_MIN_RATING: Mutex<Option<f64>> = Mutex::new(None);
+
+/// Features every new match between two players rated at least `rating`. `None` turns
+/// automatic featuring off, which is the default.
+pub fn set_featured_min_rating(rating: Option<f64>) {
+    *FEATURED_MIN_RATING.lock().unwrap() = rating;
+}
+
+pub fn qualifies_for_featuring(player_rating: f64, opponent_rating: f64) -> bool {
+    FEATURED_MIN_RATING
+        .lock()
+        .unwrap()
+        .is_some_and(|min| player_rating >= min && opponent_rating >= min)
+}
+
+/// Searches the current position of an ongoing game.
+pub fn analyze_game(game: &TakGame, max_depth: usize, max_millis: u64) -> Option<MatchAnalysis> {
+    if game.game_state != TakGameState::Ongoing {
+        return None;
+    }
+    let mut board = takumi::Board::from(game);
+    let mut last_update = None;
+    takumi::iterative_deepening_with_config(
+        &mut board,
+        max_depth,
+        max_millis,
+        &takumi::SearchConfig::default(),
+        |update| last_update = Some(update),
+    );
+    let update = last_update?;
+    let score = match game.current_player {
+        TakPlayer::White => update.score,
+        TakPlayer::Black => -update.score,
+    };
+    Some(MatchAnalysis {
+        ply_index: game.ply_index,
+        depth: update.depth,
+        score,
+        best_move: update.best_move.to_ptn(game.board.size),
+    })
+}
+
+fn analysis_after_move(
+    featured: &FeaturedMatches,
+    match_id: &MatchId,
+    game: &TakGame,
+    max_depth: usize,
+    max_millis: u64,
+) -> Option<MatchAnalysis> {
+    if !featured.claim(match_id, game.ply_index) {
+        return None;
+    }
+    analyze_game(game, max_depth, max_millis)
+}
+
+type AnalysisJob = (
+    MatchId,
+    TakGame,
+    tokio::sync::oneshot::Sender<Option<MatchAnalysis>>,
+);
+
+/// Runs every analysis on one thread. The engine keeps its transposition table per thread, so
+/// analyzing on the blocking pool would build a table on each of its threads.
+static ANALYSIS_WORKER: LazyLock<Mutex<std::sync::mpsc::Sender<AnalysisJob>>> =
+    LazyLock::new(|| {
+        let (sender, receiver) = std::sync::mpsc::channel::<AnalysisJob>();
+        std::thread::spawn(move || {
+            for (match_id, game, reply) in receiver {
+                let analysis = analysis_after_move(
+                    &FEATURED,
+                    &match_id,
+                    &game,
+                    ANALYSIS_MAX_DEPTH,
+                    ANALYSIS_MAX_MILLIS,
+                );
+                let _ = reply.send(analysis);
+            }
+        });
+        Mutex::new(sender)
+    });
+
+/// Analyzes the position of a featured match after a move and publishes the result to its
+/// spectators. Does nothing for other matches.
+pub async fn publish_analysis(match_id: MatchId) {
+    if !FEATURED.is_featured(&match_id) {
+        return;
+    }
+    let Ok(match_data) = matches::get_match_data(&match_id) else {
+        return;
+    };
+    let (reply, analysis) = tokio::sync::oneshot::channel();
+    let job = (match_id.clone(), match_data.game, reply);
+    if ANALYSIS_WORKER.lock().unwrap().send(job).is_err() {
+        log::error!("Analysis worker stopped, skipping match {match_id}");
+        return;
+    }
+    match analysis.await {
+        Ok(Some(analysis)) => {
+            ws_pubsub::publish_to_topic(
+                format!("{}/{}/{}", MATCHES_TOPIC, match_id, ANALYSIS_SUBTOPIC),
+                analysis,
+            )
+            .await;
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Analysis of match {match_id} failed: {e}"),
+    }
+}
+
+/// Returns the match whose analysis the topic carries, if it is an analysis topic.
+fn analysis_match_id(topic: &str) -> Option<&str> {
+    let mut parts = topic.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(MATCHES_TOPIC), Some(match_id), Some(ANALYSIS_SUBTOPIC), None) => Some(match_id),
+        _ => None,
+    }
+}
+
+/// Whether the user may subscribe to the topic. The players of a match can't follow its
+/// analysis, which would hand them the engine's best move during their own game.
+pub fn may_subscribe(topic: &str, user_id: &UserId) -> bool {
+    analysis_match_id(topic)
+        .is_none_or(|match_id| !matches::is_player(&match_id.to_string(), user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use tak_core::{TakAction, TakGameSettings, TakKomi};
+
+    use super::*;
+
+    fn game_with_moves(moves: &[&str]) -> TakGame {
+        let mut game = TakGame::new(TakGameSettings::new(5, None, TakKomi::none(), None)).unwrap();
+        for mv in moves {
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn test_only_featured_matches_are_analyzed() {
+        let featured = FeaturedMatches::new();
+        let featured_id = "featured".to_string();
+        let normal_id = "normal".to_string();
+        featured.feature(&featured_id);
+        let game = game_with_moves(&["a1", "e5", "c3"]);
+
+        let analysis = analysis_after_move(&featured, &featured_id, &game, 2, 10_000).unwrap();
+        assert_eq!(analysis.ply_index, 3);
+        assert_eq!(analysis.depth, 2);
+        assert!(TakAction::from_ptn(&analysis.best_move).is_some());
+        assert!(analysis_after_move(&featured, &normal_id, &game, 2, 10_000).is_none());
+    }
+
+    #[test]
+    fn test_analysis_topics() {
+        assert_eq!(analysis_match_id("matches/abc/analysis"), Some("abc"));
+        assert_eq!(analysis_match_id("matches/abc"), None);
+        assert_eq!(analysis_match_id("matches/abc/chat"), None);
+        assert_eq!(analysis_match_id("matches/abc/analysis/more"), None);
+        assert!(may_subscribe("matches/abc", &"white".to_string()));
+    }
+
+    #[test]
+    fn test_analysis_once_per_move() {
+        let featured = FeaturedMatches::new();
+        let match_id = "featured".to_string();
+        featured.feature(&match_id);
+        let mut game = game_with_moves(&["a1", "e5"]);
+
+        assert!(analysis_after_move(&featured, &match_id, &game, 1, 10_000).is_some());
+        assert!(analysis_after_move(&featured, &match_id, &game, 1, 10_000).is_none());
+        game.try_do_action(TakAction::from_ptn("c3").unwrap())
+            .unwrap();
+        assert!(analysis_after_move(&featured, &match_id, &game, 1, 10_000).is_some());
+
+        featured.unfeature(&match_id);
+        game.try_do_action(TakAction::from_ptn("c2").unwrap())
+            .unwrap();
+        assert!(analysis_after_move(&featured, &match_id, &game, 1, 10_000).is_none());
+    }
+}
@@ -95,10 +95,38 @@ fn create_token(user_id: &UserId, expires_in_hours: usize) -> ServerResult<JWTTo
         .map_err(|_| ServerError::InternalServerError("Failed to create token".to_string()))
 }
 
-pub fn renew_token(user_id: &UserId) -> ServerResult<JWTToken> {
+pub async fn renew_token(user_id: &UserId) -> ServerResult<JWTToken> {
+    check_account(user_id).await?;
     create_token(user_id, 24)
 }
 
+/// Fails with `Unauthorized` if the account a token was issued to has since been deleted.
+pub async fn check_account(user_id: &UserId) -> ServerResult<()> {
+    check_account_with(user_id, account_exists).await
+}
+
+async fn account_exists(user_id: &UserId) -> ServerResult<bool> {
+    if super::cache::get_player_info(user_id).await.is_some() {
+        return Ok(true);
+    }
+    match super::dto::try_get::<UserRecord>(user_id).await {
+        Ok(_) => Ok(true),
+        Err(ServerError::NotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+async fn check_account_with<'a, F: Future<Output = ServerResult<bool>>>(
+    user_id: &'a UserId,
+    exists: impl FnOnce(&'a UserId) -> F,
+) -> ServerResult<()> {
+    if exists(user_id).await? {
+        Ok(())
+    } else {
+        Err(ServerError::Unauthorized)
+    }
+}
+
 pub fn validate_token(token: &JWTToken) -> ServerResult<Claims> {
     decode::<Claims>(token, &KEYS.decoding, &Validation::default())
         .map(|data| data.claims)
@@ -200,3 +228,62 @@ pub async fn try_change_password(
 
     Ok(())
 }
+
+/// Deletes an account. Live matches and seeks of the user are canceled and their sockets are
+/// closed, while their stored games are kept with the name anonymized.
+pub async fn try_delete_account(user_id: &UserId) -> ServerResult<()> {
+    let ongoing = super::matches::ongoing_match_ids(user_id);
+    let match_ids = super::matches::get_match_id(user_id)
+        .await
+        .unwrap_or_default();
+    for match_id in match_ids {
+        if ongoing.contains(&match_id) {
+            super::matches::cancel_match(&match_id, user_id).await?;
+        } else {
+            super::matches::leave_match(&match_id, user_id).await?;
+        }
+    }
+    if let Err(e) = super::seek::cancel_seek(user_id).await {
+        if !matches!(e, ServerError::NotFound) {
+            return Err(e);
+        }
+    }
+    ws_pubsub::disconnect_user(user_id);
+
+    super::player::delete_player(user_id).await?;
+    super::dto::try_delete::<UserRecord>(user_id).await?;
+    super::cache::PLAYER_INFO_CACHE.invalidate(user_id).await;
+    log::info!("Deleted account of user {user_id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashSet};
+
+    use super::*;
+
+    #[test]
+    fn test_deleted_account_is_unauthorized() {
+        let user_id = "deleted".to_string();
+        let accounts = RefCell::new(HashSet::from([user_id.clone()]));
+        let exists = |user_id: &UserId| {
+            let exists = accounts.borrow().contains(user_id);
+            async move { Ok(exists) }
+        };
+        let token = create_token(&user_id, 24).unwrap();
+        let claims = validate_token(&token).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert!(check_account_with(&claims.sub, exists).await.is_ok());
+            accounts.borrow_mut().remove(&user_id);
+            assert!(matches!(
+                check_account_with(&claims.sub, exists).await,
+                Err(ServerError::Unauthorized)
+            ));
+        });
+    }
+}
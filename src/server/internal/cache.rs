@@ -1,8 +1,13 @@
 use std::{sync::LazyLock, time::Duration};
 
 use moka::future::Cache;
+use tak_core::TakGame;
 
-use crate::server::{error::ServerResult, internal::dto::UserRecord, PlayerInformation, UserId};
+use crate::server::{
+    GameId, PlayerInformation, UserId,
+    error::ServerResult,
+    internal::dto::{GameRecord, UserRecord},
+};
 
 pub static PLAYER_INFO_CACHE: LazyLock<Cache<UserId, PlayerInformation>> = LazyLock::new(|| {
     Cache::builder()
@@ -11,6 +16,14 @@ pub static PLAYER_INFO_CACHE: LazyLock<Cache<UserId, PlayerInformation>> = LazyL
         .build()
 });
 
+/// Replayed games by id, together with the PTN they were replayed from.
+pub static REPLAY_CACHE: LazyLock<Cache<GameId, (String, TakGame)>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(1000)
+        .time_to_live(Duration::from_secs(30 * 60))
+        .build()
+});
+
 pub async fn get_player_info(user_id: &UserId) -> Option<PlayerInformation> {
     PLAYER_INFO_CACHE.get(user_id).await
 }
@@ -39,3 +52,69 @@ pub async fn retrieve_player_info(user_id: &UserId) -> ServerResult<PlayerInform
     set_player_info(user_id, info.clone()).await;
     Ok(info)
 }
+
+pub async fn get_or_replay_game(game: &GameRecord) -> ServerResult<TakGame> {
+    get_or_replay_game_with(game, super::player::replay_game).await
+}
+
+/// Returns the cached replay of a game, replaying it with `replay` if it isn't cached or its
+/// PTN has changed since.
+async fn get_or_replay_game_with(
+    game: &GameRecord,
+    replay: impl FnOnce(&GameRecord) -> ServerResult<TakGame>,
+) -> ServerResult<TakGame> {
+    if let Some((ptn, replayed)) = REPLAY_CACHE.get(&game.game_id).await
+        && ptn == game.ptn
+    {
+        return Ok(replayed);
+    }
+    let replayed = replay(game)?;
+    REPLAY_CACHE
+        .insert(game.game_id.clone(), (game.ptn.clone(), replayed.clone()))
+        .await;
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_replay_is_cached_until_ptn_changes() {
+        let player = PlayerInformation {
+            user_id: "me".to_string(),
+            username: "me".to_string(),
+            rating: 1200.0,
+        };
+        let mut game = GameRecord {
+            game_id: "cached_replay".to_string(),
+            white_player: player.clone(),
+            black_player: player,
+            ptn: "[Size \"5\"]\n\n1. a1 e5\n2. c3".to_string(),
+            timestamp: chrono::Utc::now().into(),
+        };
+        let replays = Cell::new(0);
+        let replay = |game: &GameRecord| {
+            replays.set(replays.get() + 1);
+            crate::server::internal::player::replay_game(game)
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let first = get_or_replay_game_with(&game, replay).await.unwrap();
+            let second = get_or_replay_game_with(&game, replay).await.unwrap();
+            assert_eq!(replays.get(), 1);
+            assert_eq!(first.to_tps(), second.to_tps());
+            assert_eq!(second.ply_index, 3);
+
+            game.ptn += " c4";
+            let edited = get_or_replay_game_with(&game, replay).await.unwrap();
+            assert_eq!(replays.get(), 2);
+            assert_eq!(edited.ply_index, 4);
+        });
+    }
+}
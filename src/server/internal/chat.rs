@@ -0,0 +1,183 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::server::{
+    ChatMessage, MatchId, MatchInstance, PlayerInformation, UserId,
+    api::{CHAT_SUBTOPIC, MATCHES_TOPIC},
+    error::{ServerError, ServerResult},
+    internal::{cache, matches},
+};
+
+/// Longest chat message in characters. Longer messages are cut off.
+pub const MAX_CHAT_LENGTH: usize = 300;
+
+/// Limits how many messages a user may send within a sliding window.
+pub struct ChatRateLimiter {
+    window: Duration,
+    max_messages: usize,
+    sent: Mutex<HashMap<UserId, VecDeque<Instant>>>,
+}
+
+impl ChatRateLimiter {
+    pub fn new(window: Duration, max_messages: usize) -> Self {
+        Self {
+            window,
+            max_messages,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a message if the user is still below the limit. Returns whether it may be sent.
+    pub fn try_send(&self, user_id: &UserId, now: Instant) -> bool {
+        let mut sent = self.sent.lock().unwrap();
+        let times = sent.entry(user_id.clone()).or_default();
+        while times
+            .front()
+            .is_some_and(|time| now.duration_since(*time) >= self.window)
+        {
+            times.pop_front();
+        }
+        if times.len() >= self.max_messages {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+static CHAT_LIMITER: LazyLock<ChatRateLimiter> =
+    LazyLock::new(|| ChatRateLimiter::new(Duration::from_secs(10), 5));
+
+/// Removes control characters and surrounding whitespace and caps the length. Returns `None`
+/// if nothing is left to send.
+pub fn sanitize_chat(text: &str) -> Option<String> {
+    let text = text.chars().filter(|c| !c.is_control()).collect::<String>();
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.chars().take(MAX_CHAT_LENGTH).collect())
+}
+
+/// Checks a chat message of a user following `instance`. Spectators may chat as well as the
+/// players.
+pub fn prepare_chat(
+    instance: &MatchInstance,
+    is_spectator: bool,
+    limiter: &ChatRateLimiter,
+    from: PlayerInformation,
+    text: &str,
+    now: Instant,
+) -> ServerResult<ChatMessage> {
+    let is_player = from.user_id == instance.player_id || from.user_id == instance.opponent_id;
+    if !is_player && !is_spectator {
+        return Err(ServerError::Unauthorized);
+    }
+    let text = sanitize_chat(text)
+        .ok_or_else(|| ServerError::BadRequest("Chat message is empty".to_string()))?;
+    if !limiter.try_send(&from.user_id, now) {
+        return Err(ServerError::NotAllowed(
+            "Sending chat messages too quickly".to_string(),
+        ));
+    }
+    Ok(ChatMessage {
+        from,
+        text,
+        timestamp: chrono::Utc::now(),
+    })
+}
+
+pub async fn send_chat(match_id: &MatchId, user_id: &UserId, text: &str) -> ServerResult<()> {
+    let instance = matches::get_match(match_id).await?;
+    let is_spectator = matches::is_spectator(match_id, user_id);
+    let from = cache::get_or_retrieve_player_info(user_id).await?;
+    let message = prepare_chat(
+        &instance,
+        is_spectator,
+        &CHAT_LIMITER,
+        from,
+        text,
+        Instant::now(),
+    )?;
+    ws_pubsub::publish_to_topic(
+        format!("{}/{}/{}", MATCHES_TOPIC, match_id, CHAT_SUBTOPIC),
+        message,
+    )
+    .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tak_core::{TakGameSettings, TakKomi, TakPlayer};
+
+    use super::*;
+    use crate::server::RematchColor;
+
+    fn instance() -> MatchInstance {
+        MatchInstance {
+            player_id: "white".to_string(),
+            opponent_id: "black".to_string(),
+            game_settings: TakGameSettings::new(5, None, TakKomi::none(), None),
+            rated: false,
+            creator_color: TakPlayer::White,
+            rematch_color: RematchColor::Keep,
+            seed: None,
+        }
+    }
+
+    fn player(user_id: &str) -> PlayerInformation {
+        PlayerInformation {
+            user_id: user_id.to_string(),
+            username: user_id.to_string(),
+            rating: 1000.0,
+        }
+    }
+
+    #[test]
+    fn test_chat_membership() {
+        let limiter = ChatRateLimiter::new(Duration::from_secs(10), 5);
+        let now = Instant::now();
+
+        let message = prepare_chat(
+            &instance(),
+            false,
+            &limiter,
+            player("white"),
+            "gl\u{7} hf ",
+            now,
+        )
+        .unwrap();
+        assert_eq!(message.text, "gl hf");
+        assert_eq!(message.from.user_id, "white");
+        assert!(prepare_chat(&instance(), true, &limiter, player("watcher"), "hi", now).is_ok());
+        assert!(matches!(
+            prepare_chat(&instance(), false, &limiter, player("stranger"), "hi", now),
+            Err(ServerError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_chat_limits() {
+        let limiter = ChatRateLimiter::new(Duration::from_secs(10), 2);
+        let now = Instant::now();
+        let send = |text: &str, now| {
+            prepare_chat(&instance(), false, &limiter, player("white"), text, now)
+        };
+
+        assert!(send("\n\t", now).is_err());
+        assert_eq!(
+            send(&"a".repeat(MAX_CHAT_LENGTH + 10), now)
+                .unwrap()
+                .text
+                .len(),
+            MAX_CHAT_LENGTH
+        );
+        assert!(send("b", now).is_ok());
+        assert!(send("c", now + Duration::from_secs(5)).is_err());
+        assert!(send("d", now + Duration::from_secs(10)).is_ok());
+    }
+}
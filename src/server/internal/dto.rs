@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use surrealdb::RecordIdKey;
 
+use tak_core::{TakClock, TakGameSettings};
+
 use crate::server::{
-    GameId, PlayerInformation, UserId,
+    GameId, MatchId, MatchInstance, PlayerInformation, PuzzleId, UserId,
     error::{ServerError, ServerResult},
     internal::db::DB,
 };
@@ -77,6 +79,73 @@ impl Record for GameRecord {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveMatchRecord {
+    pub match_id: MatchId,
+    pub instance: MatchInstance,
+    pub ptn: String,
+    pub clock: Option<TakClock>,
+    pub rematch_agree: Vec<UserId>,
+    pub draw_agree: Vec<UserId>,
+    #[serde(default)]
+    pub takeback_request: Option<UserId>,
+    pub has_ended: bool,
+    /// Kept across restarts, so that a restored game doesn't wait for its first move anew.
+    #[serde(default)]
+    pub first_move_deadline: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl Record for ActiveMatchRecord {
+    type K = MatchId;
+    fn table_name() -> &'static str {
+        "active_match"
+    }
+    fn record_id_key(key: &Self::K) -> RecordIdKey {
+        RecordIdKey::from(key)
+    }
+}
+
+/// Keyed by the canonical TPS of the position, so symmetric positions are stored only once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PuzzleRecord {
+    pub puzzle_id: PuzzleId,
+    pub tps: String,
+    pub solution: Vec<String>,
+    /// Missing for puzzles stored before the rules of their game were kept.
+    #[serde(default)]
+    pub settings: Option<TakGameSettings>,
+}
+
+impl Record for PuzzleRecord {
+    type K = PuzzleId;
+    fn table_name() -> &'static str {
+        "puzzle"
+    }
+    fn record_id_key(key: &Self::K) -> RecordIdKey {
+        RecordIdKey::from(key)
+    }
+}
+
+/// How far the puzzle generator got through the stored games, which it scans in order of
+/// timestamp and then id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PuzzleScanRecord {
+    pub timestamp: surrealdb::sql::Datetime,
+    pub game_id: GameId,
+}
+
+impl Record for PuzzleScanRecord {
+    type K = String;
+    fn table_name() -> &'static str {
+        "puzzle_scan"
+    }
+    fn record_id_key(key: &Self::K) -> RecordIdKey {
+        RecordIdKey::from(key)
+    }
+}
+
 pub async fn setup_db() -> ServerResult<()> {
     DB.query("DEFINE FIELD IF NOT EXISTS username ON user TYPE string ASSERT $value != NONE;")
         .query("DEFINE INDEX IF NOT EXISTS idx_unique_username ON user FIELDS username UNIQUE;")
@@ -90,6 +159,10 @@ pub async fn try_get<T: Record + DeserializeOwned>(key: &T::K) -> ServerResult<T
         .ok_or(ServerError::NotFound)
 }
 
+pub async fn get_all<T: Record + DeserializeOwned>() -> ServerResult<Vec<T>> {
+    Ok(DB.select(T::table_name()).await?)
+}
+
 pub async fn try_get_or_insert<T: Record + DeserializeOwned + Serialize + 'static>(
     key: &T::K,
     default_value: impl FnOnce() -> T,
@@ -130,3 +203,18 @@ pub async fn try_merge<T: Record + DeserializeOwned + Serialize + 'static>(
         .await?
         .ok_or(ServerError::NotFound)
 }
+
+pub async fn try_upsert<T: Record + DeserializeOwned + Serialize + 'static>(
+    key: &T::K,
+    value: T,
+) -> ServerResult<T> {
+    DB.upsert((T::table_name(), T::record_id_key(key)))
+        .content(value)
+        .await?
+        .ok_or(ServerError::NotFound)
+}
+
+pub async fn try_delete<T: Record + DeserializeOwned>(key: &T::K) -> ServerResult<()> {
+    let _: Option<T> = DB.delete((T::table_name(), T::record_id_key(key))).await?;
+    Ok(())
+}
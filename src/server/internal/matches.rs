@@ -1,15 +1,26 @@
-use std::sync::{Arc, LazyLock};
+use std::{
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use dashmap::DashMap;
-use tak_core::{TakAction, TakGame, TakGameState, TakPlayer};
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+use tak_core::{TakAction, TakGame, TakGameState, TakPlayer, TakPtn};
 
 use crate::{
     components::ServerGameMessage,
     server::{
-        MatchData, MatchId, MatchInstance, MatchUpdate, PlayerInformation, ServerError,
-        ServerResult, UserId,
-        api::{DRAW_SUBTOPIC, MATCHES_TOPIC, REMATCH_SUBTOPIC},
-        internal::cache,
+        LiveMatchInfo, MatchData, MatchId, MatchInstance, MatchUpdate, PlayerInformation,
+        RematchColor, ServerError, ServerResult, UserId,
+        api::{DRAW_SUBTOPIC, MATCHES_TOPIC, REMATCH_SUBTOPIC, TAKEBACK_SUBTOPIC},
+        internal::{
+            analysis, cache, chat,
+            dto::{self, ActiveMatchRecord},
+            seek,
+        },
     },
     views::ClientGameMessage,
 };
@@ -18,7 +29,10 @@ pub struct Matches {
     match_end_senders: Arc<DashMap<MatchId, tokio::sync::oneshot::Sender<TakGameState>>>,
     match_data: Arc<DashMap<MatchId, MatchData>>,
     matches: Arc<DashMap<MatchId, MatchInstance>>,
-    players: Arc<DashMap<UserId, MatchId>>,
+    /// The matches of each player, oldest first.
+    players: Arc<DashMap<UserId, Vec<MatchId>>>,
+    /// Players allowed in more than one match at a time, like bot accounts.
+    match_limits: Arc<DashMap<UserId, usize>>,
 }
 
 fn new_match_data(instance: MatchInstance) -> ServerResult<MatchData> {
@@ -33,7 +47,13 @@ fn new_match_data(instance: MatchInstance) -> ServerResult<MatchData> {
         player_mapping,
         rematch_agree: Vec::new(),
         draw_agree: Vec::new(),
+        takeback_request: None,
         has_ended: false,
+        spectators: Vec::new(),
+        first_move_deadline: chrono::Duration::from_std(first_move_deadline())
+            .ok()
+            .map(|deadline| chrono::Utc::now() + deadline),
+        version: 0,
     })
 }
 
@@ -44,11 +64,79 @@ impl Matches {
             players: Arc::new(DashMap::new()),
             match_data: Arc::new(DashMap::new()),
             match_end_senders: Arc::new(DashMap::new()),
+            match_limits: Arc::new(DashMap::new()),
         }
     }
 
-    fn has_match(&self, player_id: &UserId) -> bool {
-        self.players.contains_key(player_id)
+    fn match_ids(&self, player_id: &UserId) -> Vec<MatchId> {
+        self.players
+            .get(player_id)
+            .map(|x| x.value().clone())
+            .unwrap_or_default()
+    }
+
+    /// Checks that a player plays in a match before they act on it. Players can be in several
+    /// matches at once, so actions name the match instead of going by the player.
+    fn check_player(&self, match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+        if self.match_ids(player_id).contains(match_id) {
+            Ok(())
+        } else {
+            Err(ServerError::NotFound)
+        }
+    }
+
+    /// Whether a player has room for another match. Everyone gets one unless configured otherwise.
+    fn can_join_match(&self, player_id: &UserId) -> bool {
+        let limit = self.match_limits.get(player_id).map_or(1, |x| *x.value());
+        self.match_ids(player_id).len() < limit
+    }
+
+    /// Adds a user to the spectators of a match, which its own players can't join. Returns the
+    /// number of spectators.
+    fn add_spectator(&self, match_id: &MatchId, user_id: &UserId) -> ServerResult<usize> {
+        if self.match_ids(user_id).contains(match_id) {
+            return Err(ServerError::Conflict(
+                "Players can't spectate their own match".to_string(),
+            ));
+        }
+        self.with_match_data(match_id, |match_data| {
+            if !match_data.spectators.contains(user_id) {
+                match_data.spectators.push(user_id.clone());
+            }
+            match_data.spectators.len()
+        })
+        .ok_or(ServerError::NotFound)
+    }
+
+    /// Removes a user from the spectators of a match. Returns the number of spectators left.
+    fn remove_spectator(&self, match_id: &MatchId, user_id: &UserId) -> ServerResult<usize> {
+        self.with_match_data(match_id, |match_data| {
+            match_data.spectators.retain(|x| x != user_id);
+            match_data.spectators.len()
+        })
+        .ok_or(ServerError::NotFound)
+    }
+
+    /// Removes a user from the spectators of every match. Returns the matches they left, each
+    /// with the number of spectators left.
+    fn remove_spectator_everywhere(&self, user_id: &UserId) -> Vec<(MatchId, usize)> {
+        self.match_data
+            .iter_mut()
+            .filter_map(|mut entry| {
+                let spectators = &mut entry.value_mut().spectators;
+                let before = spectators.len();
+                spectators.retain(|x| x != user_id);
+                let after = spectators.len();
+                (after < before).then(|| (entry.key().clone(), after))
+            })
+            .collect()
+    }
+
+    fn is_spectator(&self, match_id: &MatchId, user_id: &UserId) -> bool {
+        self.with_match_data(match_id, |match_data| {
+            match_data.spectators.contains(user_id)
+        })
+        .unwrap_or(false)
     }
 
     fn get_matches(&self) -> Vec<(MatchId, MatchInstance)> {
@@ -58,6 +146,15 @@ impl Matches {
             .collect()
     }
 
+    /// Returns the matches whose game is still being played.
+    fn get_live_matches(&self) -> Vec<(MatchId, MatchData)> {
+        self.match_data
+            .iter()
+            .filter(|x| !x.value().has_ended && x.value().game.game_state == TakGameState::Ongoing)
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect()
+    }
+
     fn with_match_data<F, R>(&self, match_id: &MatchId, f: F) -> Option<R>
     where
         F: FnOnce(&mut MatchData) -> R,
@@ -85,6 +182,33 @@ impl Matches {
         })
     }
 
+    /// Cancels a game nobody has moved in once its first move deadline has passed at `now`.
+    /// The deadline is only checked once, so taking back the first move later doesn't cancel
+    /// the game. Returns whether the game was canceled.
+    fn check_first_move_deadline(
+        &self,
+        match_id: &MatchId,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<bool> {
+        self.with_match_data(match_id, |match_data| {
+            if match_data
+                .first_move_deadline
+                .is_none_or(|deadline| now < deadline)
+            {
+                return false;
+            }
+            match_data.first_move_deadline = None;
+            if !match_data.game.action_history.is_empty()
+                || match_data.game.game_state != TakGameState::Ongoing
+            {
+                return false;
+            }
+            match_data.game.game_state = TakGameState::Canceled;
+            match_data.version += 1;
+            true
+        })
+    }
+
     fn with_ongoing_game<T>(
         &self,
         match_id: &MatchId,
@@ -108,23 +232,80 @@ impl Matches {
 
     async fn add_match(&self, match_id: MatchId, settings: MatchInstance) -> ServerResult<()> {
         let match_data = new_match_data(settings.clone())?;
-        self.players
-            .insert(settings.player_id.clone(), match_id.clone());
-        self.players
-            .insert(settings.opponent_id.clone(), match_id.clone());
-        self.matches.insert(match_id.clone(), settings);
-        self.match_data.insert(match_id.clone(), match_data);
+        self.insert_match(match_id.clone(), settings, match_data);
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.match_end_senders.insert(match_id.clone(), tx);
         tokio::spawn(check_match_finished_task(rx, match_id.clone()));
         tokio::spawn(check_match_timeout_task(match_id.clone()));
+        persist_match(&match_id).await;
         Ok(())
     }
 
+    fn insert_match(&self, match_id: MatchId, settings: MatchInstance, match_data: MatchData) {
+        for player_id in [&settings.player_id, &settings.opponent_id] {
+            let mut match_ids = self.players.entry(player_id.clone()).or_default();
+            if !match_ids.contains(&match_id) {
+                match_ids.push(match_id.clone());
+            }
+        }
+        self.matches.insert(match_id.clone(), settings);
+        self.match_data.insert(match_id, match_data);
+    }
+
+    fn restore_match(&self, match_id: MatchId, settings: MatchInstance, match_data: MatchData) {
+        let has_ended = match_data.has_ended;
+        self.insert_match(match_id.clone(), settings, match_data);
+        if has_ended {
+            return;
+        }
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.match_end_senders.insert(match_id.clone(), tx);
+        tokio::spawn(check_match_finished_task(rx, match_id.clone()));
+        tokio::spawn(check_match_timeout_task(match_id.clone()));
+    }
+
+    fn to_record(&self, match_id: &MatchId) -> Option<ActiveMatchRecord> {
+        let instance = self.matches.get(match_id)?.value().clone();
+        let match_data = self.match_data.get(match_id)?;
+        Some(ActiveMatchRecord {
+            match_id: match_id.clone(),
+            instance,
+            ptn: match_data.game.to_ptn().to_str(),
+            clock: match_data.game.clock.clone(),
+            rematch_agree: match_data.rematch_agree.clone(),
+            draw_agree: match_data.draw_agree.clone(),
+            takeback_request: match_data.takeback_request.clone(),
+            has_ended: match_data.has_ended,
+            first_move_deadline: match_data.first_move_deadline,
+            version: match_data.version,
+        })
+    }
+
+    /// Removes a finished match once one of its players leaves it.
+    fn leave_match(&self, match_id: &MatchId, player_id: &UserId) -> ServerResult<MatchInstance> {
+        self.check_player(match_id, player_id)?;
+        self.with_match_data(match_id, |match_data| {
+            if !match_data.has_ended {
+                return Err(ServerError::Conflict(
+                    "Cannot leave match while game hasn't ended".to_string(),
+                ));
+            }
+            match_data.rematch_agree.clear();
+            Ok(())
+        })
+        .unwrap_or(Err(ServerError::NotFound))?;
+        self.remove_match(match_id)
+    }
+
     fn remove_match(&self, match_id: &MatchId) -> ServerResult<MatchInstance> {
         if let Some((_, match_instance)) = self.matches.remove(match_id) {
-            self.players.remove(&match_instance.player_id);
-            self.players.remove(&match_instance.opponent_id);
+            for player_id in [&match_instance.player_id, &match_instance.opponent_id] {
+                if let Some(mut match_ids) = self.players.get_mut(player_id) {
+                    match_ids.retain(|x| x != match_id);
+                }
+                self.players
+                    .remove_if(player_id, |_, match_ids| match_ids.is_empty());
+            }
             self.match_data.remove(match_id);
             self.match_end_senders.remove(match_id);
             Ok(match_instance)
@@ -144,8 +325,131 @@ pub fn get_match_data(match_id: &MatchId) -> ServerResult<MatchData> {
 
 pub static MATCHES: LazyLock<Matches> = LazyLock::new(|| Matches::new());
 
+/// Lets a player, typically a bot account, play up to `limit` matches at the same time.
+pub fn set_max_concurrent_matches(player_id: UserId, limit: usize) {
+    MATCHES.match_limits.insert(player_id, limit);
+}
+
+static FIRST_MOVE_DEADLINE_MILLIS: AtomicU64 = AtomicU64::new(30_000);
+
+/// Sets how long a new game waits for its first move before it is canceled. Unlike the game
+/// clock, this also applies to untimed games.
+pub fn set_first_move_deadline(deadline: Duration) {
+    FIRST_MOVE_DEADLINE_MILLIS.store(deadline.as_millis() as u64, Ordering::Relaxed);
+}
+
+fn first_move_deadline() -> Duration {
+    Duration::from_millis(FIRST_MOVE_DEADLINE_MILLIS.load(Ordering::Relaxed))
+}
+
+/// How often the clocks of ongoing games are sent to their clients.
+const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The clocks of a game as seen by the server right now, or `None` if they aren't running.
+fn clock_sync_message(game: &TakGame) -> Option<ServerGameMessage> {
+    if game.game_state != TakGameState::Ongoing {
+        return None;
+    }
+    let time_remaining = TakPlayer::ALL
+        .into_iter()
+        .map(|x| Some((x, game.get_time_remaining(x, x == game.current_player)?)))
+        .collect::<Option<Vec<_>>>()?;
+    Some(ServerGameMessage::ClockSync(time_remaining))
+}
+
+static AUDIT_MOVES: AtomicBool = AtomicBool::new(false);
+
+/// Enables checking the game invariants after every move. Off by default, since it costs a
+/// full board scan per move.
+pub fn set_move_audit(enabled: bool) {
+    AUDIT_MOVES.store(enabled, Ordering::Relaxed);
+}
+
+/// Validates the game after a move. A game that fails the check can't be trusted anymore, so
+/// it gets canceled and its PTN is logged for debugging. Returns whether the game is valid.
+fn audit_game(match_id: &MatchId, match_data: &mut MatchData) -> bool {
+    let Err(e) = match_data.game.validate() else {
+        return true;
+    };
+    log::error!(
+        "Match {match_id} failed validation: {e}, canceling. PTN: {}",
+        match_data.game.to_ptn().to_str()
+    );
+    match_data.game.game_state = TakGameState::Canceled;
+    false
+}
+
+async fn persist_match(match_id: &MatchId) {
+    let Some(record) = MATCHES.to_record(match_id) else {
+        log::warn!("Cannot persist match {match_id}: match not found");
+        return;
+    };
+    if let Err(e) = dto::try_upsert(match_id, record).await {
+        log::error!("Failed to persist match {match_id}: {e}");
+    }
+}
+
+async fn delete_persisted_match(match_id: &MatchId) {
+    if let Err(e) = dto::try_delete::<ActiveMatchRecord>(match_id).await {
+        log::error!("Failed to delete persisted match {match_id}: {e}");
+    }
+}
+
+fn match_data_from_record(record: &ActiveMatchRecord) -> ServerResult<MatchData> {
+    let ptn = TakPtn::try_from_str(&record.ptn).ok_or_else(|| {
+        ServerError::InternalServerError(format!("Invalid stored PTN: {}", record.ptn))
+    })?;
+    let mut game = TakGame::try_from_ptn(ptn).map_err(|e| {
+        ServerError::InternalServerError(format!("Failed to replay stored PTN: {e}"))
+    })?;
+    // Replaying the moves restarts the clock, so restore the stored one. Its
+    // last update timestamp makes any time spent offline count against the
+    // player to move.
+    game.clock = record.clock.clone();
+    game.check_timeout();
+
+    let mut match_data = new_match_data(record.instance.clone())?;
+    match_data.game = game;
+    match_data.rematch_agree = record.rematch_agree.clone();
+    match_data.draw_agree = record.draw_agree.clone();
+    match_data.takeback_request = record.takeback_request.clone();
+    match_data.has_ended = record.has_ended;
+    match_data.first_move_deadline = record.first_move_deadline;
+    match_data.version = record.version;
+    Ok(match_data)
+}
+
+pub async fn restore_matches() -> ServerResult<()> {
+    let records = dto::get_all::<ActiveMatchRecord>().await?;
+    for record in records {
+        let match_id = record.match_id.clone();
+        let match_data = match match_data_from_record(&record) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Failed to restore match {match_id}: {e}");
+                delete_persisted_match(&match_id).await;
+                continue;
+            }
+        };
+        // The match was allowed when it was created, so it is kept even if the limits were
+        // lowered since.
+        if !MATCHES.can_join_match(&record.instance.player_id)
+            || !MATCHES.can_join_match(&record.instance.opponent_id)
+        {
+            log::warn!("Restoring match {match_id} although a player is over their match limit");
+        }
+        let game_state = match_data.game.game_state.clone();
+        MATCHES.restore_match(match_id.clone(), record.instance, match_data);
+        MATCHES.check_game_over(&match_id);
+        log::info!("Match restored: {match_id}, state: {game_state:?}");
+    }
+    Ok(())
+}
+
 pub async fn create_match(instance: MatchInstance) -> ServerResult<MatchId> {
-    if MATCHES.has_match(&instance.player_id) || MATCHES.has_match(&instance.opponent_id) {
+    if !MATCHES.can_join_match(&instance.player_id)
+        || !MATCHES.can_join_match(&instance.opponent_id)
+    {
         return Err(ServerError::Conflict(
             "Match already exists for this player".to_string(),
         ));
@@ -158,6 +462,9 @@ pub async fn create_match(instance: MatchInstance) -> ServerResult<MatchId> {
     MATCHES
         .add_match(match_id.clone(), instance.clone())
         .await?;
+    if analysis::qualifies_for_featuring(player_info.rating, opponent_info.rating) {
+        analysis::FEATURED.feature(&match_id);
+    }
     ws_pubsub::publish_to_topic(
         MATCHES_TOPIC,
         MatchUpdate::Created {
@@ -183,13 +490,34 @@ pub async fn create_match(instance: MatchInstance) -> ServerResult<MatchId> {
     Ok(match_id)
 }
 
+/// Sets up the instance of a rematch according to its color policy. A seeded `Random` policy
+/// derives a new seed from the old one, so each rematch flips its own coin while the whole
+/// sequence stays reproducible.
+fn rematch_instance(mut instance: MatchInstance) -> MatchInstance {
+    match instance.rematch_color {
+        RematchColor::Keep => {}
+        RematchColor::Alternate => instance.creator_color = instance.creator_color.other(),
+        RematchColor::Random => {
+            instance.seed = instance
+                .seed
+                .map(|seed| StdRng::seed_from_u64(seed).next_u64());
+            instance.creator_color = seek::draw_creator_color(instance.seed);
+        }
+    }
+    instance
+}
+
 pub async fn restart_match(match_id: &MatchId) -> ServerResult<()> {
-    let instance = MATCHES.remove_match(match_id)?;
+    let spectators = MATCHES
+        .with_match_data(match_id, |match_data| match_data.spectators.clone())
+        .unwrap_or_default();
+    let instance = rematch_instance(MATCHES.remove_match(match_id)?);
 
     let player_id = instance.player_id.clone();
     let opponent_id = instance.opponent_id.clone();
 
     MATCHES.add_match(match_id.clone(), instance).await?;
+    MATCHES.with_match_data(match_id, |match_data| match_data.spectators = spectators);
 
     ws_pubsub::publish_to_topic(
         format!("{}/{}", MATCHES_TOPIC, match_id),
@@ -220,11 +548,79 @@ pub async fn get_matches()
     Ok(seek_list)
 }
 
-pub async fn get_match_id(player_id: &UserId) -> ServerResult<MatchId> {
-    if let Some(match_id) = MATCHES.players.get(player_id) {
-        Ok(match_id.value().clone())
-    } else {
+pub async fn get_live_matches() -> ServerResult<Vec<LiveMatchInfo>> {
+    let mut live_matches = Vec::new();
+    for (match_id, match_data) in MATCHES.get_live_matches() {
+        let player_id = |player: TakPlayer| {
+            match_data.player_mapping.get(player).ok_or_else(|| {
+                ServerError::InternalServerError(format!("Match {match_id} is missing a player"))
+            })
+        };
+        let white_id = player_id(TakPlayer::White)?;
+        let black_id = player_id(TakPlayer::Black)?;
+        live_matches.push(LiveMatchInfo {
+            white_player: cache::get_or_retrieve_player_info(white_id).await?,
+            black_player: cache::get_or_retrieve_player_info(black_id).await?,
+            ply_index: match_data.game.ply_index,
+            time_remaining: TakPlayer::ALL
+                .into_iter()
+                .filter_map(|x| Some((x, match_data.game.get_time_remaining(x, true)?)))
+                .collect(),
+            spectators: match_data.spectators.len(),
+            match_id,
+        });
+    }
+    Ok(live_matches)
+}
+
+/// Whether the user is one of the players of the match.
+pub fn is_player(match_id: &MatchId, user_id: &UserId) -> bool {
+    MATCHES.check_player(match_id, user_id).is_ok()
+}
+
+/// Whether the user joined the match as a spectator.
+pub fn is_spectator(match_id: &MatchId, user_id: &UserId) -> bool {
+    MATCHES.is_spectator(match_id, user_id)
+}
+
+pub async fn join_match_as_spectator(match_id: &MatchId, user_id: &UserId) -> ServerResult<()> {
+    let count = MATCHES.add_spectator(match_id, user_id)?;
+    ws_pubsub::publish_to_topic(
+        format!("{}/{}", MATCHES_TOPIC, match_id),
+        ServerGameMessage::Spectators(count),
+    )
+    .await;
+    Ok(())
+}
+
+pub async fn leave_match_as_spectator(match_id: &MatchId, user_id: &UserId) -> ServerResult<()> {
+    let count = MATCHES.remove_spectator(match_id, user_id)?;
+    ws_pubsub::publish_to_topic(
+        format!("{}/{}", MATCHES_TOPIC, match_id),
+        ServerGameMessage::Spectators(count),
+    )
+    .await;
+    Ok(())
+}
+
+/// Stops a user from spectating any match, e.g. after their last connection closed.
+pub async fn leave_all_as_spectator(user_id: &UserId) {
+    for (match_id, count) in MATCHES.remove_spectator_everywhere(user_id) {
+        ws_pubsub::publish_to_topic(
+            format!("{}/{}", MATCHES_TOPIC, match_id),
+            ServerGameMessage::Spectators(count),
+        )
+        .await;
+    }
+}
+
+/// The matches of a player, oldest first.
+pub async fn get_match_id(player_id: &UserId) -> ServerResult<Vec<MatchId>> {
+    let match_ids = MATCHES.match_ids(player_id);
+    if match_ids.is_empty() {
         Err(ServerError::NotFound)
+    } else {
+        Ok(match_ids)
     }
 }
 
@@ -236,21 +632,18 @@ pub async fn get_match(match_id: &MatchId) -> ServerResult<MatchInstance> {
     }
 }
 
-pub async fn offer_draw(player_id: &UserId) -> ServerResult<()> {
-    let match_id = MATCHES
-        .players
-        .get(player_id)
-        .map(|x| x.value().clone())
-        .ok_or_else(|| ServerError::NotFound)?;
+pub async fn offer_draw(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
 
     let did_draw = MATCHES
-        .with_ongoing_game(&match_id, |match_data| {
+        .with_ongoing_game(match_id, |match_data| {
             if match_data.draw_agree.contains(player_id) {
                 return Err(ServerError::Conflict("Already offered draw".to_string()));
             }
             match_data.draw_agree.push(player_id.clone());
             if match_data.draw_agree.len() == 2 {
                 match_data.game.abort(None);
+                match_data.version += 1;
                 Ok(true)
             } else {
                 Ok(false)
@@ -265,22 +658,219 @@ pub async fn offer_draw(player_id: &UserId) -> ServerResult<()> {
     .await;
 
     if did_draw {
-        MATCHES.check_game_over(&match_id);
+        MATCHES.check_game_over(match_id);
     }
+    persist_match(match_id).await;
 
     log::info!("Player {} offered draw for match: {}", player_id, match_id);
     Ok(())
 }
 
-pub async fn agree_rematch(player_id: &UserId) -> ServerResult<()> {
-    let match_id = MATCHES
-        .players
-        .get(player_id)
-        .map(|x| x.value().clone())
-        .ok_or_else(|| ServerError::NotFound)?;
+fn try_request_takeback(match_data: &mut MatchData, player_id: &UserId) -> ServerResult<()> {
+    let tak_player = match_data
+        .player_mapping
+        .iter()
+        .find(|&(_, id)| id == player_id)
+        .map(|(player, _)| player)
+        .ok_or(ServerError::NotFound)?;
+    if match_data.game.action_history.is_empty() {
+        return Err(ServerError::NotAllowed("No move to take back".to_string()));
+    }
+    if match_data.game.current_player == tak_player {
+        return Err(ServerError::NotAllowed(
+            "Can only take back your own move".to_string(),
+        ));
+    }
+    if match_data.takeback_request.is_some() {
+        return Err(ServerError::Conflict(
+            "Already requested takeback".to_string(),
+        ));
+    }
+    match_data.takeback_request = Some(player_id.clone());
+    Ok(())
+}
+
+/// Answers the pending takeback request of the opponent. Returns whether a ply was undone.
+fn try_answer_takeback(
+    match_data: &mut MatchData,
+    player_id: &UserId,
+    accept: bool,
+) -> ServerResult<bool> {
+    let Some(requester) = &match_data.takeback_request else {
+        return Err(ServerError::NotAllowed("No takeback requested".to_string()));
+    };
+    if requester == player_id {
+        return Err(ServerError::NotAllowed(
+            "Cannot answer your own takeback request".to_string(),
+        ));
+    }
+    match_data.takeback_request = None;
+    if !accept {
+        return Ok(false);
+    }
+    if !match_data.game.undo_last_action() {
+        return Err(ServerError::NotAllowed("No move to take back".to_string()));
+    }
+    match_data.version += 1;
+    Ok(true)
+}
+
+pub async fn request_takeback(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
+
+    MATCHES
+        .with_ongoing_game(match_id, |match_data| {
+            try_request_takeback(match_data, player_id)
+        })?
+        .unwrap_or(Err(ServerError::NotAllowed("Game has ended".to_string())))?;
+
+    ws_pubsub::publish_to_topic(
+        format!("{}/{}/{}", MATCHES_TOPIC, match_id, TAKEBACK_SUBTOPIC),
+        (),
+    )
+    .await;
+    persist_match(match_id).await;
+
+    log::info!(
+        "Player {} requested takeback for match: {}",
+        player_id,
+        match_id
+    );
+    Ok(())
+}
+
+pub async fn answer_takeback(
+    match_id: &MatchId,
+    player_id: &UserId,
+    accept: bool,
+) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
+
+    let undone_to = MATCHES
+        .with_ongoing_game(match_id, |match_data| -> ServerResult<_> {
+            let did_undo = try_answer_takeback(match_data, player_id, accept)?;
+            Ok(did_undo.then_some(match_data.game.ply_index))
+        })?
+        .unwrap_or(Err(ServerError::NotAllowed("Game has ended".to_string())))?;
+
+    ws_pubsub::publish_to_topic(
+        format!("{}/{}/{}", MATCHES_TOPIC, match_id, TAKEBACK_SUBTOPIC),
+        (),
+    )
+    .await;
+    if let Some(ply_index) = undone_to {
+        ws_pubsub::publish_to_topic(
+            format!("{}/{}", MATCHES_TOPIC, match_id),
+            ServerGameMessage::Takeback(ply_index),
+        )
+        .await;
+    }
+    persist_match(match_id).await;
+
+    log::info!(
+        "Player {} {} takeback for match: {}",
+        player_id,
+        if accept { "accepted" } else { "rejected" },
+        match_id
+    );
+    Ok(())
+}
+
+/// The matches of a player whose game is still being played.
+pub fn ongoing_match_ids(player_id: &UserId) -> Vec<MatchId> {
+    MATCHES
+        .match_ids(player_id)
+        .into_iter()
+        .filter(|match_id| {
+            MATCHES
+                .with_match_data(match_id, |match_data| {
+                    !match_data.has_ended && match_data.game.game_state == TakGameState::Ongoing
+                })
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+pub fn is_in_ongoing_match(player_id: &UserId) -> bool {
+    !ongoing_match_ids(player_id).is_empty()
+}
+
+/// Forfeits the ongoing game of a player who disconnected and didn't come back.
+pub async fn abandon_match(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
+
+    MATCHES
+        .with_ongoing_game(match_id, |match_data| {
+            let tak_player = match_data
+                .player_mapping
+                .iter()
+                .find(|&(_, id)| id == player_id)
+                .map(|(player, _)| player)
+                .ok_or(ServerError::NotFound)?;
+            match_data.game.abandon(tak_player);
+            match_data.version += 1;
+            Ok(())
+        })?
+        .unwrap_or(Err(ServerError::NotAllowed("Game has ended".to_string())))?;
+
+    MATCHES.check_game_over(match_id);
+    persist_match(match_id).await;
+
+    log::info!("Player {} abandoned match: {}", player_id, match_id);
+    Ok(())
+}
+
+/// Ends the ongoing game of a player as a loss by resignation.
+pub async fn resign_match(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
+
+    MATCHES
+        .with_ongoing_game(match_id, |match_data| {
+            let tak_player = match_data
+                .player_mapping
+                .iter()
+                .find(|&(_, id)| id == player_id)
+                .map(|(player, _)| player)
+                .ok_or(ServerError::NotFound)?;
+            if match_data.game.resign(tak_player) {
+                match_data.version += 1;
+                Ok(())
+            } else {
+                Err(ServerError::NotAllowed("Game has ended".to_string()))
+            }
+        })?
+        .unwrap_or(Err(ServerError::NotAllowed("Game has ended".to_string())))?;
+
+    MATCHES.check_game_over(match_id);
+    persist_match(match_id).await;
+
+    log::info!("Player {} resigned match: {}", player_id, match_id);
+    Ok(())
+}
+
+/// Ends the ongoing game of a player without a result. Canceled matches are removed once the
+/// game is over, which frees both players.
+pub async fn cancel_match(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
+
+    MATCHES
+        .with_ongoing_game(match_id, |match_data| {
+            match_data.game.game_state = TakGameState::Canceled;
+            match_data.version += 1;
+        })?
+        .ok_or(ServerError::NotAllowed("Game has ended".to_string()))?;
+
+    MATCHES.check_game_over(match_id);
+
+    log::info!("Player {} canceled match: {}", player_id, match_id);
+    Ok(())
+}
+
+pub async fn agree_rematch(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
 
     let should_rematch = MATCHES
-        .with_match_data(&match_id, |match_data| {
+        .with_match_data(match_id, |match_data| {
             if !match_data.has_ended {
                 return Err(ServerError::Conflict(
                     "Cannot agree to rematch while game is ongoing".to_string(),
@@ -309,21 +899,19 @@ pub async fn agree_rematch(player_id: &UserId) -> ServerResult<()> {
     );
 
     if should_rematch {
-        restart_match(&match_id).await?;
+        restart_match(match_id).await?;
         log::info!("Match rematch started for match: {}", match_id);
+    } else {
+        persist_match(match_id).await;
     }
     Ok(())
 }
 
-pub async fn retract_rematch(player_id: &UserId) -> ServerResult<()> {
-    let match_id = MATCHES
-        .players
-        .get(player_id)
-        .map(|x| x.value().clone())
-        .ok_or_else(|| ServerError::NotFound)?;
+pub async fn retract_rematch(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.check_player(match_id, player_id)?;
 
     MATCHES
-        .with_match_data(&match_id, |match_data| {
+        .with_match_data(match_id, |match_data| {
             if !match_data.has_ended {
                 return Err(ServerError::Conflict(
                     "Cannot retract rematch while game hasn't ended".to_string(),
@@ -339,6 +927,8 @@ pub async fn retract_rematch(player_id: &UserId) -> ServerResult<()> {
         })
         .unwrap_or(Err(ServerError::NotFound))?;
 
+    persist_match(match_id).await;
+
     ws_pubsub::publish_to_topic(
         format!("{}/{}/{}", MATCHES_TOPIC, match_id, REMATCH_SUBTOPIC),
         (),
@@ -348,24 +938,8 @@ pub async fn retract_rematch(player_id: &UserId) -> ServerResult<()> {
     Ok(())
 }
 
-pub async fn leave_match(player_id: &UserId) -> ServerResult<()> {
-    let match_id = MATCHES
-        .players
-        .get(player_id)
-        .map(|x| x.value().clone())
-        .ok_or_else(|| ServerError::NotFound)?;
-
-    MATCHES
-        .with_match_data(&match_id, |match_data| {
-            if !match_data.has_ended {
-                return Err(ServerError::Conflict(
-                    "Cannot leave match while game hasn't ended".to_string(),
-                ));
-            }
-            match_data.rematch_agree.clear();
-            Ok(())
-        })
-        .unwrap_or(Err(ServerError::NotFound))?;
+pub async fn leave_match(match_id: &MatchId, player_id: &UserId) -> ServerResult<()> {
+    MATCHES.leave_match(match_id, player_id)?;
 
     ws_pubsub::publish_to_topic(
         format!("{}/{}/{}", MATCHES_TOPIC, match_id, REMATCH_SUBTOPIC),
@@ -375,79 +949,139 @@ pub async fn leave_match(player_id: &UserId) -> ServerResult<()> {
 
     log::info!("Player {} left match: {}", player_id, match_id);
 
-    MATCHES.remove_match(&match_id)?;
+    analysis::FEATURED.unfeature(match_id);
+    delete_persisted_match(match_id).await;
     log::info!("Match removed: {match_id}");
     Ok(())
 }
 
+/// Applies a move sent by a player and returns the message announcing it. The move must be
+/// made in the current version of the match, so a move sent twice or based on an outdated
+/// position, even one a takeback returned to, is rejected instead of being played in a
+/// position the player didn't see.
+fn apply_player_move(
+    match_id: &MatchId,
+    match_data: &mut MatchData,
+    player_id: &UserId,
+    expected_version: u64,
+    action_str: &str,
+) -> Option<ServerGameMessage> {
+    let Some(tak_player) = match_data
+        .player_mapping
+        .iter()
+        .find(|&(_, id)| id == player_id)
+        .map(|(player, _)| player)
+    else {
+        log::warn!("Player {player_id} is not part of match {match_id}");
+        return None;
+    };
+
+    if match_data.game.game_state != TakGameState::Ongoing {
+        log::warn!("Game is not ongoing");
+        return None;
+    }
+    if match_data.game.check_timeout() {
+        log::warn!("Game has timed out");
+        return None;
+    }
+    if match_data.game.current_player != tak_player {
+        log::warn!("Not your turn");
+        return None;
+    }
+    if match_data.version != expected_version {
+        log::warn!(
+            "Move {action_str} was made in version {expected_version}, but the match is at {}",
+            match_data.version
+        );
+        return None;
+    }
+    let Some(action) = TakAction::from_ptn(action_str) else {
+        log::warn!("Invalid action: {action_str}");
+        return None;
+    };
+
+    let move_index = match_data.game.ply_index;
+    let res = match match_data.game.try_do_action(action) {
+        Ok(()) => {
+            match_data.takeback_request = None;
+            match_data.version += 1;
+            match_data
+                .game
+                .get_last_action()
+                .expect("Action history should not be empty")
+        }
+        Err(e) => {
+            log::warn!(
+                "Rejected action {action_str} in match {match_id}: {e:?}, TPS: {}",
+                match_data.game.to_tps().to_string()
+            );
+            return None;
+        }
+    }
+    .clone();
+
+    if AUDIT_MOVES.load(Ordering::Relaxed) && !audit_game(match_id, match_data) {
+        log::error!(
+            "Last action of match {match_id}: {}",
+            res.describe(tak_player)
+        );
+        return None;
+    }
+
+    let time_remaining = TakPlayer::ALL
+        .into_iter()
+        .map(|x| (x, match_data.game.get_time_remaining(x, true).unwrap()))
+        .collect::<Vec<_>>();
+
+    Some(ServerGameMessage::Move(
+        move_index,
+        time_remaining,
+        res.to_ptn(),
+        match_data.version,
+    ))
+}
+
 pub async fn handle_player_publish(player_id: &UserId, topic: String, message: ClientGameMessage) {
     let match_id = topic
         .strip_prefix(&format!("{}/", MATCHES_TOPIC))
         .expect("Invalid topic format")
         .to_string();
 
-    let ClientGameMessage::Move(action_str) = message;
+    let (expected_version, action_str) = match message {
+        ClientGameMessage::Move(expected_version, action_str) => (expected_version, action_str),
+        ClientGameMessage::Resign => {
+            if let Err(e) = resign_match(&match_id, player_id).await {
+                log::warn!("Failed to resign match: {match_id}, player: {player_id}: {e}");
+            }
+            return;
+        }
+        ClientGameMessage::Chat(text) => {
+            if let Err(e) = chat::send_chat(&match_id, player_id, &text).await {
+                log::warn!("Rejected chat message for match: {match_id}, player: {player_id}: {e}");
+            }
+            return;
+        }
+    };
 
     log::info!("Received action for match: {match_id}, player: {player_id}, action: {action_str}");
     MATCHES.check_game_over(&match_id);
 
     let payload = MATCHES
         .with_match_data(&match_id, |match_data| {
-            let tak_player = match_data
-                .player_mapping
-                .iter()
-                .find(|&(_, id)| id == player_id)
-                .map(|(player, _)| player)
-                .expect("Player not found in match data");
-
-            if match_data.game.game_state != TakGameState::Ongoing {
-                log::warn!("Game is not ongoing");
-                return None;
-            }
-            if match_data.game.check_timeout() {
-                log::warn!("Game has timed out");
-                return None;
-            }
-            if match_data.game.current_player != tak_player {
-                log::warn!("Not your turn");
-                return None;
-            }
-            let Some(action) = TakAction::from_ptn(&action_str) else {
-                log::warn!("Invalid action: {action_str}");
-                return None;
-            };
-
-            let move_index = match_data.game.ply_index;
-            let res = match match_data.game.try_do_action(action) {
-                Ok(()) => match_data
-                    .game
-                    .get_last_action()
-                    .expect("Action history should not be empty"),
-                Err(e) => {
-                    println!(
-                        "Error processing action: {e:?}, {}",
-                        match_data.game.to_tps().to_string()
-                    );
-                    return None;
-                }
-            }
-            .clone();
-
-            let time_remaining = TakPlayer::ALL
-                .into_iter()
-                .map(|x| (x, match_data.game.get_time_remaining(x, true).unwrap()))
-                .collect::<Vec<_>>();
-
-            Some(ServerGameMessage::Move(
-                move_index,
-                time_remaining,
-                res.to_ptn(),
-            ))
+            apply_player_move(
+                &match_id,
+                match_data,
+                player_id,
+                expected_version,
+                &action_str,
+            )
         })
         .flatten();
 
     if let Some(msg) = payload {
+        persist_match(&match_id).await;
         ws_pubsub::publish_to_topic(format!("{}/{}", MATCHES_TOPIC, match_id), msg).await;
+        tokio::spawn(analysis::publish_analysis(match_id.clone()));
     } else {
         log::warn!("Failed to process action for match: {match_id}");
     }
@@ -456,8 +1090,12 @@ pub async fn handle_player_publish(player_id: &UserId, topic: String, message: C
 }
 
 async fn check_match_timeout_task(match_id: MatchId) {
+    let mut last_clock_sync = Instant::now();
     loop {
         tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+        if MATCHES.check_first_move_deadline(&match_id, chrono::Utc::now()) == Some(true) {
+            log::info!("Match {match_id} canceled: no first move before the deadline");
+        }
         match MATCHES.check_game_over(&match_id) {
             Some(true) => break,
             Some(false) => {}
@@ -466,6 +1104,15 @@ async fn check_match_timeout_task(match_id: MatchId) {
                 break;
             }
         }
+        if last_clock_sync.elapsed() >= CLOCK_SYNC_INTERVAL {
+            last_clock_sync = Instant::now();
+            let msg = MATCHES
+                .with_match_data(&match_id, |match_data| clock_sync_message(&match_data.game))
+                .flatten();
+            if let Some(msg) = msg {
+                ws_pubsub::publish_to_topic(format!("{}/{}", MATCHES_TOPIC, match_id), msg).await;
+            }
+        }
     }
     log::info!("Match timeout check completed for match: {match_id}");
 }
@@ -508,20 +1155,434 @@ async fn check_match_finished_task(
         return;
     };
 
+    persist_match(&match_id).await;
+
     let msg = ServerGameMessage::GameOver(game_state.clone());
 
     ws_pubsub::publish_to_topic(format!("{}/{}", MATCHES_TOPIC, match_id), msg).await;
 
     let game = match_data.game;
     let player_mapping = match_data.player_mapping;
+    let rated = MATCHES
+        .matches
+        .get(&match_id)
+        .is_some_and(|instance| instance.rated);
 
     if game.game_state == TakGameState::Canceled {
         log::info!("Game was canceled, not saving game record");
+        if MATCHES.remove_match(&match_id).is_ok() {
+            analysis::FEATURED.unfeature(&match_id);
+            delete_persisted_match(&match_id).await;
+            ws_pubsub::publish_to_topic(MATCHES_TOPIC, MatchUpdate::Removed { match_id }).await;
+        }
         return;
     }
-    if let Err(e) = super::player::add_game(game, player_mapping).await {
+    if let Err(e) = super::player::add_game(game, player_mapping, rated).await {
         log::error!("Failed to add game: {:?}", e);
     } else {
         log::info!("Game added successfully for match: {match_id}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tak_core::{TakGameSettings, TakKomi, TakTimeMode};
+
+    use super::*;
+    use crate::server::SeekSettings;
+
+    fn test_instance() -> MatchInstance {
+        MatchInstance {
+            player_id: "white".to_string(),
+            opponent_id: "black".to_string(),
+            game_settings: TakGameSettings::new(5, None, TakKomi::none(), None),
+            rated: false,
+            creator_color: TakPlayer::White,
+            rematch_color: RematchColor::Keep,
+            seed: None,
+        }
+    }
+
+    fn match_with_moves(moves: &[&str]) -> MatchData {
+        let mut match_data = new_match_data(test_instance()).unwrap();
+        for mv in moves {
+            match_data
+                .game
+                .try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        match_data
+    }
+
+    #[test]
+    fn test_takeback_accepted() {
+        let mut match_data = match_with_moves(&["a1", "e5", "c3"]);
+        let white = "white".to_string();
+        let black = "black".to_string();
+
+        assert!(try_request_takeback(&mut match_data, &black).is_err());
+        try_request_takeback(&mut match_data, &white).unwrap();
+        assert!(try_request_takeback(&mut match_data, &white).is_err());
+        assert!(try_answer_takeback(&mut match_data, &white, true).is_err());
+
+        assert!(try_answer_takeback(&mut match_data, &black, true).unwrap());
+        assert_eq!(match_data.takeback_request, None);
+        assert_eq!(match_data.game.ply_index, 2);
+        assert_eq!(match_data.game.current_player, TakPlayer::White);
+        assert!(try_answer_takeback(&mut match_data, &black, true).is_err());
+    }
+
+    #[test]
+    fn test_stale_move_is_rejected() {
+        let mut instance = test_instance();
+        instance.game_settings.time_mode = Some(TakTimeMode::new(60, 0));
+        let mut match_data = new_match_data(instance).unwrap();
+        let match_id = "match".to_string();
+        let white = "white".to_string();
+        let black = "black".to_string();
+
+        assert!(apply_player_move(&match_id, &mut match_data, &white, 0, "a1").is_some());
+        // The same version again, as from a second client that hasn't seen white's move.
+        assert!(apply_player_move(&match_id, &mut match_data, &black, 0, "e5").is_none());
+        assert!(apply_player_move(&match_id, &mut match_data, &black, 2, "e5").is_none());
+        let stranger = "stranger".to_string();
+        assert!(apply_player_move(&match_id, &mut match_data, &stranger, 1, "e5").is_none());
+        assert_eq!(match_data.game.ply_index, 1);
+
+        assert!(matches!(
+            apply_player_move(&match_id, &mut match_data, &black, 1, "e5"),
+            Some(ServerGameMessage::Move(1, _, _, 2))
+        ));
+        assert_eq!(match_data.game.ply_index, 2);
+    }
+
+    #[test]
+    fn test_move_before_takeback_is_rejected() {
+        let mut match_data = new_match_data(test_instance()).unwrap();
+        let match_id = "match".to_string();
+        let white = "white".to_string();
+        let black = "black".to_string();
+        assert!(apply_player_move(&match_id, &mut match_data, &white, 0, "a1").is_some());
+        assert!(apply_player_move(&match_id, &mut match_data, &black, 1, "e5").is_some());
+
+        try_request_takeback(&mut match_data, &black).unwrap();
+        assert!(try_answer_takeback(&mut match_data, &white, true).unwrap());
+        assert_eq!((match_data.game.ply_index, match_data.version), (1, 3));
+
+        // Black's client sent this for ply 1 before seeing the takeback.
+        assert!(apply_player_move(&match_id, &mut match_data, &black, 2, "c3").is_none());
+        assert!(apply_player_move(&match_id, &mut match_data, &black, 3, "c3").is_some());
+        assert_eq!(match_data.version, 4);
+    }
+
+    #[test]
+    fn test_takeback_rejected() {
+        let mut match_data = match_with_moves(&["a1", "e5"]);
+        let white = "white".to_string();
+        let black = "black".to_string();
+
+        try_request_takeback(&mut match_data, &black).unwrap();
+        assert!(!try_answer_takeback(&mut match_data, &white, false).unwrap());
+        assert_eq!(match_data.takeback_request, None);
+        assert_eq!(match_data.game.ply_index, 2);
+    }
+
+    #[test]
+    fn test_takeback_without_moves() {
+        let mut match_data = match_with_moves(&[]);
+        let black = "black".to_string();
+
+        assert!(try_request_takeback(&mut match_data, &black).is_err());
+        assert_eq!(match_data.takeback_request, None);
+    }
+
+    #[test]
+    fn test_live_matches() {
+        let matches = Matches::new();
+        let match_id = "match".to_string();
+        assert!(matches.get_live_matches().is_empty());
+
+        matches.insert_match(
+            match_id.clone(),
+            test_instance(),
+            match_with_moves(&["a1", "e5", "c3"]),
+        );
+        let watcher = "watcher".to_string();
+        matches.add_spectator(&match_id, &watcher).unwrap();
+        let live = matches.get_live_matches();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].0, match_id);
+        assert_eq!(live[0].1.game.ply_index, 3);
+        assert_eq!(live[0].1.spectators, vec![watcher.clone()]);
+
+        let white = "white".to_string();
+        assert!(matches!(
+            matches.leave_match(&match_id, &white),
+            Err(ServerError::Conflict(_))
+        ));
+        matches.with_match_data(&match_id, |match_data| {
+            match_data.game.resign(TakPlayer::Black);
+            match_data.has_ended = true;
+        });
+        assert!(matches.get_live_matches().is_empty());
+
+        assert!(matches.leave_match(&match_id, &watcher).is_err());
+        matches.leave_match(&match_id, &white).unwrap();
+        assert!(matches.match_ids(&white).is_empty());
+        assert!(matches.match_ids(&"black".to_string()).is_empty());
+        assert!(matches!(
+            matches.leave_match(&match_id, &white),
+            Err(ServerError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_audit_cancels_corrupted_game() {
+        let match_id = "match".to_string();
+        let mut match_data = match_with_moves(&["a1", "e5", "c3"]);
+        assert!(audit_game(&match_id, &mut match_data));
+        assert_eq!(match_data.game.game_state, TakGameState::Ongoing);
+
+        match_data.game.ply_index += 1;
+        assert!(!audit_game(&match_id, &mut match_data));
+        assert_eq!(match_data.game.game_state, TakGameState::Canceled);
+    }
+
+    #[test]
+    fn test_clock_sync() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), Some(TakTimeMode::new(60, 0)));
+        let mut game = TakGame::new(settings).unwrap();
+        game.try_do_action(TakAction::from_ptn("a1").unwrap())
+            .unwrap();
+        let times = |game: &TakGame| match clock_sync_message(game) {
+            Some(ServerGameMessage::ClockSync(times)) => times,
+            other => panic!("Expected a clock sync, got {other:?}"),
+        };
+
+        let before = times(&game);
+        let clock = game.clock.as_mut().unwrap();
+        let last_update = clock.last_update_timestamp.as_mut().unwrap();
+        last_update.millis -= 3000;
+        let after = times(&game);
+        assert_eq!(after[0], before[0]);
+        assert_eq!(after[1].0, TakPlayer::Black);
+        assert!(after[1].1 <= before[1].1 - 3000);
+        assert!(after[1].1 >= game.get_time_remaining(TakPlayer::Black, true).unwrap());
+
+        game.resign(TakPlayer::Black);
+        assert!(clock_sync_message(&game).is_none());
+        assert!(clock_sync_message(&match_with_moves(&["a1"]).game).is_none());
+    }
+
+    #[test]
+    fn test_rematch_colors() {
+        let rematches = |rematch_color, seed| {
+            let mut instance = MatchInstance {
+                creator_color: TakPlayer::Black,
+                rematch_color,
+                seed,
+                ..test_instance()
+            };
+            (0..3)
+                .map(|_| {
+                    instance = rematch_instance(instance.clone());
+                    instance.creator_color
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            rematches(RematchColor::Alternate, None),
+            vec![TakPlayer::White, TakPlayer::Black, TakPlayer::White]
+        );
+        assert_eq!(
+            rematches(RematchColor::Keep, None),
+            vec![TakPlayer::Black; 3]
+        );
+        assert_eq!(
+            rematches(RematchColor::Random, Some(7)),
+            rematches(RematchColor::Random, Some(7))
+        );
+        let flips = (0..20)
+            .flat_map(|seed| rematches(RematchColor::Random, Some(seed)))
+            .collect::<Vec<_>>();
+        assert!(flips.contains(&TakPlayer::White) && flips.contains(&TakPlayer::Black));
+    }
+
+    #[test]
+    fn test_spectators() {
+        let matches = Matches::new();
+        let match_id = "match".to_string();
+        matches.insert_match(match_id.clone(), test_instance(), match_with_moves(&["a1"]));
+        let watcher = "watcher".to_string();
+        let other = "other".to_string();
+
+        assert_eq!(matches.add_spectator(&match_id, &watcher).unwrap(), 1);
+        assert_eq!(matches.add_spectator(&match_id, &watcher).unwrap(), 1);
+        assert_eq!(matches.add_spectator(&match_id, &other).unwrap(), 2);
+        assert!(matches!(
+            matches.add_spectator(&match_id, &"white".to_string()),
+            Err(ServerError::Conflict(_))
+        ));
+        assert!(matches!(
+            matches.add_spectator(&"missing".to_string(), &watcher),
+            Err(ServerError::NotFound)
+        ));
+        assert!(matches.match_ids(&watcher).is_empty());
+        assert!(matches.is_spectator(&match_id, &watcher));
+        assert!(!matches.is_spectator(&match_id, &"white".to_string()));
+
+        assert_eq!(matches.remove_spectator(&match_id, &watcher).unwrap(), 1);
+        assert!(!matches.is_spectator(&match_id, &watcher));
+        assert_eq!(matches.remove_spectator(&match_id, &watcher).unwrap(), 1);
+        assert_eq!(
+            matches.match_ids(&"white".to_string()),
+            vec![match_id.clone()]
+        );
+        assert_eq!(matches.match_ids(&"black".to_string()), vec![match_id]);
+        assert_eq!(matches.get_live_matches().len(), 1);
+    }
+
+    #[test]
+    fn test_disconnected_spectator_leaves_every_match() {
+        let matches = Matches::new();
+        let watcher = "watcher".to_string();
+        let other = "other".to_string();
+        for match_id in ["first", "second", "third"] {
+            matches.insert_match(match_id.to_string(), test_instance(), match_with_moves(&[]));
+        }
+        for match_id in ["first", "second"] {
+            matches
+                .add_spectator(&match_id.to_string(), &watcher)
+                .unwrap();
+        }
+        matches
+            .add_spectator(&"second".to_string(), &other)
+            .unwrap();
+
+        let mut left = matches.remove_spectator_everywhere(&watcher);
+        left.sort();
+        assert_eq!(
+            left,
+            vec![("first".to_string(), 0), ("second".to_string(), 1)]
+        );
+        assert!(!matches.is_spectator(&"second".to_string(), &watcher));
+        assert!(matches.is_spectator(&"second".to_string(), &other));
+        assert!(matches.remove_spectator_everywhere(&watcher).is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_match_limit() {
+        let matches = Matches::new();
+        let bot = "bot".to_string();
+        let human = "human".to_string();
+        matches.match_limits.insert(bot.clone(), 2);
+        let instance = |player: &str, opponent: &str| MatchInstance {
+            player_id: player.to_string(),
+            opponent_id: opponent.to_string(),
+            ..test_instance()
+        };
+
+        matches.insert_match(
+            "first".to_string(),
+            instance("bot", "human"),
+            match_with_moves(&[]),
+        );
+        assert!(matches.can_join_match(&bot));
+        assert!(!matches.can_join_match(&human));
+
+        matches.insert_match(
+            "second".to_string(),
+            instance("bot", "other"),
+            match_with_moves(&[]),
+        );
+        assert!(!matches.can_join_match(&bot));
+        assert_eq!(matches.match_ids(&bot), vec!["first", "second"]);
+        let second = "second".to_string();
+        assert!(matches.check_player(&second, &bot).is_ok());
+        assert!(matches.check_player(&second, &human).is_err());
+
+        matches.remove_match(&"first".to_string()).unwrap();
+        assert!(matches.can_join_match(&bot));
+        assert!(matches.can_join_match(&human));
+        assert!(!matches.players.contains_key(&human));
+        assert_eq!(matches.match_ids(&bot), vec!["second"]);
+    }
+
+    #[test]
+    fn test_first_move_deadline() {
+        let matches = Matches::new();
+        let idle = "idle".to_string();
+        let mut match_data = match_with_moves(&[]);
+        let deadline = chrono::DateTime::from_timestamp(1_000, 0).unwrap();
+        match_data.first_move_deadline = Some(deadline);
+        matches.insert_match(idle.clone(), test_instance(), match_data.clone());
+
+        let check = |match_id: &MatchId, seconds| {
+            let now = chrono::DateTime::from_timestamp(seconds, 0).unwrap();
+            matches.check_first_move_deadline(match_id, now)
+        };
+        assert_eq!(check(&idle, 990), Some(false));
+        assert_eq!(check(&idle, 1_000), Some(true));
+        assert!(matches.get_live_matches().is_empty());
+
+        let started = "started".to_string();
+        let mut match_data = match_with_moves(&["a1"]);
+        match_data.first_move_deadline = Some(deadline);
+        matches.insert_match(started.clone(), test_instance(), match_data);
+        assert_eq!(check(&started, 1_000), Some(false));
+        matches.with_match_data(&started, |match_data| {
+            assert!(match_data.game.undo_last_action());
+        });
+        assert_eq!(check(&started, 2_000), Some(false));
+    }
+
+    #[test]
+    fn test_first_move_deadline_survives_restore() {
+        let matches = Matches::new();
+        let match_id = "restored".to_string();
+        let match_data = new_match_data(test_instance()).unwrap();
+        let deadline = match_data.first_move_deadline;
+        assert!(deadline.is_some());
+        matches.insert_match(match_id.clone(), test_instance(), match_data);
+
+        let record = matches.to_record(&match_id).unwrap();
+        let restored = match_data_from_record(&record).unwrap();
+        assert_eq!(restored.first_move_deadline, deadline);
+    }
+
+    #[test]
+    fn test_match_starts_at_seek_position() {
+        let tps = "2,x3/x,1,x2/x4/1,x3 2 3";
+        let mut settings = SeekSettings {
+            game_settings: TakGameSettings::new(4, None, TakKomi::none(), None),
+            rated: false,
+            creator_color: Some(TakPlayer::White),
+            rating_range: None,
+            seed: None,
+            start_tps: Some(tps.to_string()),
+        };
+        seek::prepare_seek(&mut settings).unwrap();
+        let instance = MatchInstance {
+            game_settings: settings.game_settings,
+            ..test_instance()
+        };
+
+        let matches = Matches::new();
+        let match_id = "from_position".to_string();
+        let match_data = new_match_data(instance.clone()).unwrap();
+        matches.insert_match(match_id.clone(), instance, match_data);
+        let game = matches
+            .with_match_data(&match_id, |match_data| match_data.game.clone())
+            .unwrap();
+        assert_eq!(game.to_tps().to_string(), tps);
+        assert_eq!(game.current_player, TakPlayer::Black);
+        assert_eq!(game.hands[0].stones, 13);
+        assert_eq!(game.hands[1].stones, 14);
+
+        let record = matches.to_record(&match_id).unwrap();
+        let restored = match_data_from_record(&record).unwrap();
+        assert_eq!(restored.game.to_tps().to_string(), tps);
+    }
+}
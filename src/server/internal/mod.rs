@@ -1,8 +1,12 @@
+pub mod analysis;
 pub mod auth;
 pub mod cache;
+pub mod chat;
 pub mod db;
 pub mod dto;
 pub mod matches;
 pub mod player;
+pub mod presence;
 pub mod pub_sub;
+pub mod puzzle;
 pub mod seek;
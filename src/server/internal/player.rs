@@ -1,15 +1,19 @@
-use tak_core::{TakGame, TakGameState, TakPlayer};
+use tak_core::{TakGame, TakGameState, TakPlayer, TakPtn};
 use uuid::Uuid;
 
 use crate::server::{
-    GameId, UserId,
+    GameId, GameSummary, H2HStats, HistoryCursor, PlayerInformation, UserId,
     error::{ServerError, ServerResult},
+    game_content_id,
     internal::{
         db::DB,
         dto::{GameRecord, PlayerRecord, Record},
     },
 };
 
+/// Shown instead of the username of a player who deleted their account.
+pub const DELETED_USERNAME: &str = "[deleted]";
+
 pub fn create_player(user_id: &str) -> PlayerRecord {
     PlayerRecord {
         user_id: user_id.to_string(),
@@ -27,6 +31,7 @@ pub async fn get_or_insert_player(user_id: &UserId) -> ServerResult<PlayerRecord
 pub async fn add_game(
     game: TakGame,
     player_mapping: fixed_map::Map<TakPlayer, String>,
+    rated: bool,
 ) -> ServerResult<()> {
     let game_id = Uuid::new_v4().to_string();
     let ptn = game.to_ptn();
@@ -61,14 +66,40 @@ pub async fn add_game(
     );
 
     super::dto::try_create(&game_id, game_record).await?;
-    add_game_result(&white_player_id, &black_player_id, &game).await?;
+    add_game_result(&white_player_id, &black_player_id, &game, rated).await?;
     Ok(())
 }
 
+/// The id of an imported game is derived from the uploader, who is stored as both players, so
+/// it matches [`crate::server::GameInformation::content_id`] and every uploader keeps their own
+/// copy.
+fn import_record(ptn: &TakPtn, uploader: PlayerInformation) -> GameRecord {
+    let ptn = ptn.to_str();
+    GameRecord {
+        game_id: game_content_id(&ptn, &uploader.user_id, &uploader.user_id),
+        white_player: uploader.clone(),
+        black_player: uploader,
+        ptn,
+        timestamp: chrono::Utc::now().into(),
+    }
+}
+
+/// Stores a game played elsewhere, e.g. on PlayTak. Imported games are filed under the
+/// uploading player and never affect ratings. They are keyed by their content, so importing
+/// the same game again updates the stored copy instead of adding another.
+pub async fn import_game(ptn: &TakPtn, uploader_id: &UserId) -> ServerResult<GameId> {
+    let uploader = super::cache::get_or_retrieve_player_info(uploader_id).await?;
+    let game_record = import_record(ptn, uploader);
+    let game_id = game_record.game_id.clone();
+    super::dto::try_upsert(&game_id, game_record).await?;
+    Ok(game_id)
+}
+
 pub async fn add_game_result(
     white_player_id: &UserId,
     black_player_id: &UserId,
     game: &TakGame,
+    rated: bool,
 ) -> ServerResult<()> {
     let mut player1 = get_or_insert_player(white_player_id).await?;
     let mut player2 = get_or_insert_player(black_player_id).await?;
@@ -101,12 +132,11 @@ pub async fn add_game_result(
         }
     };
 
-    let expected_result_player1 =
-        1f64 / (1.0 + 10f64.powf((player2.rating - player1.rating) / 400.0));
-    let k_factor = 32.0; // K-factor for rating adjustment
-    let gain = k_factor * (s - expected_result_player1);
-    player1.rating += gain;
-    player2.rating -= gain;
+    if rated {
+        let gain = elo_gain(player1.rating, player2.rating, s);
+        player1.rating += gain;
+        player2.rating -= gain;
+    }
 
     println!(
         "Updating player {}: wins={}, losses={}, draws={}, rating={}",
@@ -122,34 +152,460 @@ pub async fn add_game_result(
     Ok(())
 }
 
+const ELO_K_FACTOR: f64 = 32.0;
+
+/// Rating change of the first player given their score (1 for a win, 0.5 for a draw, 0 for a
+/// loss). The second player's rating changes by the negated amount.
+fn elo_gain(rating: f64, opponent_rating: f64, score: f64) -> f64 {
+    let expected_score = 1.0 / (1.0 + 10f64.powf((opponent_rating - rating) / 400.0));
+    ELO_K_FACTOR * (score - expected_score)
+}
+
 pub async fn get_game(game_id: &GameId) -> ServerResult<GameRecord> {
     let game = super::dto::try_get(game_id).await?;
     Ok(game)
 }
 
+/// Replays a stored game from its PTN.
+pub fn replay_game(game: &GameRecord) -> ServerResult<TakGame> {
+    let ptn = TakPtn::try_from_str(&game.ptn).ok_or_else(|| {
+        ServerError::InternalServerError(format!("Invalid stored PTN: {}", game.ptn))
+    })?;
+    TakGame::try_from_ptn(ptn)
+        .map_err(|e| ServerError::InternalServerError(format!("Failed to replay stored PTN: {e}")))
+}
+
+/// Replays a stored game to find its final position and result.
+pub fn summarize_game(game: &GameRecord) -> ServerResult<GameSummary> {
+    Ok(summary_of_replay(game, &replay_game(game)?))
+}
+
+fn summary_of_replay(game: &GameRecord, replayed: &TakGame) -> GameSummary {
+    GameSummary {
+        final_tps: replayed.to_tps().to_string(),
+        result: replayed.game_state.clone(),
+        size: replayed.board.size,
+        player_names: [
+            game.white_player.username.clone(),
+            game.black_player.username.clone(),
+        ],
+    }
+}
+
+pub async fn get_game_summary(game_id: &GameId) -> ServerResult<GameSummary> {
+    let game = get_game(game_id).await?;
+    let replayed = super::cache::get_or_replay_game(&game).await?;
+    Ok(summary_of_replay(&game, &replayed))
+}
+
+/// Returns the games of a player from newest to oldest, starting after `before`. Games are
+/// ordered by timestamp and then id, so a cursor stays valid when new games are added.
 pub async fn get_games_of_player(
     user_id: &UserId,
-    pagination: Option<(usize, usize)>,
+    before: Option<HistoryCursor>,
+    limit: Option<usize>,
 ) -> ServerResult<Vec<GameRecord>> {
-    let query = match pagination {
-        Some(_) => format!(
-            "SELECT * FROM type::table($table) WHERE black_player.user_id = type::string($user_id) OR white_player.user_id = type::string($user_id) ORDER BY timestamp DESC START $offset LIMIT $limit"
-        ),
-        None => format!(
-            "SELECT * FROM type::table($table) WHERE black_player.user_id = type::string($user_id) OR white_player.user_id = type::string($user_id) ORDER BY timestamp DESC"
-        ),
-    };
+    let mut query = "SELECT * FROM type::table($table) WHERE (black_player.user_id = type::string($user_id) OR white_player.user_id = type::string($user_id))".to_string();
+    if before.is_some() {
+        query += " AND (timestamp < $before_timestamp OR (timestamp = $before_timestamp AND game_id < $before_id))";
+    }
+    query += " ORDER BY timestamp DESC, game_id DESC";
+    if limit.is_some() {
+        query += " LIMIT $limit";
+    }
     let mut q = DB
         .query(&query)
         .bind(("table", GameRecord::table_name()))
         .bind(("user_id", user_id.clone()));
-    if let Some((page, page_size)) = pagination {
-        let offset = page * page_size;
+    if let Some((timestamp, game_id)) = before {
         q = q
-            .bind(("offset", offset as i64))
-            .bind(("limit", page_size as i64));
+            .bind((
+                "before_timestamp",
+                surrealdb::sql::Datetime::from(timestamp),
+            ))
+            .bind(("before_id", game_id));
+    }
+    if let Some(limit) = limit {
+        q = q.bind(("limit", limit as i64));
     }
     let mut result = q.await?;
     let games: Vec<GameRecord> = result.take(0)?;
     Ok(games)
 }
+
+/// Fetches up to `limit` games after `before` plus the cursor of the following page.
+pub async fn get_history_page(
+    user_id: &UserId,
+    before: Option<HistoryCursor>,
+    limit: usize,
+) -> ServerResult<(Vec<GameRecord>, Option<HistoryCursor>)> {
+    let games = get_games_of_player(user_id, before, Some(limit + 1)).await?;
+    Ok(split_page(games, limit))
+}
+
+/// Cuts a page of `limit` games from the front of `games`, which holds one extra game if there
+/// is a next page.
+fn split_page(
+    mut games: Vec<GameRecord>,
+    limit: usize,
+) -> (Vec<GameRecord>, Option<HistoryCursor>) {
+    if games.len() <= limit {
+        return (games, None);
+    }
+    games.truncate(limit);
+    let next_cursor = games
+        .last()
+        .map(|game| (game.timestamp.clone().into(), game.game_id.clone()));
+    (games, next_cursor)
+}
+
+/// Returns all stored games in which the two players faced each other.
+pub async fn get_games_between(
+    user_id: &UserId,
+    opponent_id: &UserId,
+) -> ServerResult<Vec<GameRecord>> {
+    let mut result = DB
+        .query("SELECT * FROM type::table($table) WHERE (white_player.user_id = type::string($user_id) AND black_player.user_id = type::string($opponent_id)) OR (white_player.user_id = type::string($opponent_id) AND black_player.user_id = type::string($user_id))")
+        .bind(("table", GameRecord::table_name()))
+        .bind(("user_id", user_id.clone()))
+        .bind(("opponent_id", opponent_id.clone()))
+        .await?;
+    let games: Vec<GameRecord> = result.take(0)?;
+    Ok(games)
+}
+
+/// Tallies the results of `user_id` in the given games. Games without a result are skipped.
+pub fn head_to_head(games: &[GameRecord], user_id: &UserId) -> H2HStats {
+    let mut stats = H2HStats::default();
+    for game in games {
+        let color = if game.white_player.user_id == *user_id {
+            TakPlayer::White
+        } else if game.black_player.user_id == *user_id {
+            TakPlayer::Black
+        } else {
+            continue;
+        };
+        let Some(ptn) = TakPtn::try_from_str(&game.ptn) else {
+            continue;
+        };
+        let record = match color {
+            TakPlayer::White => &mut stats.as_white,
+            TakPlayer::Black => &mut stats.as_black,
+        };
+        match ptn.game_state {
+            TakGameState::Win(winner, _) if winner == color => record.wins += 1,
+            TakGameState::Win(_, _) => record.losses += 1,
+            TakGameState::Draw(_) => record.draws += 1,
+            TakGameState::Ongoing | TakGameState::Canceled => {}
+        }
+    }
+    stats
+}
+
+pub async fn get_head_to_head(user_id: &UserId, opponent_id: &UserId) -> ServerResult<H2HStats> {
+    let games = get_games_between(user_id, opponent_id).await?;
+    Ok(head_to_head(&games, user_id))
+}
+
+/// Hides the name of a deleted player in one of their games. The opponent's side is kept, so
+/// the game still shows up in their history.
+pub fn anonymize_game(game: &mut GameRecord, user_id: &UserId) {
+    for player in [&mut game.white_player, &mut game.black_player] {
+        if player.user_id == *user_id {
+            player.username = DELETED_USERNAME.to_string();
+        }
+    }
+}
+
+/// Anonymizes all stored games of a player and deletes their stats.
+pub async fn delete_player(user_id: &UserId) -> ServerResult<()> {
+    for mut game in get_games_of_player(user_id, None, None).await? {
+        anonymize_game(&mut game, user_id);
+        let game_id = game.game_id.clone();
+        super::dto::try_update(&game_id, game).await?;
+    }
+    super::dto::try_delete::<PlayerRecord>(user_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::H2HRecord;
+
+    #[test]
+    fn test_elo_gain() {
+        assert_eq!(elo_gain(1200.0, 1200.0, 1.0), ELO_K_FACTOR / 2.0);
+        assert_eq!(elo_gain(1200.0, 1200.0, 0.5), 0.0);
+
+        let upset = elo_gain(1000.0, 1600.0, 1.0);
+        let expected = elo_gain(1600.0, 1000.0, 1.0);
+        assert!(upset > ELO_K_FACTOR / 2.0);
+        assert!(expected < ELO_K_FACTOR / 2.0);
+        assert!((upset + expected - ELO_K_FACTOR).abs() < 1e-9);
+
+        // A draw moves the ratings toward each other.
+        assert!(elo_gain(1000.0, 1600.0, 0.5) > 0.0);
+        assert!(elo_gain(1600.0, 1000.0, 0.5) < 0.0);
+    }
+
+    #[test]
+    fn test_anonymize_game() {
+        let player = |user_id: &str| crate::server::PlayerInformation {
+            user_id: user_id.to_string(),
+            username: format!("{user_id}_name"),
+            rating: 1200.0,
+        };
+        let ptn = "[Size \"5\"]\n[Komi \"0\"]\n\n1. a1 e5\n2. c3";
+        let mut game = GameRecord {
+            game_id: "game".to_string(),
+            white_player: player("gone"),
+            black_player: player("opponent"),
+            ptn: ptn.to_string(),
+            timestamp: chrono::Utc::now().into(),
+        };
+
+        anonymize_game(&mut game, &"gone".to_string());
+        assert_eq!(game.white_player.username, DELETED_USERNAME);
+        assert_eq!(game.white_player.user_id, "gone");
+        assert_eq!(game.black_player.username, "opponent_name");
+        assert_eq!(game.ptn, ptn);
+        let ptn = TakPtn::try_from_str(&game.ptn).unwrap();
+        assert_eq!(TakGame::try_from_ptn(ptn).unwrap().ply_index, 3);
+    }
+
+    #[test]
+    fn test_summary_matches_replay() {
+        let player = |user_id: &str| PlayerInformation {
+            user_id: user_id.to_string(),
+            username: format!("{user_id}_name"),
+            rating: 1200.0,
+        };
+        let moves = [
+            "a1", "e5", "c3", "d3", "c4", "d4", "c2", "d2", "c1", "d1", "c5",
+        ];
+        let ptn = "[Size \"5\"]\n[Komi \"0\"]\n\n1. a1 e5\n2. c3 d3\n3. c4 d4\n4. c2 d2\n5. c1 d1\n6. c5 R-0";
+        let game = GameRecord {
+            game_id: "game".to_string(),
+            white_player: player("white"),
+            black_player: player("black"),
+            ptn: ptn.to_string(),
+            timestamp: chrono::Utc::now().into(),
+        };
+
+        let summary = summarize_game(&game).unwrap();
+        let settings = tak_core::TakGameSettings::new(5, None, tak_core::TakKomi::none(), None);
+        let mut replayed = TakGame::new(settings).unwrap();
+        for mv in moves {
+            replayed
+                .try_do_action(tak_core::TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        assert_eq!(summary.final_tps, replayed.to_tps().to_string());
+        assert_eq!(summary.result, replayed.game_state);
+        assert_eq!(
+            summary.result,
+            TakGameState::Win(TakPlayer::White, tak_core::TakWinReason::Road)
+        );
+        assert_eq!(summary.size, 5);
+        assert_eq!(summary.player_names, ["white_name", "black_name"]);
+    }
+
+    #[test]
+    fn test_reimport_is_idempotent() {
+        let uploader = |user_id: &str| PlayerInformation {
+            user_id: user_id.to_string(),
+            username: user_id.to_string(),
+            rating: 1200.0,
+        };
+        let ptn = "[Size \"5\"]\n[Date \"2024.05.01\"]\n\n1. a1 e5\n2. c3 d3";
+        let reformatted = "[Size \"5\"]\n[Date \"2024.05.01\"]\n1. a1   e5\n\n2. c3 d3\n";
+        let ptn = TakPtn::try_from_str(ptn).unwrap();
+        let reformatted = TakPtn::try_from_str(reformatted).unwrap();
+
+        let mut stored = std::collections::HashMap::new();
+        for ptn in [&ptn, &reformatted, &ptn] {
+            let record = import_record(ptn, uploader("me"));
+            stored.insert(record.game_id.clone(), record);
+        }
+        assert_eq!(stored.len(), 1);
+
+        let record = stored.into_values().next().unwrap();
+        let info = crate::server::GameInformation {
+            game_id: record.game_id.clone(),
+            white_player: record.white_player,
+            black_player: record.black_player,
+            ptn: record.ptn,
+            timestamp: chrono::Utc::now(),
+        };
+        assert_eq!(info.content_id(), record.game_id);
+        assert_ne!(
+            import_record(&ptn, uploader("other")).game_id,
+            record.game_id
+        );
+        let other_date = TakPtn::try_from_str(&info.ptn.replace("2024.05.01", "2024.05.02"));
+        assert_ne!(
+            import_record(&other_date.unwrap(), uploader("me")).game_id,
+            record.game_id
+        );
+    }
+
+    #[test]
+    fn test_import_id_is_per_uploader() {
+        let uploader = |user_id: &str| PlayerInformation {
+            user_id: user_id.to_string(),
+            username: user_id.to_string(),
+            rating: 1200.0,
+        };
+        let ptn = "[Player1 \"alice\"]\n[Player2 \"bob\"]\n[Size \"5\"]\n\n1. a1 e5";
+        let ptn = TakPtn::try_from_str(ptn).unwrap();
+
+        let by_white = import_record(&ptn, uploader("alice"));
+        let by_black = import_record(&ptn, uploader("bob"));
+        assert_ne!(by_white.game_id, by_black.game_id);
+        for record in [by_white, by_black] {
+            let info = crate::server::GameInformation {
+                game_id: record.game_id.clone(),
+                white_player: record.white_player,
+                black_player: record.black_player,
+                ptn: record.ptn,
+                timestamp: chrono::Utc::now(),
+            };
+            assert_eq!(info.content_id(), record.game_id);
+        }
+    }
+
+    fn record_at(game_id: &str, seconds: i64) -> GameRecord {
+        let player = crate::server::PlayerInformation {
+            user_id: "me".to_string(),
+            username: "me".to_string(),
+            rating: 1200.0,
+        };
+        GameRecord {
+            game_id: game_id.to_string(),
+            white_player: player.clone(),
+            black_player: player,
+            ptn: String::new(),
+            timestamp: chrono::DateTime::from_timestamp(seconds, 0).unwrap().into(),
+        }
+    }
+
+    /// Orders and filters like the history query does.
+    fn query_history(
+        games: &[GameRecord],
+        before: &Option<HistoryCursor>,
+        limit: usize,
+    ) -> Vec<GameRecord> {
+        let key = |game: &GameRecord| -> HistoryCursor {
+            (game.timestamp.clone().into(), game.game_id.clone())
+        };
+        let mut games = games
+            .iter()
+            .filter(|game| before.as_ref().is_none_or(|cursor| key(game) < *cursor))
+            .cloned()
+            .collect::<Vec<_>>();
+        games.sort_by_key(|game| std::cmp::Reverse(key(game)));
+        games.truncate(limit);
+        games
+    }
+
+    #[test]
+    fn test_history_cursor_is_stable() {
+        let mut games = vec![
+            record_at("a", 100),
+            record_at("b", 200),
+            record_at("c", 200),
+            record_at("d", 300),
+            record_at("e", 400),
+        ];
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (page, next_cursor) = split_page(query_history(&games, &cursor, 3), 2);
+            seen.extend(page.into_iter().map(|game| game.game_id));
+            if seen.len() == 2 {
+                games.push(record_at("new", 500));
+                games.push(record_at("f", 400));
+            }
+            cursor = next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(seen, vec!["e", "d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_split_page() {
+        let games = vec![
+            record_at("e", 400),
+            record_at("d", 300),
+            record_at("c", 300),
+        ];
+        let ids = |page: &[GameRecord]| {
+            page.iter()
+                .map(|game| game.game_id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let (page, cursor) = split_page(games.clone(), 2);
+        assert_eq!(ids(&page), vec!["e", "d"]);
+        let timestamp = chrono::DateTime::from_timestamp(300, 0).unwrap();
+        assert_eq!(cursor, Some((timestamp, "d".to_string())));
+
+        let (page, cursor) = split_page(games.clone(), 3);
+        assert_eq!(ids(&page), ids(&games));
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn test_head_to_head() {
+        let game = |white: &str, black: &str, moves: &str| {
+            let player = |user_id: &str| crate::server::PlayerInformation {
+                user_id: user_id.to_string(),
+                username: user_id.to_string(),
+                rating: 1200.0,
+            };
+            GameRecord {
+                game_id: format!("{white}-{black}-{moves}"),
+                white_player: player(white),
+                black_player: player(black),
+                ptn: format!("[Size \"5\"]\n[Komi \"0\"]\n\n1. a1 e5\n2. c3 {moves}"),
+                timestamp: chrono::Utc::now().into(),
+            }
+        };
+        let games = vec![
+            game("me", "rival", "1-0"),
+            game("rival", "me", "0-F"),
+            game("rival", "me", "R-0"),
+            game("me", "rival", "1/2-1/2"),
+            game("me", "rival", ""),
+        ];
+
+        let stats = head_to_head(&games, &"me".to_string());
+        assert_eq!(
+            stats.as_white,
+            H2HRecord {
+                wins: 1,
+                losses: 0,
+                draws: 1
+            }
+        );
+        assert_eq!(
+            stats.as_black,
+            H2HRecord {
+                wins: 1,
+                losses: 1,
+                draws: 0
+            }
+        );
+        assert_eq!(
+            stats.total(),
+            H2HRecord {
+                wins: 2,
+                losses: 1,
+                draws: 1
+            }
+        );
+        assert_eq!(head_to_head(&[], &"me".to_string()), H2HStats::default());
+    }
+}
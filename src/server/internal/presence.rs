@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ws_pubsub::PresenceEvent;
+
+use crate::server::{
+    UserId,
+    internal::{matches, seek},
+};
+
+/// Tracks players whose last connection closed, so that a short network interruption doesn't
+/// forfeit their game right away.
+pub struct DisconnectTimers {
+    grace: Duration,
+    disconnected: Mutex<HashMap<UserId, Instant>>,
+}
+
+impl DisconnectTimers {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            disconnected: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    /// Starts the countdown for a player, restarting it if one was already running.
+    pub fn disconnect(&self, user_id: &UserId, now: Instant) {
+        self.disconnected
+            .lock()
+            .unwrap()
+            .insert(user_id.clone(), now);
+    }
+
+    /// Cancels the countdown of a player. Returns whether one was running.
+    pub fn reconnect(&self, user_id: &UserId) -> bool {
+        self.disconnected.lock().unwrap().remove(user_id).is_some()
+    }
+
+    /// Ends the countdown if the player has been gone for the whole grace period.
+    pub fn take_expired(&self, user_id: &UserId, now: Instant) -> bool {
+        let mut disconnected = self.disconnected.lock().unwrap();
+        let expired = disconnected
+            .get(user_id)
+            .is_some_and(|since| now.duration_since(*since) >= self.grace);
+        if expired {
+            disconnected.remove(user_id);
+        }
+        expired
+    }
+}
+
+pub fn setup_presence_handler(grace: Duration) {
+    let timers = Arc::new(DisconnectTimers::new(grace));
+    let mut rx = ws_pubsub::subscribe_to_presence();
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                PresenceEvent::Connected(user_id) => {
+                    if timers.reconnect(&user_id) {
+                        log::info!("Player {user_id} reconnected within the grace period");
+                    }
+                }
+                PresenceEvent::Disconnected(user_id) => {
+                    matches::leave_all_as_spectator(&user_id).await;
+                    seek::cancel_seek_on_disconnect(&user_id).await;
+                    if !matches::is_in_ongoing_match(&user_id) {
+                        continue;
+                    }
+                    log::info!("Player {user_id} disconnected during a match");
+                    timers.disconnect(&user_id, Instant::now());
+                    let timers = timers.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(timers.grace()).await;
+                        if !timers.take_expired(&user_id, Instant::now()) {
+                            return;
+                        }
+                        for match_id in matches::ongoing_match_ids(&user_id) {
+                            if let Err(e) = matches::abandon_match(&match_id, &user_id).await {
+                                log::warn!("Failed to abandon match {match_id} of {user_id}: {e}");
+                            }
+                        }
+                    });
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_within_grace_cancels() {
+        let timers = DisconnectTimers::new(Duration::from_secs(30));
+        let user = "a".to_string();
+        let start = Instant::now();
+
+        timers.disconnect(&user, start);
+        assert!(!timers.take_expired(&user, start + Duration::from_secs(10)));
+        assert!(timers.reconnect(&user));
+        assert!(!timers.take_expired(&user, start + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_reconnect_after_grace_forfeits() {
+        let timers = DisconnectTimers::new(Duration::from_secs(30));
+        let user = "a".to_string();
+        let start = Instant::now();
+
+        timers.disconnect(&user, start);
+        assert!(timers.take_expired(&user, start + Duration::from_secs(30)));
+        assert!(!timers.reconnect(&user));
+    }
+
+    #[test]
+    fn test_disconnecting_again_restarts_countdown() {
+        let timers = DisconnectTimers::new(Duration::from_secs(30));
+        let user = "a".to_string();
+        let start = Instant::now();
+
+        timers.disconnect(&user, start);
+        timers.reconnect(&user);
+        timers.disconnect(&user, start + Duration::from_secs(20));
+        assert!(!timers.take_expired(&user, start + Duration::from_secs(30)));
+        assert!(timers.take_expired(&user, start + Duration::from_secs(50)));
+    }
+}
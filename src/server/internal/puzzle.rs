@@ -0,0 +1,313 @@
+use std::collections::HashSet;
+
+use tak_core::{
+    TakAction, TakBoard, TakGame, TakGameSettings, TakGameState, TakKomi, TakPlayer, TakPtn,
+    TakTps, TakWinReason,
+};
+
+use crate::server::{
+    Puzzle, PuzzleId, PuzzleVerdict,
+    error::{ServerError, ServerResult},
+    internal::{
+        db::DB,
+        dto::{GameRecord, PuzzleRecord, PuzzleScanRecord, Record},
+    },
+};
+
+/// Deepest tinue searched for when scanning stored games. Deeper searches get too slow to run
+/// over the whole game history on startup.
+pub const PUZZLE_MAX_DEPTH: usize = 5;
+
+/// Key of the record that remembers the last game scanned for puzzles.
+const PUZZLE_SCAN_KEY: &str = "games";
+
+/// Replays a game and collects every position where the player to move could have forced a
+/// road within `max_depth` plies. Positions with an immediate road win are too obvious to be
+/// puzzles, and positions that merely continue a tinue found earlier in the game are skipped.
+pub fn generate_puzzles_from_game(ptn: &str, max_depth: usize) -> Vec<Puzzle> {
+    let Some(ptn) = TakPtn::try_from_str(ptn) else {
+        return Vec::new();
+    };
+    let Some(mut settings) = ptn.get_settings() else {
+        return Vec::new();
+    };
+    let Ok(game) = TakGame::try_from_ptn(ptn) else {
+        return Vec::new();
+    };
+    settings.time_mode = None;
+    let Some(mut position) = TakGame::new(settings.clone()) else {
+        return Vec::new();
+    };
+
+    let mut puzzles = Vec::new();
+    let mut seen = HashSet::new();
+    let mut had_tinue = [false; 2];
+    let mut records = game.action_history.iter();
+    loop {
+        let player = position.current_player.index();
+        let solution = if position.is_tak() {
+            None
+        } else {
+            position.tinue_line(max_depth)
+        };
+        if let Some(solution) = solution {
+            if !had_tinue[player]
+                && seen.insert(canonical_key(&position.board, position.current_player))
+            {
+                puzzles.push(Puzzle {
+                    tps: position.to_tps().to_string(),
+                    solution: solution.iter().map(|record| record.to_ptn()).collect(),
+                    settings: TakGameSettings {
+                        start_position: position.to_tps(),
+                        ..settings.clone()
+                    },
+                });
+            }
+            had_tinue[player] = true;
+        } else {
+            had_tinue[player] = false;
+        }
+
+        let Some(record) = records.next() else {
+            break;
+        };
+        if position.try_do_action(record.to_action()).is_err() {
+            break;
+        }
+    }
+    puzzles
+}
+
+/// Identifies a position up to rotation and reflection of the board.
+fn canonical_key(board: &TakBoard, player: TakPlayer) -> String {
+    format!(
+        "{} {}",
+        board.canonical().to_partial_tps(),
+        player.index() + 1
+    )
+}
+
+/// Returns the stored games that come after `after`, oldest first. Games are ordered by
+/// timestamp and then id, like the history, so games added later are never skipped.
+async fn get_games_after(after: Option<&PuzzleScanRecord>) -> ServerResult<Vec<GameRecord>> {
+    let mut query = "SELECT * FROM type::table($table)".to_string();
+    if after.is_some() {
+        query += " WHERE timestamp > $after_timestamp OR (timestamp = $after_timestamp AND game_id > $after_id)";
+    }
+    query += " ORDER BY timestamp ASC, game_id ASC";
+    let mut q = DB.query(&query).bind(("table", GameRecord::table_name()));
+    if let Some(scan) = after {
+        q = q
+            .bind(("after_timestamp", scan.timestamp.clone()))
+            .bind(("after_id", scan.game_id.clone()));
+    }
+    let mut result = q.await?;
+    let games: Vec<GameRecord> = result.take(0)?;
+    Ok(games)
+}
+
+/// Scans the stored games added since the last scan for puzzles and stores the ones not seen
+/// before. Returns the number of new puzzles.
+pub async fn generate_puzzles(max_depth: usize) -> ServerResult<usize> {
+    let scan_key = PUZZLE_SCAN_KEY.to_string();
+    let last_scan = match super::dto::try_get::<PuzzleScanRecord>(&scan_key).await {
+        Ok(scan) => Some(scan),
+        Err(ServerError::NotFound) => None,
+        Err(e) => return Err(e),
+    };
+    let games = get_games_after(last_scan.as_ref()).await?;
+    let mut created = 0;
+    for game in games {
+        let scan = PuzzleScanRecord {
+            timestamp: game.timestamp.clone(),
+            game_id: game.game_id.clone(),
+        };
+        let puzzles =
+            tokio::task::spawn_blocking(move || generate_puzzles_from_game(&game.ptn, max_depth))
+                .await
+                .map_err(|e| ServerError::InternalServerError(e.to_string()))?;
+        for puzzle in puzzles {
+            let Some(tps) = TakTps::try_from_str(&puzzle.tps) else {
+                continue;
+            };
+            let Some(board) = TakBoard::try_from_partial_tps(&tps.position) else {
+                continue;
+            };
+            let puzzle_id = canonical_key(&board, tps.player);
+            if super::dto::try_get::<PuzzleRecord>(&puzzle_id)
+                .await
+                .is_ok()
+            {
+                continue;
+            }
+            super::dto::try_create(
+                &puzzle_id,
+                PuzzleRecord {
+                    puzzle_id: puzzle_id.clone(),
+                    tps: puzzle.tps,
+                    solution: puzzle.solution,
+                    settings: Some(puzzle.settings),
+                },
+            )
+            .await?;
+            created += 1;
+        }
+        super::dto::try_upsert(&scan_key, scan).await?;
+    }
+    Ok(created)
+}
+
+/// The rules to replay a puzzle with. Puzzles stored without the rules of their game fall back
+/// to the defaults for the board size.
+fn puzzle_settings(puzzle: &PuzzleRecord) -> ServerResult<TakGameSettings> {
+    if let Some(settings) = &puzzle.settings {
+        return Ok(settings.clone());
+    }
+    let invalid_puzzle =
+        || ServerError::InternalServerError(format!("Invalid puzzle TPS: {}", puzzle.tps));
+    let tps = TakTps::try_from_str(&puzzle.tps).ok_or_else(invalid_puzzle)?;
+    let size = TakBoard::try_from_partial_tps(&tps.position)
+        .ok_or_else(invalid_puzzle)?
+        .size;
+    Ok(TakGameSettings::new_with_position(
+        size,
+        tps,
+        None,
+        TakKomi::none(),
+        None,
+    ))
+}
+
+/// Plays the submitted moves from the puzzle position, which `settings` start at. The solver's
+/// moves must keep the road forced within `max_depth` plies of the start, while any legal reply
+/// of the opponent is accepted.
+pub fn check_solution(
+    settings: TakGameSettings,
+    moves: &[String],
+    max_depth: usize,
+) -> ServerResult<PuzzleVerdict> {
+    let mut game = TakGame::new(settings)
+        .ok_or_else(|| ServerError::InternalServerError("Invalid puzzle settings".to_string()))?;
+    let solver = game.current_player;
+
+    for (ply, mv) in moves.iter().enumerate() {
+        let Some(action) = TakAction::from_ptn(mv) else {
+            return Ok(PuzzleVerdict::WrongMove { ply });
+        };
+        if game.try_do_action(action).is_err() {
+            return Ok(PuzzleVerdict::WrongMove { ply });
+        }
+        if game.game_state == TakGameState::Win(solver, TakWinReason::Road) {
+            return Ok(PuzzleVerdict::Solved);
+        }
+        let solver_moved = ply % 2 == 0;
+        if game.game_state != TakGameState::Ongoing
+            || (solver_moved && !game.is_tinue(max_depth.saturating_sub(ply + 1)))
+        {
+            return Ok(PuzzleVerdict::WrongMove { ply });
+        }
+    }
+    Ok(PuzzleVerdict::NotForced)
+}
+
+pub async fn submit_solution(
+    puzzle_id: &PuzzleId,
+    moves: Vec<String>,
+) -> ServerResult<PuzzleVerdict> {
+    let puzzle = super::dto::try_get::<PuzzleRecord>(puzzle_id).await?;
+    let max_depth = puzzle.solution.len();
+    let settings = puzzle_settings(&puzzle)?;
+    tokio::task::spawn_blocking(move || check_solution(settings, &moves, max_depth))
+        .await
+        .map_err(|e| ServerError::InternalServerError(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MISSED_TINUE_PTN: &str = r#"
+[Size "5"]
+[Komi "0"]
+
+1. a1 a5
+2. b5 a2
+3. c5 c2
+4. e4 e2
+5. b1 d5
+6. c4 d4
+"#;
+
+    #[test]
+    fn test_finds_missed_tinue() {
+        let puzzles = generate_puzzles_from_game(MISSED_TINUE_PTN, 3);
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].tps, "1,1,1,x2/x4,1/x5/2,x,2,x,2/2,x4 1 5");
+        assert_eq!(puzzles[0].solution.len(), 3);
+    }
+
+    #[test]
+    fn test_puzzle_keeps_game_rules() {
+        let ptn = MISSED_TINUE_PTN.replace("[Komi \"0\"]", "[Komi \"2\"]\n[Flats \"18\"]");
+        let game_settings = TakPtn::try_from_str(&ptn).unwrap().get_settings().unwrap();
+        let puzzle = generate_puzzles_from_game(&ptn, 3).remove(0);
+        assert_ne!(puzzle.settings.komi, TakKomi::none());
+        assert_eq!(puzzle.settings.komi, game_settings.komi);
+        assert_eq!(puzzle.settings.stones.stones, 18);
+        assert_eq!(puzzle.settings.start_position.to_string(), puzzle.tps);
+
+        let record = PuzzleRecord {
+            puzzle_id: "puzzle".to_string(),
+            tps: puzzle.tps.clone(),
+            solution: puzzle.solution.clone(),
+            settings: Some(puzzle.settings.clone()),
+        };
+        let game = TakGame::new(puzzle_settings(&record).unwrap()).unwrap();
+        assert_eq!(game.settings.stones.stones, 18);
+        assert_eq!(game.to_tps().to_string(), puzzle.tps);
+        assert_eq!(
+            check_solution(puzzle_settings(&record).unwrap(), &puzzle.solution, 3).unwrap(),
+            PuzzleVerdict::Solved
+        );
+
+        let old_record = PuzzleRecord {
+            settings: None,
+            ..record
+        };
+        let game = TakGame::new(puzzle_settings(&old_record).unwrap()).unwrap();
+        assert_eq!(game.settings.komi, TakKomi::none());
+        assert_eq!(game.to_tps().to_string(), puzzle.tps);
+    }
+
+    #[test]
+    fn test_check_solution() {
+        let puzzle = generate_puzzles_from_game(MISSED_TINUE_PTN, 3).remove(0);
+        let depth = puzzle.solution.len();
+        let moves = |moves: &[&str]| moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            check_solution(puzzle.settings.clone(), &puzzle.solution, depth).unwrap(),
+            PuzzleVerdict::Solved
+        );
+        assert_eq!(
+            check_solution(puzzle.settings.clone(), &moves(&["d5", "Se5", "d4"]), depth).unwrap(),
+            PuzzleVerdict::Solved
+        );
+        assert_eq!(
+            check_solution(puzzle.settings.clone(), &moves(&["d5", "Se5"]), depth).unwrap(),
+            PuzzleVerdict::NotForced
+        );
+        assert_eq!(
+            check_solution(puzzle.settings.clone(), &moves(&["b1", "Sd5"]), depth).unwrap(),
+            PuzzleVerdict::WrongMove { ply: 0 }
+        );
+        assert_eq!(
+            check_solution(puzzle.settings.clone(), &moves(&["d5", "Se5", "b1"]), depth).unwrap(),
+            PuzzleVerdict::WrongMove { ply: 2 }
+        );
+        assert_eq!(
+            check_solution(puzzle.settings.clone(), &moves(&["d5", "f5"]), depth).unwrap(),
+            PuzzleVerdict::WrongMove { ply: 1 }
+        );
+    }
+}
@@ -1,65 +1,200 @@
-use std::sync::{Arc, LazyLock};
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
 
-use dashmap::DashMap;
-use tak_core::TakPlayer;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tak_core::{TakGame, TakGameSettings, TakKomi, TakPlayer, TakTps};
 
 use crate::server::{
-    MatchId, MatchInstance, NOTIFICATION_TOPIC, PlayerInformation, RematchColor,
-    SEEK_ACCEPTED_SUBTOPIC, SeekSettings, SeekUpdate, ServerError, ServerResult, UserId,
+    MATCH_CREATED_SUBTOPIC, MatchId, MatchInstance, MatchUpdate, NOTIFICATION_TOPIC,
+    PlayerInformation, RematchColor, SEEK_ACCEPTED_SUBTOPIC, SeekSettings, SeekUpdate, ServerError,
+    ServerResult, UserId, ValidatedPosition,
     api::SEEK_TOPIC,
-    internal::{cache, matches},
+    internal::{cache, matches, player},
 };
 
+fn is_compatible(a: &SeekSettings, a_rating: f64, b: &SeekSettings, b_rating: f64) -> bool {
+    let colors_match = match (a.creator_color, b.creator_color) {
+        (Some(a), Some(b)) => a != b,
+        _ => true,
+    };
+    a.game_settings == b.game_settings
+        && a.rated == b.rated
+        && colors_match
+        && a.accepts_rating(b_rating)
+        && b.accepts_rating(a_rating)
+}
+
+/// Tidies up a hand-written TPS: drops stray whitespace inside the position and uppercases the
+/// wall and capstone markers.
+fn normalize_tps(tps: &str) -> String {
+    let parts = tps.split_whitespace().collect::<Vec<_>>();
+    let Some(split) = parts.len().checked_sub(2).filter(|&split| split > 0) else {
+        return tps.to_string();
+    };
+    let position = parts[..split].concat().replace('s', "S").replace('c', "C");
+    format!("{} {}", position, parts[split..].join(" "))
+}
+
+/// Sets the start position of the game from a TPS string and returns the game starting there.
+/// The position must fit the board size and piece counts, and must not be decided already.
+fn apply_start_tps(game_settings: &mut TakGameSettings, tps: &str) -> ServerResult<TakGame> {
+    let tps = normalize_tps(tps);
+    TakTps::validate(&tps, game_settings.size)
+        .map_err(|e| ServerError::BadRequest(format!("Invalid TPS: {}", e)))?;
+    let tps = TakTps::try_from_str(&tps)
+        .ok_or_else(|| ServerError::BadRequest(format!("Invalid TPS: {}", tps)))?;
+    game_settings.start_position = tps;
+    let game = TakGame::new(game_settings.clone()).ok_or_else(|| {
+        ServerError::BadRequest("Start position doesn't fit the game settings".to_string())
+    })?;
+    game.validate().map_err(ServerError::BadRequest)?;
+    Ok(game)
+}
+
+/// Checks a start position for a board of the given size with the default piece counts.
+pub fn validate_position(tps: &str, size: usize) -> ServerResult<ValidatedPosition> {
+    let mut settings = TakGameSettings::new(size, None, TakKomi::none(), None);
+    if !settings.validate() {
+        return Err(ServerError::BadRequest(format!(
+            "Invalid board size: {size}"
+        )));
+    }
+    let game = apply_start_tps(&mut settings, tps)?;
+    Ok(ValidatedPosition {
+        tps: game.to_tps().to_string(),
+        hands: game.hands,
+    })
+}
+
+/// Flips a coin for the color of the seek's creator. With a seed the result is reproducible.
+pub fn draw_creator_color(seed: Option<u64>) -> TakPlayer {
+    let is_white = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed).gen_bool(0.5),
+        None => rand::random(),
+    };
+    if is_white {
+        TakPlayer::White
+    } else {
+        TakPlayer::Black
+    }
+}
+
+/// All open seeks. Every access goes through a single lock, so checking for a compatible seek
+/// and claiming it happens atomically. Each seek is stored with its creator's rating.
 pub struct Seeks {
-    seeks: Arc<DashMap<UserId, SeekSettings>>,
+    seeks: Mutex<HashMap<UserId, (SeekSettings, f64)>>,
 }
 
 impl Seeks {
     fn new() -> Self {
         Self {
-            seeks: Arc::new(DashMap::new()),
+            seeks: Mutex::new(HashMap::new()),
         }
     }
 
-    fn add_seek(&self, player_id: UserId, seek: SeekSettings) {
-        self.seeks.insert(player_id, seek);
+    /// Either removes and returns a compatible seek of another player, or adds the new seek to
+    /// the pool if there is none. Returns an error if the player already has a seek.
+    fn add_or_pair(
+        &self,
+        player_id: UserId,
+        seek: SeekSettings,
+        rating: f64,
+    ) -> ServerResult<Option<(UserId, SeekSettings)>> {
+        let mut seeks = self.seeks.lock().unwrap();
+        if seeks.contains_key(&player_id) {
+            return Err(ServerError::Conflict(
+                "Seek already exists for this player".to_string(),
+            ));
+        }
+        let opponent_id = seeks
+            .iter()
+            .find(|(_, (other, other_rating))| is_compatible(&seek, rating, other, *other_rating))
+            .map(|(opponent_id, _)| opponent_id.clone());
+        if let Some(opponent_id) = opponent_id {
+            let (opponent_seek, _) = seeks.remove(&opponent_id).unwrap();
+            return Ok(Some((opponent_id, opponent_seek)));
+        }
+        seeks.insert(player_id, (seek, rating));
+        Ok(None)
     }
 
-    fn has_seek(&self, player_id: &UserId) -> bool {
-        self.seeks.contains_key(player_id)
+    /// Removes and returns the seek of another player if it accepts the given rating. The
+    /// check and the removal happen under the same lock, so a seek can't be accepted twice.
+    fn take_if_accepts(&self, opponent_id: &UserId, rating: f64) -> ServerResult<SeekSettings> {
+        let mut seeks = self.seeks.lock().unwrap();
+        let (seek, _) = seeks.get(opponent_id).ok_or(ServerError::NotFound)?;
+        if !seek.accepts_rating(rating) {
+            return Err(ServerError::NotAllowed(
+                "Rating outside of the seek's range".to_string(),
+            ));
+        }
+        Ok(seeks.remove(opponent_id).unwrap().0)
+    }
+
+    /// Puts back a seek that was claimed for a match that couldn't be created, unless its
+    /// player has made a new one in the meantime.
+    fn restore_seek(&self, player_id: UserId, seek: SeekSettings, rating: f64) {
+        self.seeks
+            .lock()
+            .unwrap()
+            .entry(player_id)
+            .or_insert((seek, rating));
     }
 
     fn get_seek(&self, player_id: &UserId) -> Option<SeekSettings> {
-        self.seeks.get(player_id).map(|x| x.clone())
+        self.seeks
+            .lock()
+            .unwrap()
+            .get(player_id)
+            .map(|(seek, _)| seek.clone())
     }
 
-    fn remove_seek(&self, player_id: &UserId) {
-        self.seeks.remove(player_id);
+    fn remove_seek(&self, player_id: &UserId) -> Option<SeekSettings> {
+        self.seeks
+            .lock()
+            .unwrap()
+            .remove(player_id)
+            .map(|(seek, _)| seek)
     }
 
     fn get_seeks(&self) -> Vec<(UserId, SeekSettings)> {
         self.seeks
+            .lock()
+            .unwrap()
             .iter()
-            .map(|x| (x.key().clone(), x.value().clone()))
+            .map(|(player_id, (seek, _))| (player_id.clone(), seek.clone()))
             .collect()
     }
 }
 
 pub static SEEKS: LazyLock<Seeks> = LazyLock::new(|| Seeks::new());
 
-pub async fn create_seek(player_id: &UserId, settings: SeekSettings) -> ServerResult<()> {
-    if SEEKS.has_seek(player_id) {
-        return Err(ServerError::Conflict(
-            "Seek already exists for this player".to_string(),
-        ));
+/// Moves the start position of a seek into its game settings and checks that they make a
+/// playable game.
+pub fn prepare_seek(settings: &mut SeekSettings) -> ServerResult<()> {
+    if let Some(tps) = settings.start_tps.take() {
+        apply_start_tps(&mut settings.game_settings, &tps)?;
     }
     if !settings.game_settings.validate() {
         return Err(ServerError::BadRequest(
             "Invalid game settings for seek".to_string(),
         ));
     }
+    Ok(())
+}
+
+pub async fn create_seek(player_id: &UserId, mut settings: SeekSettings) -> ServerResult<()> {
+    prepare_seek(&mut settings)?;
     let player_info = cache::get_or_retrieve_player_info(player_id).await?;
-    SEEKS.add_seek(player_id.clone(), settings.clone());
+    let rating = player::get_or_insert_player(player_id).await?.rating;
+    if let Some((opponent_id, opponent_seek)) =
+        SEEKS.add_or_pair(player_id.clone(), settings.clone(), rating)?
+    {
+        pair_seeks(player_id, &settings, &opponent_id, opponent_seek).await?;
+        return Ok(());
+    }
     ws_pubsub::publish_to_topic(
         SEEK_TOPIC,
         SeekUpdate::Created {
@@ -72,11 +207,102 @@ pub async fn create_seek(player_id: &UserId, settings: SeekSettings) -> ServerRe
     Ok(())
 }
 
+/// Starts a match between a new seek and the compatible seek it was paired with. The claimed
+/// seek goes back to the pool if the match can't be created.
+async fn pair_seeks(
+    player_id: &UserId,
+    settings: &SeekSettings,
+    opponent_id: &UserId,
+    opponent_seek: SeekSettings,
+) -> ServerResult<()> {
+    let seed = opponent_seek.seed.or(settings.seed);
+    let creator_color = match (opponent_seek.creator_color, settings.creator_color) {
+        (Some(color), _) => color,
+        (None, Some(color)) => color.other(),
+        (None, None) => draw_creator_color(seed),
+    };
+
+    log::info!(
+        "Pairing seeks of player: {}, opponent: {}, opponent plays {:?}",
+        player_id,
+        opponent_id,
+        creator_color
+    );
+
+    let instance = MatchInstance {
+        player_id: opponent_id.clone(),
+        opponent_id: player_id.clone(),
+        game_settings: opponent_seek.game_settings.clone(),
+        rated: opponent_seek.rated,
+        creator_color,
+        rematch_color: RematchColor::Alternate,
+        seed,
+    };
+    let match_id = match matches::create_match(instance.clone()).await {
+        Ok(match_id) => match_id,
+        Err(e) => {
+            let rating = player::get_or_insert_player(opponent_id).await?.rating;
+            SEEKS.restore_seek(opponent_id.clone(), opponent_seek, rating);
+            return Err(e);
+        }
+    };
+
+    ws_pubsub::publish_to_topic(
+        SEEK_TOPIC,
+        SeekUpdate::Removed {
+            player_id: opponent_id.clone(),
+        },
+    )
+    .await;
+
+    let update = MatchUpdate::Created {
+        player_info: cache::get_or_retrieve_player_info(opponent_id).await?,
+        opponent_info: cache::get_or_retrieve_player_info(player_id).await?,
+        match_id,
+        settings: instance,
+    };
+    for id in [player_id, opponent_id] {
+        ws_pubsub::publish_to_topic(
+            format!("{}/{}/{}", NOTIFICATION_TOPIC, id, MATCH_CREATED_SUBTOPIC),
+            update.clone(),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Removes the seek of a player whose last socket closed, so that nobody gets paired with
+/// them. Keeps it if they have connected again in the meantime.
+fn remove_seek_of_disconnected(
+    seeks: &Seeks,
+    player_id: &UserId,
+    is_connected: bool,
+) -> Option<SeekSettings> {
+    if is_connected {
+        return None;
+    }
+    seeks.remove_seek(player_id)
+}
+
+pub async fn cancel_seek_on_disconnect(player_id: &UserId) {
+    let is_connected = ws_pubsub::is_connected(player_id);
+    if remove_seek_of_disconnected(&SEEKS, player_id, is_connected).is_none() {
+        return;
+    }
+    ws_pubsub::publish_to_topic(
+        SEEK_TOPIC,
+        SeekUpdate::Removed {
+            player_id: player_id.clone(),
+        },
+    )
+    .await;
+    log::info!("Seek removed after player {} disconnected", player_id);
+}
+
 pub async fn cancel_seek(player_id: &UserId) -> ServerResult<()> {
-    if !SEEKS.has_seek(player_id) {
+    if SEEKS.remove_seek(player_id).is_none() {
         return Err(ServerError::NotFound);
     }
-    SEEKS.remove_seek(player_id);
     ws_pubsub::publish_to_topic(
         SEEK_TOPIC,
         SeekUpdate::Removed {
@@ -88,9 +314,14 @@ pub async fn cancel_seek(player_id: &UserId) -> ServerResult<()> {
     Ok(())
 }
 
-pub async fn get_seeks() -> ServerResult<Vec<(PlayerInformation, SeekSettings)>> {
+/// Lists the seeks the given player could accept, i.e. those whose rating range includes them.
+pub async fn get_seeks(player_id: &UserId) -> ServerResult<Vec<(PlayerInformation, SeekSettings)>> {
+    let rating = player::get_or_insert_player(player_id).await?.rating;
     let mut seek_list = Vec::new();
     for (opponent_id, seek) in SEEKS.get_seeks() {
+        if !seek.accepts_rating(rating) {
+            continue;
+        }
         seek_list.push((
             cache::get_or_retrieve_player_info(&opponent_id).await?,
             seek,
@@ -100,15 +331,14 @@ pub async fn get_seeks() -> ServerResult<Vec<(PlayerInformation, SeekSettings)>>
 }
 
 pub async fn accept_seek(player_id: &UserId, opponent_id: &UserId) -> ServerResult<MatchId> {
-    let seek = SEEKS.get_seek(opponent_id).ok_or(ServerError::NotFound)?;
-
     if opponent_id == player_id {
         return Err(ServerError::Conflict(
             "Cannot accept your own seek".to_string(),
         ));
     }
 
-    SEEKS.remove_seek(opponent_id);
+    let rating = player::get_or_insert_player(player_id).await?.rating;
+    let seek = SEEKS.take_if_accepts(opponent_id, rating)?;
     ws_pubsub::publish_to_topic(
         SEEK_TOPIC,
         SeekUpdate::Removed {
@@ -117,22 +347,15 @@ pub async fn accept_seek(player_id: &UserId, opponent_id: &UserId) -> ServerResu
     )
     .await;
 
-    let first_player_is_white = match seek.creator_color {
-        Some(TakPlayer::White) => true,
-        Some(TakPlayer::Black) => false,
-        None => rand::random(),
-    };
-
-    let creator_color = if first_player_is_white {
-        TakPlayer::White
-    } else {
-        TakPlayer::Black
-    };
+    let creator_color = seek
+        .creator_color
+        .unwrap_or_else(|| draw_creator_color(seek.seed));
 
     log::info!(
-        "Accepting seek for player: {}, opponent: {}",
+        "Accepting seek for player: {}, opponent: {}, opponent plays {:?}",
         player_id,
-        opponent_id
+        opponent_id,
+        creator_color
     );
 
     let match_id = matches::create_match(MatchInstance {
@@ -142,6 +365,7 @@ pub async fn accept_seek(player_id: &UserId, opponent_id: &UserId) -> ServerResu
         rated: seek.rated,
         creator_color,
         rematch_color: RematchColor::Alternate,
+        seed: seek.seed,
     })
     .await?;
 
@@ -164,3 +388,249 @@ pub async fn get_seek(player_id: &UserId) -> ServerResult<SeekSettings> {
         Err(ServerError::NotFound)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tak_core::{TakGameSettings, TakHand, TakKomi};
+
+    use super::*;
+
+    fn seek(size: usize, rated: bool, creator_color: Option<TakPlayer>) -> SeekSettings {
+        SeekSettings {
+            game_settings: TakGameSettings::new(size, None, TakKomi::none(), None),
+            rated,
+            creator_color,
+            rating_range: None,
+            seed: None,
+            start_tps: None,
+        }
+    }
+
+    #[test]
+    fn test_disconnect_removes_seek() {
+        let seeks = Seeks::new();
+        let (gone, reconnected) = ("gone".to_string(), "reconnected".to_string());
+        seeks
+            .add_or_pair(gone.clone(), seek(5, true, Some(TakPlayer::White)), 1200.0)
+            .unwrap();
+        seeks
+            .add_or_pair(reconnected.clone(), seek(6, true, None), 1200.0)
+            .unwrap();
+
+        assert!(remove_seek_of_disconnected(&seeks, &gone, false).is_some());
+        assert!(remove_seek_of_disconnected(&seeks, &reconnected, true).is_none());
+        assert!(seeks.get_seek(&gone).is_none());
+        assert!(seeks.get_seek(&reconnected).is_some());
+    }
+
+    #[test]
+    fn test_complementary_seeks_pair() {
+        let seeks = Seeks::new();
+        let white = seek(5, true, Some(TakPlayer::White));
+        let black = seek(5, true, Some(TakPlayer::Black));
+        assert_eq!(
+            seeks
+                .add_or_pair("a".to_string(), white.clone(), 1200.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks.add_or_pair("b".to_string(), black, 1200.0).unwrap(),
+            Some(("a".to_string(), white))
+        );
+        assert!(seeks.get_seeks().is_empty());
+    }
+
+    #[test]
+    fn test_claimed_seek_can_be_restored() {
+        let seeks = Seeks::new();
+        let white = seek(5, true, Some(TakPlayer::White));
+        let black = seek(5, true, Some(TakPlayer::Black));
+        seeks
+            .add_or_pair("a".to_string(), white.clone(), 1200.0)
+            .unwrap();
+        let (opponent_id, opponent_seek) = seeks
+            .add_or_pair("b".to_string(), black.clone(), 1200.0)
+            .unwrap()
+            .unwrap();
+
+        seeks.restore_seek(opponent_id, opponent_seek, 1200.0);
+        assert_eq!(seeks.get_seek(&"a".to_string()), Some(white.clone()));
+        assert!(seeks.get_seek(&"b".to_string()).is_none());
+
+        let newer = seek(6, true, None);
+        seeks.remove_seek(&"a".to_string());
+        seeks
+            .add_or_pair("a".to_string(), newer.clone(), 1200.0)
+            .unwrap();
+        seeks.restore_seek("a".to_string(), white, 1200.0);
+        assert_eq!(seeks.get_seek(&"a".to_string()), Some(newer));
+    }
+
+    #[test]
+    fn test_mismatched_seeks_do_not_pair() {
+        let seeks = Seeks::new();
+        let white = seek(5, true, Some(TakPlayer::White));
+        assert_eq!(
+            seeks
+                .add_or_pair("a".to_string(), white.clone(), 1200.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks.add_or_pair("b".to_string(), white, 1200.0).unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks
+                .add_or_pair("c".to_string(), seek(5, false, None), 1200.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks
+                .add_or_pair("d".to_string(), seek(6, true, None), 1200.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(seeks.get_seeks().len(), 4);
+        assert!(
+            seeks
+                .add_or_pair("a".to_string(), seek(7, true, None), 1200.0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rating_range_is_respected_both_ways() {
+        let seeks = Seeks::new();
+        let white = seek(5, true, Some(TakPlayer::White));
+        let ranged = SeekSettings {
+            rating_range: Some((1000.0, 1400.0)),
+            ..seek(5, true, None)
+        };
+        assert_eq!(
+            seeks
+                .add_or_pair("low".to_string(), white.clone(), 900.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks
+                .add_or_pair("mid".to_string(), white.clone(), 1300.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks
+                .add_or_pair("picky".to_string(), ranged.clone(), 1200.0)
+                .unwrap(),
+            Some(("mid".to_string(), white))
+        );
+
+        // The remaining seeks both fall outside the other's range.
+        let narrow = SeekSettings {
+            rating_range: Some((1250.0, 1350.0)),
+            ..seek(5, true, None)
+        };
+        assert_eq!(
+            seeks
+                .add_or_pair("narrow".to_string(), narrow, 1300.0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            seeks
+                .add_or_pair("other".to_string(), ranged, 1200.0)
+                .unwrap(),
+            None
+        );
+        assert!(seek(5, false, None).accepts_rating(0.0));
+    }
+
+    #[test]
+    fn test_accepted_seek_is_taken_once() {
+        let seeks = Seeks::new();
+        let ranged = SeekSettings {
+            rating_range: Some((1000.0, 1400.0)),
+            ..seek(5, true, None)
+        };
+        seeks
+            .add_or_pair("a".to_string(), ranged.clone(), 1200.0)
+            .unwrap();
+
+        assert!(matches!(
+            seeks.take_if_accepts(&"a".to_string(), 1500.0),
+            Err(ServerError::NotAllowed(_))
+        ));
+        assert_eq!(
+            seeks.take_if_accepts(&"a".to_string(), 1300.0).unwrap(),
+            ranged
+        );
+        assert!(matches!(
+            seeks.take_if_accepts(&"a".to_string(), 1300.0),
+            Err(ServerError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_seeded_color_is_reproducible() {
+        for seed in 0..16 {
+            assert_eq!(
+                draw_creator_color(Some(seed)),
+                draw_creator_color(Some(seed))
+            );
+        }
+        let colors = (0..16)
+            .map(|seed| draw_creator_color(Some(seed)))
+            .collect::<Vec<_>>();
+        assert!(colors.contains(&TakPlayer::White));
+        assert!(colors.contains(&TakPlayer::Black));
+    }
+
+    #[test]
+    fn test_start_tps_sets_start_position() {
+        let mut settings = seek(4, false, None).game_settings;
+        apply_start_tps(&mut settings, "2,x3/x,1,x2/x4/1,x3 2 3").unwrap();
+        let game = TakGame::new(settings).unwrap();
+        assert_eq!(game.to_tps().to_string(), "2,x3/x,1,x2/x4/1,x3 2 3");
+        assert_eq!(game.current_player, TakPlayer::Black);
+    }
+
+    #[test]
+    fn test_position_is_normalized() {
+        for tps in [
+            "x5/x5/x5/x5/x5 1 1",
+            "x,x,x,x,x/x5/x2,x3/x5/x,x4 1 1",
+            "  x5/x5/x5/x5/x5   1  1 ",
+            "x5/ x5/x5 /x5/x5 1 1",
+        ] {
+            let position = validate_position(tps, 5).unwrap();
+            assert_eq!(position.tps, "x5/x5/x5/x5/x5 1 1");
+            assert_eq!(position.hands, [TakHand::new(21, 1), TakHand::new(21, 1)]);
+        }
+
+        let position = validate_position("2,x4/x,1c,x3/x5/1s,x4/x5 2 3", 5).unwrap();
+        assert_eq!(position.tps, "2,x4/x,1C,x3/x5/1S,x4/x5 2 3");
+        assert_eq!(position.hands, [TakHand::new(20, 0), TakHand::new(20, 1)]);
+
+        assert!(validate_position("x5/x5/x5/x5/x5 1 1", 4).is_err());
+        assert!(validate_position("x5/x5/x5/x5/x5 1 1", 9).is_err());
+    }
+
+    #[test]
+    fn test_invalid_start_tps_is_rejected() {
+        for tps in [
+            "not a tps",
+            "2,x3/x4/1,x3 2 3",
+            "1,1,1,1/x4/x4/2,x3 2 3",
+            "1C,1C,x2/x4/x4/2,x3 2 3",
+        ] {
+            let mut settings = seek(4, false, None).game_settings;
+            assert!(matches!(
+                apply_start_tps(&mut settings, tps),
+                Err(ServerError::BadRequest(_))
+            ));
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use tak_core::{TakGame, TakGameSettings, TakPlayer};
+use tak_core::{TakGame, TakGameSettings, TakGameState, TakHand, TakPlayer};
 
 pub mod api;
 
@@ -11,6 +11,7 @@ pub type UserId = String;
 pub type GameId = String;
 pub type RoomId = String;
 pub type MatchId = String;
+pub type PuzzleId = String;
 
 pub type JWTToken = String;
 
@@ -26,6 +27,34 @@ pub struct SeekSettings {
     pub game_settings: TakGameSettings,
     pub rated: bool,
     pub creator_color: Option<TakPlayer>,
+    /// Inclusive bounds for the opponent's rating. Only applies to rated seeks.
+    #[serde(default)]
+    pub rating_range: Option<(f64, f64)>,
+    /// Makes the coin flip for the first player reproducible when no color is chosen.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// A position to start from instead of an empty board. The server checks it and moves it
+    /// into the game settings.
+    #[serde(default)]
+    pub start_tps: Option<String>,
+}
+
+impl SeekSettings {
+    pub fn accepts_rating(&self, rating: f64) -> bool {
+        !self.rated
+            || self
+                .rating_range
+                .is_none_or(|(min, max)| min <= rating && rating <= max)
+    }
+}
+
+/// A start position checked by the server, for the position editor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValidatedPosition {
+    /// The position in canonical TPS.
+    pub tps: String,
+    /// Pieces each player has left to place, white first.
+    pub hands: [TakHand; 2],
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -66,15 +95,65 @@ pub struct MatchInstance {
     pub rated: bool,
     pub creator_color: TakPlayer,
     pub rematch_color: RematchColor,
+    /// The seed the colors were drawn with, if the seek had one.
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct MatchData {
+    /// Sent with the clock relative to the receiver's time, so clients with a skewed clock
+    /// still show the right time left.
+    #[serde(with = "tak_core::live_game")]
     pub game: TakGame,
     pub player_mapping: fixed_map::Map<TakPlayer, UserId>,
     pub rematch_agree: Vec<UserId>,
     pub draw_agree: Vec<UserId>,
+    /// The player asking to take back their last move, until the opponent answers.
+    pub takeback_request: Option<UserId>,
     pub has_ended: bool,
+    /// Users watching the match. They take no part in rematches, draws or takebacks.
+    #[serde(default)]
+    pub spectators: Vec<UserId>,
+    /// When the game gets canceled if nobody has moved yet. Cleared once it has passed.
+    #[serde(default)]
+    pub first_move_deadline: Option<chrono::DateTime<chrono::Utc>>,
+    /// Counts the changes to the game, takebacks included. Moves name the version they were
+    /// made in, so a move for a position that has changed since is rejected even if the game
+    /// is back at the same ply.
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// An ongoing match as shown in the list of games to watch.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LiveMatchInfo {
+    pub match_id: MatchId,
+    pub white_player: PlayerInformation,
+    pub black_player: PlayerInformation,
+    pub ply_index: usize,
+    /// Milliseconds left on each clock. Empty for untimed games.
+    pub time_remaining: Vec<(TakPlayer, u64)>,
+    /// Number of users following the match who aren't playing in it.
+    pub spectators: usize,
+}
+
+/// Engine evaluation of a featured match, published to spectators after every move.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MatchAnalysis {
+    /// Number of plies played in the analyzed position.
+    pub ply_index: usize,
+    pub depth: usize,
+    /// Positive scores favor white.
+    pub score: i32,
+    pub best_move: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatMessage {
+    pub from: PlayerInformation,
+    pub text: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -100,7 +179,105 @@ pub struct GameInformation {
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+impl GameInformation {
+    /// An id derived from what was played instead of when it was stored. See
+    /// [`game_content_id`].
+    pub fn content_id(&self) -> GameId {
+        game_content_id(
+            &self.ptn,
+            &self.white_player.user_id,
+            &self.black_player.user_id,
+        )
+    }
+}
+
+/// Hashes the canonical PTN of a game together with its players, so the same game always gets
+/// the same id. The date is part of the PTN's own tags. FNV-1a is used instead of the std
+/// hasher because these ids are stored and must not change between builds.
+pub fn game_content_id(ptn: &str, white_id: &UserId, black_id: &UserId) -> GameId {
+    let ptn = tak_core::TakPtn::try_from_str(ptn).map_or_else(|| ptn.to_string(), |x| x.to_str());
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in [ptn.as_str(), white_id, black_id] {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// The end of a stored game, for previews that don't need the moves.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GameSummary {
+    pub final_tps: String,
+    pub result: TakGameState,
+    pub size: usize,
+    /// Usernames of white and black.
+    pub player_names: [String; 2],
+}
+
+/// Position in a player's history: the timestamp and id of the last game already seen.
+pub type HistoryCursor = (chrono::DateTime<chrono::Utc>, GameId);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryPage {
+    pub games: Vec<GameInformation>,
+    /// Where the next page starts, if there are older games.
+    pub next_cursor: Option<HistoryCursor>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct H2HRecord {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+}
+
+/// A player's results against one opponent, split by the color they played.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct H2HStats {
+    pub as_white: H2HRecord,
+    pub as_black: H2HRecord,
+}
+
+impl H2HStats {
+    pub fn total(&self) -> H2HRecord {
+        H2HRecord {
+            wins: self.as_white.wins + self.as_black.wins,
+            losses: self.as_white.losses + self.as_black.losses,
+            draws: self.as_white.draws + self.as_black.draws,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PtnImportSummary {
+    pub imported: Vec<GameId>,
+    pub failed: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Puzzle {
+    pub tps: String,
+    /// The winning line in PTN, starting with the solver's move and ending with the road.
+    pub solution: Vec<String>,
+    /// The rules of the game the puzzle comes from, starting at the puzzle position.
+    pub settings: TakGameSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PuzzleVerdict {
+    Solved,
+    /// The move at this ply of the submission is illegal or lets the opponent escape.
+    WrongMove {
+        ply: usize,
+    },
+    /// Every move kept the win forced, but the submission ends before the road is built.
+    NotForced,
+}
+
 pub use error::{ServerError, ServerResult};
 
 pub const NOTIFICATION_TOPIC: &str = "notifications";
 pub const SEEK_ACCEPTED_SUBTOPIC: &str = "seek_accepted";
+pub const MATCH_CREATED_SUBTOPIC: &str = "match_created";
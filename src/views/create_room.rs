@@ -4,19 +4,22 @@ use crate::server::{SeekSettings, ServerError};
 use dioxus::prelude::*;
 use dioxus_free_icons::Icon;
 use dioxus_free_icons::icons::fa_solid_icons::{
-    FaBolt, FaChessBoard, FaClock, FaPalette, FaPlusMinus,
+    FaBolt, FaChessBoard, FaClock, FaPalette, FaPlusMinus, FaPuzzlePiece, FaRobot,
 };
-use tak_core::{TakGameSettings, TakKomi, TakPlayer, TakTimeMode};
+use tak_core::{TakGameSettings, TakKomi, TakPlayer, TakTimeMode, TakTps};
+use takumi::EngineLevel;
 
 pub static LOCAL_SETTINGS: GlobalSignal<LocalSettings> = GlobalSignal::new(|| LocalSettings {
     game_settings: TakGameSettings::new(6, None, TakKomi::new(2, false), None),
     first_player_mode: None,
+    engine_level: EngineLevel::default(),
 });
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct LocalSettings {
     pub game_settings: TakGameSettings,
     pub first_player_mode: Option<TakPlayer>,
+    pub engine_level: EngineLevel,
 }
 
 #[component]
@@ -54,6 +57,8 @@ pub fn CreateRoomView(is_local: Option<bool>) -> Element {
     let mut time_mode = use_signal(|| (10, 0));
     let mut komi = use_signal(|| TakKomi::new(2, false));
     let mut first_player_mode = use_signal(|| None);
+    let mut start_tps = use_signal(String::new);
+    let mut engine_level = use_signal(|| LOCAL_SETTINGS.peek().engine_level);
 
     let on_click_create = move |_| {
         let time_mode = time_mode.read().clone();
@@ -61,16 +66,33 @@ pub fn CreateRoomView(is_local: Option<bool>) -> Element {
         let board_size = *board_size.read();
         let komi = komi.read().clone();
         let first_player_mode = first_player_mode.read().clone();
+        let start_tps = expand_start_position(start_tps.read().trim(), board_size);
         let create_room_params = SeekSettings {
             game_settings: TakGameSettings::new(board_size, None, komi, Some(time_mode)),
             creator_color: first_player_mode,
             rated: true,
+            rating_range: None,
+            seed: None,
+            start_tps: (!start_tps.is_empty()).then_some(start_tps),
         };
         if let Some(is_computer) = is_local {
+            let mut game_settings = create_room_params.game_settings;
+            if let Some(tps) = &create_room_params.start_tps {
+                let Some(tps) = TakTps::try_from_str(tps) else {
+                    dioxus::logger::tracing::error!("Invalid start position: {}", tps);
+                    return;
+                };
+                game_settings.start_position = tps;
+                if !game_settings.validate() {
+                    dioxus::logger::tracing::error!("Start position doesn't fit the settings");
+                    return;
+                }
+            }
             let mut local_settings = LOCAL_SETTINGS.write();
             *local_settings = LocalSettings {
-                game_settings: create_room_params.game_settings,
+                game_settings,
                 first_player_mode,
+                engine_level: *engine_level.read(),
             };
             if is_computer {
                 nav.push(Route::PlayComputer {});
@@ -202,6 +224,39 @@ pub fn CreateRoomView(is_local: Option<bool>) -> Element {
                     }
                 }
             }
+            if is_local == Some(true) {
+                div { id: "engine-level-chooser",
+                    div { class: "category-header",
+                        Icon { icon: FaRobot, width: 20, height: 20 }
+                        "Difficulty"
+                    }
+                    div { class: "category-container",
+                        for level in EngineLevel::ALL {
+                            button {
+                                class: "choice-button",
+                                onclick: move |_| engine_level.set(level),
+                                class: if *engine_level.read() == level { "current" } else { "" },
+                                "{level.name()}"
+                            }
+                        }
+                    }
+                }
+            }
+            div { id: "start-position-chooser",
+                div { class: "category-header",
+                    Icon { icon: FaPuzzlePiece, width: 20, height: 20 }
+                    "Start Position"
+                }
+                div { class: "category-container",
+                    input {
+                        class: "start-position-input",
+                        r#type: "text",
+                        placeholder: "TPS, setup name or a1:1C shorthand (empty board if left blank)",
+                        value: "{start_tps}",
+                        oninput: move |e| start_tps.set(e.value()),
+                    }
+                }
+            }
             button {
                 id: "create-room-button",
                 class: "primary-button",
@@ -211,3 +266,13 @@ pub fn CreateRoomView(is_local: Option<bool>) -> Element {
         }
     }
 }
+
+/// Accepts the name of a standard setup or the occupied-squares shorthand in place of a TPS.
+fn expand_start_position(input: &str, size: usize) -> String {
+    if input.is_empty() || TakTps::validate(input, size).is_ok() {
+        return input.to_string();
+    }
+    TakTps::from_named(input, size)
+        .or_else(|| TakTps::from_shorthand(input, size))
+        .map_or_else(|| input.to_string(), |tps| tps.to_string())
+}
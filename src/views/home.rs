@@ -54,12 +54,17 @@ pub fn Home() -> Element {
 
     let is_loading = use_memo(move || player_match.read().is_none());
 
+    let rejoin_match_id = use_memo(move || match &*player_match.read() {
+        Some(Ok(Ok(match_ids))) => match_ids.first().cloned(),
+        _ => None,
+    });
+
     rsx! {
         div { id: "home-view",
             div { class: "home-options",
                 if !*is_loading.read() {
                     if !*is_logged_out.read() {
-                        if let Some(Ok(Ok(match_id))) = player_match.read().clone() {
+                        if let Some(match_id) = rejoin_match_id.read().clone() {
                             button {
                                 class: "primary-button",
                                 onclick: move |_| {
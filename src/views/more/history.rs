@@ -3,26 +3,37 @@ use dioxus::prelude::*;
 
 use crate::{
     Route,
-    server::{GameInformation, UserId, api::get_history, error::ServerError},
+    server::{GameInformation, HistoryCursor, UserId, api::get_history, error::ServerError},
 };
 
+const HISTORY_PAGE_SIZE: usize = 20;
+
 #[component]
 pub fn History() -> Element {
     let nav = use_navigator();
-    let stats = use_resource(|| async { get_history(Some((0, 100))).await });
+    let mut user_id = use_signal(|| None::<UserId>);
+    let mut games = use_signal(Vec::<GameInformation>::new);
+    let mut next_cursor = use_signal(|| None::<HistoryCursor>);
 
-    use_effect(move || {
-        if let Some(Ok(Err(ServerError::Unauthorized))) = stats.read().as_ref() {
-            nav.push(Route::Auth {});
-        }
-    });
+    let load_page = move |before: Option<HistoryCursor>| {
+        spawn(async move {
+            match get_history(before, HISTORY_PAGE_SIZE).await {
+                Ok(Ok((id, page))) => {
+                    user_id.set(Some(id));
+                    games.write().extend(page.games);
+                    next_cursor.set(page.next_cursor);
+                }
+                Ok(Err(ServerError::Unauthorized)) => {
+                    nav.push(Route::Auth {});
+                }
+                res => {
+                    dioxus::logger::tracing::error!("[History] Failed to load games: {:?}", res);
+                }
+            }
+        });
+    };
 
-    let data = use_memo(move || {
-        stats.read().as_ref().map_or(None, |s| match s {
-            Ok(Ok(data)) => Some(data.clone()),
-            _ => None,
-        })
-    });
+    use_hook(move || load_page(None));
 
     let get_opponent_info = |game: &GameInformation, user_id: &UserId| {
         if game.white_player.user_id == *user_id {
@@ -40,8 +51,8 @@ pub fn History() -> Element {
 
     rsx! {
         div { id: "history-view",
-            if let Some((user_id, data)) = &*data.read() {
-                for game in data {
+            if let Some(user_id) = &*user_id.read() {
+                for game in games.read().iter() {
                     div { class: "history-game-entry",
                         p {
                             {
@@ -61,9 +72,16 @@ pub fn History() -> Element {
                         }
                     }
                 }
-                if data.len() == 0 {
+                if games.read().is_empty() {
                     p { "No games found." }
                 }
+                if let Some(cursor) = next_cursor.read().clone() {
+                    button {
+                        class: "history-load-more",
+                        onclick: move |_| load_page(Some(cursor.clone())),
+                        "Load more"
+                    }
+                }
             } else {
                 p { "Loading..." }
             }
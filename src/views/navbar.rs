@@ -1,5 +1,5 @@
 use crate::Route;
-use crate::components::{NavButton, NavButtonIcon, Notifications};
+use crate::components::{NavButton, NavButtonIcon, Notifications, TokenRenewer};
 use dioxus::prelude::*;
 use dioxus_free_icons::Icon;
 use dioxus_free_icons::icons::fa_solid_icons::FaChessBoard;
@@ -39,5 +39,6 @@ pub fn Navbar() -> Element {
         }
 
         Notifications {}
+        TokenRenewer {}
     }
 }
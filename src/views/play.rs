@@ -1,17 +1,25 @@
 use crate::Route;
 use crate::components::tak_board_state::{PlayerInfo, PlayerType, TakBoardState};
-use crate::components::{GameActionsOnline, TakBoard, TakEngine, TakWebSocket, TakWinModal, TakWinModalLocal};
-use crate::server::api::get_match;
+use crate::components::{
+    GameActionsOnline, TakBoard, TakChat, TakEngine, TakWebSocket, TakWinModal, TakWinModalLocal,
+};
+use crate::server::api::{get_match, join_match_as_spectator, leave_match_as_spectator};
 use crate::server::{MatchId, ServerError};
 use crate::views::LOCAL_SETTINGS;
 use dioxus::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use tak_core::TakPlayer;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientGameMessage {
-    Move(String),
+    /// A move in PTN and the version of the match it was made in. The server rejects it if the
+    /// match has changed since.
+    Move(u64, String),
+    Resign,
+    Chat(String),
 }
 
 #[component]
@@ -56,6 +64,33 @@ pub fn PlayComputer() -> Element {
         board_clone.has_game()
     });
 
+    // Join as a spectator once the players are known, and leave again with the view.
+    let spectating = use_hook(|| Rc::new(Cell::new(false)));
+    let board_clone = board.clone();
+    let match_id_clone = match_id.clone();
+    let spectating_clone = spectating.clone();
+    use_effect(move || {
+        if !*show_board.read() || !board_clone.is_spectator() || spectating_clone.get() {
+            return;
+        }
+        spectating_clone.set(true);
+        let match_id = match_id_clone.clone();
+        spawn(async move {
+            if let Err(e) = join_match_as_spectator(match_id).await {
+                dioxus::logger::tracing::error!("Failed to join as spectator: {e}");
+            }
+        });
+    });
+    let match_id_clone = match_id.clone();
+    use_drop(move || {
+        if !spectating.get() {
+            return;
+        }
+        spawn_forever(async move {
+            let _ = leave_match_as_spectator(match_id_clone).await;
+        });
+    });
+
     rsx! {
         div { id: "play-view",
             if *show_board.read() {
@@ -96,6 +131,7 @@ pub fn PlayLocal() -> Element {
     });
 
     let board_clone = state.clone();
+    let mut flip_state = state.clone();
 
     use_effect(move || {
         let settings = LOCAL_SETTINGS.peek().clone();
@@ -114,6 +150,11 @@ pub fn PlayLocal() -> Element {
         div { id: "play-view",
             if *show_board.read() {
                 TakBoard {}
+                button {
+                    class: "choice-button",
+                    onclick: move |_| flip_state.toggle_orientation(),
+                    "Flip board"
+                }
                 TakWinModalLocal {}
             }
         }
@@ -131,10 +172,12 @@ pub fn PlayOnline(match_id: MatchId) -> Element {
     let board = use_context_provider(|| TakBoardState::new(player_info));
     let board_clone = board.clone();
 
+    let match_id_clone = match_id.clone();
     use_effect(move || {
         let mut board = board_clone.clone();
+        let match_id = match_id_clone.clone();
         spawn(async move {
-            board.update_from_remote().await;
+            board.update_from_remote(&match_id).await;
         });
     });
 
@@ -169,6 +212,7 @@ pub fn PlayOnline(match_id: MatchId) -> Element {
                     GameActionsOnline {}
                     TakWinModal { match_id: match_id.clone() }
                     TakWebSocket { match_id: match_id.clone() }
+                    TakChat { match_id: match_id.clone() }
                 }
             } else {
                 h2 { "No room found or not connected." }
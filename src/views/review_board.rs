@@ -13,6 +13,21 @@ use crate::{
 
 #[component]
 pub fn ReviewBoard(game_id: String) -> Element {
+    rsx! {
+        ReviewBoardView { game_id, ply: None }
+    }
+}
+
+/// Opens the review at a given ply, so a link can point at a specific move.
+#[component]
+pub fn ReviewBoardAt(game_id: String, ply: usize) -> Element {
+    rsx! {
+        ReviewBoardView { game_id, ply: Some(ply) }
+    }
+}
+
+#[component]
+pub fn ReviewBoardView(game_id: String, ply: Option<usize>) -> Element {
     let game = use_resource(move || get_game(game_id.clone()));
 
     let state = use_context_provider(|| TakBoardState::new(HashMap::new()));
@@ -56,7 +71,7 @@ pub fn ReviewBoard(game_id: String) -> Element {
         drop(player_info);
         ply_index.set(
             board_clone
-                .with_game(|game| game.game().ply_index)
+                .with_game(|game| ply.map_or(game.game().ply_index, |x| game.clamp_ply_index(x)))
                 .expect("Game should exist to get ply index"),
         );
     });
@@ -83,10 +98,15 @@ pub fn ReviewBoard(game_id: String) -> Element {
     });
 
     let ply_index_clone = ply_index.clone();
+    let state_clone = state.clone();
     let on_press_backwards = move || {
         let mut ply_index_clone = ply_index_clone.clone();
         let val = *ply_index_clone.peek();
-        if val > 0 {
+        if val
+            > state_clone
+                .with_game(|game| game.game().settings.start_position.get_ply_index())
+                .expect("Game should exist to get start ply index")
+        {
             ply_index_clone.set(val - 1);
         }
     };
@@ -1,5 +1,5 @@
 use dioxus::prelude::*;
-use tak_core::TakTimeMode;
+use tak_core::{TakTimeControl, TakTimeMode};
 use ws_pubsub::use_ws_topic_receive;
 
 use crate::{
@@ -196,10 +196,14 @@ fn formatted_time_mode(time_mode: &Option<TakTimeMode>) -> String {
     };
     let minutes = time_mode.time / 60;
     let seconds = time_mode.time % 60;
-    if time_mode.increment > 0 {
-        format!("{}:{:02} + {}s", minutes, seconds, time_mode.increment)
-    } else {
-        format!("{}:{:02}", minutes, seconds)
+    let increment = time_mode.increment;
+    match time_mode.control {
+        _ if increment == 0 => format!("{}:{:02}", minutes, seconds),
+        TakTimeControl::Fischer => format!("{}:{:02} + {}s", minutes, seconds, increment),
+        TakTimeControl::ByoYomi { periods } => {
+            format!("{}:{:02} + {}×{}s", minutes, seconds, periods, increment)
+        }
+        TakTimeControl::Delay => format!("{}:{:02} d{}s", minutes, seconds, increment),
     }
 }
 
@@ -8,6 +8,8 @@ pub enum TakActionRecord {
         variant: TakPieceVariant,
         player: TakPlayer,
         time_remaining: Option<u64>,
+        /// Milliseconds the player spent on this move, if it was played on a clock.
+        elapsed: Option<u64>,
     },
     MovePiece {
         pos: TakCoord,
@@ -16,6 +18,7 @@ pub enum TakActionRecord {
         drops: Vec<usize>,
         flattened: bool,
         time_remaining: Option<u64>,
+        elapsed: Option<u64>,
     },
 }
 
@@ -42,6 +45,20 @@ impl TakActionRecord {
         }
     }
 
+    pub fn elapsed(&self) -> Option<u64> {
+        match self {
+            TakActionRecord::PlacePiece { elapsed, .. } => *elapsed,
+            TakActionRecord::MovePiece { elapsed, .. } => *elapsed,
+        }
+    }
+
+    pub fn set_elapsed(&mut self, millis: Option<u64>) {
+        match self {
+            TakActionRecord::PlacePiece { elapsed, .. } => *elapsed = millis,
+            TakActionRecord::MovePiece { elapsed, .. } => *elapsed = millis,
+        }
+    }
+
     pub fn to_action(&self) -> TakAction {
         match self {
             TakActionRecord::PlacePiece { pos, variant, .. } => TakAction::PlacePiece {
@@ -70,6 +87,7 @@ impl TakActionRecord {
                 variant,
                 player: _,
                 time_remaining: _,
+                elapsed: _,
             } => {
                 let prefix = match variant {
                     TakPieceVariant::Flat => "",
@@ -87,6 +105,7 @@ impl TakActionRecord {
                 drops,
                 flattened,
                 time_remaining: _,
+                elapsed: _,
             } => {
                 let take_str = if *take == 1 {
                     String::new()
@@ -114,6 +133,66 @@ impl TakActionRecord {
             }
         }
     }
+
+    /// Describes the action in words for logs, e.g. "White places a wall on c4". Move records
+    /// don't know their player, so the one who took the turn is passed in.
+    pub fn describe(&self, mover: TakPlayer) -> String {
+        let player_name = |player: TakPlayer| match player {
+            TakPlayer::White => "White",
+            TakPlayer::Black => "Black",
+        };
+        let square = |pos: &TakCoord| format!("{}{}", (b'a' + pos.x as u8) as char, pos.y + 1);
+        match self {
+            Self::PlacePiece {
+                pos,
+                variant,
+                player,
+                ..
+            } => {
+                let piece = match variant {
+                    TakPieceVariant::Flat => "flat",
+                    TakPieceVariant::Wall => "wall",
+                    TakPieceVariant::Capstone => "capstone",
+                };
+                let piece = if *player == mover {
+                    format!("a {}", piece)
+                } else {
+                    format!("{}'s {}", player_name(*player), piece)
+                };
+                format!("{} places {} on {}", player_name(mover), piece, square(pos))
+            }
+            Self::MovePiece {
+                pos,
+                dir,
+                take,
+                drops,
+                flattened,
+                ..
+            } => {
+                let dir = match dir {
+                    TakDir::Up => "up",
+                    TakDir::Down => "down",
+                    TakDir::Left => "left",
+                    TakDir::Right => "right",
+                };
+                let mut text = format!(
+                    "{} moves {} from {} {}",
+                    player_name(mover),
+                    take,
+                    square(pos),
+                    dir
+                );
+                if drops.len() > 1 {
+                    let drops = drops.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+                    text.push_str(&format!(", dropping {}", drops.join(",")));
+                }
+                if *flattened {
+                    text.push_str(", flattening a wall");
+                }
+                text
+            }
+        }
+    }
 }
 
 impl TakAction {
@@ -209,6 +288,59 @@ impl TakAction {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_describe() {
+        let place = |ptn: &str, player| {
+            let TakAction::PlacePiece { pos, variant } = TakAction::from_ptn(ptn).unwrap() else {
+                unreachable!()
+            };
+            TakActionRecord::PlacePiece {
+                pos,
+                variant,
+                player,
+                time_remaining: None,
+                elapsed: None,
+            }
+        };
+        let spread = |ptn: &str, flattened| {
+            let TakAction::MovePiece {
+                pos,
+                dir,
+                take,
+                drops,
+            } = TakAction::from_ptn(ptn).unwrap()
+            else {
+                unreachable!()
+            };
+            TakActionRecord::MovePiece {
+                pos,
+                dir,
+                take,
+                drops,
+                flattened,
+                time_remaining: None,
+                elapsed: None,
+            }
+        };
+
+        assert_eq!(
+            place("Sc4", TakPlayer::White).describe(TakPlayer::White),
+            "White places a wall on c4"
+        );
+        assert_eq!(
+            place("a1", TakPlayer::Black).describe(TakPlayer::White),
+            "White places Black's flat on a1"
+        );
+        assert_eq!(
+            spread("a2+", false).describe(TakPlayer::White),
+            "White moves 1 from a2 up"
+        );
+        assert_eq!(
+            spread("3d4>12", true).describe(TakPlayer::Black),
+            "Black moves 3 from d4 right, dropping 1,2, flattening a wall"
+        );
+    }
+
     #[test]
     fn test_roundtrip_ptn() {
         let actions = vec![
@@ -218,6 +350,7 @@ mod tests {
                     variant: TakPieceVariant::Flat,
                     player: TakPlayer::White,
                     time_remaining: None,
+                    elapsed: None,
                 },
                 "a1",
                 TakAction::PlacePiece {
@@ -233,6 +366,7 @@ mod tests {
                     drops: vec![2, 1],
                     flattened: false,
                     time_remaining: None,
+                    elapsed: None,
                 },
                 "3b4+21",
                 TakAction::MovePiece {
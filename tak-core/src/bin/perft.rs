@@ -41,12 +41,13 @@ fn run(
         return *memo_val;
     }
 
-    let mut count = 0;
-    let moves = gen_moves(game, partition_memo);
     if depth == 1 {
-        memo.insert((tps, depth), moves.len());
-        return moves.len();
+        let count = game.count_legal_actions();
+        memo.insert((tps, depth), count);
+        return count;
     }
+    let mut count = 0;
+    let moves = gen_moves(game, partition_memo);
     for action in moves {
         let mut clone = game.clone();
         match clone.try_do_action(action) {
@@ -1,7 +1,8 @@
 use std::collections::VecDeque;
 
 use crate::{
-    TakCoord, TakDir, TakInvalidMoveError, TakInvalidPlaceError, TakPieceVariant, TakPlayer,
+    TakCoord, TakDir, TakInvalidMoveError, TakInvalidPlaceError, TakKomi, TakPieceVariant,
+    TakPlayer, TakScoringRules, TakTps, TakTpsError,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -44,6 +45,9 @@ impl TakStack {
     }
 }
 
+/// The owner of a road and the coordinates of its endpoints.
+pub type TakRoadWin = (TakPlayer, (TakCoord, TakCoord));
+
 /// Represents a Tak board with a specified size and a vector of stacks.
 /// It provides methods to place, move, and undo moves of pieces on the board,
 /// as well as to check for valid placements and moves.
@@ -292,24 +296,31 @@ impl TakBoard {
     /// Attempts to create a TakBoard from a partial TPS string.
     /// The partial TPS format is a simplified version that only includes the occupied positions and their stack compositions.
     pub fn try_from_partial_tps(tps: &str) -> Option<Self> {
-        let mut size = None;
+        Self::parse_partial_tps(tps, None).ok()
+    }
+
+    /// Reads the position part of a TPS, reporting the first problem found. Without a `size`,
+    /// the first row decides how large the board is.
+    pub fn parse_partial_tps(tps: &str, size: Option<usize>) -> Result<Self, TakTpsError> {
+        let rows = tps.split('/').collect::<Vec<_>>();
+        if let Some(size) = size.filter(|&size| size != rows.len()) {
+            return Err(TakTpsError::WrongRowCount {
+                expected: size,
+                found: rows.len(),
+            });
+        }
+        let mut size = size;
         let mut board = Vec::new();
         let mut id_counter = 0;
         let mut empty_spaces = 0;
-        for line in tps.split('/') {
+        for (y, line) in rows.iter().enumerate() {
             let mut x = 0;
-            let mut row = match size {
-                None => Vec::new(),
-                Some(s) => Vec::with_capacity(s),
-            };
+            let mut row = Vec::new();
             for part in line.split(',') {
+                let count = TakTps::validate_square(part, y, x)?;
                 if part.starts_with('x') {
-                    let empty_count: usize = part[1..].parse().unwrap_or(1);
-                    for _ in 0..empty_count {
-                        row.push(None);
-                    }
-                    x += empty_count;
-                    empty_spaces += empty_count;
+                    row.extend(std::iter::repeat_n(None, count));
+                    empty_spaces += count;
                 } else {
                     let mut composition = Vec::new();
                     let mut variant = TakPieceVariant::Flat;
@@ -324,29 +335,69 @@ impl TakBoard {
                                 id_counter += 1;
                             }
                             'S' => variant = TakPieceVariant::Wall,
-                            'C' => variant = TakPieceVariant::Capstone,
-                            _ => continue,
+                            _ => variant = TakPieceVariant::Capstone,
                         }
                     }
                     row.push(Some(TakStack::new(variant, composition)));
-                    x += 1;
                 }
+                x += count;
             }
-            match size {
-                None => size = Some(x),
-                Some(s) => {
-                    if s != x {
-                        return None;
-                    }
-                }
+            let expected = *size.get_or_insert(x);
+            if x != expected {
+                return Err(TakTpsError::WrongRowLength {
+                    row: y,
+                    expected,
+                    found: x,
+                });
             }
             board.extend(row.into_iter().rev());
         }
-        let size = size?;
-        if board.len() != size * size {
-            return None;
+        let size = size.unwrap_or_default();
+        if rows.len() != size {
+            return Err(TakTpsError::WrongRowCount {
+                expected: size,
+                found: rows.len(),
+            });
         }
         board.reverse();
+        Ok(TakBoard {
+            size,
+            board,
+            id_counter,
+            empty_spaces,
+        })
+    }
+
+    /// Builds a board from the stacks on each square, listed in the order of
+    /// `TakCoord::iter_board` with each stack from the bottom up. Piece IDs are assigned in
+    /// that order.
+    pub fn from_stacks(
+        size: usize,
+        stacks: Vec<Option<(TakPieceVariant, Vec<TakPlayer>)>>,
+    ) -> Option<Self> {
+        if stacks.len() != size * size {
+            return None;
+        }
+        let mut id_counter = 0;
+        let mut empty_spaces = 0;
+        let board = stacks
+            .into_iter()
+            .map(|stack| {
+                let Some((variant, players)) = stack.filter(|(_, players)| !players.is_empty())
+                else {
+                    empty_spaces += 1;
+                    return None;
+                };
+                let composition = players
+                    .into_iter()
+                    .map(|player| {
+                        id_counter += 1;
+                        TakPiece::new(id_counter - 1, player)
+                    })
+                    .collect();
+                Some(TakStack::new(variant, composition))
+            })
+            .collect();
         Some(TakBoard {
             size,
             board,
@@ -355,6 +406,57 @@ impl TakBoard {
         })
     }
 
+    /// Reassigns all piece IDs in the order of `TakCoord::iter_board`, with each stack from
+    /// the bottom up. Boards with equal stacks end up with equal IDs, no matter whether they
+    /// were played out or loaded from TPS.
+    pub fn normalize_piece_ids(&mut self) {
+        let pieces = self.board.iter_mut().flatten();
+        let mut id_counter = 0;
+        for piece in pieces.flat_map(|stack| &mut stack.composition) {
+            piece.id = id_counter;
+            id_counter += 1;
+        }
+        self.id_counter = id_counter;
+    }
+
+    /// Returns a copy of the board transformed by one of the 8 symmetries of the square.
+    /// Bit 2 of `symmetry` mirrors the board horizontally, bits 0-1 then rotate it
+    /// by 90° steps.
+    pub fn transformed(&self, symmetry: usize) -> TakBoard {
+        let mut board = vec![None; self.board.len()];
+        for pos in TakCoord::iter_board(self.size) {
+            let target = pos.transformed(self.size, symmetry);
+            *target.get_mut(&mut board, self.size) = pos.get(&self.board, self.size).clone();
+        }
+        TakBoard {
+            size: self.size,
+            board,
+            id_counter: self.id_counter,
+            empty_spaces: self.empty_spaces,
+        }
+    }
+
+    /// Returns the canonical form of the board under rotations and reflections, i.e. the
+    /// variant with the lexicographically smallest partial TPS.
+    /// Piece IDs are reassigned from the TPS, so they don't affect the result. Colors are
+    /// never swapped, since the board doesn't know which player is to move.
+    pub fn canonical(&self) -> TakBoard {
+        let tps = (0..8)
+            .map(|symmetry| self.transformed(symmetry).to_partial_tps())
+            .min()
+            .expect("There is at least one symmetry");
+        TakBoard::try_from_partial_tps(&tps).expect("Canonical TPS should be valid")
+    }
+
+    /// Returns a hash of the canonical form of the board, which is equal for all
+    /// positions that are symmetric to each other.
+    pub fn canonical_hash(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.canonical().to_partial_tps().hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Checks if there is a road for the given player that passes through the specified positions.
     /// Returns `Some((start, end))` if a road is found, where `start` and `end` are the coordinates of the road's endpoints.
     pub fn check_for_road(
@@ -408,6 +510,103 @@ impl TakBoard {
         None
     }
 
+    /// Checks for a road of either player through the given positions, which should be the ones
+    /// changed by the last action. Returns the road's owner along with its endpoints. If both
+    /// players have a road, the mover wins, so theirs is reported.
+    pub fn check_for_new_road(
+        &self,
+        positions: &[TakCoord],
+        mover: TakPlayer,
+    ) -> Option<TakRoadWin> {
+        [mover, mover.other()].into_iter().find_map(|player| {
+            self.check_for_road(positions, player)
+                .map(|road| (player, road))
+        })
+    }
+
+    /// Attempts to place a piece like `try_place`, then checks whether it completed a road.
+    pub fn place_and_check(
+        &mut self,
+        pos: TakCoord,
+        variant: TakPieceVariant,
+        player: TakPlayer,
+    ) -> Result<Option<TakRoadWin>, TakInvalidPlaceError> {
+        self.try_place(pos, variant, player)?;
+        Ok(self.check_for_new_road(&[pos], player))
+    }
+
+    /// Attempts a move like `try_move`, then checks whether it completed a road. Uncovering
+    /// pieces can complete a road for the opponent of the mover as well.
+    pub fn move_and_check(
+        &mut self,
+        pos: TakCoord,
+        dir: TakDir,
+        take: usize,
+        drops: &[usize],
+    ) -> Result<Option<TakRoadWin>, TakInvalidMoveError> {
+        let mover = self.try_get_stack(pos).map(|stack| stack.player());
+        self.try_move(pos, dir, take, drops)?;
+        let mover = mover.expect("Valid move should start from a stack");
+        let mut positions = vec![pos];
+        let mut current_pos = pos;
+        for _ in 0..drops.len() {
+            current_pos = current_pos.offset_dir(dir);
+            positions.push(current_pos);
+        }
+        Ok(self.check_for_new_road(&positions, mover))
+    }
+
+    /// Checks if the given player has a road anywhere on the board.
+    pub fn has_road(&self, player: TakPlayer) -> bool {
+        let positions = TakCoord::iter_board(self.size).collect::<Vec<_>>();
+        self.check_for_road(&positions, player).is_some()
+    }
+
+    /// Counts the empty squares where a flat of the given player would complete a road. Two or
+    /// more means the opponent can't block them all with one placement.
+    pub fn count_road_threats(&self, player: TakPlayer) -> usize {
+        self.iter_empty_spaces()
+            .filter(|&pos| {
+                let mut board = self.clone();
+                board
+                    .place_and_check(pos, TakPieceVariant::Flat, player)
+                    .is_ok_and(|road| road.is_some_and(|(owner, _)| owner == player))
+            })
+            .count()
+    }
+
+    /// Measures the longest chain of road pieces of the given player as the number of squares on
+    /// the shortest path between its two farthest-apart pieces. Each group of connected pieces is
+    /// measured on its own and the longest counts. Returns 0 if the player has no road pieces.
+    pub fn longest_road(&self, player: TakPlayer) -> usize {
+        let is_road = |pos: TakCoord| {
+            pos.try_get(&self.board, self.size)
+                .and_then(|stack| stack.as_ref())
+                .is_some_and(|stack| {
+                    stack.player() == player && stack.variant != TakPieceVariant::Wall
+                })
+        };
+        let mut longest = 0;
+        for start in TakCoord::iter_board(self.size).filter(|&pos| is_road(pos)) {
+            // Squares on the shortest path from the start, including both ends. 0 is unvisited.
+            let mut lengths = vec![0; self.size * self.size];
+            *start.get_mut(&mut lengths, self.size) = 1;
+            let mut queue = VecDeque::from([start]);
+            while let Some(pos) = queue.pop_front() {
+                let length = *pos.get(&lengths, self.size);
+                longest = longest.max(length);
+                for dir in TakDir::ALL {
+                    let next = pos.offset_dir(dir);
+                    if is_road(next) && *next.get(&lengths, self.size) == 0 {
+                        *next.get_mut(&mut lengths, self.size) = length + 1;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        longest
+    }
+
     /// Finds the shortest path from the start position to the end position for the specified player.
     /// This path follows the rules of a Tak road, meaning it can only traverse through pieces of the same player
     /// and cannot pass through walls.
@@ -491,14 +690,15 @@ impl TakBoard {
         (stone_count, capstone_count)
     }
 
-    /// Counts the number of topmost flats for each player on the board.
+    /// Counts the number of topmost flats for each player on the board, along with walls and
+    /// capstones if the rules say so.
     /// This is used to determine the winner if a player has no stones left or there are no
     /// empty spaces left.
-    pub fn count_flats(&self) -> [usize; 2] {
+    pub fn count_flats(&self, rules: &TakScoringRules) -> [usize; 2] {
         let mut counts = [0, 0];
         for tile in &self.board {
             if let Some(stack) = tile {
-                if stack.variant == TakPieceVariant::Flat {
+                if rules.counts(stack.variant) {
                     counts[stack.player().index()] += 1;
                 }
             }
@@ -506,6 +706,24 @@ impl TakBoard {
         counts
     }
 
+    /// Computes the flat differential from White's perspective, adjusted by the komi, and
+    /// the player who would win a flat count right now.
+    /// A komi tiebreak counts as half a flat in favor of the komi's target, so the sign of the
+    /// score always agrees with `TakKomi::determine_winner`.
+    pub fn flat_score(&self, komi: &TakKomi, rules: &TakScoringRules) -> (f32, Option<TakPlayer>) {
+        let counts = self.count_flats(rules);
+        let mut bonus = komi.amount as f32;
+        if komi.tiebreak {
+            bonus += 0.5;
+        }
+        let bonus = match komi.target() {
+            TakPlayer::White => bonus,
+            TakPlayer::Black => -bonus,
+        };
+        let score = counts[0] as f32 - counts[1] as f32 + bonus;
+        (score, komi.determine_winner(counts))
+    }
+
     /// Returns an iterator over all pieces of the specified player on the board.
     /// Each item in the iterator is a tuple containing the position and a reference to the stack
     /// at that position.
@@ -529,7 +747,8 @@ impl TakBoard {
     /// Returns an iterator over all empty spaces on the board.
     /// Each item in the iterator is a `TakCoord` representing an empty position.
     pub fn iter_empty_spaces<'a>(&'a self) -> impl Iterator<Item = TakCoord> + 'a {
-        TakCoord::iter_board(self.size).filter(|pos| pos.try_get(&self.board, self.size).is_none())
+        TakCoord::iter_board(self.size)
+            .filter(|pos| matches!(pos.try_get(&self.board, self.size), Some(None)))
     }
 
     /// Returns a reference to the stack at the specified position, if it exists.
@@ -813,4 +1032,118 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_canonical_rotation() {
+        let board = TakBoard::try_from_partial_tps("2,x3/x,12S,x2/x3,1C/21,x3").unwrap();
+        let rotated = board.transformed(1);
+        assert_ne!(board.to_partial_tps(), rotated.to_partial_tps());
+        assert_eq!(
+            board.canonical().to_partial_tps(),
+            rotated.canonical().to_partial_tps()
+        );
+        assert_eq!(board.canonical_hash(), rotated.canonical_hash());
+        for symmetry in 0..8 {
+            assert_eq!(board.canonical(), board.transformed(symmetry).canonical());
+        }
+    }
+
+    #[test]
+    fn test_canonical_distinct_positions() {
+        let a = TakBoard::try_from_partial_tps("1,x3/x4/x4/x4").unwrap();
+        let b = TakBoard::try_from_partial_tps("x,1,x2/x4/x4/x4").unwrap();
+        let c = TakBoard::try_from_partial_tps("2,x3/x4/x4/x4").unwrap();
+        assert_ne!(a.canonical_hash(), b.canonical_hash());
+        assert_ne!(a.canonical_hash(), c.canonical_hash());
+        assert_ne!(a.canonical(), b.canonical());
+    }
+
+    #[test]
+    fn test_flat_score_half_komi() {
+        let board = TakBoard::try_from_partial_tps("1,2,x/2,1,x/x3").unwrap();
+        let rules = TakScoringRules::default();
+        let (score, leader) = board.flat_score(&TakKomi::new(0, true), &rules);
+        assert_eq!(score, -0.5);
+        assert_eq!(leader, Some(TakPlayer::Black));
+
+        let (score, leader) = board.flat_score(&TakKomi::none(), &rules);
+        assert_eq!(score, 0.0);
+        assert_eq!(leader, None);
+    }
+
+    #[test]
+    fn test_flat_score_komi_flips_leader() {
+        let board = TakBoard::try_from_partial_tps("1,1,2/1,2S,1C/12,x2").unwrap();
+        let rules = TakScoringRules::default();
+        let (score, leader) = board.flat_score(&TakKomi::none(), &rules);
+        assert_eq!(score, 1.0);
+        assert_eq!(leader, Some(TakPlayer::White));
+
+        let (score, leader) = board.flat_score(&TakKomi::new(2, false), &rules);
+        assert_eq!(score, -1.0);
+        assert_eq!(leader, Some(TakPlayer::Black));
+    }
+
+    #[test]
+    fn test_move_and_check_completes_road() {
+        let mut board = TakBoard::try_from_partial_tps("1,1,1,x/x3,1/x4/2,2,x2").unwrap();
+        let road = board
+            .move_and_check(TakCoord::new(3, 2), TakDir::Up, 1, &[1])
+            .unwrap();
+        assert_eq!(
+            road,
+            Some((TakPlayer::White, (TakCoord::new(0, 3), TakCoord::new(3, 3))))
+        );
+    }
+
+    #[test]
+    fn test_non_connecting_action_reports_no_road() {
+        let mut board = TakBoard::try_from_partial_tps("1,1,1,x/x3,1/x4/2,2,x2").unwrap();
+        let road = board
+            .move_and_check(TakCoord::new(3, 2), TakDir::Down, 1, &[1])
+            .unwrap();
+        assert_eq!(road, None);
+        let road = board
+            .place_and_check(TakCoord::new(2, 0), TakPieceVariant::Flat, TakPlayer::Black)
+            .unwrap();
+        assert_eq!(road, None);
+    }
+
+    #[test]
+    fn test_place_and_check_completes_road() {
+        let mut board = TakBoard::try_from_partial_tps("1,1,1,x/x4/x4/2,2,x2").unwrap();
+        let road = board
+            .place_and_check(TakCoord::new(3, 3), TakPieceVariant::Flat, TakPlayer::White)
+            .unwrap();
+        assert_eq!(
+            road,
+            Some((TakPlayer::White, (TakCoord::new(0, 3), TakCoord::new(3, 3))))
+        );
+    }
+
+    #[test]
+    fn test_count_road_threats() {
+        let board = TakBoard::try_from_partial_tps("x5/1,x4/1,1,1,1,x/1,x4/1,x4").unwrap();
+        assert_eq!(board.count_road_threats(TakPlayer::White), 2);
+        assert_eq!(board.count_road_threats(TakPlayer::Black), 0);
+        assert_eq!(board.iter_empty_spaces().count(), 18);
+
+        let board = TakBoard::try_from_partial_tps("x5/1,x4/1,1,1,1,2S/1,x4/1,x4").unwrap();
+        assert_eq!(board.count_road_threats(TakPlayer::White), 1);
+    }
+
+    #[test]
+    fn test_longest_road() {
+        let board =
+            TakBoard::try_from_partial_tps("1,x4/1,1,x3/x,1,1,x2/x2,1,2S,x/2,2,2,2,1").unwrap();
+        // The zigzag from a5 down to c2, not the lone flat on e1.
+        assert_eq!(board.longest_road(TakPlayer::White), 6);
+        // The straight line on the first rank, without the wall on d2.
+        assert_eq!(board.longest_road(TakPlayer::Black), 4);
+
+        // An L-shaped chain counts both of its legs.
+        let board = TakBoard::try_from_partial_tps("1,1,1/x2,1/x2,1").unwrap();
+        assert_eq!(board.longest_road(TakPlayer::White), 5);
+        assert_eq!(board.longest_road(TakPlayer::Black), 0);
+    }
 }
@@ -0,0 +1,283 @@
+use bincode::Options;
+
+use crate::{
+    TakAction, TakActionRecord, TakClock, TakCoord, TakDir, TakGame, TakGameSettings, TakGameState,
+    TakPieceVariant, TakPlayer, TakPtnAttr,
+};
+
+/// The leading byte of buffers written by `TakGame::to_bytes`.
+pub const TAK_GAME_BYTES_VERSION: u8 = 1;
+
+/// Everything needed to rebuild a game. The board, the movers and flattened walls follow from
+/// replaying the actions, so they aren't stored.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TakGameBytesV1 {
+    settings: TakGameSettings,
+    actions: Vec<u8>,
+    /// Remaining and elapsed time of every action. Left empty if no action was timed.
+    times: Vec<(Option<u64>, Option<u64>)>,
+    game_state: TakGameState,
+    clock: Option<TakClock>,
+    ptn_tags: Vec<TakPtnAttr>,
+}
+
+const MOVE_TAG: u8 = 3;
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+/// Packs an action into a tag byte, the square and, for moves, the drops as a bitmask where
+/// each set bit ends a drop.
+fn pack_action(action: &TakAction, size: usize, out: &mut Vec<u8>) {
+    let square = |pos: &TakCoord| (pos.y as usize * size + pos.x as usize) as u8;
+    match action {
+        TakAction::PlacePiece { pos, variant } => {
+            out.push(match variant {
+                TakPieceVariant::Flat => 0,
+                TakPieceVariant::Wall => 1,
+                TakPieceVariant::Capstone => 2,
+            });
+            out.push(square(pos));
+        }
+        TakAction::MovePiece {
+            pos, dir, drops, ..
+        } => {
+            let dir_bits = TakDir::ALL.iter().position(|d| d == dir).unwrap() as u8;
+            out.push(MOVE_TAG | dir_bits << 2);
+            out.push(square(pos));
+            let mut spread = 0u8;
+            let mut carried = 0;
+            for drop in drops {
+                carried += drop;
+                spread |= 1 << (carried - 1);
+            }
+            out.push(spread);
+        }
+    }
+}
+
+fn unpack_action(bytes: &mut impl Iterator<Item = u8>, size: usize) -> Option<TakAction> {
+    let tag = bytes.next()?;
+    let square = bytes.next()? as usize;
+    let pos = TakCoord::new((square % size) as i32, (square / size) as i32);
+    let variant = match tag {
+        0 => TakPieceVariant::Flat,
+        1 => TakPieceVariant::Wall,
+        2 => TakPieceVariant::Capstone,
+        _ => {
+            let dir = *TakDir::ALL.get((tag >> 2) as usize)?;
+            let spread = bytes.next()?;
+            let mut drops = Vec::new();
+            let mut count = 0;
+            for bit in 0..8 {
+                count += 1;
+                if spread & (1 << bit) != 0 {
+                    drops.push(count);
+                    count = 0;
+                }
+            }
+            let take = drops.iter().sum();
+            return Some(TakAction::MovePiece {
+                pos,
+                dir,
+                take,
+                drops,
+            });
+        }
+    };
+    Some(TakAction::PlacePiece { pos, variant })
+}
+
+impl TakGame {
+    /// Encodes the game into a compact binary buffer, much smaller than its PTN.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let size = self.settings.size;
+        let mut actions = Vec::new();
+        for record in &self.action_history {
+            pack_action(&record.to_action(), size, &mut actions);
+        }
+        let mut times = self
+            .action_history
+            .iter()
+            .map(|record| (record.time_remaining(), record.elapsed()))
+            .collect::<Vec<_>>();
+        if times.iter().all(|time| *time == (None, None)) {
+            times.clear();
+        }
+        let data = TakGameBytesV1 {
+            settings: self.settings.clone(),
+            actions,
+            times,
+            game_state: self.game_state.clone(),
+            clock: self.clock.clone(),
+            ptn_tags: self.ptn_tags.clone(),
+        };
+        let mut bytes = vec![TAK_GAME_BYTES_VERSION];
+        options()
+            .serialize_into(&mut bytes, &data)
+            .expect("Game should be serializable");
+        bytes
+    }
+
+    /// Decodes a buffer written by `to_bytes`. Returns `None` if the buffer is malformed, has
+    /// an unknown version or contains an illegal action.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&version, rest) = bytes.split_first()?;
+        if version != TAK_GAME_BYTES_VERSION {
+            return None;
+        }
+        let data = options().deserialize::<TakGameBytesV1>(rest).ok()?;
+        let size = data.settings.size;
+        let mut game = TakGame::new(data.settings)?;
+        game.clock = None;
+        let mut actions = data.actions.into_iter().peekable();
+        let mut index = 0;
+        while actions.peek().is_some() {
+            let action = unpack_action(&mut actions, size)?;
+            let (time_remaining, elapsed) = if data.times.is_empty() {
+                (None, None)
+            } else {
+                *data.times.get(index)?
+            };
+            let record = match action {
+                TakAction::PlacePiece { pos, variant } => TakActionRecord::PlacePiece {
+                    pos,
+                    variant,
+                    player: TakPlayer::White,
+                    time_remaining,
+                    elapsed,
+                },
+                TakAction::MovePiece {
+                    pos,
+                    dir,
+                    take,
+                    drops,
+                } => TakActionRecord::MovePiece {
+                    pos,
+                    dir,
+                    take,
+                    drops,
+                    flattened: false,
+                    time_remaining,
+                    elapsed,
+                },
+            };
+            game.try_do_action_record(&record).ok()?;
+            index += 1;
+        }
+        game.game_state = data.game_state;
+        game.clock = data.clock;
+        game.ptn_tags = data.ptn_tags;
+        Some(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{TakKomi, TakOpening, TakPtn, TakScoringRules, TakTimeMode, TakTimestamp};
+
+    use super::*;
+
+    const FORTY_MOVE_PTN: &str = r#"
+[Player1 "alice"]
+[Player2 "bob"]
+[Size "6"]
+[Komi "2"]
+[Flats "30"]
+[Caps "1"]
+
+1. a6 f1
+2. d3 c4
+3. d4 d5
+4. c3 b3
+5. c5 b4
+6. c2 b2
+7. c1 Cd2
+8. b1 d1
+9. c6 d2<
+10. Cb5 d2
+11. e3 e2
+12. f3 a4
+13. b5- a3
+14. 2b4- a2
+15. 3b3- a1
+16. b1< b4
+17. f2 2c2+
+18. e5 e2+
+19. a5 f4
+20. e4 3c3>
+21. c3 d6
+22. c1> e6
+23. f5 4d3+
+24. 4b2+13 f6
+25. 4b4> c2
+26. Sd3 b2
+27. e2 b5
+28. b4 b1
+29. b4+ b4
+30. c1 b6
+31. e2< d6<
+32. c5+ b6>
+33. Sb6 Sd6
+34. b6> Sb6
+35. 4c6- e1
+36. c1+ d6<
+37. d3> d6
+38. 3e3-12 b6-
+39. c1 b6
+40. 5c4< c4 0-F
+"#;
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let ptn = TakPtn::try_from_str(FORTY_MOVE_PTN).unwrap();
+        let game = TakGame::try_from_ptn(ptn).unwrap();
+        assert_eq!(game.ptn_tags.len(), 2);
+        let bytes = game.to_bytes();
+        assert_eq!(bytes[0], TAK_GAME_BYTES_VERSION);
+        assert_eq!(TakGame::from_bytes(&bytes), Some(game.clone()));
+        assert!(bytes.len() * 2 < game.to_ptn().to_str().len());
+    }
+
+    #[test]
+    fn test_bytes_roundtrip_timed() {
+        let time_mode = TakTimeMode::new(60, 2);
+        let settings = TakGameSettings::new(5, None, TakKomi::none(), Some(time_mode));
+        let mut game = TakGame::new(settings).unwrap();
+        for (mv, spent) in [("a1", 1500), ("e5", 300), ("c3", 12300), ("c3<", 4000)] {
+            let now = TakTimestamp::now().millis;
+            game.clock.as_mut().unwrap().last_update_timestamp = Some(TakTimestamp {
+                millis: now - spent,
+            });
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        assert_eq!(TakGame::from_bytes(&game.to_bytes()), Some(game));
+    }
+
+    #[test]
+    fn test_bytes_roundtrip_opening_and_scoring() {
+        let settings = TakGameSettings::new(5, None, TakKomi::none(), None)
+            .with_opening(TakOpening::NoSwap)
+            .with_scoring(TakScoringRules {
+                count_walls: true,
+                count_capstones: false,
+            });
+        let mut game = TakGame::new(settings).unwrap();
+        for mv in ["a1", "e5", "Sc3"] {
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        assert_eq!(TakGame::from_bytes(&game.to_bytes()), Some(game));
+    }
+
+    #[test]
+    fn test_bytes_rejects_unknown_version() {
+        let game = TakGame::try_from_ptn(TakPtn::try_from_str(FORTY_MOVE_PTN).unwrap()).unwrap();
+        let mut bytes = game.to_bytes();
+        bytes[0] = TAK_GAME_BYTES_VERSION + 1;
+        assert_eq!(TakGame::from_bytes(&bytes), None);
+        assert_eq!(TakGame::from_bytes(&[]), None);
+    }
+}
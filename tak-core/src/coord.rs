@@ -25,6 +25,20 @@ impl TakCoord {
         self.x >= 0 && self.y >= 0 && (self.x as usize) < size && (self.y as usize) < size
     }
 
+    /// Maps the coordinate through one of the 8 symmetries of a board of the given size, as
+    /// `TakBoard::transformed` does. Coordinates just off the board are mapped the same way.
+    pub fn transformed(&self, size: usize, symmetry: usize) -> Self {
+        let size = size as i32;
+        let (mut x, mut y) = (self.x, self.y);
+        if symmetry & 4 != 0 {
+            x = size - 1 - x;
+        }
+        for _ in 0..(symmetry & 3) {
+            (x, y) = (size - 1 - y, x);
+        }
+        TakCoord { x, y }
+    }
+
     pub fn offset(&self, dx: i32, dy: i32) -> Self {
         TakCoord {
             x: self.x + dx,
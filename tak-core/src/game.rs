@@ -1,7 +1,8 @@
 use crate::{
-    TakAction, TakActionRecord, TakBoard, TakClock, TakCoord, TakDir, TakDrawReason, TakGameState,
-    TakInvalidActionError, TakInvalidMoveError, TakInvalidPlaceError, TakPieceVariant, TakPlayer,
-    TakPtn, TakPtnAttr, TakTimeMode, TakTimestamp, TakTps, TakWinReason,
+    PtnReplayError, TakAction, TakActionRecord, TakBoard, TakClock, TakCoord, TakDir,
+    TakDrawReason, TakGameState, TakInvalidActionError, TakInvalidMoveError, TakInvalidPlaceError,
+    TakPieceVariant, TakPlayer, TakPtn, TakPtnAttr, TakTimeMode, TakTimeSource, TakTps,
+    TakWinReason,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -9,33 +10,121 @@ use crate::{
 pub struct TakKomi {
     pub amount: usize,
     pub tiebreak: bool,
+    /// The player the komi is awarded to. Defaults to Black.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub komi_target: Option<TakPlayer>,
 }
 
 impl TakKomi {
     pub fn new(amount: usize, tiebreak: bool) -> Self {
-        TakKomi { amount, tiebreak }
+        TakKomi {
+            amount,
+            tiebreak,
+            komi_target: None,
+        }
     }
     pub fn none() -> Self {
         TakKomi {
             amount: 0,
             tiebreak: false,
+            komi_target: None,
         }
     }
+    pub fn with_target(mut self, komi_target: TakPlayer) -> Self {
+        self.komi_target = Some(komi_target);
+        self
+    }
+    pub fn target(&self) -> TakPlayer {
+        self.komi_target.unwrap_or(TakPlayer::Black)
+    }
     pub fn determine_winner(&self, counts: [usize; 2]) -> Option<TakPlayer> {
-        let white_score = counts[0];
-        let black_score = counts[1] + self.amount;
+        let mut scores = counts;
+        scores[self.target().index()] += self.amount;
+        let [white_score, black_score] = scores;
         if white_score > black_score {
             Some(TakPlayer::White)
         } else if black_score > white_score {
             Some(TakPlayer::Black)
         } else if self.tiebreak {
-            Some(TakPlayer::Black)
+            Some(self.target())
         } else {
             None
         }
     }
 }
 
+/// How the first ply of each player is played.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TakOpening {
+    /// Each player first places a flat of the opponent's color.
+    #[default]
+    Swap,
+    /// The game starts with normal moves.
+    NoSwap,
+}
+
+impl TakOpening {
+    /// The value of the PTN `Opening` tag.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            TakOpening::Swap => "swap",
+            TakOpening::NoSwap => "no-swap",
+        }
+    }
+
+    pub fn try_from_str(s: &str) -> Option<Self> {
+        match s {
+            "swap" => Some(TakOpening::Swap),
+            "no-swap" => Some(TakOpening::NoSwap),
+            _ => None,
+        }
+    }
+}
+
+/// Which pieces count in a flat count besides topmost flats, for variants that score
+/// differently. The default is the standard rules.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TakScoringRules {
+    pub count_walls: bool,
+    pub count_capstones: bool,
+}
+
+impl TakScoringRules {
+    pub fn counts(&self, variant: TakPieceVariant) -> bool {
+        match variant {
+            TakPieceVariant::Flat => true,
+            TakPieceVariant::Wall => self.count_walls,
+            TakPieceVariant::Capstone => self.count_capstones,
+        }
+    }
+
+    /// The value of the PTN `Scoring` tag, listing the pieces that count.
+    pub fn to_str(&self) -> &'static str {
+        match (self.count_walls, self.count_capstones) {
+            (false, false) => "flats",
+            (true, false) => "flats+walls",
+            (false, true) => "flats+caps",
+            (true, true) => "flats+walls+caps",
+        }
+    }
+
+    pub fn try_from_str(s: &str) -> Option<Self> {
+        let (count_walls, count_capstones) = match s {
+            "flats" => (false, false),
+            "flats+walls" => (true, false),
+            "flats+caps" => (false, true),
+            "flats+walls+caps" => (true, true),
+            _ => return None,
+        };
+        Some(TakScoringRules {
+            count_walls,
+            count_capstones,
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TakStones {
@@ -48,7 +137,8 @@ impl TakStones {
         TakStones { stones, capstones }
     }
 
-    pub fn from_size(size: usize) -> Self {
+    /// The standard piece count for a board size, if the size is supported.
+    pub fn from_size(size: usize) -> Option<Self> {
         let (stones, capstones) = match size {
             3 => (10, 0),
             4 => (15, 0),
@@ -56,9 +146,16 @@ impl TakStones {
             6 => (30, 1),
             7 => (40, 2),
             8 => (50, 2),
-            _ => panic!("Invalid Tak board size"),
+            _ => return None,
         };
-        TakStones::new(stones, capstones)
+        Some(TakStones::new(stones, capstones))
+    }
+
+    /// Checks that a custom piece count makes a playable game on a board of the given size.
+    /// Each player needs at least one stone and enough pieces to span the board with a road,
+    /// and may have at most one capstone per two rows.
+    pub fn is_valid_for(&self, size: usize) -> bool {
+        self.stones > 0 && self.capstones <= size / 2 && self.stones + self.capstones >= size
     }
 }
 
@@ -70,6 +167,16 @@ pub struct TakGameSettings {
     pub komi: TakKomi,
     pub time_mode: Option<TakTimeMode>,
     pub start_position: TakTps,
+    /// Opt-in draw rule: the game is drawn once this many plies have been played.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_plies: Option<usize>,
+    /// Opt-in draw rule: the game is drawn after this many plies in a row without a placement.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_plies_without_placement: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub opening: TakOpening,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub scoring: TakScoringRules,
 }
 
 impl TakGameSettings {
@@ -81,10 +188,14 @@ impl TakGameSettings {
     ) -> Self {
         TakGameSettings {
             size,
-            stones: stones.unwrap_or_else(|| TakStones::from_size(size)),
+            stones: stones.unwrap_or_else(|| Self::default_stones(size)),
             komi,
             time_mode,
             start_position: TakTps::new_empty(size),
+            max_plies: None,
+            max_plies_without_placement: None,
+            opening: TakOpening::Swap,
+            scoring: TakScoringRules::default(),
         }
     }
 
@@ -97,13 +208,66 @@ impl TakGameSettings {
     ) -> Self {
         TakGameSettings {
             size,
-            stones: stones.unwrap_or_else(|| TakStones::from_size(size)),
+            stones: stones.unwrap_or_else(|| Self::default_stones(size)),
             komi,
             time_mode,
             start_position,
+            max_plies: None,
+            max_plies_without_placement: None,
+            opening: TakOpening::Swap,
+            scoring: TakScoringRules::default(),
         }
     }
 
+    pub fn with_move_limits(
+        mut self,
+        max_plies: Option<usize>,
+        max_plies_without_placement: Option<usize>,
+    ) -> Self {
+        self.max_plies = max_plies;
+        self.max_plies_without_placement = max_plies_without_placement;
+        self
+    }
+
+    pub fn with_opening(mut self, opening: TakOpening) -> Self {
+        self.opening = opening;
+        self
+    }
+
+    pub fn with_scoring(mut self, scoring: TakScoringRules) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Settings that are written to PTN as plain tags: the tag name, its value and whether the
+    /// setting is at its default. Defaults are only written if the tag was there already.
+    pub(crate) fn ptn_setting_tags(&self) -> Vec<(&'static str, String, bool)> {
+        let limit = |limit: Option<usize>| limit.map_or(String::new(), |x| x.to_string());
+        vec![
+            (
+                "Opening",
+                self.opening.to_str().to_string(),
+                self.opening == TakOpening::Swap,
+            ),
+            ("MaxPlies", limit(self.max_plies), self.max_plies.is_none()),
+            (
+                "MaxPliesWithoutPlacement",
+                limit(self.max_plies_without_placement),
+                self.max_plies_without_placement.is_none(),
+            ),
+            (
+                "Scoring",
+                self.scoring.to_str().to_string(),
+                self.scoring == TakScoringRules::default(),
+            ),
+        ]
+    }
+
+    /// Unsupported sizes get no stones, which `TakGame::new` rejects.
+    fn default_stones(size: usize) -> TakStones {
+        TakStones::from_size(size).unwrap_or(TakStones::new(0, 0))
+    }
+
     pub fn validate(&self) -> bool {
         TakGame::new(self.clone()).is_some()
     }
@@ -161,6 +325,49 @@ impl TakHand {
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TakPlayerMaterial {
+    pub stones_in_hand: usize,
+    pub capstones_in_hand: usize,
+    pub flats_on_board: usize,
+    pub walls_on_board: usize,
+    pub capstones_on_board: usize,
+    /// Pieces covered by another piece. Capstones can never be covered.
+    pub buried: usize,
+}
+
+impl TakPlayerMaterial {
+    pub fn total_stones(&self) -> usize {
+        self.stones_in_hand + self.flats_on_board + self.walls_on_board + self.buried
+    }
+
+    pub fn total_capstones(&self) -> usize {
+        self.capstones_in_hand + self.capstones_on_board
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TakMaterialSummary {
+    pub players: [TakPlayerMaterial; 2],
+}
+
+impl TakMaterialSummary {
+    pub fn get(&self, player: TakPlayer) -> &TakPlayerMaterial {
+        &self.players[player.index()]
+    }
+}
+
+/// Number of legal actions in a position, split by kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TakMoveStats {
+    pub placements: usize,
+    pub spreads: usize,
+    pub total: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TakGame {
@@ -172,6 +379,10 @@ pub struct TakGame {
     pub hands: [TakHand; 2],
     pub game_state: TakGameState,
     pub clock: Option<TakClock>,
+    /// Tags of the PTN the game was read from that don't affect play, written back by
+    /// `to_ptn`. The `Result` tag is kept up to date with the game.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ptn_tags: Vec<TakPtnAttr>,
 }
 
 impl TakGame {
@@ -180,7 +391,7 @@ impl TakGame {
         if size < 3 || size > 8 {
             return None;
         }
-        if settings.stones.stones == 0 {
+        if !settings.stones.is_valid_for(size) {
             return None;
         }
         let board = TakBoard::try_from_partial_tps(&settings.start_position.position)?;
@@ -215,6 +426,7 @@ impl TakGame {
             hands,
             game_state: TakGameState::Ongoing,
             clock,
+            ptn_tags: Vec::new(),
             settings,
         })
     }
@@ -230,8 +442,45 @@ impl TakGame {
         };
     }
 
+    /// Ends the game in favor of the opponent of the resigning player. Returns false if the game
+    /// was already over.
+    pub fn resign(&mut self, player: TakPlayer) -> bool {
+        self.check_timeout();
+        if self.game_state != TakGameState::Ongoing {
+            return false;
+        }
+        self.game_state = TakGameState::Win(player.other(), TakWinReason::Resignation);
+        true
+    }
+
+    /// Ends the game in favor of the opponent of a player who left it.
+    pub fn abandon(&mut self, player: TakPlayer) {
+        self.check_timeout();
+        if self.game_state != TakGameState::Ongoing {
+            return;
+        }
+        self.game_state = TakGameState::Win(player.other(), TakWinReason::Abandonment);
+    }
+
+    /// Whether the player to move has to place a flat of the opponent's color. Walls,
+    /// capstones and spreads aren't allowed on these plies.
+    pub fn is_swap_ply(&self) -> bool {
+        self.settings.opening == TakOpening::Swap && self.ply_index < 2
+    }
+
     pub fn reset(&mut self) {
+        let time_source = self.clock.as_ref().map(|clock| clock.time_source.clone());
         *self = TakGame::new(self.settings.clone()).expect("Game should be valid");
+        if let Some(time_source) = time_source {
+            self.set_time_source(time_source);
+        }
+    }
+
+    /// Makes the clock read the current time from the given source, e.g. a `MockTime` in tests.
+    pub fn set_time_source(&mut self, time_source: TakTimeSource) {
+        if let Some(clock) = &mut self.clock {
+            clock.time_source = time_source;
+        }
     }
 
     pub fn get_time_remaining(&self, player: TakPlayer, apply_elapsed: bool) -> Option<u64> {
@@ -247,6 +496,34 @@ impl TakGame {
         }
     }
 
+    /// Returns the player to move the first time their time left drops to the clock's warning
+    /// threshold.
+    pub fn time_warning(&mut self) -> Option<TakPlayer> {
+        if self.game_state != TakGameState::Ongoing {
+            return None;
+        }
+        let player = self.current_player;
+        let clock = self.clock.as_mut()?;
+        clock
+            .check_warning_at(player, clock.now())
+            .then_some(player)
+    }
+
+    /// Sets the time left of a player as measured right now. For the player to move, the time
+    /// spent since the last move is added back, since the clock keeps running from there.
+    pub fn sync_time_remaining(&mut self, player: TakPlayer, time_remaining: u64) {
+        let to_move = player == self.current_player && self.game_state == TakGameState::Ongoing;
+        if let Some(clock) = &mut self.clock {
+            let spent = if to_move {
+                clock.charged(clock.elapsed_at(clock.now()))
+            } else {
+                0
+            };
+            clock.set_time_remaining(player, time_remaining + spent);
+            self.check_timeout();
+        }
+    }
+
     pub fn check_timeout(&mut self) -> bool {
         if self.game_state != TakGameState::Ongoing {
             return false;
@@ -265,6 +542,15 @@ impl TakGame {
         false
     }
 
+    /// Checks if the action took the last piece from a player's reserve.
+    /// Only placements draw from a reserve, so a hand that was already empty doesn't end the game.
+    fn emptied_reserve(&self, record: &TakActionRecord) -> bool {
+        match record {
+            TakActionRecord::PlacePiece { player, .. } => self.hands[player.index()].is_empty(),
+            TakActionRecord::MovePiece { .. } => false,
+        }
+    }
+
     fn on_end_move(&mut self, record: TakActionRecord) {
         let affected_positions = match &record {
             TakActionRecord::PlacePiece { pos, .. } => vec![*pos],
@@ -281,18 +567,13 @@ impl TakGame {
             }
         };
 
-        if let Some(_road) = self
-            .board
-            .check_for_road(&affected_positions, self.current_player)
-        {
-            self.game_state = TakGameState::Win(self.current_player, TakWinReason::Road);
-        } else if let Some(_road) = self
+        if let Some((winner, _road)) = self
             .board
-            .check_for_road(&affected_positions, self.current_player.other())
+            .check_for_new_road(&affected_positions, self.current_player)
         {
-            self.game_state = TakGameState::Win(self.current_player.other(), TakWinReason::Road);
-        } else if !self.board.has_empty_space() || self.hands.iter().any(TakHand::is_empty) {
-            let counts = self.board.count_flats();
+            self.game_state = TakGameState::Win(winner, TakWinReason::Road);
+        } else if !self.board.has_empty_space() || self.emptied_reserve(&record) {
+            let counts = self.board.count_flats(&self.settings.scoring);
             if let Some(winner) = self.settings.komi.determine_winner(counts) {
                 self.game_state = TakGameState::Win(winner, TakWinReason::Flat);
             } else {
@@ -301,39 +582,114 @@ impl TakGame {
         }
 
         self.action_history.push(record);
+        if self.game_state == TakGameState::Ongoing && self.reached_move_limit() {
+            self.game_state = TakGameState::Draw(TakDrawReason::MoveLimit);
+        }
         self.ply_index += 1;
         self.current_player = self.current_player.other();
     }
 
+    /// Counts the moves at the end of the history that were spreads rather than placements.
+    pub fn plies_since_placement(&self) -> usize {
+        self.action_history
+            .iter()
+            .rev()
+            .take_while(|record| matches!(record, TakActionRecord::MovePiece { .. }))
+            .count()
+    }
+
+    fn reached_move_limit(&self) -> bool {
+        self.settings
+            .max_plies
+            .is_some_and(|max| self.action_history.len() >= max)
+            || self
+                .settings
+                .max_plies_without_placement
+                .is_some_and(|max| self.plies_since_placement() >= max)
+    }
+
+    /// Returns the game as it was at the given ply index. Ply indices count from the start of
+    /// the game, so for games started from a position they begin at the position's ply index.
     pub fn seek_ply_index(&self, ply_index: usize) -> Option<Self> {
-        if ply_index > self.ply_index {
+        let start = self.settings.start_position.get_ply_index();
+        if ply_index > self.ply_index || ply_index < start {
             return None;
         }
         let mut game = self.clone();
         if ply_index < self.ply_index {
             game.game_state = TakGameState::Ongoing;
         }
-        game.action_history.truncate(ply_index);
+        game.action_history.truncate(ply_index - start);
         let ptn = game.to_ptn();
-        Some(TakGame::try_from_ptn(ptn).expect("Should be able to seek to ply index"))
+        TakGame::try_from_ptn(ptn).ok()
+    }
+
+    /// Takes back the last ply by replaying the ones before it. The player whose move is undone
+    /// gets back the time they spent on it and is on the clock again. Returns false if the game
+    /// is over or there is nothing to undo. Since the earlier plies are replayed, undoing an
+    /// opening placement also returns the stone to the hand of its owner, not the mover.
+    pub fn undo_last_action(&mut self) -> bool {
+        if self.game_state != TakGameState::Ongoing {
+            return false;
+        }
+        let Some((last, history)) = self.action_history.split_last() else {
+            return false;
+        };
+        let Some(mut game) = TakGame::new(self.settings.clone()) else {
+            return false;
+        };
+        game.clock = None;
+        for record in history {
+            if game.try_do_action_record(record).is_err() {
+                return false;
+            }
+        }
+
+        let mut clock = self.clock.take();
+        if let Some(clock) = &mut clock {
+            if let Some(time_remaining) = last.time_remaining() {
+                let spent = clock.charged(last.elapsed().unwrap_or(0));
+                clock.set_time_remaining(game.current_player, time_remaining + spent);
+            }
+            clock.last_update_timestamp = if history.is_empty() {
+                None
+            } else {
+                Some(clock.now())
+            };
+        }
+        game.clock = clock;
+        game.ptn_tags = std::mem::take(&mut self.ptn_tags);
+        *self = game;
+        true
+    }
+
+    /// Returns the game right after the given player's action in the given move, numbered from 1
+    /// like in PTN. In the opening swap the player is the one taking the turn, not the owner of
+    /// the stone placed. Returns `None` if that action isn't part of the game.
+    pub fn seek_move(&self, move_number: usize, player: TakPlayer) -> Option<Self> {
+        let ply_index = move_number.checked_sub(1)? * 2 + player.index() + 1;
+        if ply_index <= self.settings.start_position.get_ply_index() {
+            return None;
+        }
+        self.seek_ply_index(ply_index)
     }
 
     pub fn try_do_action(&mut self, action: TakAction) -> Result<(), TakInvalidActionError> {
         let current_player = self.current_player;
-        let (now, time_remaining) = if let Some(clock) = &mut self.clock {
-            let now = TakTimestamp::now();
+        let (now, time_remaining, elapsed) = if let Some(clock) = &mut self.clock {
+            let now = clock.now();
             let time_remaining = clock.get_time_remaining_at(current_player, now);
             if time_remaining == 0 {
                 self.game_state = TakGameState::Win(current_player.other(), TakWinReason::Timeout);
                 clock.set_time_remaining(current_player, 0);
             }
-            (Some(now), Some(time_remaining))
+            (Some(now), Some(time_remaining), Some(clock.elapsed_at(now)))
         } else {
-            (None, None)
+            (None, None, None)
         };
         match action {
             TakAction::PlacePiece { pos, variant } => self
-                .try_place(pos, variant, time_remaining)
+                .try_place(pos, variant, time_remaining, elapsed)
                 .map_err(TakInvalidActionError::InvalidPlace),
             TakAction::MovePiece {
                 pos,
@@ -341,7 +697,7 @@ impl TakGame {
                 take,
                 drops,
             } => self
-                .try_move(pos, dir, take, &drops, time_remaining)
+                .try_move(pos, dir, take, &drops, time_remaining, elapsed)
                 .map_err(TakInvalidActionError::InvalidMove),
         }?;
         if let Some(clock) = &mut self.clock {
@@ -364,7 +720,7 @@ impl TakGame {
         }
         match action {
             TakAction::PlacePiece { pos, variant } => self
-                .try_place(pos, variant, time_remaining)
+                .try_place(pos, variant, time_remaining, record.elapsed())
                 .map_err(TakInvalidActionError::InvalidPlace),
             TakAction::MovePiece {
                 pos,
@@ -372,7 +728,7 @@ impl TakGame {
                 take,
                 drops,
             } => self
-                .try_move(pos, dir, take, &drops, time_remaining)
+                .try_move(pos, dir, take, &drops, time_remaining, record.elapsed())
                 .map_err(TakInvalidActionError::InvalidMove),
         }?;
         Ok(())
@@ -387,13 +743,14 @@ impl TakGame {
         pos: TakCoord,
         variant: TakPieceVariant,
         time_remaining: Option<u64>,
+        elapsed: Option<u64>,
     ) -> Result<(), TakInvalidPlaceError> {
         if self.game_state != TakGameState::Ongoing {
             return Err(TakInvalidPlaceError::NotAllowed);
         }
 
         self.board.can_place(pos)?;
-        let player = if self.ply_index < 2 {
+        let player = if self.is_swap_ply() {
             if variant != TakPieceVariant::Flat {
                 return Err(TakInvalidPlaceError::InvalidVariant);
             }
@@ -411,6 +768,7 @@ impl TakGame {
             variant,
             player,
             time_remaining,
+            elapsed,
         };
         self.on_end_move(record);
         Ok(())
@@ -423,12 +781,13 @@ impl TakGame {
         take: usize,
         drops: &[usize],
         time_remaining: Option<u64>,
+        elapsed: Option<u64>,
     ) -> Result<(), TakInvalidMoveError> {
         if self.game_state != TakGameState::Ongoing {
             return Err(TakInvalidMoveError::NotAllowed);
         }
 
-        if self.ply_index < 2 {
+        if self.is_swap_ply() {
             return Err(TakInvalidMoveError::NotAllowed);
         }
         let flattened = self.board.try_move(pos, dir, take, drops)?;
@@ -439,11 +798,38 @@ impl TakGame {
             drops: drops.to_vec(),
             flattened,
             time_remaining,
+            elapsed,
         };
         self.on_end_move(record);
         Ok(())
     }
 
+    /// Counts the pieces of each player, both in hand and on the board.
+    pub fn material_summary(&self) -> TakMaterialSummary {
+        let mut summary = TakMaterialSummary::default();
+        for player in TakPlayer::ALL {
+            let material = &mut summary.players[player.index()];
+            material.stones_in_hand = self.hands[player.index()].stones;
+            material.capstones_in_hand = self.hands[player.index()].capstones;
+        }
+        for (_, stack) in self.board.iter_pieces(None) {
+            let top = stack.composition.len() - 1;
+            for (i, piece) in stack.composition.iter().enumerate() {
+                let material = &mut summary.players[piece.player.index()];
+                if i < top {
+                    material.buried += 1;
+                    continue;
+                }
+                match stack.variant {
+                    TakPieceVariant::Flat => material.flats_on_board += 1,
+                    TakPieceVariant::Wall => material.walls_on_board += 1,
+                    TakPieceVariant::Capstone => material.capstones_on_board += 1,
+                }
+            }
+        }
+        summary
+    }
+
     pub fn to_tps(&self) -> TakTps {
         TakTps::new(self.board.to_partial_tps(), self.ply_index)
     }
@@ -460,62 +846,253 @@ impl TakGame {
             TakPtnAttr::Flats(self.settings.stones.stones),
             TakPtnAttr::Caps(self.settings.stones.capstones),
         ];
+        if let Some(komi_target) = self.settings.komi.komi_target {
+            attributes.push(TakPtnAttr::KomiTarget(komi_target));
+        }
         if let Some(time_mode) = &self.settings.time_mode {
-            attributes.push(TakPtnAttr::Clock(time_mode.time, time_mode.increment));
+            attributes.push(TakPtnAttr::Clock(time_mode.clone()));
         }
         if self.settings.start_position != TakTps::new_empty(self.board.size) {
             attributes.push(TakPtnAttr::TPS(self.settings.start_position.clone()));
         }
         let mut ptn = TakPtn::new(
             turns,
-            self.settings.start_position.move_index,
+            self.settings.start_position.get_ply_index(),
             self.game_state.clone(),
         );
         ptn.attributes = attributes;
+        let mut tags = self.ptn_tags.clone();
+        let setting_tags = self.settings.ptn_setting_tags();
+        for (name, _, is_default) in &setting_tags {
+            let has_tag = tags
+                .iter()
+                .any(|tag| matches!(tag, TakPtnAttr::Other(tag_name, _) if tag_name == name));
+            if !is_default && !has_tag {
+                tags.push(TakPtnAttr::Other(name.to_string(), String::new()));
+            }
+        }
+        for tag in tags {
+            let tag = match tag {
+                TakPtnAttr::Other(name, _) if name == "Result" => {
+                    TakPtnAttr::Other(name, ptn.game_state_to_str())
+                }
+                TakPtnAttr::Other(name, value) => {
+                    let value = setting_tags
+                        .iter()
+                        .find(|(setting, ..)| *setting == name)
+                        .map_or(value, |(_, setting_value, _)| setting_value.clone());
+                    TakPtnAttr::Other(name, value)
+                }
+                tag => tag,
+            };
+            ptn.attributes.push(tag);
+        }
+        for (ply, record) in self.action_history.iter().enumerate() {
+            if let Some(elapsed) = record.elapsed() {
+                ptn.set_elapsed(ply, elapsed);
+            }
+        }
         ptn
     }
 
-    pub fn try_from_ptn(ptn: TakPtn) -> Option<Self> {
-        let settings = ptn.get_settings()?;
-        let mut game = Self::new(settings)?;
+    pub fn try_from_ptn(ptn: TakPtn) -> Result<Self, PtnReplayError> {
+        let mut game = Self::replay_ptn(&ptn)?;
+
+        if let TakGameState::Win(_, TakWinReason::Timeout) = &ptn.game_state {
+            game.game_state = ptn.game_state;
+        } else if ptn.game_state != game.game_state {
+            return Err(PtnReplayError::WrongResult {
+                claimed: ptn.game_state,
+                actual: game.game_state,
+            });
+        }
+
+        Ok(game)
+    }
+
+    /// Plays through the moves of a PTN without looking at its recorded result.
+    pub(crate) fn replay_ptn(ptn: &TakPtn) -> Result<Self, PtnReplayError> {
+        let settings = ptn.get_settings().ok_or(PtnReplayError::InvalidSettings)?;
+        let start = settings.start_position.get_ply_index();
+        let mut game = Self::new(settings).ok_or(PtnReplayError::InvalidSettings)?;
+        game.ptn_tags = ptn.extra_tags();
 
         let mut actions = Vec::new();
         for (i, (_, white_turn, black_turn)) in ptn.turns.iter().enumerate() {
-            if let Some(white_turn) = white_turn {
-                actions.push(TakAction::from_ptn(&white_turn)?);
-            } else if i != 0 {
-                return None;
+            let elapsed = |slot: usize| {
+                ptn.annotations
+                    .get(i)
+                    .and_then(|annotations| annotations[slot].comment.as_deref())
+                    .and_then(crate::parse_elapsed)
+            };
+            for (slot, turn) in [white_turn, black_turn].into_iter().enumerate() {
+                let ply = start + actions.len();
+                match turn {
+                    Some(turn) => {
+                        let action = TakAction::from_ptn(turn)
+                            .ok_or(PtnReplayError::InvalidNotation { ply })?;
+                        actions.push((action, elapsed(slot)));
+                    }
+                    // Only the first move may skip white and only the last may skip black.
+                    None if (slot == 0 && i == 0) || (slot == 1 && i == ptn.turns.len() - 1) => {}
+                    None => return Err(PtnReplayError::MissingMove { ply }),
+                }
             }
-            if let Some(black_turn) = black_turn {
-                actions.push(TakAction::from_ptn(&black_turn)?);
-            } else if i != ptn.turns.len() - 1 {
-                return None;
+        }
+
+        for (action, elapsed) in actions {
+            let (ply, player) = (game.ply_index, game.current_player);
+            game.try_do_action(action)
+                .map_err(|error| PtnReplayError::IllegalAction { ply, player, error })?;
+            if let Some(record) = game.action_history.last_mut() {
+                record.set_elapsed(elapsed);
             }
         }
 
-        for action in actions {
-            let res = game.try_do_action(action);
-            if let Err(e) = res {
-                eprintln!(
-                    "Error applying action to game: {}, error: {:?}",
-                    game.board.to_partial_tps(),
-                    e
-                );
-                return None;
+        Ok(game)
+    }
+
+    /// The result of a flat count, komi included, if the game were scored in the current
+    /// position. Roads are ignored, so this is never a road win.
+    pub fn projected_result(&self) -> TakGameState {
+        match self
+            .settings
+            .komi
+            .determine_winner(self.board.count_flats(&self.settings.scoring))
+        {
+            Some(winner) => TakGameState::Win(winner, TakWinReason::Flat),
+            None => TakGameState::Draw(TakDrawReason::Flat),
+        }
+    }
+
+    pub fn legal_actions(&self) -> Vec<TakAction> {
+        crate::gen_moves(self, &Vec::new())
+    }
+
+    /// Returns the number of legal actions without allocating them.
+    pub fn count_legal_actions(&self) -> usize {
+        crate::count_moves(self)
+    }
+
+    /// Counts the legal actions of the current position, i.e. its branching factor.
+    pub fn move_stats(&self) -> TakMoveStats {
+        let mut stats = TakMoveStats::default();
+        for action in self.legal_actions() {
+            match action {
+                TakAction::PlacePiece { .. } => stats.placements += 1,
+                TakAction::MovePiece { .. } => stats.spreads += 1,
             }
         }
+        stats.total = stats.placements + stats.spreads;
+        stats
+    }
 
-        if let TakGameState::Win(_, TakWinReason::Timeout) = &ptn.game_state {
-            game.game_state = ptn.game_state;
-        } else if ptn.game_state != game.game_state {
-            eprintln!(
-                "PTN game state does not match actual game state: {:?} != {:?}",
-                ptn.game_state, game.game_state
-            );
+    /// Plays the action on a copy of the game without a clock, for looking ahead.
+    fn after_action(&self, action: TakAction) -> Option<Self> {
+        let mut game = self.clone();
+        game.clock = None;
+        game.try_do_action(action).ok()?;
+        Some(game)
+    }
+
+    fn wins_by_road(&self, player: TakPlayer) -> bool {
+        self.game_state == TakGameState::Win(player, TakWinReason::Road)
+    }
+
+    /// Checks if the player to move can complete a road with their next action.
+    pub fn is_tak(&self) -> bool {
+        let player = self.current_player;
+        self.legal_actions().into_iter().any(|action| {
+            self.after_action(action)
+                .is_some_and(|game| game.wins_by_road(player))
+        })
+    }
+
+    /// Checks if the player to move is in tinue, i.e. loses to a forced road within `max_depth`
+    /// plies whatever they play. Only lines where every non-winning attacking move creates a tak
+    /// are searched.
+    pub fn is_tinue(&self, max_depth: usize) -> bool {
+        self.game_state == TakGameState::Ongoing
+            && max_depth > 0
+            && self.cannot_defend(self.current_player.other(), max_depth)
+    }
+
+    /// Returns a forced road win for the player to move within `max_depth` plies, ending with the
+    /// road. Where the opponent loses whatever they do, the line continues with their first reply
+    /// that doesn't end the game.
+    pub fn tinue_line(&self, max_depth: usize) -> Option<Vec<TakActionRecord>> {
+        if self.game_state != TakGameState::Ongoing {
             return None;
         }
+        let attacker = self.current_player;
+        let mut game = self.clone();
+        let mut depth = max_depth;
+        let mut line = Vec::new();
+        loop {
+            let action = game.road_win(attacker, depth)?;
+            game = game.after_action(action)?;
+            line.extend(game.get_last_action().cloned());
+            if game.game_state != TakGameState::Ongoing {
+                return Some(line);
+            }
+            let Some((_, next)) = game
+                .successors()
+                .into_iter()
+                .find(|(_, next)| next.game_state == TakGameState::Ongoing)
+            else {
+                return Some(line);
+            };
+            game = next;
+            line.extend(game.get_last_action().cloned());
+            depth -= 2;
+        }
+    }
 
-        Some(game)
+    fn successors(&self) -> Vec<(TakAction, Self)> {
+        self.legal_actions()
+            .into_iter()
+            .filter_map(|action| Some((action.clone(), self.after_action(action)?)))
+            .collect()
+    }
+
+    /// Finds the attacker's move that forces a road within `depth` plies.
+    fn road_win(&self, attacker: TakPlayer, depth: usize) -> Option<TakAction> {
+        if depth == 0 {
+            return None;
+        }
+        let successors = self.successors();
+        if let Some((action, _)) = successors
+            .iter()
+            .find(|(_, game)| game.wins_by_road(attacker))
+        {
+            return Some(action.clone());
+        }
+        if depth < 3 {
+            return None;
+        }
+        successors
+            .into_iter()
+            .find(|(_, game)| {
+                game.game_state == TakGameState::Ongoing
+                    && game.threatens_road()
+                    && game.cannot_defend(attacker, depth - 1)
+            })
+            .map(|(action, _)| action)
+    }
+
+    fn cannot_defend(&self, attacker: TakPlayer, depth: usize) -> bool {
+        self.successors().iter().all(|(_, game)| {
+            game.wins_by_road(attacker)
+                || (game.game_state == TakGameState::Ongoing
+                    && game.road_win(attacker, depth - 1).is_some())
+        })
+    }
+
+    /// Checks if the player who just moved would have a tak if it were their turn again.
+    fn threatens_road(&self) -> bool {
+        let mut game = self.clone();
+        game.current_player = game.current_player.other();
+        game.is_tak()
     }
 
     pub fn validate(&self) -> Result<(), String> {
@@ -563,6 +1140,22 @@ impl TakGame {
             ));
         }
 
+        let white_road = self.board.has_road(TakPlayer::White);
+        let black_road = self.board.has_road(TakPlayer::Black);
+        if white_road && black_road {
+            // A single spread can complete roads for both players, in which case the
+            // player who moved wins. Any other combination can't come from real play.
+            let last_mover = self.current_player.other();
+            if self.game_state != TakGameState::Win(last_mover, TakWinReason::Road) {
+                return Err(format!(
+                    "Both players have a road, but game state is {:?}",
+                    self.game_state
+                ));
+            }
+        } else if (white_road || black_road) && self.game_state == TakGameState::Ongoing {
+            return Err("A player has a road, but the game is still ongoing".to_string());
+        }
+
         Ok(())
     }
 }
@@ -570,67 +1163,13 @@ impl TakGame {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{MockTime, TakTimestamp};
+
+    const PLAYTAK_PTN: &str = include_str!("../tests/playtak.ptn");
 
     #[test]
     fn test_from_ptn() {
-        let ptn = r#"
-[Site "PlayTak.com"]
-[Event "Online Play"]
-[Date "2025.06.26"]
-[Time "19:32:14"]
-[Player1 "Abyss"]
-[Rating1 "2100"]
-[Player2 "alion02"]
-[Rating2 "2240"]
-[Clock "15:0 +10"]
-[Result "0-F"]
-[Size "6"]
-[Komi "2"]
-[Flats "30"]
-[Caps "1"]
-[Opening "swap"]
-
-1. a6 f1
-2. d3 c4
-3. d4 d5
-4. c3 b3
-5. c5 b4
-6. c2 b2
-7. c1 Cd2
-8. b1 d1
-9. c6 d2<
-10. Cb5 d2
-11. e3 e2
-12. f3 a4
-13. b5- a3
-14. 2b4- a2
-15. 3b3- a1
-16. b1< b4
-17. f2 2c2+
-18. e5 e2+
-19. a5 f4
-20. e4 3c3>
-21. c3 d6
-22. c1> e6
-23. f5 4d3+
-24. 4b2+13 f6
-25. 4b4> c2
-26. Sd3 b2
-27. e2 b5
-28. b4 b1
-29. b4+ b4
-30. c1 b6
-31. e2< d6<
-32. c5+ b6>
-33. Sb6 Sd6
-34. b6> Sb6
-35. 4c6- e1
-36. c1+ d6<
-37. d3> d6
-38. 3e3-12 b6-
-39. c1 b6
-40. 5c4< c4 0-F
-"#;
+        let ptn = PLAYTAK_PTN;
         let ptn = TakPtn::try_from_str(ptn).expect("Failed to parse PTN");
         let game = TakGame::try_from_ptn(ptn).expect("Failed to create game from PTN");
         assert_eq!(game.board.size, 6);
@@ -648,4 +1187,838 @@ mod tests {
             "2,2,12S,2,2,2/1,212S,2121S,2,1,1/2,222221C,2,11112C,1,2/2,2,1,x2,1/2,2,21,21,1,1/21,2,1,21,221S,1 1 41"
         );
     }
+
+    fn game_from_position(tps: &str) -> TakGame {
+        let tps = TakTps::try_from_str(tps).unwrap();
+        let size = TakBoard::try_from_partial_tps(&tps.position).unwrap().size;
+        let settings = TakGameSettings::new_with_position(size, tps, None, TakKomi::none(), None);
+        TakGame::new(settings).unwrap()
+    }
+
+    #[test]
+    fn test_validate_both_roads() {
+        let mut game = game_from_position("1,1,1,1/2,2,2,2/x4/x4 1 5");
+        assert!(game.validate().is_err());
+
+        game.game_state = TakGameState::Win(TakPlayer::White, TakWinReason::Road);
+        assert!(game.validate().is_err());
+
+        game.game_state = TakGameState::Win(TakPlayer::Black, TakWinReason::Road);
+        assert!(game.validate().is_ok());
+    }
+
+    #[test]
+    fn test_double_road_goes_to_mover() {
+        for (tps, winner) in [
+            ("1,1,x/2,2,x/x2,121 1 10", TakPlayer::White),
+            ("2,2,x/1,1,x/x2,212 2 10", TakPlayer::Black),
+            // Only the road of the opponent is completed, so they win even on the mover's turn.
+            ("1,1S,x/2,2,x/x2,121 1 10", TakPlayer::Black),
+        ] {
+            let mut game = game_from_position(tps);
+            game.try_do_action(TakAction::from_ptn("3c1+21").unwrap())
+                .unwrap();
+            assert_eq!(
+                game.game_state,
+                TakGameState::Win(winner, TakWinReason::Road)
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_road_while_ongoing() {
+        let mut game = game_from_position("1,1,1,1/2,2,x2/x4/x4 2 4");
+        assert!(game.validate().is_err());
+
+        game.game_state = TakGameState::Win(TakPlayer::White, TakWinReason::Road);
+        assert!(game.validate().is_ok());
+
+        let game = game_from_position("1,1,1,x/2,2,x2/x4/x4 1 4");
+        assert!(game.validate().is_ok());
+    }
+
+    #[test]
+    fn test_playtak_headers_roundtrip() {
+        let headers = PLAYTAK_PTN
+            .lines()
+            .filter(|line| line.starts_with('['))
+            .collect::<Vec<_>>();
+        let ptn = TakPtn::try_from_str(PLAYTAK_PTN).unwrap();
+        assert!(
+            ptn.to_str()
+                .starts_with(&format!("{}\n", headers.join("\n")))
+        );
+
+        let game = TakGame::try_from_ptn(ptn).unwrap();
+        let output = game.to_ptn().to_str();
+        let written = output
+            .lines()
+            .filter(|line| line.starts_with('['))
+            .collect::<Vec<_>>();
+        assert!(headers.iter().all(|header| written.contains(header)));
+        let is_setting = |header: &&&str| {
+            ["[Size ", "[Komi ", "[Flats ", "[Caps ", "[Clock "]
+                .iter()
+                .any(|prefix| header.starts_with(prefix))
+        };
+        assert!(
+            written
+                .iter()
+                .filter(|h| !is_setting(h))
+                .eq(headers.iter().filter(|h| !is_setting(h)))
+        );
+
+        let mut midgame = game.seek_ply_index(40).unwrap();
+        assert_eq!(midgame.to_ptn().tag("Result"), Some(""));
+        assert_eq!(midgame.to_ptn().tag("Player2"), Some("alion02"));
+        assert!(midgame.undo_last_action());
+        assert_eq!(midgame.to_ptn().tag("Opening"), Some("swap"));
+    }
+
+    #[test]
+    fn test_material_summary_totals() {
+        let ptn = TakPtn::try_from_str(PLAYTAK_PTN).unwrap();
+        let finished = TakGame::try_from_ptn(ptn).unwrap();
+        let mut game = TakGame::new(finished.settings.clone()).unwrap();
+        let stones = finished.settings.stones.clone();
+        for record in finished.action_history.iter() {
+            game.try_do_action(record.to_action()).unwrap();
+            let summary = game.material_summary();
+            for player in TakPlayer::ALL {
+                let material = summary.get(player);
+                assert_eq!(material.total_stones(), stones.stones);
+                assert_eq!(material.total_capstones(), stones.capstones);
+            }
+        }
+        let white = game.material_summary().get(TakPlayer::White).clone();
+        assert_eq!(
+            white.flats_on_board,
+            game.board.count_flats(&game.settings.scoring)[0]
+        );
+    }
+
+    #[test]
+    fn test_move_stats() {
+        let game = TakGame::new(TakGameSettings::new(5, None, TakKomi::none(), None)).unwrap();
+        assert_eq!(
+            game.move_stats(),
+            TakMoveStats {
+                placements: 25,
+                spreads: 0,
+                total: 25
+            }
+        );
+
+        let tps = TakTps::try_from_str("x5/x5/x2,1,x2/x5/x5 1 3").unwrap();
+        let settings = TakGameSettings::new_with_position(5, tps, None, TakKomi::none(), None);
+        let game = TakGame::new(settings).unwrap();
+        assert_eq!(
+            game.move_stats(),
+            TakMoveStats {
+                placements: 72,
+                spreads: 4,
+                total: 76
+            }
+        );
+
+        let ptn = TakPtn::try_from_str(PLAYTAK_PTN).unwrap();
+        let finished = TakGame::try_from_ptn(ptn).unwrap();
+        let midgame = finished
+            .seek_ply_index(finished.ply_index / 2)
+            .unwrap()
+            .move_stats();
+        assert!(midgame.spreads > 0);
+        assert_eq!(midgame.total, midgame.placements + midgame.spreads);
+        assert_eq!(finished.move_stats().total, 0);
+    }
+
+    #[test]
+    fn test_count_legal_actions() {
+        let ptn = TakPtn::try_from_str(PLAYTAK_PTN).unwrap();
+        let finished = TakGame::try_from_ptn(ptn).unwrap();
+        for ply in 0..=finished.ply_index {
+            let game = finished.seek_ply_index(ply).unwrap();
+            assert_eq!(game.count_legal_actions(), game.legal_actions().len());
+        }
+
+        for tps in [
+            "x5/x5/x5/x5/x5 1 1",
+            "x3/x,1,x/x3 2 2",
+            "x6/x,1S,x4/x,1112C,x,1S,x2/x,1,x4/x,1C,x4/x6 2 12",
+            "x2,2,22,2C,1/21221S,1112,x,2211,1,2/x2,111S,x,11S,12S/11S,1S,2S,2,12S,1211C/x,12S,2,122S,x,212S/12,x2,1S,22222S,21121 2 31",
+        ] {
+            let tps = TakTps::try_from_str(tps).unwrap();
+            let size = TakBoard::try_from_partial_tps(&tps.position).unwrap().size;
+            let settings =
+                TakGameSettings::new_with_position(size, tps, None, TakKomi::none(), None);
+            let game = TakGame::new(settings).unwrap();
+            assert_eq!(game.count_legal_actions(), game.legal_actions().len());
+        }
+    }
+
+    #[test]
+    fn test_normalized_piece_ids_match_tps_reload() {
+        let ptn = TakPtn::try_from_str(PLAYTAK_PTN).unwrap();
+        let game = TakGame::try_from_ptn(ptn).unwrap();
+        let mut reloaded = TakBoard::try_from_partial_tps(&game.board.to_partial_tps()).unwrap();
+        assert_ne!(game.board, reloaded);
+
+        let mut played = game.board.clone();
+        played.normalize_piece_ids();
+        reloaded.normalize_piece_ids();
+        assert_eq!(played, reloaded);
+        assert!(played.validate().is_ok());
+    }
+
+    #[test]
+    fn test_last_flat_fills_board() {
+        let tps = TakTps::try_from_str("1,2,1/2,1,2/2,1,x 1 5").unwrap();
+        let settings = TakGameSettings::new_with_position(
+            3,
+            tps,
+            Some(TakStones::new(5, 0)),
+            TakKomi::none(),
+            None,
+        );
+        let mut game = TakGame::new(settings).unwrap();
+        game.try_do_action(TakAction::PlacePiece {
+            pos: TakCoord::new(2, 0),
+            variant: TakPieceVariant::Flat,
+        })
+        .unwrap();
+        assert!(game.hands[0].is_empty());
+        assert_eq!(
+            game.game_state,
+            TakGameState::Win(TakPlayer::White, TakWinReason::Flat)
+        );
+    }
+
+    #[test]
+    fn test_wall_counting_changes_winner() {
+        let play = |scoring: TakScoringRules| {
+            let tps = TakTps::try_from_str("1S,2,1S/2,1S,2/1S,2,x 1 5").unwrap();
+            let settings = TakGameSettings::new_with_position(
+                3,
+                tps,
+                Some(TakStones::new(5, 0)),
+                TakKomi::none(),
+                None,
+            )
+            .with_scoring(scoring);
+            let mut game = TakGame::new(settings).unwrap();
+            game.try_do_action(TakAction::PlacePiece {
+                pos: TakCoord::new(2, 0),
+                variant: TakPieceVariant::Flat,
+            })
+            .unwrap();
+            game.game_state
+        };
+        assert_eq!(
+            play(TakScoringRules::default()),
+            TakGameState::Win(TakPlayer::Black, TakWinReason::Flat)
+        );
+        let walls = TakScoringRules {
+            count_walls: true,
+            count_capstones: false,
+        };
+        assert_eq!(
+            play(walls),
+            TakGameState::Win(TakPlayer::White, TakWinReason::Flat)
+        );
+    }
+
+    #[test]
+    fn test_scoring_roundtrips_through_ptn() {
+        let tps = TakTps::try_from_str("1S,2,1S/2,1S,2/1S,2,x 1 5").unwrap();
+        let scoring = TakScoringRules {
+            count_walls: true,
+            count_capstones: false,
+        };
+        let settings = TakGameSettings::new_with_position(
+            3,
+            tps,
+            Some(TakStones::new(5, 0)),
+            TakKomi::none(),
+            None,
+        )
+        .with_scoring(scoring);
+        let mut game = TakGame::new(settings).unwrap();
+        game.try_do_action(TakAction::from_ptn("c1").unwrap())
+            .unwrap();
+
+        let ptn = game.to_ptn();
+        assert_eq!(ptn.tag("Scoring"), Some("flats+walls"));
+        let reloaded = TakGame::try_from_ptn(ptn).unwrap();
+        assert_eq!(reloaded.settings.scoring, scoring);
+        assert_eq!(reloaded.game_state, game.game_state);
+        let start = game.settings.start_position.get_ply_index();
+        assert_eq!(
+            game.seek_ply_index(start).unwrap().settings.scoring,
+            scoring
+        );
+    }
+
+    #[test]
+    fn test_opponent_empty_reserve_does_not_end_game() {
+        let tps = TakTps::try_from_str("2,2,2,x/2,2,2,x/1,1,x2/x4 1 6").unwrap();
+        let settings = TakGameSettings::new_with_position(
+            4,
+            tps,
+            Some(TakStones::new(6, 0)),
+            TakKomi::none(),
+            None,
+        );
+        let mut game = TakGame::new(settings).unwrap();
+        assert!(game.hands[1].is_empty());
+        game.try_do_action(TakAction::PlacePiece {
+            pos: TakCoord::new(3, 0),
+            variant: TakPieceVariant::Flat,
+        })
+        .unwrap();
+        assert_eq!(game.game_state, TakGameState::Ongoing);
+    }
+
+    #[test]
+    fn test_komi_targeting_white() {
+        let komi = TakKomi::new(0, true).with_target(TakPlayer::White);
+        assert_eq!(
+            TakKomi::new(0, true).determine_winner([5, 5]),
+            Some(TakPlayer::Black)
+        );
+        assert_eq!(komi.determine_winner([5, 5]), Some(TakPlayer::White));
+
+        let komi = TakKomi::new(2, false).with_target(TakPlayer::White);
+        assert_eq!(komi.determine_winner([5, 7]), None);
+        assert_eq!(komi.determine_winner([5, 6]), Some(TakPlayer::White));
+
+        let board = TakBoard::try_from_partial_tps("1,2,x/x3/x3").unwrap();
+        assert_eq!(
+            board.flat_score(&komi, &TakScoringRules::default()),
+            (2.0, Some(TakPlayer::White))
+        );
+
+        let settings = TakGameSettings::new(3, None, komi.clone(), None);
+        let game = TakGame::new(settings).unwrap();
+        let ptn = game.to_ptn();
+        assert!(ptn.to_str().contains("[KomiTarget \"white\"]"));
+        let parsed = TakPtn::try_from_str(&ptn.to_str()).unwrap();
+        assert_eq!(parsed.get_settings().unwrap().komi, komi);
+    }
+
+    #[test]
+    fn test_is_tak() {
+        let game = game_from_position("1,1,1,1,x/x5/x5/2,2,2,x2/x5 2 5");
+        assert!(!game.is_tak());
+        let game = game_from_position("1,1,1,1,x/x5/x5/2,2,x3/x5 1 5");
+        assert!(game.is_tak());
+        let game = game_from_position("x,1,x3/x5/x5/2,x4/x5 1 2");
+        assert!(!game.is_tak());
+    }
+
+    #[test]
+    fn test_is_tinue() {
+        for (position, attacker) in [
+            ("1,1,1,x2/x4,1/x5/2,x,2,x,2/2,x4 1 5", TakPlayer::White),
+            ("2,2,2,x2/x4,2/x5/1,x,1,x,1/1,x4 2 5", TakPlayer::Black),
+        ] {
+            let game = game_from_position(position);
+            assert!(!game.is_tak());
+            assert!(!game.is_tinue(5));
+            let line = game.tinue_line(3).unwrap();
+            assert_eq!(line.len(), 3);
+            let mut played = game.clone();
+            for record in &line {
+                played.try_do_action(record.to_action()).unwrap();
+            }
+            assert_eq!(
+                played.game_state,
+                TakGameState::Win(attacker, TakWinReason::Road)
+            );
+
+            let mut defending = game.clone();
+            defending.try_do_action(line[0].to_action()).unwrap();
+            assert_eq!(defending.current_player, attacker.other());
+            assert!(defending.is_tinue(2));
+            assert!(!defending.is_tinue(1));
+        }
+
+        let game = game_from_position("x,1,x3/x5/x5/2,x4/x5 1 2");
+        assert!(!game.is_tinue(5));
+        assert!(game.tinue_line(5).is_none());
+    }
+
+    #[test]
+    fn test_elapsed_times_roundtrip() {
+        let time_mode = TakTimeMode::new(60, 2);
+        let settings = TakGameSettings::new(5, None, TakKomi::none(), Some(time_mode));
+        let mut game = TakGame::new(settings).unwrap();
+        for (mv, spent) in [
+            ("a1", 1500),
+            ("e5", 300),
+            ("c3", 12300),
+            ("b2", 4000),
+            ("d4", 50),
+        ] {
+            let now = TakTimestamp::now().millis;
+            game.clock.as_mut().unwrap().last_update_timestamp = Some(TakTimestamp {
+                millis: now - spent,
+            });
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+            assert!(game.get_last_action().unwrap().elapsed().unwrap() >= spent);
+        }
+
+        let clock = game.clock.as_ref().unwrap();
+        for player in TakPlayer::ALL {
+            let records = game.action_history.iter().skip(player.index()).step_by(2);
+            let moves = records.clone().count() as u64;
+            let spent: u64 = records.map(|record| record.elapsed().unwrap()).sum();
+            assert_eq!(
+                spent,
+                60_000 + moves * 2_000 - clock.time_remaining_millis[player.index()]
+            );
+        }
+
+        let ptn = game.to_ptn().to_str();
+        assert!(ptn.contains("c3 {+12.3"));
+        let parsed = TakGame::try_from_ptn(TakPtn::try_from_str(&ptn).unwrap()).unwrap();
+        let elapsed = |game: &TakGame| {
+            game.action_history
+                .iter()
+                .map(|record| record.elapsed())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(elapsed(&parsed), elapsed(&game));
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut game = game_from_position("1,1,x2/2,2,x2/x4/x4 1 3");
+        assert!(game.resign(TakPlayer::Black));
+        assert_eq!(
+            game.game_state,
+            TakGameState::Win(TakPlayer::White, TakWinReason::Resignation)
+        );
+        assert!(!game.resign(TakPlayer::White));
+    }
+
+    #[test]
+    fn test_resign_after_game_over() {
+        let mut game = game_from_position("1,1,1,x/2,2,x2/x4/x4 1 4");
+        game.try_do_action(TakAction::from_ptn("d4").unwrap())
+            .unwrap();
+        assert_eq!(
+            game.game_state,
+            TakGameState::Win(TakPlayer::White, TakWinReason::Road)
+        );
+        assert!(!game.resign(TakPlayer::White));
+        assert_eq!(
+            game.game_state,
+            TakGameState::Win(TakPlayer::White, TakWinReason::Road)
+        );
+    }
+
+    #[test]
+    fn test_undo_last_action() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), Some(TakTimeMode::new(60, 2)));
+        let mut game = TakGame::new(settings).unwrap();
+        for mv in ["a1", "e5", "c3"] {
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        let before = game.seek_ply_index(2).unwrap();
+        let white_time = game.clock.as_ref().unwrap().time_remaining_millis[0];
+
+        assert!(game.undo_last_action());
+        assert_eq!(game.ply_index, 2);
+        assert_eq!(game.current_player, TakPlayer::White);
+        assert_eq!(game.board, before.board);
+        assert_eq!(game.hands, before.hands);
+        assert_eq!(game.action_history, before.action_history);
+        let clock = game.clock.as_ref().unwrap();
+        assert!(clock.time_remaining_millis[0] < white_time);
+        assert!(clock.last_update_timestamp.is_some());
+
+        assert!(game.undo_last_action());
+        assert!(game.undo_last_action());
+        assert_eq!(game.ply_index, 0);
+        assert!(!game.undo_last_action());
+    }
+
+    #[test]
+    fn test_undo_opening_swap() {
+        let mut game = TakGame::new(TakGameSettings::new(5, None, TakKomi::none(), None)).unwrap();
+        let initial_hands = game.hands.clone();
+
+        game.try_do_action(TakAction::from_ptn("a1").unwrap())
+            .unwrap();
+        let a1 = game.board.try_get_stack(TakCoord::new(0, 0)).unwrap();
+        assert_eq!(a1.player(), TakPlayer::Black);
+        assert_eq!(game.hands[0], initial_hands[0]);
+        assert_eq!(game.hands[1].stones, initial_hands[1].stones - 1);
+        let after_first = game.clone();
+
+        game.try_do_action(TakAction::from_ptn("e5").unwrap())
+            .unwrap();
+        let e5 = game.board.try_get_stack(TakCoord::new(4, 4)).unwrap();
+        assert_eq!(e5.player(), TakPlayer::White);
+        assert_eq!(game.hands[0].stones, initial_hands[0].stones - 1);
+
+        assert!(game.undo_last_action());
+        assert_eq!(game.current_player, TakPlayer::Black);
+        assert_eq!(game.hands, after_first.hands);
+        assert_eq!(game.board, after_first.board);
+
+        assert!(game.undo_last_action());
+        assert_eq!(game.ply_index, 0);
+        assert_eq!(game.current_player, TakPlayer::White);
+        assert_eq!(game.hands, initial_hands);
+        assert!(game.board.try_get_stack(TakCoord::new(0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_replay_error_names_ply() {
+        let replay = |moves: &str| {
+            let ptn = TakPtn::try_from_str(&format!("[Size \"5\"]\n[Komi \"0\"]\n{}", moves));
+            TakGame::try_from_ptn(ptn.unwrap())
+        };
+        let error = replay("1. a1 e5\n2. Cc3 Cc2\n3. Cb3 d4\n").unwrap_err();
+        assert_eq!(
+            error,
+            PtnReplayError::IllegalAction {
+                ply: 4,
+                player: TakPlayer::White,
+                error: TakInvalidActionError::InvalidPlace(TakInvalidPlaceError::NotEnoughStones),
+            }
+        );
+        assert_eq!(error.to_string(), "ply 4: NotEnoughStones for White");
+
+        assert_eq!(
+            replay("1. a1 e5\n2. c3 ab\n").unwrap_err(),
+            PtnReplayError::InvalidNotation { ply: 3 }
+        );
+        assert_eq!(
+            replay("1. a1 e5\n2. c3 --\n3. d4\n").unwrap_err(),
+            PtnReplayError::MissingMove { ply: 3 }
+        );
+        assert!(matches!(
+            replay("1. a1 e5\n2. c3 R-0\n").unwrap_err(),
+            PtnReplayError::WrongResult { .. }
+        ));
+    }
+
+    #[test]
+    fn test_no_swap_opening() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), None).with_opening(TakOpening::NoSwap);
+        let mut game = TakGame::new(settings).unwrap();
+        assert!(!game.is_swap_ply());
+        game.try_do_action(TakAction::from_ptn("a1").unwrap())
+            .unwrap();
+        let a1 = game.board.try_get_stack(TakCoord::new(0, 0)).unwrap();
+        assert_eq!(a1.player(), TakPlayer::White);
+        assert!(
+            game.legal_actions()
+                .contains(&TakAction::from_ptn("Cc3").unwrap())
+        );
+
+        let ptn = game.to_ptn();
+        assert_eq!(ptn.tag("Opening"), Some("no-swap"));
+        let reloaded = TakGame::try_from_ptn(TakPtn::try_from_str(&ptn.to_str()).unwrap()).unwrap();
+        assert_eq!(reloaded.settings.opening, TakOpening::NoSwap);
+        assert_eq!(reloaded.board, game.board);
+
+        let ptn = TakPtn::try_from_str("[Size \"5\"]\n[Komi \"0\"]\n1. a1\n").unwrap();
+        let game = TakGame::try_from_ptn(ptn).unwrap();
+        assert_eq!(game.settings.opening, TakOpening::Swap);
+        let a1 = game.board.try_get_stack(TakCoord::new(0, 0)).unwrap();
+        assert_eq!(a1.player(), TakPlayer::Black);
+        assert_eq!(game.to_ptn().tag("Opening"), None);
+    }
+
+    #[test]
+    fn test_timeout_is_exact_to_the_millisecond() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), Some(TakTimeMode::new(60, 0)));
+        let move_after = |spent: u64| {
+            let mut game = TakGame::new(settings.clone()).unwrap();
+            game.set_time_remaining(TakPlayer::White, 500);
+            game.clock.as_mut().unwrap().last_update_timestamp = Some(TakTimestamp {
+                millis: TakTimestamp::now().millis - spent,
+            });
+            let _ = game.try_do_action(TakAction::from_ptn("a1").unwrap());
+            game
+        };
+
+        let game = move_after(600);
+        assert_eq!(
+            game.game_state,
+            TakGameState::Win(TakPlayer::Black, TakWinReason::Timeout)
+        );
+        assert_eq!(game.get_time_remaining(TakPlayer::White, false), Some(0));
+
+        let game = move_after(300);
+        assert_eq!(game.game_state, TakGameState::Ongoing);
+        assert!(game.get_time_remaining(TakPlayer::White, false).unwrap() <= 200);
+    }
+
+    #[test]
+    fn test_time_warning() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), Some(TakTimeMode::new(60, 0)));
+        let mut game = TakGame::new(settings).unwrap();
+        assert_eq!(game.time_warning(), None);
+        game.clock
+            .as_mut()
+            .unwrap()
+            .set_warning_threshold(Some(10_000));
+        assert_eq!(game.time_warning(), None);
+
+        game.clock.as_mut().unwrap().last_update_timestamp = Some(TakTimestamp {
+            millis: TakTimestamp::now().millis - 55_000,
+        });
+        assert_eq!(game.time_warning(), Some(TakPlayer::White));
+        assert_eq!(game.time_warning(), None);
+    }
+
+    #[test]
+    fn test_mock_time_times_out_once() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), Some(TakTimeMode::new(60, 0)));
+        let mut game = TakGame::new(settings).unwrap();
+        let time = MockTime::new(1_000_000);
+        game.set_time_source(TakTimeSource::new(time.clone()));
+        for mv in ["a1", "e5"] {
+            time.advance(10_000);
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        let white = game.get_time_remaining(TakPlayer::White, true);
+        assert_eq!(white, Some(60_000));
+        let black = game.get_time_remaining(TakPlayer::Black, false);
+        assert_eq!(black, Some(50_000));
+
+        time.advance(59_999);
+        assert!(!game.check_timeout());
+        assert_eq!(game.get_time_remaining(TakPlayer::White, true), Some(1));
+        time.advance(1);
+        assert!(game.check_timeout());
+        assert_eq!(
+            game.game_state,
+            TakGameState::Win(TakPlayer::Black, TakWinReason::Timeout)
+        );
+        time.advance(60_000);
+        assert!(!game.check_timeout());
+        assert_eq!(game.get_time_remaining(TakPlayer::Black, false), black);
+
+        game.reset();
+        game.try_do_action(TakAction::from_ptn("a1").unwrap())
+            .unwrap();
+        time.advance(60_000);
+        assert!(game.check_timeout());
+    }
+
+    #[test]
+    fn test_undo_after_game_over() {
+        let mut game = game_from_position("1,1,1,x/2,2,x2/x4/x4 1 4");
+        game.try_do_action(TakAction::from_ptn("d4").unwrap())
+            .unwrap();
+        let ply_index = game.ply_index;
+        assert!(!game.undo_last_action());
+        assert_eq!(game.ply_index, ply_index);
+        assert_eq!(game.action_history.len(), 1);
+    }
+
+    #[test]
+    fn test_placement_drought_draws() {
+        let settings =
+            TakGameSettings::new(4, None, TakKomi::none(), None).with_move_limits(None, Some(4));
+        let mut game = TakGame::new(settings).unwrap();
+        let play = |game: &mut TakGame, moves: &[&str]| {
+            for mv in moves {
+                game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                    .unwrap();
+            }
+        };
+
+        // After the swap opening white owns d4 and black owns a1.
+        play(&mut game, &["a1", "d4", "d4<", "a1>"]);
+        assert_eq!(game.plies_since_placement(), 2);
+        play(&mut game, &["b2", "b1<", "c4<"]);
+        assert_eq!(game.game_state, TakGameState::Ongoing);
+        play(&mut game, &["a1>"]);
+        assert_eq!(game.plies_since_placement(), 3);
+        play(&mut game, &["b4>"]);
+        assert_eq!(
+            game.game_state,
+            TakGameState::Draw(TakDrawReason::MoveLimit)
+        );
+
+        let mut unlimited =
+            TakGame::new(TakGameSettings::new(4, None, TakKomi::none(), None)).unwrap();
+        play(
+            &mut unlimited,
+            &["a1", "d4", "d4<", "a1>", "c4>", "b1<", "d4<", "a1>"],
+        );
+        assert_eq!(unlimited.game_state, TakGameState::Ongoing);
+    }
+
+    #[test]
+    fn test_max_plies_draws() {
+        let settings =
+            TakGameSettings::new(4, None, TakKomi::none(), None).with_move_limits(Some(3), None);
+        let mut game = TakGame::new(settings).unwrap();
+        for mv in ["a1", "d4"] {
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        assert_eq!(game.game_state, TakGameState::Ongoing);
+        game.try_do_action(TakAction::from_ptn("b2").unwrap())
+            .unwrap();
+        assert_eq!(
+            game.game_state,
+            TakGameState::Draw(TakDrawReason::MoveLimit)
+        );
+
+        let reloaded = TakGame::try_from_ptn(game.to_ptn()).unwrap();
+        assert_eq!(reloaded.settings, game.settings);
+        assert_eq!(reloaded.game_state, game.game_state);
+        assert_eq!(game.seek_ply_index(1).unwrap().settings.max_plies, Some(3));
+    }
+
+    #[test]
+    fn test_projected_result_even_board() {
+        let game = game_from_position("1,2,x/x3/2,1,x 1 3");
+        assert_eq!(
+            game.projected_result(),
+            TakGameState::Draw(TakDrawReason::Flat)
+        );
+
+        let settings = TakGameSettings::new_with_position(
+            3,
+            TakTps::try_from_str("1,2,x/x3/2,1,x 1 3").unwrap(),
+            None,
+            TakKomi::new(0, true),
+            None,
+        );
+        let game = TakGame::new(settings).unwrap();
+        assert_eq!(
+            game.projected_result(),
+            TakGameState::Win(TakPlayer::Black, TakWinReason::Flat)
+        );
+    }
+
+    #[test]
+    fn test_projected_result_leader() {
+        let game = game_from_position("1,1,1S,1/2,2,x2/1,x3/x4 2 5");
+        assert_eq!(
+            game.projected_result(),
+            TakGameState::Win(TakPlayer::White, TakWinReason::Flat)
+        );
+    }
+
+    #[test]
+    fn test_projected_result_ignores_roads() {
+        let game = game_from_position("1,1,1,1/2,2,2,2/2,x3/2,x3 2 6");
+        assert!(game.board.has_road(TakPlayer::White));
+        assert_eq!(
+            game.projected_result(),
+            TakGameState::Win(TakPlayer::Black, TakWinReason::Flat)
+        );
+    }
+
+    #[test]
+    fn test_custom_stones_rejects_zero_stones() {
+        let settings = TakGameSettings::new(5, Some(TakStones::new(0, 1)), TakKomi::none(), None);
+        assert!(TakGame::new(settings).is_none());
+    }
+
+    #[test]
+    fn test_custom_stones_on_5x5() {
+        let settings = TakGameSettings::new(5, Some(TakStones::new(30, 2)), TakKomi::none(), None);
+        let game = TakGame::new(settings).unwrap();
+        assert_eq!(game.hands[0], TakHand::new(30, 2));
+        assert_eq!(game.hands[1], TakHand::new(30, 2));
+
+        for stones in [TakStones::new(30, 3), TakStones::new(3, 1)] {
+            let settings = TakGameSettings::new(5, Some(stones), TakKomi::none(), None);
+            assert!(!settings.validate());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_size_is_rejected() {
+        assert_eq!(TakStones::from_size(9), None);
+        assert_eq!(TakStones::from_size(6), Some(TakStones::new(30, 1)));
+        let settings = TakGameSettings::new(9, None, TakKomi::none(), None);
+        assert!(!settings.validate());
+    }
+
+    #[test]
+    fn test_seek_move_from_tps() {
+        let ptn = TakPtn::try_from_str(
+            r#"
+[Size "4"]
+[Komi "0"]
+[TPS "2,x3/x4/x4/1,x3 2 3"]
+
+3. -- d4
+4. b1 c3
+5. c1
+"#,
+        )
+        .unwrap();
+        let game = TakGame::try_from_ptn(ptn).unwrap();
+        assert_eq!(game.ply_index, 9);
+
+        let after = |move_number, player| {
+            game.seek_move(move_number, player)
+                .map(|game| game.to_tps().to_string())
+        };
+        assert_eq!(
+            after(3, TakPlayer::Black),
+            Some("2,x2,2/x4/x4/1,x3 1 4".to_string())
+        );
+        assert_eq!(
+            after(4, TakPlayer::White),
+            Some("2,x2,2/x4/x4/1,1,x2 2 4".to_string())
+        );
+        assert_eq!(after(5, TakPlayer::White), Some(game.to_tps().to_string()));
+        assert_eq!(after(3, TakPlayer::White), None);
+        assert_eq!(after(5, TakPlayer::Black), None);
+        assert_eq!(after(0, TakPlayer::White), None);
+    }
+
+    #[test]
+    fn test_to_ptn_from_mid_position() {
+        let ptn = TakPtn::try_from_str(
+            r#"
+[Size "4"]
+[Komi "0"]
+[TPS "2,x3/x4/x4/1,x3 2 3"]
+
+3. -- d4
+4. b1 c3
+5. c1
+"#,
+        )
+        .unwrap();
+        let mut game = TakGame::try_from_ptn(ptn).unwrap();
+        let moves = |game: &TakGame| {
+            game.to_ptn()
+                .to_str()
+                .lines()
+                .filter(|line| !line.starts_with('['))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(moves(&game), ["3. -- d4", "4. b1 c3", "5. c1"]);
+
+        game.game_state = TakGameState::Win(TakPlayer::White, TakWinReason::Resignation);
+        assert_eq!(moves(&game), ["3. -- d4", "4. b1 c3", "5. c1 1-0"]);
+
+        let reparsed = TakPtn::try_from_str(&game.to_ptn().to_str()).unwrap();
+        assert_eq!(TakGame::try_from_ptn(reparsed).unwrap().ply_index, 9);
+    }
 }
@@ -30,6 +30,7 @@ impl GameRecord {
             let is_mismatch_allowed = game.game_state == TakGameState::Ongoing
                 && match self.result {
                     TakGameState::Win(_, TakWinReason::Resignation) => true,
+                    TakGameState::Win(_, TakWinReason::Abandonment) => true,
                     TakGameState::Draw(TakDrawReason::Agreement) => true,
                     TakGameState::Canceled => true,
                     _ => false,
@@ -45,3 +46,118 @@ impl GameRecord {
         game
     }
 }
+
+/// Plays back the moves of a game with the pauses between them, for watching a finished game
+/// unfold. Each item is the delay in milliseconds before the move and the move itself.
+/// Moves with a recorded thinking time wait that long, the others wait a fixed interval. Both
+/// are divided by the speed.
+#[derive(Debug, Clone)]
+pub struct GameReplay {
+    actions: std::vec::IntoIter<TakActionRecord>,
+    speed: f64,
+    interval_millis: u64,
+}
+
+impl GameReplay {
+    pub const DEFAULT_INTERVAL_MILLIS: u64 = 1000;
+
+    pub fn new(game: &TakGame) -> Self {
+        Self {
+            actions: game.action_history.clone().into_iter(),
+            speed: 1.0,
+            interval_millis: Self::DEFAULT_INTERVAL_MILLIS,
+        }
+    }
+
+    /// Plays recorded times this many times faster, e.g. 2.0 for double speed. Speeds that
+    /// aren't positive and finite are ignored.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        if speed.is_finite() && speed > 0.0 {
+            self.speed = speed;
+        }
+        self
+    }
+
+    /// Sets the delay before moves without a recorded time, at normal speed.
+    pub fn with_interval(mut self, interval_millis: u64) -> Self {
+        self.interval_millis = interval_millis;
+        self
+    }
+}
+
+impl Iterator for GameReplay {
+    type Item = (u64, TakActionRecord);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let action = self.actions.next()?;
+        let delay = action.elapsed().unwrap_or(self.interval_millis);
+        let delay = (delay as f64 / self.speed).round() as u64;
+        Some((delay, action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{TakAction, TakKomi};
+
+    use super::*;
+
+    fn game(elapsed: &[Option<u64>]) -> TakGame {
+        let settings = TakGameSettings::new(5, None, TakKomi::none(), None);
+        let mut game = TakGame::new(settings).unwrap();
+        for mv in ["a1", "e5", "c3", "c4"] {
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        for (record, elapsed) in game.action_history.iter_mut().zip(elapsed) {
+            record.set_elapsed(*elapsed);
+        }
+        game
+    }
+
+    #[test]
+    fn test_replay_without_times_uses_interval() {
+        let game = game(&[None; 4]);
+        let replay = GameReplay::new(&game).with_interval(500);
+        assert!(replay.map(|(delay, _)| delay).all(|delay| delay == 500));
+        let replay = GameReplay::new(&game).with_speed(4.0).with_interval(500);
+        let items = replay.collect::<Vec<_>>();
+        assert_eq!(items.len(), 4);
+        assert!(items.iter().all(|(delay, _)| *delay == 125));
+        let moves = items
+            .iter()
+            .map(|(_, record)| record.to_ptn())
+            .collect::<Vec<_>>();
+        assert_eq!(moves, ["a1", "e5", "c3", "c4"]);
+    }
+
+    #[test]
+    fn test_replay_scales_recorded_times() {
+        let game = game(&[Some(0), Some(3000), Some(1500), None]);
+        let delays = GameReplay::new(&game)
+            .map(|(delay, _)| delay)
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [0, 3000, 1500, GameReplay::DEFAULT_INTERVAL_MILLIS]);
+
+        let delays = GameReplay::new(&game)
+            .with_speed(2.0)
+            .map(|(delay, _)| delay)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [0, 1500, 750, GameReplay::DEFAULT_INTERVAL_MILLIS / 2]
+        );
+
+        for speed in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+            let delays = GameReplay::new(&game)
+                .with_speed(2.0)
+                .with_speed(speed)
+                .map(|(delay, _)| delay)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                delays,
+                [0, 1500, 750, GameReplay::DEFAULT_INTERVAL_MILLIS / 2]
+            );
+        }
+    }
+}
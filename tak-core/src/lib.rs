@@ -1,20 +1,30 @@
 mod action;
 mod board;
+#[cfg(feature = "serde")]
+mod bytes;
 mod coord;
 mod game;
 mod movegen;
 mod ptn;
+#[cfg(feature = "svg")]
+mod svg;
 mod time;
 mod tps;
 mod ui;
 mod history;
+#[cfg(feature = "serde")]
+pub mod live_game;
 
 pub use action::*;
 pub use board::*;
+#[cfg(feature = "serde")]
+pub use bytes::*;
 pub use coord::*;
 pub use game::*;
 pub use movegen::*;
 pub use ptn::*;
+#[cfg(feature = "svg")]
+pub use svg::*;
 pub use time::*;
 pub use tps::*;
 pub use ui::*;
@@ -81,6 +91,8 @@ pub enum TakInvalidMoveError {
 pub enum TakInvalidActionError {
     InvalidPlace(TakInvalidPlaceError),
     InvalidMove(TakInvalidMoveError),
+    /// The move couldn't be read as PTN.
+    InvalidNotation,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -90,6 +102,8 @@ pub enum TakWinReason {
     Flat,
     Timeout,
     Resignation,
+    /// The player left the game and didn't reconnect in time.
+    Abandonment,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -97,6 +111,7 @@ pub enum TakWinReason {
 pub enum TakDrawReason {
     Flat,
     Agreement,
+    MoveLimit,
 }
 
 #[derive(Debug, Clone, PartialEq)]
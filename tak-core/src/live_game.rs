@@ -0,0 +1,97 @@
+//! Serde representation of a game in progress for sending it to another machine, used as
+//! `#[serde(with = "tak_core::live_game")]`.
+//!
+//! The clock of a `TakGame` stamps its last update with the wall clock of the machine that
+//! made the move, and the time left is derived from the difference to the current time.
+//! Machines don't agree on the current time, so the plain representation shows a different
+//! time left on each of them. This one stores how long the player to move has been thinking
+//! instead, and the receiver restarts the clock from its own current time minus that. Time
+//! spent in transit isn't charged to anyone.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{TakGame, TakGameState, TakTimestamp};
+
+#[derive(Serialize, Deserialize)]
+struct LiveGame {
+    game: TakGame,
+    /// Time since the last update of a running clock, or `None` if it isn't running.
+    elapsed_millis: Option<u64>,
+}
+
+pub fn serialize<S: Serializer>(game: &TakGame, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut game = game.clone();
+    let mut elapsed_millis = None;
+    if game.game_state == TakGameState::Ongoing
+        && let Some(clock) = &mut game.clock
+        && clock.last_update_timestamp.is_some()
+    {
+        elapsed_millis = Some(clock.elapsed_at(clock.now()));
+        clock.last_update_timestamp = None;
+    }
+    LiveGame {
+        game,
+        elapsed_millis,
+    }
+    .serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TakGame, D::Error> {
+    let LiveGame {
+        mut game,
+        elapsed_millis,
+    } = LiveGame::deserialize(deserializer)?;
+    if let (Some(clock), Some(elapsed)) = (&mut game.clock, elapsed_millis) {
+        let now = clock.now();
+        clock.last_update_timestamp = Some(TakTimestamp {
+            millis: now.millis.saturating_sub(elapsed),
+        });
+    }
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{TakAction, TakGameSettings, TakKomi, TakPlayer, TakTimeMode};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Wrapper(#[serde(with = "crate::live_game")] TakGame);
+
+    #[test]
+    fn test_clock_survives_round_trip() {
+        let settings =
+            TakGameSettings::new(5, None, TakKomi::none(), Some(TakTimeMode::new(60, 2)));
+        let mut game = TakGame::new(settings).unwrap();
+        for mv in ["a1", "e5", "c3"] {
+            game.try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        // Black has been thinking for three seconds, as stamped by another machine.
+        let clock = game.clock.as_mut().unwrap();
+        clock.last_update_timestamp = Some(TakTimestamp {
+            millis: TakTimestamp::now().millis - 3000,
+        });
+
+        let json = serde_json::to_string(&Wrapper(game.clone())).unwrap();
+        let Wrapper(loaded) = serde_json::from_str(&json).unwrap();
+        for player in TakPlayer::ALL {
+            let is_current = player == game.current_player;
+            let before = game.get_time_remaining(player, is_current).unwrap();
+            let after = loaded.get_time_remaining(player, is_current).unwrap();
+            assert!(before.abs_diff(after) < 100, "{before} vs {after}");
+        }
+        let stored = loaded.get_time_remaining(TakPlayer::Black, false).unwrap();
+        let running = loaded.get_time_remaining(TakPlayer::Black, true).unwrap();
+        assert!(stored - running >= 2900, "{stored} vs {running}");
+        assert_eq!(loaded.board, game.board);
+        assert_eq!(loaded.action_history, game.action_history);
+
+        // A clock that hasn't started stays stopped.
+        let fresh = TakGame::new(game.settings.clone()).unwrap();
+        let json = serde_json::to_string(&Wrapper(fresh)).unwrap();
+        let Wrapper(loaded) = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.clock.unwrap().last_update_timestamp, None);
+    }
+}
@@ -16,14 +16,14 @@ pub fn gen_moves(game: &TakGame, partition_memo: &Vec<Vec<Vec<Vec<usize>>>>) ->
                     pos,
                     variant: TakPieceVariant::Flat,
                 });
-                if game.ply_index >= 2 {
+                if !game.is_swap_ply() {
                     moves.push(TakAction::PlacePiece {
                         pos,
                         variant: TakPieceVariant::Wall,
                     });
                 }
             }
-            if hand.capstones > 0 && game.ply_index >= 2 {
+            if hand.capstones > 0 && !game.is_swap_ply() {
                 moves.push(TakAction::PlacePiece {
                     pos,
                     variant: TakPieceVariant::Capstone,
@@ -32,7 +32,7 @@ pub fn gen_moves(game: &TakGame, partition_memo: &Vec<Vec<Vec<Vec<usize>>>>) ->
         }
     }
 
-    if game.ply_index < 2 {
+    if game.is_swap_ply() {
         return moves;
     }
 
@@ -80,6 +80,78 @@ pub fn gen_moves(game: &TakGame, partition_memo: &Vec<Vec<Vec<Vec<usize>>>>) ->
     moves
 }
 
+/// Counts the actions `gen_moves` would generate without building them. Spreads are counted
+/// per direction from how far the stack can travel, so no drop sequences are enumerated.
+pub fn count_moves(game: &TakGame) -> usize {
+    if game.game_state != TakGameState::Ongoing {
+        return 0;
+    }
+
+    let player = game.current_player;
+    let hand = &game.hands[player.index()];
+    let opening = game.is_swap_ply();
+    let mut placements_per_square = 0;
+    if hand.stones > 0 {
+        placements_per_square += if opening { 1 } else { 2 };
+    }
+    if hand.capstones > 0 && !opening {
+        placements_per_square += 1;
+    }
+    let empty_squares = TakCoord::iter_board(game.board.size)
+        .filter(|&pos| game.board.can_place(pos).is_ok())
+        .count();
+    let mut count = empty_squares * placements_per_square;
+
+    if opening {
+        return count;
+    }
+
+    for (pos, stack) in game.board.iter_pieces(Some(player)) {
+        let max_take = stack.height().min(game.board.size);
+        for &dir in &TakDir::ALL {
+            let mut distance = 0;
+            let mut can_smash = false;
+            loop {
+                let next = pos.offset_dir_many(dir, distance as i32 + 1);
+                if !next.is_valid(game.board.size) {
+                    break;
+                }
+                match game.board.try_get_stack(next).map(|t| t.variant) {
+                    None | Some(TakPieceVariant::Flat) => distance += 1,
+                    Some(TakPieceVariant::Wall) => {
+                        can_smash = stack.variant == TakPieceVariant::Capstone;
+                        break;
+                    }
+                    Some(TakPieceVariant::Capstone) => break,
+                }
+            }
+            for take in 1..=max_take {
+                for drop_len in 1..=take.min(distance) {
+                    count += compositions(take, drop_len);
+                }
+                if can_smash && distance < take {
+                    // The capstone flattens the wall alone, so the other pieces are spread
+                    // over the squares before it.
+                    count += compositions(take - 1, distance);
+                }
+            }
+        }
+    }
+
+    count
+}
+
+/// Number of ways to split `num` pieces into `parts` non-empty drops.
+fn compositions(num: usize, parts: usize) -> usize {
+    if parts == 0 {
+        return usize::from(num == 0);
+    }
+    if num < parts {
+        return 0;
+    }
+    (0..parts - 1).fold(1, |acc, i| acc * (num - 1 - i) / (i + 1))
+}
+
 pub fn compute_partition_memo(max_take: usize) -> Vec<Vec<Vec<Vec<usize>>>> {
     let mut partition_memo = Vec::new();
     for take in 0..=max_take {
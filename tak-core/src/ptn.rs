@@ -1,16 +1,21 @@
 use crate::{
-    TakDrawReason, TakGameSettings, TakGameState, TakKomi, TakPlayer, TakStones, TakTimeMode,
-    TakTps, TakWinReason,
+    TakDrawReason, TakGameSettings, TakGameState, TakInvalidActionError, TakKomi, TakOpening,
+    TakPlayer, TakScoringRules, TakStones, TakTimeControl, TakTimeMode, TakTps, TakWinReason,
 };
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TakPtnAttr {
     Size(usize),
-    Clock(usize, usize),
+    Clock(TakTimeMode),
     Komi(usize, bool),
+    KomiTarget(TakPlayer),
     Flats(usize),
     Caps(usize),
     TPS(TakTps),
+    /// Any other `[Name "value"]` tag, kept so it survives a round trip.
+    Other(String, String),
+    /// A tag that isn't of the `[Name "value"]` form.
     Unknown(String),
 }
 
@@ -18,10 +23,17 @@ impl TakPtnAttr {
     pub fn to_str(&self) -> String {
         match self {
             TakPtnAttr::Size(size) => format!("[Size \"{}\"]", size),
-            TakPtnAttr::Clock(time, increment) => {
-                let mins = time / 60;
-                let secs = time % 60;
-                format!("[Clock \"{}:{} +{}\"]", mins, secs, increment)
+            TakPtnAttr::Clock(mode) => {
+                let mins = mode.time / 60;
+                let secs = mode.time % 60;
+                let extra = match mode.control {
+                    TakTimeControl::Fischer => format!("+{}", mode.increment),
+                    TakTimeControl::ByoYomi { periods } => {
+                        format!("{}x{}", periods, mode.increment)
+                    }
+                    TakTimeControl::Delay => format!("d{}", mode.increment),
+                };
+                format!("[Clock \"{}:{} {}\"]", mins, secs, extra)
             }
             TakPtnAttr::Komi(amount, tiebreak) => {
                 if *tiebreak {
@@ -30,9 +42,14 @@ impl TakPtnAttr {
                     format!("[Komi \"{}\"]", amount)
                 }
             }
+            TakPtnAttr::KomiTarget(player) => match player {
+                TakPlayer::White => "[KomiTarget \"white\"]".to_string(),
+                TakPlayer::Black => "[KomiTarget \"black\"]".to_string(),
+            },
             TakPtnAttr::Flats(flats) => format!("[Flats \"{}\"]", flats),
             TakPtnAttr::Caps(caps) => format!("[Caps \"{}\"]", caps),
             TakPtnAttr::TPS(attr) => format!("[TPS \"{}\"]", attr.to_string()),
+            TakPtnAttr::Other(name, value) => format!("[{} \"{}\"]", name, value),
             TakPtnAttr::Unknown(attr) => format!("[{}]", attr),
         }
     }
@@ -41,7 +58,15 @@ impl TakPtnAttr {
         if str.is_empty() || !str.starts_with('[') || !str.ends_with(']') {
             return None;
         }
-        let patterns = ["Size", "Clock", "Komi", "Flats", "Caps", "TPS"];
+        let patterns = [
+            "Size",
+            "Clock",
+            "Komi",
+            "KomiTarget",
+            "Flats",
+            "Caps",
+            "TPS",
+        ];
         let mut matching = None;
 
         for pattern in patterns {
@@ -53,6 +78,13 @@ impl TakPtnAttr {
         }
         if matching.is_none() {
             let inner = &str[1..str.len() - 1];
+            if let Some((name, value)) = inner.split_once(" \"")
+                && let Some(value) = value.strip_suffix('"')
+                && !name.is_empty()
+                && !name.contains(char::is_whitespace)
+            {
+                return Some(TakPtnAttr::Other(name.to_string(), value.to_string()));
+            }
             return Some(TakPtnAttr::Unknown(inner.to_string()));
         }
         let (pattern, inner) = matching?;
@@ -60,17 +92,27 @@ impl TakPtnAttr {
         match pattern {
             "Size" => inner.parse::<usize>().ok().map(TakPtnAttr::Size),
             "Clock" => {
-                let parts = inner
-                    .split(|c| c == ':' || c == '+')
-                    .map(str::trim)
-                    .collect::<Vec<&str>>();
-                if parts.len() != 3 {
-                    return None;
-                }
-                let mins = parts[0].parse::<usize>().ok()?;
-                let secs = parts[1].parse::<usize>().ok()?;
-                let increment = parts[2].parse::<usize>().ok()?;
-                Some(TakPtnAttr::Clock(mins * 60 + secs, increment))
+                let (mins, rest) = inner.split_once(':')?;
+                let rest = rest.trim_start();
+                let secs_end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let time = mins.trim().parse::<usize>().ok()? * 60
+                    + rest[..secs_end].parse::<usize>().ok()?;
+                let extra = rest[secs_end..].trim();
+                let mode = if let Some(increment) = extra.strip_prefix('+') {
+                    TakTimeMode::new(time, increment.trim().parse().ok()?)
+                } else if let Some(delay) = extra.strip_prefix('d') {
+                    TakTimeMode::delay(time, delay.trim().parse().ok()?)
+                } else {
+                    let (periods, period) = extra.split_once('x')?;
+                    TakTimeMode::byo_yomi(
+                        time,
+                        periods.trim().parse().ok()?,
+                        period.trim().parse().ok()?,
+                    )
+                };
+                Some(TakPtnAttr::Clock(mode))
             }
             "Komi" => {
                 let num = inner.parse::<f32>().ok()?;
@@ -78,6 +120,11 @@ impl TakPtnAttr {
                 let num = num.trunc() as usize;
                 Some(TakPtnAttr::Komi(num, add_half))
             }
+            "KomiTarget" => match inner.to_lowercase().as_str() {
+                "white" => Some(TakPtnAttr::KomiTarget(TakPlayer::White)),
+                "black" => Some(TakPtnAttr::KomiTarget(TakPlayer::Black)),
+                _ => None,
+            },
             "Flats" => inner.parse::<usize>().ok().map(TakPtnAttr::Flats),
             "Caps" => inner.parse::<usize>().ok().map(TakPtnAttr::Caps),
             "TPS" => TakTps::try_from_str(inner).map(TakPtnAttr::TPS),
@@ -86,20 +133,148 @@ impl TakPtnAttr {
     }
 }
 
+/// Annotations attached to a single move: a glyph like `!`, `?` or `'` directly after the
+/// move, and a `{comment}` following it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TakPtnAnnotation {
+    pub glyph: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl TakPtnAnnotation {
+    fn to_str(&self) -> String {
+        let mut result = self.glyph.clone().unwrap_or_default();
+        if let Some(comment) = &self.comment {
+            result.push_str(&format!(" {{{}}}", comment));
+        }
+        result
+    }
+}
+
+/// Formats a move time as a PTN comment body, e.g. `+12.3s`.
+pub fn format_elapsed(millis: u64) -> String {
+    let fraction = format!("{:03}", millis % 1000);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("+{}s", millis / 1000)
+    } else {
+        format!("+{}.{}s", millis / 1000, fraction)
+    }
+}
+
+/// Parses a comment written by `format_elapsed` back into milliseconds. Anything after a comma,
+/// like an engine evaluation, is ignored.
+pub fn parse_elapsed(comment: &str) -> Option<u64> {
+    let comment = comment.split(',').next()?;
+    let seconds = comment.trim().strip_prefix('+')?.strip_suffix('s')?;
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let fraction = format!("{:0<3}", fraction).parse::<u64>().ok()?;
+    Some(whole.parse::<u64>().ok()? * 1000 + fraction)
+}
+
+/// Formats an engine evaluation as a PTN comment body, e.g. `eval: +1.2`. Positive values favor
+/// white.
+pub fn format_eval(eval: f64) -> String {
+    format!("eval: {:+.1}", eval)
+}
+
+/// Finds an evaluation written by `format_eval` in a comment.
+pub fn parse_eval(comment: &str) -> Option<f64> {
+    comment
+        .split(',')
+        .find_map(|part| part.trim().strip_prefix("eval:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+const GLYPH_CHARS: [char; 4] = ['!', '?', '\'', '"'];
+
+enum PtnToken {
+    Word(String),
+    Comment(String),
+}
+
+fn tokenize_moves(s: &str) -> Option<Vec<PtnToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '{' {
+            chars.next();
+            let mut comment = String::new();
+            loop {
+                match chars.next()? {
+                    '}' => break,
+                    c => comment.push(c),
+                }
+            }
+            tokens.push(PtnToken::Comment(comment));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '{' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(PtnToken::Word(word));
+        }
+    }
+    Some(tokens)
+}
+
+fn parse_result(token: &str) -> Option<TakGameState> {
+    match token {
+        "R-0" => Some(TakGameState::Win(TakPlayer::White, TakWinReason::Road)),
+        "0-R" => Some(TakGameState::Win(TakPlayer::Black, TakWinReason::Road)),
+        "F-0" => Some(TakGameState::Win(TakPlayer::White, TakWinReason::Flat)),
+        "0-F" => Some(TakGameState::Win(TakPlayer::Black, TakWinReason::Flat)),
+        "1-0" => Some(TakGameState::Win(TakPlayer::White, TakWinReason::Timeout)),
+        "0-1" => Some(TakGameState::Win(TakPlayer::Black, TakWinReason::Timeout)),
+        "1/2-1/2" => Some(TakGameState::Draw(TakDrawReason::Flat)),
+        _ => None,
+    }
+}
+
+/// How the result recorded in a PTN differs from the result of replaying its moves.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultMismatch {
+    InvalidTag(String),
+    IllegalGame,
+    WrongWinner {
+        claimed: TakGameState,
+        actual: TakGameState,
+    },
+    WrongReason {
+        claimed: TakWinReason,
+        actual: TakWinReason,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct TakPtn {
     pub attributes: Vec<TakPtnAttr>,
     pub turns: Vec<(usize, Option<String>, Option<String>)>,
+    /// Annotations of the white and black move of each turn, parallel to `turns`.
+    pub annotations: Vec<[TakPtnAnnotation; 2]>,
     pub game_state: TakGameState,
 }
 
 impl TakPtn {
-    pub fn new(turns: Vec<String>, first_turn_index: usize, game_state: TakGameState) -> Self {
+    /// Groups moves into numbered turns. `first_ply_index` is the ply of the first move, so a
+    /// game starting with Black to move opens with a `--` placeholder for White.
+    pub fn new(turns: Vec<String>, first_ply_index: usize, game_state: TakGameState) -> Self {
         assert!(
             game_state == TakGameState::Ongoing || !turns.is_empty(),
             "At least one turn is required"
         );
-        let mut turn_vec = if first_turn_index % 2 == 0 {
+        let mut turn_vec = if first_ply_index % 2 == 0 {
             Vec::new()
         } else {
             vec![None]
@@ -111,64 +286,179 @@ impl TakPtn {
             .map(|(i, chunk)| {
                 let white_turn = chunk.get(0).cloned().flatten();
                 let black_turn = chunk.get(1).cloned().flatten();
-                (i + first_turn_index / 2, white_turn, black_turn)
+                (i + first_ply_index / 2, white_turn, black_turn)
             })
-            .collect();
+            .collect::<Vec<_>>();
         TakPtn {
             attributes: Vec::new(),
+            annotations: vec![Default::default(); turns.len()],
             turns,
             game_state,
         }
     }
 
+    /// Returns the annotation of the given move, counting from the first move in the PTN.
+    pub fn annotation_mut(&mut self, ply: usize) -> Option<&mut TakPtnAnnotation> {
+        let skipped = self
+            .turns
+            .first()
+            .is_some_and(|(_, white_turn, _)| white_turn.is_none());
+        let index = ply + skipped as usize;
+        self.annotations
+            .get_mut(index / 2)
+            .map(|annotations| &mut annotations[index % 2])
+    }
+
     pub fn get_settings(&self) -> Option<TakGameSettings> {
         let mut size = None;
         let mut komi = None;
+        let mut komi_target = None;
         let mut flats = None;
         let mut caps = None;
-        let mut clock = None;
+        let mut time_mode = None;
         let mut tps = None;
         for attr in &self.attributes {
             match attr {
                 TakPtnAttr::Size(s) => size = Some(*s),
                 TakPtnAttr::Komi(k, add_half) => komi = Some((*k, *add_half)),
+                TakPtnAttr::KomiTarget(player) => komi_target = Some(*player),
                 TakPtnAttr::Flats(f) => flats = Some(*f),
                 TakPtnAttr::Caps(c) => caps = Some(*c),
-                TakPtnAttr::Clock(time, increment) => clock = Some((*time, *increment)),
+                TakPtnAttr::Clock(mode) => time_mode = Some(mode.clone()),
                 TakPtnAttr::TPS(t) => tps = Some(t.clone()),
-                TakPtnAttr::Unknown(_) => {}
+                TakPtnAttr::Other(..) | TakPtnAttr::Unknown(_) => {}
             }
         }
         if size.is_some() && komi.is_some() {
-            let time_mode = clock.map(|(time, increment)| TakTimeMode::new(time, increment));
-            let mut stones = TakStones::from_size(size.unwrap());
+            let mut komi = TakKomi::new(komi.unwrap().0, komi.unwrap().1);
+            komi.komi_target = komi_target;
+            let mut stones = TakStones::from_size(size.unwrap())?;
             if let Some(flats) = flats {
                 stones.stones = flats;
             }
             if let Some(caps) = caps {
                 stones.capstones = caps;
             }
-            if let Some(tps) = tps {
-                return Some(TakGameSettings::new_with_position(
+            let settings = match tps {
+                Some(tps) => TakGameSettings::new_with_position(
                     size.unwrap(),
                     tps,
                     Some(stones),
-                    TakKomi::new(komi.unwrap().0, komi.unwrap().1),
+                    komi,
                     time_mode,
-                ));
-            }
-            Some(TakGameSettings::new(
-                size.unwrap(),
-                Some(stones),
-                TakKomi::new(komi.unwrap().0, komi.unwrap().1),
-                time_mode,
-            ))
+                ),
+                None => TakGameSettings::new(size.unwrap(), Some(stones), komi, time_mode),
+            };
+            let opening = self
+                .tag("Opening")
+                .and_then(TakOpening::try_from_str)
+                .unwrap_or_default();
+            let scoring = self
+                .tag("Scoring")
+                .and_then(TakScoringRules::try_from_str)
+                .unwrap_or_default();
+            let limit = |name| self.tag(name).and_then(|limit| limit.parse().ok());
+            Some(
+                settings
+                    .with_opening(opening)
+                    .with_scoring(scoring)
+                    .with_move_limits(limit("MaxPlies"), limit("MaxPliesWithoutPlacement")),
+            )
         } else {
             None
         }
     }
 
-    fn game_state_to_str(&self) -> String {
+    /// The tags without a dedicated variant, like players, event and result, in their order.
+    pub fn extra_tags(&self) -> Vec<TakPtnAttr> {
+        self.attributes
+            .iter()
+            .filter(|attr| matches!(attr, TakPtnAttr::Other(..) | TakPtnAttr::Unknown(_)))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the value of the first tag with the given name that has no dedicated variant.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find_map(|attr| match attr {
+            TakPtnAttr::Other(tag, value) if tag == name => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Marks the game as played by an engine searching to the given depth.
+    pub fn set_engine_info(&mut self, engine: &str, depth: usize) {
+        self.attributes
+            .retain(|attr| !matches!(attr, TakPtnAttr::Other(tag, _) if tag == "Engine" || tag == "EngineDepth"));
+        self.attributes
+            .push(TakPtnAttr::Other("Engine".to_string(), engine.to_string()));
+        self.attributes.push(TakPtnAttr::Other(
+            "EngineDepth".to_string(),
+            depth.to_string(),
+        ));
+    }
+
+    /// Writes the time spent on the given move at the start of its comment, keeping any comment
+    /// that is already there.
+    pub fn set_elapsed(&mut self, ply: usize, millis: u64) {
+        let Some(annotation) = self.annotation_mut(ply) else {
+            return;
+        };
+        let elapsed = format_elapsed(millis);
+        annotation.comment = Some(match annotation.comment.take() {
+            Some(comment) => format!("{}, {}", elapsed, comment),
+            None => elapsed,
+        });
+    }
+
+    /// Adds the engine's evaluation to the comment of the given move, after any move time.
+    pub fn set_eval(&mut self, ply: usize, eval: f64) {
+        let Some(annotation) = self.annotation_mut(ply) else {
+            return;
+        };
+        let eval = format_eval(eval);
+        annotation.comment = Some(match annotation.comment.take() {
+            Some(comment) => format!("{}, {}", comment, eval),
+            None => eval,
+        });
+    }
+
+    /// Checks the `Result` tag, or the result after the moves if there is no tag, against the
+    /// state reached by replaying the game. Results that cannot show up on the board, like
+    /// timeouts, resignations and agreed draws, are accepted as long as the game is undecided.
+    pub fn verify_result(&self) -> Result<(), ResultMismatch> {
+        let claimed = match self.tag("Result") {
+            Some("") => TakGameState::Ongoing,
+            Some(tag) => {
+                parse_result(tag).ok_or_else(|| ResultMismatch::InvalidTag(tag.to_string()))?
+            }
+            None => self.game_state.clone(),
+        };
+        let actual = crate::TakGame::replay_ptn(self)
+            .map_err(|_| ResultMismatch::IllegalGame)?
+            .game_state;
+        match (&claimed, &actual) {
+            (TakGameState::Win(_, TakWinReason::Timeout), TakGameState::Ongoing) => Ok(()),
+            (TakGameState::Draw(_), TakGameState::Ongoing | TakGameState::Draw(_)) => Ok(()),
+            (
+                TakGameState::Win(claimed_winner, claimed_reason),
+                TakGameState::Win(actual_winner, actual_reason),
+            ) if claimed_winner == actual_winner => {
+                if claimed_reason == actual_reason {
+                    Ok(())
+                } else {
+                    Err(ResultMismatch::WrongReason {
+                        claimed: claimed_reason.clone(),
+                        actual: actual_reason.clone(),
+                    })
+                }
+            }
+            _ if claimed == actual => Ok(()),
+            _ => Err(ResultMismatch::WrongWinner { claimed, actual }),
+        }
+    }
+
+    pub(crate) fn game_state_to_str(&self) -> String {
         match self.game_state {
             TakGameState::Win(TakPlayer::White, TakWinReason::Road) => "R-0".to_string(),
             TakGameState::Win(TakPlayer::Black, TakWinReason::Road) => "0-R".to_string(),
@@ -178,6 +468,8 @@ impl TakPtn {
             TakGameState::Win(TakPlayer::Black, TakWinReason::Timeout) => "0-1".to_string(),
             TakGameState::Win(TakPlayer::White, TakWinReason::Resignation) => "1-0".to_string(),
             TakGameState::Win(TakPlayer::Black, TakWinReason::Resignation) => "0-1".to_string(),
+            TakGameState::Win(TakPlayer::White, TakWinReason::Abandonment) => "1-0".to_string(),
+            TakGameState::Win(TakPlayer::Black, TakWinReason::Abandonment) => "0-1".to_string(),
             TakGameState::Draw(_) => "1/2-1/2".to_string(),
             TakGameState::Ongoing => "".to_string(),
             TakGameState::Canceled => "".to_string(),
@@ -190,16 +482,23 @@ impl TakPtn {
             result.push_str(&attr.to_str());
             result.push('\n');
         });
-        for (i, white_turn, black_turn) in self.turns.iter() {
+        for (turn_index, (i, white_turn, black_turn)) in self.turns.iter().enumerate() {
+            let [white_annotation, black_annotation] = self
+                .annotations
+                .get(turn_index)
+                .cloned()
+                .unwrap_or_default();
             result.push_str(&format!("{}.", i + 1));
             result.push_str(&format!(
                 " {}",
                 white_turn.as_ref().unwrap_or(&"--".to_string())
             ));
+            result.push_str(&white_annotation.to_str());
             if let Some(black_turn) = black_turn {
                 result.push_str(&format!(" {}", black_turn));
+                result.push_str(&black_annotation.to_str());
             }
-            if *i == self.turns.len() - 1 && self.game_state != TakGameState::Ongoing {
+            if turn_index == self.turns.len() - 1 && self.game_state != TakGameState::Ongoing {
                 result.push_str(&format!(" {}", self.game_state_to_str()));
             }
             result.push('\n');
@@ -208,66 +507,175 @@ impl TakPtn {
     }
 
     pub fn try_from_str(s: &str) -> Option<Self> {
-        let mut lines = s.lines();
         let mut attributes = Vec::new();
+        let mut body = String::new();
+
+        for line in s.lines() {
+            if line.starts_with('[') && line.ends_with(']') {
+                attributes.push(TakPtnAttr::from_str(line)?);
+            } else {
+                body.push_str(line);
+                body.push('\n');
+            }
+        }
+
         let mut turns: Vec<(usize, Option<String>, Option<String>)> = Vec::new();
+        let mut annotations: Vec<[TakPtnAnnotation; 2]> = Vec::new();
         let mut game_state = TakGameState::Ongoing;
+        // Index of the next move slot (0 for white, 1 for black) in the current turn.
+        let mut slot = 0;
 
-        while let Some(line) = lines.next() {
-            if line.starts_with('[') && line.ends_with(']') {
-                if let Some(attr) = TakPtnAttr::from_str(line) {
-                    attributes.push(attr);
-                } else {
-                    return None;
+        for token in tokenize_moves(&body)? {
+            let word = match token {
+                PtnToken::Comment(comment) => {
+                    if slot > 0 {
+                        let annotation = &mut annotations.last_mut()?[slot - 1];
+                        annotation.comment = Some(comment);
+                    }
+                    continue;
                 }
-            } else if !line.trim().is_empty() {
-                let mut turn: Vec<String> = line.split_whitespace().map(String::from).collect();
-
-                let maybe_result = turn.last().and_then(|x| match x.as_str() {
-                    "R-0" => Some(TakGameState::Win(TakPlayer::White, TakWinReason::Road)),
-                    "0-R" => Some(TakGameState::Win(TakPlayer::Black, TakWinReason::Road)),
-                    "F-0" => Some(TakGameState::Win(TakPlayer::White, TakWinReason::Flat)),
-                    "0-F" => Some(TakGameState::Win(TakPlayer::Black, TakWinReason::Flat)),
-                    "1-0" => Some(TakGameState::Win(TakPlayer::White, TakWinReason::Timeout)),
-                    "0-1" => Some(TakGameState::Win(TakPlayer::Black, TakWinReason::Timeout)),
-                    "1/2-1/2" => Some(TakGameState::Draw(TakDrawReason::Flat)),
-                    _ => None,
-                });
-                if let Some(result) = maybe_result {
-                    game_state = result;
-                    turn.pop();
-                }
-
-                if turn.len() < 2 || turn.len() > 3 {
+                PtnToken::Word(word) => word,
+            };
+            if let Some(result) = parse_result(&word) {
+                game_state = result;
+                continue;
+            }
+            if let Some(number) = word.strip_suffix('.') {
+                let Ok(turn_index) = number.parse::<usize>() else {
                     return None;
-                }
-                let turn_index = turn[0].trim_end_matches('.').parse::<usize>().ok()?;
+                };
                 if turn_index == 0 {
                     return None;
                 }
-                if turns.len() > 0 && turns.last().unwrap().0 + 1 != turn_index - 1 {
-                    return None;
+                if let Some(last) = turns.last() {
+                    if slot == 0 || last.0 + 1 != turn_index - 1 {
+                        return None;
+                    }
                 }
-                let white_turn =
-                    Some(turn[1].trim()).filter(|s| s.chars().any(|c| c != '.' && c != '-'));
-                let black_turn = turn.get(2).map(|s| s.trim());
-
-                turns.push((
-                    turn_index - 1,
-                    white_turn.map(|x| x.to_string()),
-                    black_turn.map(|x| x.to_string()),
-                ));
+                turns.push((turn_index - 1, None, None));
+                annotations.push(Default::default());
+                slot = 0;
+                continue;
             }
+            let turn = turns.last_mut()?;
+            let mv = word.trim_end_matches(GLYPH_CHARS);
+            let glyph = &word[mv.len()..];
+            let mv = Some(mv.to_string()).filter(|s| s.chars().any(|c| c != '.' && c != '-'));
+            match slot {
+                0 => turn.1 = mv,
+                1 => turn.2 = mv,
+                _ => return None,
+            }
+            if !glyph.is_empty() {
+                annotations.last_mut()?[slot].glyph = Some(glyph.to_string());
+            }
+            slot += 1;
+        }
+        if slot == 0 && !turns.is_empty() {
+            return None;
         }
 
         Some(Self {
             attributes,
             turns,
+            annotations,
             game_state,
         })
     }
 }
 
+/// Why the moves of a PTN couldn't be played through. Plies are counted like `ply_index`, from
+/// the start of the game.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PtnReplayError {
+    InvalidSettings,
+    InvalidNotation {
+        ply: usize,
+    },
+    /// A turn other than the first or last lacks a move.
+    MissingMove {
+        ply: usize,
+    },
+    IllegalAction {
+        ply: usize,
+        player: TakPlayer,
+        error: TakInvalidActionError,
+    },
+    /// The moves end in a different result than the one recorded.
+    WrongResult {
+        claimed: TakGameState,
+        actual: TakGameState,
+    },
+}
+
+impl std::fmt::Display for PtnReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PtnReplayError::InvalidSettings => write!(f, "invalid game settings"),
+            PtnReplayError::InvalidNotation { ply } => write!(f, "ply {}: invalid notation", ply),
+            PtnReplayError::MissingMove { ply } => write!(f, "ply {}: missing move", ply),
+            PtnReplayError::IllegalAction { ply, player, error } => {
+                let reason = match error {
+                    TakInvalidActionError::InvalidPlace(e) => format!("{:?}", e),
+                    TakInvalidActionError::InvalidMove(e) => format!("{:?}", e),
+                    TakInvalidActionError::InvalidNotation => "InvalidNotation".to_string(),
+                };
+                write!(f, "ply {}: {} for {:?}", ply, reason, player)
+            }
+            PtnReplayError::WrongResult { claimed, actual } => {
+                write!(
+                    f,
+                    "recorded result {:?} but the game ends {:?}",
+                    claimed, actual
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PtnParseError {
+    Malformed,
+    MissingSettings,
+    IllegalGame,
+}
+
+/// Splits a file containing several PTN games and parses each one on its own, so that a broken
+/// game does not affect the others. A new game starts at the first header line following a move.
+pub fn parse_ptn_collection(input: &str) -> Vec<Result<TakPtn, PtnParseError>> {
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut has_moves = false;
+    for line in input.lines() {
+        let line = line.trim();
+        let is_header = line.starts_with('[') && line.ends_with(']');
+        if is_header && has_moves {
+            games.push(std::mem::take(&mut current));
+            has_moves = false;
+        }
+        if !is_header && !line.is_empty() {
+            has_moves = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        games.push(current);
+    }
+
+    games
+        .iter()
+        .map(|game| {
+            let ptn = TakPtn::try_from_str(game).ok_or(PtnParseError::Malformed)?;
+            if ptn.get_settings().is_none() {
+                return Err(PtnParseError::MissingSettings);
+            }
+            crate::TakGame::try_from_ptn(ptn.clone()).map_err(|_| PtnParseError::IllegalGame)?;
+            Ok(ptn)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -277,7 +685,7 @@ mod tests {
         let attr = TakPtnAttr::Size(5);
         assert_eq!(attr.to_str(), "[Size \"5\"]");
 
-        let attr = TakPtnAttr::Clock(300, 10);
+        let attr = TakPtnAttr::Clock(TakTimeMode::new(300, 10));
         assert_eq!(attr.to_str(), "[Clock \"5:0 +10\"]");
 
         let attr = TakPtnAttr::Komi(6, false);
@@ -297,5 +705,156 @@ mod tests {
 
         let attr = TakPtnAttr::Unknown("Unknown".to_string());
         assert_eq!(attr.to_str(), "[Unknown]");
+
+        let attr = TakPtnAttr::Other("Engine".to_string(), "takumi".to_string());
+        assert_eq!(attr.to_str(), "[Engine \"takumi\"]");
+    }
+
+    #[test]
+    fn test_other_tags_roundtrip() {
+        let input = "[Size \"5\"]\n[Player1 \"Alice\"]\n[Komi \"0\"]\n[Event \"Club Night\"]\n[Broken]\n1. a1 e5\n";
+        let mut ptn = TakPtn::try_from_str(input).unwrap();
+        assert_eq!(
+            ptn.attributes[1],
+            TakPtnAttr::Other("Player1".to_string(), "Alice".to_string())
+        );
+        assert_eq!(ptn.attributes[4], TakPtnAttr::Unknown("Broken".to_string()));
+        assert_eq!(ptn.tag("Event"), Some("Club Night"));
+        assert_eq!(ptn.to_str(), input);
+
+        ptn.set_engine_info("takumi", 6);
+        ptn.set_engine_info("takumi", 8);
+        ptn.set_eval(0, 0.25);
+        ptn.set_eval(1, -1.0);
+        let output = ptn.to_str();
+        assert!(output.ends_with(
+            "[Broken]\n[Engine \"takumi\"]\n[EngineDepth \"8\"]\n1. a1 {eval: +0.2} e5 {eval: -1.0}\n"
+        ));
+        let reparsed = TakPtn::try_from_str(&output).unwrap();
+        assert_eq!(reparsed.attributes, ptn.attributes);
+        assert_eq!(reparsed.tag("EngineDepth"), Some("8"));
+        let comment = reparsed.annotations[0][1].comment.as_deref().unwrap();
+        assert_eq!(parse_eval(comment), Some(-1.0));
+    }
+
+    #[test]
+    fn test_eval_after_elapsed() {
+        let mut ptn = TakPtn::new(vec!["a1".to_string()], 0, TakGameState::Ongoing);
+        ptn.annotation_mut(0).unwrap().comment = Some(format_elapsed(1500));
+        ptn.set_eval(0, 3.0);
+        let comment = ptn.annotations[0][0].comment.clone().unwrap();
+        assert_eq!(comment, "+1.5s, eval: +3.0");
+        assert_eq!(parse_elapsed(&comment), Some(1500));
+        assert_eq!(parse_eval(&comment), Some(3.0));
+    }
+
+    #[test]
+    fn test_elapsed_keeps_comment() {
+        let mut ptn = TakPtn::new(vec!["a1".to_string()], 0, TakGameState::Ongoing);
+        ptn.annotation_mut(0).unwrap().comment = Some("great opening".to_string());
+        ptn.set_elapsed(0, 2250);
+        let comment = ptn.annotations[0][0].comment.clone().unwrap();
+        assert_eq!(comment, "+2.25s, great opening");
+        assert_eq!(parse_elapsed(&comment), Some(2250));
+    }
+
+    #[test]
+    fn test_clock_attr_roundtrip() {
+        for (mode, str) in [
+            (TakTimeMode::new(600, 5), "[Clock \"10:0 +5\"]"),
+            (TakTimeMode::byo_yomi(300, 3, 30), "[Clock \"5:0 3x30\"]"),
+            (TakTimeMode::delay(90, 4), "[Clock \"1:30 d4\"]"),
+        ] {
+            let attr = TakPtnAttr::Clock(mode);
+            assert_eq!(attr.to_str(), str);
+            assert_eq!(TakPtnAttr::from_str(str), Some(attr));
+        }
+        assert_eq!(
+            TakPtnAttr::from_str("[Clock \"10:0+5\"]"),
+            Some(TakPtnAttr::Clock(TakTimeMode::new(600, 5)))
+        );
+        assert_eq!(TakPtnAttr::from_str("[Clock \"10:0\"]"), None);
+    }
+
+    #[test]
+    fn test_annotations_roundtrip() {
+        let input = "[Size \"6\"]\n[Komi \"0\"]\n1. a1 {great opening} f6?\n2. c3!! d4' {threatens a road}\n3. c4 0-1\n";
+        let ptn = TakPtn::try_from_str(input).unwrap();
+        assert_eq!(ptn.turns[0].1.as_deref(), Some("a1"));
+        assert_eq!(ptn.turns[0].2.as_deref(), Some("f6"));
+        assert_eq!(
+            ptn.annotations[0][0].comment.as_deref(),
+            Some("great opening")
+        );
+        assert_eq!(ptn.annotations[0][1].glyph.as_deref(), Some("?"));
+        assert_eq!(ptn.annotations[1][0].glyph.as_deref(), Some("!!"));
+        assert_eq!(ptn.to_str(), input);
+
+        let game = crate::TakGame::try_from_ptn(ptn).unwrap();
+        assert_eq!(game.ply_index, 5);
+    }
+
+    #[test]
+    fn test_parse_ptn_collection_isolates_errors() {
+        let input = "[Size \"5\"]\n[Komi \"0\"]\n\n1. a1 e5\n2. c3 c4\n\n\
+[Size \"5\"]\n[Komi \"0\"]\n\n1. a1 e5\n2. 2e5-11 c4\n\n\
+[Size \"5\"]\n[Komi \"0\"]\n\n1. e1 a5\n2. b2 1-0\n";
+        let games = parse_ptn_collection(input);
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].as_ref().unwrap().turns.len(), 2);
+        assert_eq!(games[1].as_ref().unwrap_err(), &PtnParseError::IllegalGame);
+        assert_eq!(games[2].as_ref().unwrap().turns[0].1.as_deref(), Some("e1"));
+    }
+
+    fn road_game_with_result(result: &str) -> TakPtn {
+        let input = format!(
+            "[Size \"3\"]\n[Komi \"0\"]\n[Result \"{}\"]\n1. c1 a1\n2. a2 c2\n3. a3\n",
+            result
+        );
+        TakPtn::try_from_str(&input).unwrap()
+    }
+
+    #[test]
+    fn test_verify_result() {
+        assert_eq!(road_game_with_result("R-0").verify_result(), Ok(()));
+        assert_eq!(
+            road_game_with_result("0-R").verify_result(),
+            Err(ResultMismatch::WrongWinner {
+                claimed: TakGameState::Win(TakPlayer::Black, TakWinReason::Road),
+                actual: TakGameState::Win(TakPlayer::White, TakWinReason::Road),
+            })
+        );
+        assert_eq!(
+            road_game_with_result("F-0").verify_result(),
+            Err(ResultMismatch::WrongReason {
+                claimed: TakWinReason::Flat,
+                actual: TakWinReason::Road,
+            })
+        );
+        assert_eq!(
+            road_game_with_result("").verify_result(),
+            Err(ResultMismatch::WrongWinner {
+                claimed: TakGameState::Ongoing,
+                actual: TakGameState::Win(TakPlayer::White, TakWinReason::Road),
+            })
+        );
+        assert_eq!(
+            road_game_with_result("2-0").verify_result(),
+            Err(ResultMismatch::InvalidTag("2-0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_verify_result_timeouts_are_lenient() {
+        let ptn =
+            TakPtn::try_from_str("[Size \"3\"]\n[Komi \"0\"]\n[Result \"0-1\"]\n1. c1 a1\n2. a2\n")
+                .unwrap();
+        assert_eq!(ptn.verify_result(), Ok(()));
+
+        let ptn = TakPtn::try_from_str(
+            "[Size \"3\"]\n[Komi \"0\"]\n[Result \"0-1\"]\n1. c1 a1\n2. a2 a4\n",
+        )
+        .unwrap();
+        assert_eq!(ptn.verify_result(), Err(ResultMismatch::IllegalGame));
     }
 }
@@ -0,0 +1,255 @@
+use std::fmt::Write;
+
+use crate::{TakBoard, TakCoord, TakPieceVariant, TakPlayer, TakStack};
+
+/// Colors of a rendered board. Any CSS color value works.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TakSvgTheme {
+    pub background: String,
+    pub light_tile: String,
+    pub dark_tile: String,
+    pub white_piece: String,
+    pub black_piece: String,
+    pub outline: String,
+    pub label: String,
+}
+
+impl Default for TakSvgTheme {
+    fn default() -> Self {
+        Self {
+            background: "#2b2b2b".to_string(),
+            light_tile: "#c8b38a".to_string(),
+            dark_tile: "#b09a70".to_string(),
+            white_piece: "#f4f1ea".to_string(),
+            black_piece: "#303030".to_string(),
+            outline: "#1a1a1a".to_string(),
+            label: "#e0e0e0".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TakSvgOptions {
+    /// Width and height of the image in pixels.
+    pub size_px: usize,
+    pub theme: TakSvgTheme,
+    /// Writes the id of the top piece onto every stack.
+    pub show_piece_ids: bool,
+}
+
+impl Default for TakSvgOptions {
+    fn default() -> Self {
+        Self {
+            size_px: 480,
+            theme: TakSvgTheme::default(),
+            show_piece_ids: false,
+        }
+    }
+}
+
+/// Most pieces below the top one drawn per stack. Taller stacks get their height written next
+/// to them instead.
+const MAX_DRAWN_BURIED: usize = 8;
+
+impl TakBoard {
+    /// Draws the board as a standalone SVG image, with the files below and the ranks left of
+    /// the board. Pieces below the top of a stack are shown as a column of strips.
+    pub fn to_svg(&self, options: &TakSvgOptions) -> String {
+        let theme = &options.theme;
+        let image_size = options.size_px as f64;
+        let tile = image_size / (self.size as f64 + 0.5);
+        let margin = tile / 2.0;
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">"#,
+            size = options.size_px
+        );
+        let _ = write!(
+            svg,
+            r#"<rect class="background" width="{0}" height="{0}" fill="{1}"/>"#,
+            options.size_px,
+            escape(&theme.background)
+        );
+
+        for pos in TakCoord::iter_board(self.size) {
+            let x = margin + pos.x as f64 * tile;
+            let y = (self.size as i32 - 1 - pos.y) as f64 * tile;
+            let fill = if (pos.x + pos.y) % 2 == 0 {
+                &theme.dark_tile
+            } else {
+                &theme.light_tile
+            };
+            let _ = write!(
+                svg,
+                r#"<rect class="tile" x="{x:.1}" y="{y:.1}" width="{tile:.1}" height="{tile:.1}" fill="{}"/>"#,
+                escape(fill)
+            );
+            if let Some(stack) = self.try_get_stack(pos) {
+                self.write_stack(&mut svg, stack, x, y, tile, options);
+            }
+        }
+
+        let font_size = tile * 0.3;
+        for i in 0..self.size {
+            let file = (b'a' + i as u8) as char;
+            let file_x = margin + (i as f64 + 0.5) * tile;
+            let file_y = self.size as f64 * tile + margin * 0.7;
+            let rank_y = (self.size - 1 - i) as f64 * tile + tile * 0.6;
+            let _ = write!(
+                svg,
+                r#"<text class="label" x="{file_x:.1}" y="{file_y:.1}" font-size="{font_size:.1}" text-anchor="middle" fill="{0}">{file}</text><text class="label" x="{1:.1}" y="{rank_y:.1}" font-size="{font_size:.1}" text-anchor="middle" fill="{0}">{2}</text>"#,
+                escape(&theme.label),
+                margin / 2.0,
+                i + 1
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    fn write_stack(
+        &self,
+        svg: &mut String,
+        stack: &TakStack,
+        x: f64,
+        y: f64,
+        tile: f64,
+        options: &TakSvgOptions,
+    ) {
+        let theme = &options.theme;
+        let color = |player: TakPlayer| match player {
+            TakPlayer::White => escape(&theme.white_piece),
+            TakPlayer::Black => escape(&theme.black_piece),
+        };
+        let outline = escape(&theme.outline);
+        let (top, buried) = stack
+            .composition
+            .split_last()
+            .expect("TakStack should not be empty");
+
+        let strip_height = tile * 0.06;
+        for (i, piece) in buried.iter().rev().take(MAX_DRAWN_BURIED).enumerate() {
+            let strip_y = y + tile - (i + 1) as f64 * strip_height * 1.3;
+            let _ = write!(
+                svg,
+                r#"<rect class="buried" x="{0:.1}" y="{strip_y:.1}" width="{1:.1}" height="{strip_height:.1}" fill="{2}" stroke="{outline}" stroke-width="0.5"/>"#,
+                x + tile * 0.1,
+                tile * 0.8,
+                color(piece.player)
+            );
+        }
+        if buried.len() > MAX_DRAWN_BURIED {
+            let _ = write!(
+                svg,
+                r#"<text class="height" x="{0:.1}" y="{1:.1}" font-size="{2:.1}" text-anchor="end" fill="{3}">{4}</text>"#,
+                x + tile * 0.95,
+                y + tile * 0.2,
+                tile * 0.18,
+                escape(&theme.label),
+                stack.height()
+            );
+        }
+
+        let (cx, cy) = (x + tile / 2.0, y + tile * 0.45);
+        let fill = color(top.player);
+        let _ = match stack.variant {
+            TakPieceVariant::Flat => write!(
+                svg,
+                r#"<rect class="flat" x="{0:.1}" y="{1:.1}" width="{2:.1}" height="{2:.1}" fill="{fill}" stroke="{outline}"/>"#,
+                cx - tile * 0.25,
+                cy - tile * 0.25,
+                tile * 0.5
+            ),
+            TakPieceVariant::Wall => write!(
+                svg,
+                r#"<rect class="wall" x="{0:.1}" y="{1:.1}" width="{2:.1}" height="{3:.1}" fill="{fill}" stroke="{outline}" transform="rotate(45 {cx:.1} {cy:.1})"/>"#,
+                cx - tile * 0.1,
+                cy - tile * 0.28,
+                tile * 0.2,
+                tile * 0.56
+            ),
+            TakPieceVariant::Capstone => write!(
+                svg,
+                r#"<circle class="capstone" cx="{cx:.1}" cy="{cy:.1}" r="{0:.1}" fill="{fill}" stroke="{outline}"/>"#,
+                tile * 0.25
+            ),
+        };
+        if options.show_piece_ids {
+            let _ = write!(
+                svg,
+                r#"<text class="piece-id" x="{cx:.1}" y="{0:.1}" font-size="{1:.1}" text-anchor="middle" fill="{2}">{3}</text>"#,
+                cy + tile * 0.07,
+                tile * 0.2,
+                color(top.player.other()),
+                top.id
+            );
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that every tag is closed in order and returns the names of all opened elements.
+    fn parse_tags(svg: &str) -> Option<Vec<String>> {
+        let mut open = Vec::new();
+        let mut elements = Vec::new();
+        let mut rest = svg;
+        while let Some(start) = rest.find('<') {
+            let end = start + rest[start..].find('>')?;
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                if open.pop()? != name {
+                    return None;
+                }
+                continue;
+            }
+            let name = tag.split_whitespace().next()?.trim_end_matches('/');
+            elements.push(name.to_string());
+            if !tag.ends_with('/') {
+                open.push(name.to_string());
+            }
+        }
+        open.is_empty().then_some(elements)
+    }
+
+    #[test]
+    fn test_svg_is_well_formed() {
+        let board = TakBoard::try_from_partial_tps("x5/x2,2S,1C,x/x,2,121,x,2C/x5/1,x4").unwrap();
+        let options = TakSvgOptions {
+            show_piece_ids: true,
+            ..TakSvgOptions::default()
+        };
+        let svg = board.to_svg(&options);
+        let elements = parse_tags(&svg).unwrap();
+        assert_eq!(elements[0], "svg");
+        assert_eq!(svg.matches(r#"class="tile""#).count(), 25);
+        assert_eq!(svg.matches(r#"class="flat""#).count(), 3);
+        assert_eq!(svg.matches(r#"class="wall""#).count(), 1);
+        assert_eq!(svg.matches(r#"class="capstone""#).count(), 2);
+        assert_eq!(svg.matches(r#"class="buried""#).count(), 2);
+        assert_eq!(svg.matches(r#"class="piece-id""#).count(), 6);
+        assert_eq!(svg.matches(r#"class="label""#).count(), 10);
+    }
+
+    #[test]
+    fn test_svg_escapes_theme_colors() {
+        let mut options = TakSvgOptions::default();
+        options.theme.background = r##"url("#a")&"##.to_string();
+        let svg = TakBoard::new(3).to_svg(&options);
+        assert!(parse_tags(&svg).is_some());
+        assert!(svg.contains("url(&quot;#a&quot;)&amp;"));
+        assert!(!svg.contains("piece-id"));
+    }
+}
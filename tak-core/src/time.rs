@@ -1,16 +1,63 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
 use crate::TakPlayer;
 
+/// How the per-move time of a `TakTimeMode` is granted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TakTimeControl {
+    /// The increment is added to the clock after every move.
+    #[default]
+    Fischer,
+    /// Once the main time runs out, the player has `periods` periods as long as the
+    /// increment. Moving within a period starts it over, running past it uses it up.
+    ByoYomi { periods: usize },
+    /// The clock only starts to run once the increment has passed in each move.
+    Delay,
+}
+
+/// A time control in whole seconds. The `TakClock` created from it keeps time in milliseconds.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TakTimeMode {
     pub time: usize,
     pub increment: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub control: TakTimeControl,
 }
 
 impl TakTimeMode {
     /// Creates a new TakTimeMode with the given time in seconds and increment in seconds.
     pub fn new(time: usize, increment: usize) -> Self {
-        TakTimeMode { time, increment }
+        TakTimeMode {
+            time,
+            increment,
+            control: TakTimeControl::Fischer,
+        }
+    }
+
+    /// Main time followed by `periods` byo-yomi periods, all in seconds.
+    pub fn byo_yomi(time: usize, periods: usize, period: usize) -> Self {
+        TakTimeMode {
+            time,
+            increment: period,
+            control: TakTimeControl::ByoYomi { periods },
+        }
+    }
+
+    /// Main time with a delay before the clock runs each move, both in seconds.
+    pub fn delay(time: usize, delay: usize) -> Self {
+        TakTimeMode {
+            time,
+            increment: delay,
+            control: TakTimeControl::Delay,
+        }
     }
 }
 
@@ -32,60 +79,313 @@ impl TakTimestamp {
     }
 }
 
+/// Where a clock gets the current time from.
+pub trait TimeSource: Debug + Send + Sync {
+    fn now(&self) -> TakTimestamp;
+}
+
+/// The wall clock, used unless a clock is given another source.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RealTime;
+
+impl TimeSource for RealTime {
+    fn now(&self) -> TakTimestamp {
+        TakTimestamp::now()
+    }
+}
+
+/// A time that only moves when advanced, so tests can hit clock edges exactly. Clones share
+/// the same time.
+#[derive(Clone, Debug, Default)]
+pub struct MockTime {
+    millis: Arc<AtomicU64>,
+}
+
+impl MockTime {
+    pub fn new(millis: u64) -> Self {
+        MockTime {
+            millis: Arc::new(AtomicU64::new(millis)),
+        }
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.millis.fetch_add(millis, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.millis.store(millis, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for MockTime {
+    fn now(&self) -> TakTimestamp {
+        TakTimestamp {
+            millis: self.millis.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// The time source of a `TakClock`. It isn't part of the clock's state, so all sources compare
+/// equal and deserialized clocks use the wall clock.
+#[derive(Clone, Debug)]
+pub struct TakTimeSource(Arc<dyn TimeSource>);
+
+impl TakTimeSource {
+    pub fn new(source: impl TimeSource + 'static) -> Self {
+        TakTimeSource(Arc::new(source))
+    }
+
+    pub fn now(&self) -> TakTimestamp {
+        self.0.now()
+    }
+}
+
+impl Default for TakTimeSource {
+    fn default() -> Self {
+        TakTimeSource::new(RealTime)
+    }
+}
+
+impl PartialEq for TakTimeSource {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The time left of both players in milliseconds. For byo-yomi the remaining periods count towards the time
+/// left, so a player in overtime has a multiple of the period length after each move.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TakClock {
     pub time_remaining_millis: [u64; 2],
+    /// The increment, delay or byo-yomi period, depending on `control`.
     pub increment_millis: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub control: TakTimeControl,
     pub last_update_timestamp: Option<TakTimestamp>,
+    /// Time left below which `check_warning_at` warns a player. Local to each client, so it
+    /// isn't serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub warning_threshold_millis: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    warned: [bool; 2],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub time_source: TakTimeSource,
 }
 
 impl TakClock {
     pub fn new(mode: &TakTimeMode) -> Self {
-        let time_remaining = mode.time as u64 * 1000;
+        let increment_millis = mode.increment as u64 * 1000;
+        let mut time_remaining = mode.time as u64 * 1000;
+        if let TakTimeControl::ByoYomi { periods } = mode.control {
+            time_remaining += periods as u64 * increment_millis;
+        }
         TakClock {
             time_remaining_millis: [time_remaining, time_remaining],
-            increment_millis: mode.increment as u64 * 1000,
+            increment_millis,
+            control: mode.control,
             last_update_timestamp: None,
+            warning_threshold_millis: None,
+            warned: [false; 2],
+            time_source: TakTimeSource::default(),
+        }
+    }
+
+    /// The current time according to the clock's time source.
+    pub fn now(&self) -> TakTimestamp {
+        self.time_source.now()
+    }
+
+    pub fn set_warning_threshold(&mut self, threshold_millis: Option<u64>) {
+        self.warning_threshold_millis = threshold_millis;
+        self.warned = [false; 2];
+    }
+
+    /// Returns true the first time the player's time left is at or below the warning
+    /// threshold. Getting back above it, e.g. through an increment, arms the warning again.
+    pub fn check_warning_at(&mut self, player: TakPlayer, now: TakTimestamp) -> bool {
+        let Some(threshold) = self.warning_threshold_millis else {
+            return false;
+        };
+        let below = self.get_time_remaining_at(player, now) <= threshold;
+        let warned = &mut self.warned[player.index()];
+        let fire = below && !*warned;
+        *warned = below;
+        fire
+    }
+
+    /// Time passed since the last move, which is what the player to move has spent so far.
+    pub fn elapsed_at(&self, now: TakTimestamp) -> u64 {
+        self.last_update_timestamp
+            .map(|t| now.elapsed_since(t))
+            .unwrap_or(0)
+    }
+
+    /// The part of `elapsed` that is taken off the clock.
+    pub fn charged(&self, elapsed: u64) -> u64 {
+        match self.control {
+            TakTimeControl::Delay => elapsed.saturating_sub(self.increment_millis),
+            TakTimeControl::Fischer | TakTimeControl::ByoYomi { .. } => elapsed,
         }
     }
 
     pub fn update(&mut self, time: TakTimestamp, player: TakPlayer) {
-        let elapsed = self
-            .last_update_timestamp
-            .map(|t| time.elapsed_since(t))
-            .unwrap_or(0);
+        let elapsed = self.charged(self.elapsed_at(time));
         self.last_update_timestamp = Some(time);
+        let increment = self.increment_millis;
         let time_left = &mut self.time_remaining_millis[player.index()];
         *time_left = time_left.saturating_sub(elapsed);
-        if *time_left > 0 {
-            *time_left += self.increment_millis;
+        if *time_left == 0 {
+            return;
+        }
+        match self.control {
+            TakTimeControl::Fischer => *time_left += increment,
+            TakTimeControl::ByoYomi { periods } => {
+                if *time_left < periods as u64 * increment {
+                    *time_left = time_left.div_ceil(increment) * increment;
+                }
+            }
+            TakTimeControl::Delay => {}
         }
     }
 
     pub fn get_time_remaining_at(&self, player: TakPlayer, now: TakTimestamp) -> u64 {
         let time_left = self.time_remaining_millis[player.index()];
-        let elapsed = self
-            .last_update_timestamp
-            .map(|t| now.elapsed_since(t))
-            .unwrap_or(0);
-        time_left.saturating_sub(elapsed)
+        time_left.saturating_sub(self.charged(self.elapsed_at(now)))
     }
 
     pub fn get_time_remaining(&self, player: TakPlayer, apply_elapsed: bool) -> u64 {
-        let time_left = self.time_remaining_millis[player.index()];
         if !apply_elapsed {
-            return time_left;
+            return self.time_remaining_millis[player.index()];
         }
-        let now = TakTimestamp::now();
-        let elapsed = self
-            .last_update_timestamp
-            .map(|t| now.elapsed_since(t))
-            .unwrap_or(0);
-        time_left.saturating_sub(elapsed)
+        self.get_time_remaining_at(player, self.now())
     }
 
     pub fn set_time_remaining(&mut self, player: TakPlayer, time_remaining: u64) {
         self.time_remaining_millis[player.index()] = time_remaining;
     }
 }
+
+/// Formats time left for a clock display, with tenths of a second below 20 seconds. Rounds up,
+/// so the display only shows zero once the player has actually run out of time.
+pub fn format_time_remaining(millis: u64) -> String {
+    let tenths = millis.div_ceil(100);
+    if tenths >= 200 {
+        let seconds = millis.div_ceil(1000);
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", tenths / 10, tenths % 10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays moves taking the given milliseconds each, alternating from white, and returns the
+    /// time left of the mover after each of them.
+    fn play(mode: TakTimeMode, spent: &[u64]) -> Vec<u64> {
+        let mut clock = TakClock::new(&mode);
+        let mut now = TakTimestamp { millis: 1_000_000 };
+        clock.last_update_timestamp = Some(now);
+        let mut player = TakPlayer::White;
+        let mut left = Vec::new();
+        for &millis in spent {
+            now.millis += millis;
+            clock.update(now, player);
+            left.push(clock.get_time_remaining_at(player, now));
+            player = player.other();
+        }
+        left
+    }
+
+    #[test]
+    fn test_sub_second_timeout() {
+        let mut clock = TakClock::new(&TakTimeMode::new(60, 0));
+        clock.set_time_remaining(TakPlayer::White, 500);
+        clock.last_update_timestamp = Some(TakTimestamp { millis: 0 });
+        let at = |millis| clock.get_time_remaining_at(TakPlayer::White, TakTimestamp { millis });
+        assert_eq!(at(499), 1);
+        assert_eq!(at(500), 0);
+        assert_eq!(at(600), 0);
+
+        clock.update(TakTimestamp { millis: 600 }, TakPlayer::White);
+        assert_eq!(clock.get_time_remaining(TakPlayer::White, false), 0);
+    }
+
+    #[test]
+    fn test_warning_fires_once() {
+        let mut clock = TakClock::new(&TakTimeMode::new(30, 15));
+        clock.set_warning_threshold(Some(10_000));
+        clock.last_update_timestamp = Some(TakTimestamp { millis: 0 });
+        let mut check = |millis| clock.check_warning_at(TakPlayer::White, TakTimestamp { millis });
+
+        assert!(!check(15_000));
+        assert!(check(21_000));
+        assert!(!check(22_000));
+        assert!(!check(29_000));
+
+        clock.update(TakTimestamp { millis: 25_000 }, TakPlayer::White);
+        assert!(!clock.check_warning_at(TakPlayer::White, TakTimestamp { millis: 25_000 }));
+        assert!(clock.check_warning_at(TakPlayer::White, TakTimestamp { millis: 36_000 }));
+        assert!(!clock.check_warning_at(TakPlayer::Black, TakTimestamp { millis: 36_000 }));
+    }
+
+    #[test]
+    fn test_mock_time_drives_clock() {
+        let time = MockTime::new(0);
+        let mut clock = TakClock::new(&TakTimeMode::new(10, 0));
+        clock.time_source = TakTimeSource::new(time.clone());
+        clock.last_update_timestamp = Some(clock.now());
+
+        time.advance(9_999);
+        assert_eq!(clock.get_time_remaining(TakPlayer::White, true), 1);
+        assert_eq!(clock.clone().get_time_remaining(TakPlayer::White, true), 1);
+        time.advance(1);
+        assert_eq!(clock.get_time_remaining(TakPlayer::White, true), 0);
+        assert_eq!(clock.get_time_remaining(TakPlayer::Black, false), 10_000);
+    }
+
+    #[test]
+    fn test_format_time_remaining() {
+        assert_eq!(format_time_remaining(600_000), "10:00");
+        assert_eq!(format_time_remaining(59_001), "1:00");
+        assert_eq!(format_time_remaining(20_000), "0:20");
+        assert_eq!(format_time_remaining(19_950), "0:20");
+        assert_eq!(format_time_remaining(19_900), "0:19.9");
+        assert_eq!(format_time_remaining(1), "0:00.1");
+        assert_eq!(format_time_remaining(0), "0:00.0");
+    }
+
+    #[test]
+    fn test_fischer_clock() {
+        let left = play(TakTimeMode::new(60, 2), &[10_000, 500, 55_000]);
+        assert_eq!(left, vec![52_000, 61_500, 0]);
+    }
+
+    #[test]
+    fn test_byo_yomi_clock() {
+        let mode = TakTimeMode::byo_yomi(10, 3, 5);
+        let clock = TakClock::new(&mode);
+        assert_eq!(clock.get_time_remaining(TakPlayer::White, false), 25_000);
+
+        let left = play(mode, &[8_000, 0, 3_000, 0, 4_000, 0, 7_000, 0, 11_000]);
+        let white = left.iter().step_by(2).copied().collect::<Vec<_>>();
+        assert_eq!(white, vec![17_000, 15_000, 15_000, 10_000, 0]);
+    }
+
+    #[test]
+    fn test_delay_clock() {
+        let mode = TakTimeMode::delay(60, 3);
+        let left = play(mode.clone(), &[2_000, 0, 5_000, 0, 58_000]);
+        let white = left.iter().step_by(2).copied().collect::<Vec<_>>();
+        assert_eq!(white, vec![60_000, 58_000, 3_000]);
+
+        let mut clock = TakClock::new(&mode);
+        clock.last_update_timestamp = Some(TakTimestamp { millis: 0 });
+        let at = |millis| clock.get_time_remaining_at(TakPlayer::White, TakTimestamp { millis });
+        assert_eq!(at(3_000), 60_000);
+        assert_eq!(at(4_000), 59_000);
+        assert_eq!(at(63_000), 0);
+    }
+}
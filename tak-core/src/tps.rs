@@ -1,4 +1,78 @@
-use crate::TakPlayer;
+use crate::{TakBoard, TakPlayer};
+
+/// Why a TPS string can't describe a position. Rows are numbered from the top as written and
+/// columns count squares from the left, both starting at 0.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TakTpsError {
+    /// A TPS consists of the position, the player to move and the move number.
+    WrongPartCount(usize),
+    WrongRowCount {
+        expected: usize,
+        found: usize,
+    },
+    WrongRowLength {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownCharacter {
+        row: usize,
+        column: usize,
+        character: char,
+    },
+    /// A square made of known characters in an invalid order, like `S1` or `x0`.
+    InvalidSquare {
+        row: usize,
+        column: usize,
+    },
+    InvalidPlayer(String),
+    InvalidMoveIndex(String),
+}
+
+impl std::fmt::Display for TakTpsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TakTpsError::WrongPartCount(found) => {
+                write!(
+                    f,
+                    "expected a position, player and move number, found {} parts",
+                    found
+                )
+            }
+            TakTpsError::WrongRowCount { expected, found } => {
+                write!(f, "expected {} rows, found {}", expected, found)
+            }
+            TakTpsError::WrongRowLength {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {}: expected {} squares, found {}",
+                row + 1,
+                expected,
+                found
+            ),
+            TakTpsError::UnknownCharacter {
+                row,
+                column,
+                character,
+            } => write!(
+                f,
+                "row {}, square {}: unknown character '{}'",
+                row + 1,
+                column + 1,
+                character
+            ),
+            TakTpsError::InvalidSquare { row, column } => {
+                write!(f, "row {}, square {}: invalid square", row + 1, column + 1)
+            }
+            TakTpsError::InvalidPlayer(player) => write!(f, "invalid player '{}'", player),
+            TakTpsError::InvalidMoveIndex(index) => write!(f, "invalid move number '{}'", index),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -52,22 +126,160 @@ impl TakTps {
         )
     }
 
+    /// Checks that a TPS string describes a position on a board of the given size, reporting
+    /// the first problem found.
+    pub fn validate(s: &str, size: usize) -> Result<(), TakTpsError> {
+        let tps = Self::parse(s)?;
+        TakBoard::parse_partial_tps(&tps.position, Some(size))?;
+        Ok(())
+    }
+
+    /// Checks a single comma-separated entry of a row and returns how many squares it covers.
+    pub(crate) fn validate_square(
+        square: &str,
+        row: usize,
+        column: usize,
+    ) -> Result<usize, TakTpsError> {
+        if let Some(character) = square
+            .chars()
+            .find(|c| !matches!(c, '0'..='9' | 'x' | 'S' | 'C'))
+        {
+            return Err(TakTpsError::UnknownCharacter {
+                row,
+                column,
+                character,
+            });
+        }
+        let invalid = TakTpsError::InvalidSquare { row, column };
+        if let Some(count) = square.strip_prefix('x') {
+            if count.is_empty() {
+                return Ok(1);
+            }
+            return match count.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(count),
+                _ => Err(invalid),
+            };
+        }
+        let pieces = square.strip_suffix(['S', 'C']).unwrap_or(square);
+        if pieces.is_empty() || !pieces.chars().all(|c| c == '1' || c == '2') {
+            return Err(invalid);
+        }
+        Ok(1)
+    }
+
+    /// Looks up a standard setup by name: `empty` or `empty-<size>` for an empty board, or
+    /// `swap-opening` for the position after both players placed their first stone in opposite
+    /// corners.
+    pub fn from_named(name: &str, size: usize) -> Option<Self> {
+        if size == 0 {
+            return None;
+        }
+        match name {
+            "empty" => Some(Self::new_empty(size)),
+            "swap-opening" => {
+                let corner = format!("a1:2 {}{}:1", (b'a' + size as u8 - 1) as char, size);
+                Self::from_shorthand(&format!("{corner} 1 2"), size)
+            }
+            _ => {
+                let named_size = name.strip_prefix("empty-")?.parse::<usize>().ok()?;
+                (named_size == size).then(|| Self::new_empty(size))
+            }
+        }
+    }
+
+    /// Reads a position given as a list of its occupied squares, like `a1:1C b2:21S`, where
+    /// each square is written as in TPS. The player to move and the move number may follow as
+    /// in TPS and default to white's first move.
+    pub fn from_shorthand(s: &str, size: usize) -> Option<Self> {
+        let mut parts = s.split_whitespace().collect::<Vec<_>>();
+        let mut player = TakPlayer::White;
+        let mut move_index = 0;
+        if parts.len() >= 2 && parts.iter().rev().take(2).all(|part| !part.contains(':')) {
+            let tps = Self::try_from_str(&format!(
+                "x {} {}",
+                parts[parts.len() - 2],
+                parts[parts.len() - 1]
+            ))?;
+            player = tps.player;
+            move_index = tps.move_index;
+            parts.truncate(parts.len() - 2);
+        }
+
+        let mut squares = vec![vec![None; size]; size];
+        for part in parts {
+            let (coord, square) = part.split_once(':')?;
+            let mut chars = coord.chars();
+            let file = chars.next()?;
+            let x = (file as usize).checked_sub('a' as usize)?;
+            let rank = chars.as_str().parse::<usize>().ok()?;
+            if x >= size || rank == 0 || rank > size || square.starts_with('x') {
+                return None;
+            }
+            let row = size - rank;
+            Self::validate_square(square, row, x).ok()?;
+            if squares[row][x].replace(square).is_some() {
+                return None;
+            }
+        }
+
+        let empty_squares = |count: usize| match count {
+            1 => "x".to_string(),
+            _ => format!("x{count}"),
+        };
+        let position = squares
+            .iter()
+            .map(|row| {
+                let mut entries = Vec::new();
+                let mut empty = 0;
+                for square in row {
+                    match square {
+                        Some(square) => {
+                            if empty > 0 {
+                                entries.push(empty_squares(empty));
+                                empty = 0;
+                            }
+                            entries.push(square.to_string());
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    entries.push(empty_squares(empty));
+                }
+                entries.join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(TakTps {
+            position,
+            player,
+            move_index,
+        })
+    }
+
     pub fn try_from_str(s: &str) -> Option<Self> {
+        Self::parse(s).ok()
+    }
+
+    /// Reads a TPS string without looking at the position, which `validate` checks against the
+    /// board size.
+    pub fn parse(s: &str) -> Result<Self, TakTpsError> {
         let parts: Vec<&str> = s.split_whitespace().collect();
         if parts.len() != 3 {
-            return None;
+            return Err(TakTpsError::WrongPartCount(parts.len()));
         }
         let position = parts[0].to_string();
         let player = match parts[1] {
             "1" => TakPlayer::White,
             "2" => TakPlayer::Black,
-            _ => return None,
+            _ => return Err(TakTpsError::InvalidPlayer(parts[1].to_string())),
         };
-        let move_index = parts[2].parse::<usize>().ok()?;
-        if move_index == 0 {
-            return None;
-        }
-        Some(TakTps {
+        let move_index = parts[2]
+            .parse::<usize>()
+            .ok()
+            .filter(|&move_index| move_index > 0)
+            .ok_or_else(|| TakTpsError::InvalidMoveIndex(parts[2].to_string()))?;
+        Ok(TakTps {
             position,
             player,
             move_index: move_index - 1,
@@ -140,4 +352,126 @@ mod tests {
         let invalid_tps = TakTps::try_from_str("x3/x2,112C/x3 1  ");
         assert!(invalid_tps.is_none());
     }
+
+    #[test]
+    fn test_validate_tps() {
+        assert_eq!(TakTps::validate("x3/x2,112C/1S,2,x 2 5", 3), Ok(()));
+        assert_eq!(
+            TakTps::validate("x3/x3/x3 1", 3),
+            Err(TakTpsError::WrongPartCount(2))
+        );
+        assert_eq!(
+            TakTps::validate("x3/x3 1 1", 3),
+            Err(TakTpsError::WrongRowCount {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            TakTps::validate("x3/x2,1,2/x3 1 1", 3),
+            Err(TakTpsError::WrongRowLength {
+                row: 1,
+                expected: 3,
+                found: 4
+            })
+        );
+        assert_eq!(
+            TakTps::validate("x3/x3/x,1,x 1 1", 4),
+            Err(TakTpsError::WrongRowCount {
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(
+            TakTps::validate("x3/x3/x3 3 1", 3),
+            Err(TakTpsError::InvalidPlayer("3".to_string()))
+        );
+        assert_eq!(
+            TakTps::validate("x3/x3/x3 1 0", 3).unwrap_err().to_string(),
+            "invalid move number '0'"
+        );
+        assert_eq!(
+            TakTps::validate("x3/x2,1,2/x3 1 1", 3)
+                .unwrap_err()
+                .to_string(),
+            "row 2: expected 3 squares, found 4"
+        );
+    }
+
+    #[test]
+    fn test_validate_tps_squares() {
+        assert_eq!(
+            TakTps::validate("x3/x,12W,x/x3 1 1", 3),
+            Err(TakTpsError::UnknownCharacter {
+                row: 1,
+                column: 1,
+                character: 'W'
+            })
+        );
+        for (tps, column) in [
+            ("x3/x3/x,S1,x 1 1", 1),
+            ("x3/x3/x2,13 1 1", 2),
+            ("x3/x3/x0,x3 1 1", 0),
+            ("x3/x3/1SC,x2 1 1", 0),
+            ("x3/x3/1,,x 1 1", 1),
+        ] {
+            assert_eq!(
+                TakTps::validate(tps, 3),
+                Err(TakTpsError::InvalidSquare { row: 2, column }),
+                "{}",
+                tps
+            );
+        }
+    }
+
+    #[test]
+    fn test_named_positions() {
+        assert_eq!(TakTps::from_named("empty-6", 6), Some(TakTps::new_empty(6)));
+        assert_eq!(TakTps::from_named("empty", 5), Some(TakTps::new_empty(5)));
+        assert_eq!(TakTps::from_named("empty-6", 5), None);
+        assert_eq!(TakTps::from_named("sicilian", 5), None);
+        assert_eq!(
+            TakTps::from_named("swap-opening", 5).unwrap().to_string(),
+            "x4,1/x5/x5/x5/2,x4 1 2"
+        );
+    }
+
+    #[test]
+    fn test_shorthand_positions() {
+        let tps = TakTps::from_shorthand("a1:1C b2:2S c5:121", 5).unwrap();
+        assert_eq!(tps.to_string(), "x2,121,x2/x5/x5/x,2S,x3/1C,x4 1 1");
+        assert_eq!(TakTps::validate(&tps.to_string(), 5), Ok(()));
+
+        let tps = TakTps::from_shorthand("e3:12 2 7", 5).unwrap();
+        assert_eq!(tps.to_string(), "x5/x5/x4,12/x5/x5 2 7");
+        assert_eq!(TakTps::from_shorthand("", 3), Some(TakTps::new_empty(3)));
+
+        for invalid in [
+            "f1:1",
+            "a0:1",
+            "a1:1W",
+            "a1:x",
+            "a1:1 a1:2",
+            "a1",
+            "a1:1 3 1",
+        ] {
+            assert_eq!(TakTps::from_shorthand(invalid, 5), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_validate_tps_player_and_move() {
+        assert_eq!(
+            TakTps::validate("x3/x3/x3 3 1", 3),
+            Err(TakTpsError::InvalidPlayer("3".to_string()))
+        );
+        assert_eq!(
+            TakTps::validate("x3/x3/x3 1 0", 3),
+            Err(TakTpsError::InvalidMoveIndex("0".to_string()))
+        );
+        assert_eq!(
+            TakTps::validate("x3/x3/x3 1 a", 3),
+            Err(TakTpsError::InvalidMoveIndex("a".to_string()))
+        );
+    }
 }
@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 
 use crate::{
-    TakAction, TakActionRecord, TakCoord, TakDir, TakGame, TakGameState, TakInvalidActionError,
-    TakPieceVariant, TakPlayer, TakWinReason,
+    TakAction, TakActionRecord, TakBoard, TakCoord, TakDir, TakGame, TakGameState,
+    TakInvalidActionError, TakPieceVariant, TakPlayer, TakWinReason,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,23 +42,95 @@ impl TakPartialMove {
     pub fn is_valid(&self) -> bool {
         self.dir.is_some() && self.drops.iter().sum::<usize>() == self.take
     }
+
+    /// Lists the squares a click may continue this move on, so that the move can still be
+    /// completed legally on `board`. Before a direction is chosen, the origin is included for
+    /// lowering the take. A neighbor only counts if dropping all remaining pieces there is a
+    /// legal move, which rules out capstones and walls that can't be flattened.
+    pub fn remaining_legal_drops(&self, board: &TakBoard) -> Vec<TakCoord> {
+        let remaining = self.take.saturating_sub(self.drops.iter().sum());
+        let drop_pos = self.dir.map_or(self.pos, |d| {
+            self.pos.offset_dir_many(d, self.drops.len() as i32)
+        });
+        let mut squares = Vec::new();
+        if self.dir.is_none() || remaining > 0 {
+            squares.push(drop_pos);
+        }
+        let dirs = self.dir.map_or(TakDir::ALL.to_vec(), |d| vec![d]);
+        for dir in dirs {
+            let mut drops = self.drops.clone();
+            drops.push(remaining);
+            if board.can_move(self.pos, dir, self.take, &drops).is_ok() {
+                squares.push(drop_pos.offset_dir(dir));
+            }
+        }
+        squares
+    }
 }
 
 pub struct TakUIState {
     preview_game: TakGame,
     actual_game: TakGame,
+    /// The preview from before the first move of a what-if line, while exploring one.
+    branch_origin: Option<TakGame>,
+    /// Whether the board is shown turned around, with black's side at the bottom.
+    flipped: bool,
     pub pieces: HashMap<usize, TakUIPiece>,
     pub tiles: HashMap<TakCoord, TakUITile>,
     pub partial_move: Option<TakPartialMove>,
     pub priority_pieces: Vec<usize>,
     pub available_piece_types: [Vec<TakPieceVariant>; 2],
     pub flat_counts: [usize; 2],
+    /// A reserve of at most this many stones and capstones is reported as low.
+    pub low_reserve_threshold: usize,
+    /// What the last update changed in `pieces` and `tiles`.
+    pub diff: TakBoardDiff,
     pub on_game_update: Vec<Box<dyn FnMut()>>,
 }
 
+pub const DEFAULT_LOW_RESERVE_THRESHOLD: usize = 3;
+
+/// How close a player is to running out of pieces, which ends the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TakReserveStatus {
+    Plenty,
+    Low,
+    Empty,
+}
+
+/// The pieces and tiles that changed in an update, so views only need to redraw those.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TakBoardDiff {
+    pub added: Vec<usize>,
+    /// Pieces that were already shown but changed in any way, not only in position.
+    pub moved: Vec<usize>,
+    pub removed: Vec<usize>,
+    pub changed_tiles: Vec<TakCoord>,
+}
+
+impl TakBoardDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.moved.is_empty()
+            && self.removed.is_empty()
+            && self.changed_tiles.is_empty()
+    }
+
+    pub fn touches_piece(&self, id: usize) -> bool {
+        self.added.contains(&id) || self.moved.contains(&id) || self.removed.contains(&id)
+    }
+
+    pub fn touches_tile(&self, pos: TakCoord) -> bool {
+        self.changed_tiles.contains(&pos)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct TakUITile {
+    /// Where the tile is shown, which differs from its square when the board is flipped.
+    pub display_pos: TakCoord,
     pub owner: Option<TakPlayer>,
+    /// Bridges by the direction they are shown in.
     pub bridges: Vec<(TakDir, bool)>,
     pub highlighted: bool,
     pub selectable: bool,
@@ -86,6 +158,7 @@ impl TakUITile {
 pub struct TakUIPiece {
     pub player: TakPlayer,
     pub variant: TakPieceVariant,
+    /// Where the piece is shown, which differs from its square when the board is flipped.
     pub pos: TakCoord,
     pub height: usize,
     pub is_floating: bool,
@@ -100,11 +173,15 @@ impl TakUIState {
         let mut state = Self {
             actual_game: game.clone(),
             preview_game: game,
+            branch_origin: None,
+            flipped: false,
             pieces: HashMap::new(),
             partial_move: None,
             tiles: HashMap::new(),
             available_piece_types: [Vec::new(), Vec::new()],
             flat_counts: [0, 0],
+            low_reserve_threshold: DEFAULT_LOW_RESERVE_THRESHOLD,
+            diff: TakBoardDiff::default(),
             on_game_update: Vec::new(),
             priority_pieces: Vec::new(),
         };
@@ -123,10 +200,50 @@ impl TakUIState {
         &self.preview_game
     }
 
+    /// Classifies the reserves shown in the preview, so a warning can be shown before a player
+    /// runs out.
+    pub fn reserve_status(&self) -> [TakReserveStatus; 2] {
+        self.preview_game.hands.clone().map(|hand| {
+            let remaining = hand.stones + hand.capstones;
+            if remaining == 0 {
+                TakReserveStatus::Empty
+            } else if remaining <= self.low_reserve_threshold {
+                TakReserveStatus::Low
+            } else {
+                TakReserveStatus::Plenty
+            }
+        })
+    }
+
+    /// Turns the board around for the player on the other side, without affecting the game.
+    pub fn set_orientation(&mut self, flip: bool) {
+        self.flipped = flip;
+        self.on_game_update();
+    }
+
+    pub fn is_flipped(&self) -> bool {
+        self.flipped
+    }
+
+    /// Maps a square to where it is shown. Turning the board around is its own inverse, so
+    /// this also maps a shown position back to its square.
+    pub fn orient(&self, pos: TakCoord) -> TakCoord {
+        if self.flipped {
+            let last = self.preview_game.board.size as i32 - 1;
+            TakCoord::new(last - pos.x, last - pos.y)
+        } else {
+            pos
+        }
+    }
+
     pub fn is_review(&self) -> bool {
         self.actual_game.ply_index > self.preview_game.ply_index
     }
 
+    pub fn is_branching(&self) -> bool {
+        self.branch_origin.is_some()
+    }
+
     pub fn get_visible_active_player(&self) -> TakPlayer {
         if self.is_review() {
             self.preview_game.current_player
@@ -156,6 +273,11 @@ impl TakUIState {
         self.on_game_update();
     }
 
+    pub fn sync_time_remaining(&mut self, player: TakPlayer, time_remaining: u64) {
+        self.actual_game.sync_time_remaining(player, time_remaining);
+        self.on_game_update();
+    }
+
     pub fn check_timeout(&mut self) {
         if self.actual_game.check_timeout() {
             self.partial_move = None;
@@ -166,6 +288,7 @@ impl TakUIState {
     fn clone_actual_game_into_preview(&mut self) {
         self.preview_game = self.actual_game.clone();
         self.preview_game.clock = None;
+        self.branch_origin = None;
     }
 
     pub fn try_do_action(&mut self, action: TakAction) -> Result<(), TakInvalidActionError> {
@@ -177,7 +300,15 @@ impl TakUIState {
         Ok(())
     }
 
+    /// Limits a ply index to the plies of the game, from its start position to the last move.
+    pub fn clamp_ply_index(&self, ply_index: usize) -> usize {
+        let start = self.actual_game.settings.start_position.get_ply_index();
+        ply_index.clamp(start, self.actual_game.ply_index)
+    }
+
+    /// Shows the position after `ply_index` in the preview. Out of range plies are clamped.
     pub fn try_seek_ply_index(&mut self, ply_index: usize) {
+        let ply_index = self.clamp_ply_index(ply_index);
         let old_preview_game = std::mem::replace(
             &mut self.preview_game,
             self.actual_game
@@ -185,6 +316,7 @@ impl TakUIState {
                 .expect("Should be able to seek to ply index"),
         );
         self.preview_game.clock = None;
+        self.branch_origin = None;
         self.partial_move = None;
         self.priority_pieces = if old_preview_game.ply_index + 1 == ply_index {
             Self::get_stones_from_last_action_in_order(&self.preview_game)
@@ -196,6 +328,70 @@ impl TakUIState {
         self.on_game_update();
     }
 
+    /// Lists every square a spread of the stack at `pos` could end on this turn.
+    pub fn reachable_squares(&self, pos: TakCoord) -> Vec<TakCoord> {
+        let game = &self.actual_game;
+        if game.game_state != TakGameState::Ongoing || game.is_swap_ply() {
+            return Vec::new();
+        }
+        let Some(stack) = game.board.try_get_stack(pos) else {
+            return Vec::new();
+        };
+        if stack.player() != game.current_player {
+            return Vec::new();
+        }
+        let max_take = stack.height().min(game.board.size);
+        let mut squares = Vec::new();
+        for dir in TakDir::ALL {
+            for distance in 1..=max_take {
+                // Dropping single pieces until the last square leaves the most pieces for it,
+                // while a capstone flattening a wall needs a single piece at the end.
+                let reachable = (distance..=max_take).any(|take| {
+                    let mut drops = vec![1; distance];
+                    drops[distance - 1] += take - distance;
+                    game.board.can_move(pos, dir, take, &drops).is_ok()
+                });
+                if !reachable {
+                    break;
+                }
+                squares.push(pos.offset_dir_many(dir, distance as i32));
+            }
+        }
+        squares
+    }
+
+    /// Plays a move typed as PTN, as if it had been entered by clicking. A move that can't be
+    /// parsed or played leaves the game and any partial move as they were.
+    pub fn apply_ptn_move(&mut self, ptn: &str) -> Result<(), TakInvalidActionError> {
+        let action =
+            TakAction::from_ptn(ptn.trim()).ok_or(TakInvalidActionError::InvalidNotation)?;
+        self.try_do_action(action)
+    }
+
+    /// Plays a move on the shown position only, leaving the actual game untouched. Further
+    /// calls continue the same line until `exit_branch` is called.
+    pub fn branch_preview(&mut self, action: TakAction) -> Result<(), TakInvalidActionError> {
+        let mut branched = self.preview_game.clone();
+        branched.try_do_action(action)?;
+        let origin = std::mem::replace(&mut self.preview_game, branched);
+        self.branch_origin.get_or_insert(origin);
+        self.partial_move = None;
+        self.priority_pieces = Self::get_stones_from_last_action_in_order(&self.preview_game);
+        self.on_game_update();
+        Ok(())
+    }
+
+    /// Returns to the position shown before the what-if line was started.
+    pub fn exit_branch(&mut self) {
+        let Some(origin) = self.branch_origin.take() else {
+            return;
+        };
+        self.preview_game = origin;
+        self.partial_move = None;
+        self.priority_pieces = Self::get_stones_from_last_action_in_order(&self.preview_game);
+        self.on_game_update();
+    }
+
     fn do_partial_move(&mut self, action: TakAction) {
         self.clone_actual_game_into_preview();
         self.preview_game
@@ -329,10 +525,13 @@ impl TakUIState {
     }
 
     pub fn on_game_update(&mut self) {
-        let prev_pieces = self.pieces.clone();
-        self.pieces.clear();
-        self.tiles.clear();
-        self.flat_counts = self.preview_game.board.count_flats();
+        let prev_pieces = std::mem::take(&mut self.pieces);
+        let prev_tiles = std::mem::take(&mut self.tiles);
+        let mut diff = TakBoardDiff::default();
+        self.flat_counts = self
+            .preview_game
+            .board
+            .count_flats(&self.preview_game.settings.scoring);
 
         let drop_diff = match &self.partial_move {
             Some(TakPartialMove {
@@ -348,6 +547,7 @@ impl TakUIState {
         };
 
         for (pos, stack) in self.preview_game.board.iter_pieces(None) {
+            let display_pos = self.orient(pos);
             let stack_height = stack.height();
             let floating_threshold = drop_diff
                 .filter(|x| x.0 == pos)
@@ -365,7 +565,7 @@ impl TakUIState {
                     stone.id,
                     TakUIPiece {
                         player: stone.player,
-                        pos,
+                        pos: display_pos,
                         height: effective_height,
                         is_floating: floating_threshold.is_some_and(|x| height >= x),
                         z_priority: priority_index,
@@ -381,47 +581,29 @@ impl TakUIState {
                 );
             }
         }
+        for (id, piece) in &self.pieces {
+            match prev_pieces.get(id) {
+                Some(prev) if prev.deleted => diff.added.push(*id),
+                Some(prev) if prev != piece => diff.moved.push(*id),
+                Some(_) => {}
+                None => diff.added.push(*id),
+            }
+        }
         for (id, mut data) in prev_pieces {
             if !self.pieces.contains_key(&id) {
+                if !data.deleted {
+                    diff.removed.push(id);
+                }
                 data.deleted = true;
                 self.pieces.insert(id, data);
             }
         }
 
-        let mut click_options = Vec::new();
-        if let Some(partial_move) = &self.partial_move {
-            let drop_pos = partial_move.dir.map_or(partial_move.pos, |d| {
-                partial_move
-                    .pos
-                    .offset_dir_many(d, partial_move.drops.len() as i32)
-            });
-            click_options.push(drop_pos);
-            let check_dirs = partial_move.dir.map_or(TakDir::ALL.to_vec(), |d| vec![d]);
-            for dir in check_dirs {
-                let new_pos = drop_pos.offset_dir(dir);
-                if !new_pos.is_valid(self.preview_game.board.size) {
-                    continue;
-                }
-                if let Some(other_stack) = self.preview_game.board.try_get_stack(new_pos) {
-                    if other_stack.variant == TakPieceVariant::Flat {
-                        click_options.push(new_pos);
-                    } else if other_stack.variant == TakPieceVariant::Wall {
-                        let stack = self
-                            .actual_game
-                            .board
-                            .try_get_stack(partial_move.pos)
-                            .expect("Partial move position should have a stack");
-                        let drops_diff =
-                            partial_move.take - partial_move.drops.iter().sum::<usize>();
-                        if drops_diff == 1 && stack.variant == TakPieceVariant::Capstone {
-                            click_options.push(new_pos);
-                        }
-                    }
-                } else {
-                    click_options.push(new_pos);
-                }
-            }
-        }
+        let click_options = self
+            .partial_move
+            .as_ref()
+            .map(|partial_move| partial_move.remaining_legal_drops(&self.actual_game.board))
+            .unwrap_or_default();
 
         let mut highlighted_tiles = Vec::new();
         if self.preview_game.game_state != TakGameState::Ongoing {
@@ -456,7 +638,9 @@ impl TakUIState {
 
         let mut last_action_tiles = Vec::new();
         if highlighted_tiles.len() == 0 {
-            let game = if self.actual_game.ply_index <= self.preview_game.ply_index {
+            let game = if self.branch_origin.is_none()
+                && self.actual_game.ply_index <= self.preview_game.ply_index
+            {
                 &self.actual_game
             } else {
                 &self.preview_game
@@ -483,15 +667,20 @@ impl TakUIState {
             let bridges = TakDir::ALL
                 .iter()
                 .map(|&dir| {
+                    let board_dir = if self.flipped { dir.opposite() } else { dir };
                     (
                         dir,
-                        bridges.as_ref().map_or(false, |(_, b)| b.contains(&dir)),
+                        bridges
+                            .as_ref()
+                            .map_or(false, |(_, b)| b.contains(&board_dir)),
                     )
                 })
                 .collect::<Vec<_>>();
+            let display_pos = self.orient(pos);
             self.tiles.insert(
                 pos,
                 TakUITile {
+                    display_pos,
                     owner,
                     bridges,
                     highlighted: false,
@@ -509,8 +698,15 @@ impl TakUIState {
         for pos in last_action_tiles {
             self.tiles.get_mut(&pos).unwrap().last_action = true;
         }
+        diff.changed_tiles = TakCoord::iter_board(self.preview_game.board.size)
+            .filter(|pos| prev_tiles.get(pos) != self.tiles.get(pos))
+            .collect();
+        diff.added.sort_unstable();
+        diff.moved.sort_unstable();
+        diff.removed.sort_unstable();
+        self.diff = diff;
 
-        if self.actual_game.ply_index < 2 {
+        if self.actual_game.is_swap_ply() {
             self.available_piece_types = [vec![TakPieceVariant::Flat], vec![TakPieceVariant::Flat]];
         } else {
             self.available_piece_types =
@@ -560,3 +756,287 @@ impl TakUIState {
         Some((player, bridges))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{TakGameSettings, TakKomi, TakStones, TakTps};
+
+    fn state_with_moves(moves: &[&str]) -> TakUIState {
+        let game = TakGame::new(TakGameSettings::new(5, None, TakKomi::none(), None)).unwrap();
+        let mut state = TakUIState::new(game);
+        for mv in moves {
+            state
+                .try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        state
+    }
+
+    fn visible_pieces(state: &TakUIState) -> HashMap<usize, TakUIPiece> {
+        state
+            .pieces
+            .iter()
+            .filter(|(_, piece)| !piece.deleted)
+            .map(|(id, piece)| (*id, piece.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_branch_and_exit_restores_position() {
+        let mut state = state_with_moves(&["a1", "e5", "c3", "c4"]);
+        let tiles = state.tiles.clone();
+        let pieces = visible_pieces(&state);
+
+        state
+            .branch_preview(TakAction::from_ptn("c3+").unwrap())
+            .unwrap();
+        state
+            .branch_preview(TakAction::from_ptn("d4").unwrap())
+            .unwrap();
+        assert!(state.is_branching());
+        assert_eq!(state.preview_game().ply_index, 6);
+        assert_eq!(state.game().ply_index, 4);
+        assert_ne!(visible_pieces(&state), pieces);
+        assert!(state.tiles[&TakCoord::new(3, 3)].last_action);
+
+        assert!(
+            state
+                .branch_preview(TakAction::from_ptn("d4").unwrap())
+                .is_err()
+        );
+        assert_eq!(state.preview_game().ply_index, 6);
+
+        state.exit_branch();
+        assert!(!state.is_branching());
+        assert_eq!(state.preview_game().to_tps(), state.game().to_tps());
+        assert_eq!(state.tiles, tiles);
+        assert_eq!(visible_pieces(&state), pieces);
+    }
+
+    #[test]
+    fn test_apply_ptn_move() {
+        let mut state = state_with_moves(&["a1", "e5", "c3", "c4"]);
+        state.add_square_to_partial_move(TakCoord::new(2, 2));
+        let partial_move = state.partial_move.clone();
+        assert!(partial_move.is_some());
+        let tps = state.game().to_tps();
+
+        assert_eq!(
+            state.apply_ptn_move("c3>>"),
+            Err(TakInvalidActionError::InvalidNotation)
+        );
+        assert!(state.apply_ptn_move("2c3+").is_err());
+        assert!(state.apply_ptn_move("b2+").is_err());
+        assert_eq!(state.game().to_tps(), tps);
+        assert_eq!(state.partial_move, partial_move);
+
+        state.apply_ptn_move("c3+").unwrap();
+        assert_eq!(state.game().ply_index, 5);
+        assert_eq!(state.partial_move, None);
+        state.apply_ptn_move("d4").unwrap();
+        state.apply_ptn_move(" 2c4>11 ").unwrap();
+        assert_eq!(state.game().ply_index, 7);
+        assert_eq!(state.preview_game().to_tps(), state.game().to_tps());
+    }
+
+    #[test]
+    fn test_reachable_squares() {
+        let tps = TakTps::try_from_str("x5/x2,2S,1C,x/x,2,121,x,2C/x5/x5 1 10").unwrap();
+        let settings = TakGameSettings::new_with_position(5, tps, None, TakKomi::none(), None);
+        let state = TakUIState::new(TakGame::new(settings).unwrap());
+        let reachable = |x, y| {
+            state
+                .reachable_squares(TakCoord::new(x, y))
+                .into_iter()
+                .collect::<HashSet<_>>()
+        };
+        let squares = |coords: &[(i32, i32)]| {
+            coords
+                .iter()
+                .map(|&(x, y)| TakCoord::new(x, y))
+                .collect::<HashSet<_>>()
+        };
+
+        // The wall above and the capstone two squares to the right block the stack on c3.
+        assert_eq!(
+            reachable(2, 2),
+            squares(&[(0, 2), (1, 2), (3, 2), (2, 1), (2, 0)])
+        );
+        // The capstone on d4 may flatten the wall next to it.
+        assert_eq!(reachable(3, 3), squares(&[(2, 3), (4, 3), (3, 4), (3, 2)]));
+        assert!(reachable(4, 2).is_empty());
+        assert!(reachable(0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_real_move_ends_branch() {
+        let mut state = state_with_moves(&["a1", "e5"]);
+        state
+            .branch_preview(TakAction::from_ptn("c3").unwrap())
+            .unwrap();
+        state
+            .try_do_action(TakAction::from_ptn("b2").unwrap())
+            .unwrap();
+        assert!(!state.is_branching());
+        assert_eq!(state.preview_game().to_tps(), state.game().to_tps());
+    }
+
+    #[test]
+    fn test_seek_clamps_ply_index() {
+        let mut state = state_with_moves(&["a1", "e5", "c3"]);
+        state.try_seek_ply_index(1);
+        assert_eq!(state.preview_game().ply_index, 1);
+        state.try_seek_ply_index(50);
+        assert_eq!(state.preview_game().ply_index, 3);
+
+        let tps = TakTps::try_from_str("x5/x5/x,2,1,x2/x5/x5 1 4").unwrap();
+        let start = tps.get_ply_index();
+        let settings = TakGameSettings::new_with_position(5, tps, None, TakKomi::none(), None);
+        let mut state = TakUIState::new(TakGame::new(settings).unwrap());
+        for mv in ["a1", "e5"] {
+            state
+                .try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        assert_eq!(state.clamp_ply_index(0), start);
+        assert_eq!(state.clamp_ply_index(start + 1), start + 1);
+        assert_eq!(state.clamp_ply_index(start + 9), start + 2);
+        state.try_seek_ply_index(0);
+        assert_eq!(state.preview_game().ply_index, start);
+        assert_eq!(
+            state.preview_game().to_tps().to_string(),
+            "x5/x5/x,2,1,x2/x5/x5 1 4"
+        );
+        state.try_seek_ply_index(start + 1);
+        assert_eq!(state.preview_game().ply_index, start + 1);
+    }
+
+    #[test]
+    fn test_remaining_legal_drops() {
+        let tps = TakTps::try_from_str("x5/x5/2121C,x,2S,x2/x5/x5 1 5").unwrap();
+        let settings = TakGameSettings::new_with_position(5, tps, None, TakKomi::none(), None);
+        let mut state = TakUIState::new(TakGame::new(settings).unwrap());
+        let (a3, b3, c3) = (
+            TakCoord::new(0, 2),
+            TakCoord::new(1, 2),
+            TakCoord::new(2, 2),
+        );
+        let legal = |state: &TakUIState| {
+            let squares = state
+                .partial_move
+                .as_ref()
+                .unwrap()
+                .remaining_legal_drops(&state.game().board);
+            let selectable = state
+                .tiles
+                .iter()
+                .filter(|(_, tile)| tile.selectable)
+                .map(|(pos, _)| *pos)
+                .collect::<HashSet<_>>();
+            assert_eq!(squares.iter().copied().collect::<HashSet<_>>(), selectable);
+            squares
+        };
+
+        state.add_square_to_partial_move(a3);
+        state.add_square_to_partial_move(a3);
+        assert_eq!(state.partial_move.as_ref().unwrap().take, 3);
+        assert_eq!(
+            legal(&state).into_iter().collect::<HashSet<_>>(),
+            HashSet::from([a3, b3, TakCoord::new(0, 3), TakCoord::new(0, 1)])
+        );
+
+        // Two pieces are still in hand, which is too many to flatten the wall on c3.
+        state.add_square_to_partial_move(b3);
+        assert_eq!(legal(&state), vec![b3]);
+
+        // With only the capstone left, it may flatten the wall.
+        state.add_square_to_partial_move(b3);
+        assert_eq!(legal(&state), vec![b3, c3]);
+        state.add_square_to_partial_move(c3).unwrap().unwrap();
+        assert_eq!(
+            state.game().board.try_get_stack(c3).unwrap().variant,
+            TakPieceVariant::Capstone
+        );
+    }
+
+    #[test]
+    fn test_board_diff() {
+        let mut state = state_with_moves(&[]);
+        assert_eq!(state.diff.changed_tiles.len(), 25);
+
+        state
+            .try_do_action(TakAction::from_ptn("c3").unwrap())
+            .unwrap();
+        let id = state
+            .game()
+            .board
+            .try_get_stack(TakCoord::new(2, 2))
+            .unwrap()
+            .composition[0]
+            .id;
+        assert_eq!(state.diff.added, vec![id]);
+        assert!(state.diff.moved.is_empty());
+        assert!(state.diff.removed.is_empty());
+        assert_eq!(state.diff.changed_tiles, vec![TakCoord::new(2, 2)]);
+
+        state.try_seek_ply_index(0);
+        assert_eq!(state.diff.removed, vec![id]);
+        assert!(state.diff.touches_tile(TakCoord::new(2, 2)));
+        state.on_game_update();
+        assert!(state.diff.is_empty());
+    }
+
+    #[test]
+    fn test_reserve_status_turns_low_at_threshold() {
+        let settings = TakGameSettings::new(5, Some(TakStones::new(5, 0)), TakKomi::none(), None);
+        let mut state = TakUIState::new(TakGame::new(settings).unwrap());
+        state.low_reserve_threshold = 3;
+        assert_eq!(state.reserve_status(), [TakReserveStatus::Plenty; 2]);
+
+        for mv in ["a1", "e5", "a2"] {
+            state
+                .try_do_action(TakAction::from_ptn(mv).unwrap())
+                .unwrap();
+        }
+        assert_eq!(
+            state.reserve_status(),
+            [TakReserveStatus::Low, TakReserveStatus::Plenty]
+        );
+
+        state
+            .try_do_action(TakAction::from_ptn("e4").unwrap())
+            .unwrap();
+        assert_eq!(state.reserve_status(), [TakReserveStatus::Low; 2]);
+    }
+
+    #[test]
+    fn test_flip_mirrors_positions() {
+        let mut state = state_with_moves(&["a1", "e5", "b3", "a2"]);
+        let tiles = state.tiles.clone();
+        let pieces = visible_pieces(&state);
+        let board = state.game().board.clone();
+
+        state.set_orientation(true);
+        assert!(state.is_flipped());
+        for (id, piece) in visible_pieces(&state) {
+            let unflipped = pieces[&id].pos;
+            assert_eq!(piece.pos, TakCoord::new(4 - unflipped.x, 4 - unflipped.y));
+        }
+        let b3 = TakCoord::new(1, 2);
+        assert_eq!(state.tiles[&b3].display_pos, TakCoord::new(3, 2));
+        // The bridge from a1 to a2 points down and the one to the edge right once turned around.
+        let a1 = &state.tiles[&TakCoord::new(0, 0)];
+        assert!(a1.bridges.contains(&(TakDir::Down, true)));
+        assert!(a1.bridges.contains(&(TakDir::Right, true)));
+        assert!(a1.bridges.contains(&(TakDir::Left, false)));
+        assert_eq!(state.orient(state.orient(b3)), b3);
+        assert_eq!(state.game().board, board);
+
+        state.set_orientation(false);
+        assert_eq!(state.tiles, tiles);
+        assert_eq!(visible_pieces(&state), pieces);
+    }
+}
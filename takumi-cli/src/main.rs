@@ -0,0 +1,106 @@
+//! Analyzes every position of a game with takumi and prints the results as CSV.
+//!
+//! Usage: `takumi-cli <game.ptn> [--depth <plies>] [--millis <per move>]`
+
+use std::process::ExitCode;
+
+use tak_core::{TakGame, TakPlayer, TakPtn};
+
+const DEFAULT_DEPTH: usize = 6;
+const DEFAULT_MILLIS: u64 = 1_000;
+
+struct Options {
+    path: String,
+    depth: usize,
+    millis: u64,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut path = None;
+    let mut depth = DEFAULT_DEPTH;
+    let mut millis = DEFAULT_MILLIS;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => depth = parse_value(&arg, args.next())?,
+            "--millis" => millis = parse_value(&arg, args.next())?,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(format!("Unexpected argument: {arg}")),
+        }
+    }
+    let path = path.ok_or("Missing PTN file")?;
+    Ok(Options {
+        path,
+        depth,
+        millis,
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{name} needs a number"))
+}
+
+/// One CSV row per ply: the move played and what the engine thinks of the position before it.
+/// Scores are from white's point of view.
+fn analyze_game(game: &TakGame, depth: usize, millis: u64) -> Vec<String> {
+    let mut settings = game.settings.clone();
+    settings.time_mode = None;
+    let mut replay = TakGame::new(settings).expect("Settings were valid for the game");
+    let mut rows = vec!["ply,move,depth,score,best_move".to_string()];
+    for record in &game.action_history {
+        let mut board = takumi::Board::from(&replay);
+        let mut last_update = None;
+        takumi::iterative_deepening_with_config(
+            &mut board,
+            depth,
+            millis,
+            &takumi::SearchConfig::default(),
+            |update| last_update = Some(update),
+        );
+        let (depth, score, best_move) = match last_update {
+            Some(update) => {
+                let score = match replay.current_player {
+                    TakPlayer::White => update.score,
+                    TakPlayer::Black => -update.score,
+                };
+                let best_move = update.best_move.to_ptn(replay.board.size);
+                (update.depth.to_string(), score.to_string(), best_move)
+            }
+            None => Default::default(),
+        };
+        rows.push(format!(
+            "{},{},{depth},{score},{best_move}",
+            replay.ply_index,
+            record.to_ptn()
+        ));
+        replay
+            .try_do_action(record.to_action())
+            .expect("Move was legal when the game was replayed");
+    }
+    rows
+}
+
+fn run(options: &Options) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(&options.path)
+        .map_err(|err| format!("Failed to read {}: {err}", options.path))?;
+    let ptn = TakPtn::try_from_str(&text).ok_or("Invalid PTN")?;
+    let game = TakGame::try_from_ptn(ptn).map_err(|err| format!("Invalid game: {err}"))?;
+    Ok(analyze_game(&game, options.depth, options.millis))
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(|options| run(&options));
+    match result {
+        Ok(rows) => {
+            for row in rows {
+                println!("{row}");
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::process::Command;
+
+#[test]
+fn test_one_row_per_ply() {
+    let sample = concat!(env!("CARGO_MANIFEST_DIR"), "/../tak-core/tests/playtak.ptn");
+    let output = Command::new(env!("CARGO_BIN_EXE_takumi-cli"))
+        .args([sample, "--depth", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("ply,move,depth,score,best_move"));
+    let rows = lines
+        .map(|line| line.split(',').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 80);
+    for (ply, row) in rows.iter().enumerate() {
+        assert_eq!(row.len(), 5);
+        assert_eq!(row[0], ply.to_string());
+    }
+    assert_eq!(rows[0][1], "a6");
+    assert_eq!(rows[1][1], "f1");
+    // Black finishes the flat win with the last placement.
+    assert_eq!(rows[79][1], "c4");
+}
@@ -0,0 +1,148 @@
+use tak_core::{TakDrawReason, TakGameState, TakPlayer, TakWinReason};
+
+use crate::{Action, Board, Settings, gen_moves, iterative_deepening};
+
+pub trait Engine {
+    fn choose(&mut self, board: &Board) -> Action;
+}
+
+/// Plays uniformly random legal moves. Uses a small xorshift generator so runs are
+/// reproducible from the seed.
+pub struct RandomEngine {
+    state: u64,
+}
+
+impl RandomEngine {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed.max(1) }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl Engine for RandomEngine {
+    fn choose(&mut self, board: &Board) -> Action {
+        let moves = gen_moves(board);
+        let index = (self.next() % moves.len() as u64) as usize;
+        moves[index].clone()
+    }
+}
+
+/// Runs the regular iterative deepening search with a fixed time budget per move.
+pub struct SearchEngine {
+    pub max_depth: usize,
+    pub move_time: u64,
+}
+
+impl Engine for SearchEngine {
+    fn choose(&mut self, board: &Board) -> Action {
+        let mut board = board.clone();
+        match iterative_deepening(&mut board, self.max_depth, self.move_time) {
+            (_, Some((_, action))) => action,
+            (_, None) => gen_moves(&board).swap_remove(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchSettings {
+    pub size: usize,
+    pub settings: Settings,
+    pub max_plies: usize,
+}
+
+fn tak_player(player: u64) -> TakPlayer {
+    if player == Board::PLAYER_WHITE {
+        TakPlayer::White
+    } else {
+        TakPlayer::Black
+    }
+}
+
+/// Plays a game between two engines. A player who tries an illegal move forfeits, which is
+/// reported as a resignation. A game still going after `max_plies` plies is drawn.
+pub fn play_match(
+    white: &mut dyn Engine,
+    black: &mut dyn Engine,
+    settings: MatchSettings,
+) -> TakGameState {
+    let mut board = Board::empty(settings.size, settings.settings);
+    for _ in 0..settings.max_plies {
+        let player = board.current_player;
+        let action = if player == Board::PLAYER_WHITE {
+            white.choose(&board)
+        } else {
+            black.choose(&board)
+        };
+        if !gen_moves(&board).contains(&action) {
+            return TakGameState::Win(tak_player(1 - player), TakWinReason::Resignation);
+        }
+        board.make(&action);
+        match board.result {
+            Some(2) => return TakGameState::Draw(TakDrawReason::Flat),
+            Some(winner) => {
+                let reason = if board.has_road(winner) {
+                    TakWinReason::Road
+                } else {
+                    TakWinReason::Flat
+                };
+                return TakGameState::Win(tak_player(winner), reason);
+            }
+            None => {}
+        }
+    }
+    TakGameState::Draw(TakDrawReason::MoveLimit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedEngine(Action);
+
+    impl Engine for FixedEngine {
+        fn choose(&mut self, _board: &Board) -> Action {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_random_engines_terminate() {
+        for seed in 1..20 {
+            let settings = MatchSettings {
+                size: 4,
+                settings: Settings::new(0),
+                max_plies: 1000,
+            };
+            let mut white = RandomEngine::new(seed);
+            let mut black = RandomEngine::new(seed * 31);
+            match play_match(&mut white, &mut black, settings) {
+                TakGameState::Win(player, TakWinReason::Resignation) => {
+                    panic!("{player:?} won because of an illegal move")
+                }
+                TakGameState::Win(_, _) | TakGameState::Draw(_) => {}
+                TakGameState::Ongoing | TakGameState::Canceled => panic!("Game didn't end"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_illegal_move_loses() {
+        let settings = MatchSettings {
+            size: 5,
+            settings: Settings::new(0),
+            max_plies: 10,
+        };
+        let mut white = RandomEngine::new(1);
+        let mut black = FixedEngine(Action::Spread(0, Board::DIR_RIGHT, 1, 1));
+        assert_eq!(
+            play_match(&mut white, &mut black, settings),
+            TakGameState::Win(TakPlayer::White, TakWinReason::Resignation)
+        );
+    }
+}
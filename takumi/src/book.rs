@@ -0,0 +1,206 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use tak_core::{TakBoard, TakCoord};
+
+use crate::{Action, Board, Settings};
+
+const SYMMETRY_COUNT: usize = 8;
+
+/// The book the search consults when `SearchConfig::opening_book` is set.
+pub static STANDARD_BOOK: LazyLock<OpeningBook> = LazyLock::new(OpeningBook::standard);
+
+fn to_coord(size: usize, pos: usize) -> TakCoord {
+    TakCoord::new((pos % size) as i32, (size - 1 - pos / size) as i32)
+}
+
+fn to_pos(size: usize, coord: TakCoord) -> usize {
+    (size - 1 - coord.y as usize) * size + coord.x as usize
+}
+
+fn inverse_symmetry(size: usize, symmetry: usize) -> usize {
+    (0..SYMMETRY_COUNT)
+        .find(|&inverse| {
+            TakCoord::iter_board(size)
+                .all(|coord| coord.transformed(size, symmetry).transformed(size, inverse) == coord)
+        })
+        .expect("Every symmetry has an inverse")
+}
+
+/// Maps an action the same way `TakBoard::transformed` maps the board. The direction of a
+/// spread follows from where its first neighbour ends up.
+fn transform_action(size: usize, symmetry: usize, action: &Action) -> Action {
+    let transform = |pos: usize| to_coord(size, pos).transformed(size, symmetry);
+    match action {
+        Action::Place(pos, variant) => Action::Place(to_pos(size, transform(*pos)), *variant),
+        Action::Spread(pos, dir, take, spreads) => {
+            let (dx, dy) = match *dir {
+                Board::DIR_RIGHT => (1, 0),
+                Board::DIR_LEFT => (-1, 0),
+                Board::DIR_DOWN => (0, -1),
+                _ => (0, 1),
+            };
+            let from = transform(*pos);
+            let to = to_coord(size, *pos)
+                .offset(dx, dy)
+                .transformed(size, symmetry);
+            let dir = match (to.x - from.x, to.y - from.y) {
+                (1, 0) => Board::DIR_RIGHT,
+                (-1, 0) => Board::DIR_LEFT,
+                (0, -1) => Board::DIR_DOWN,
+                _ => Board::DIR_UP,
+            };
+            Action::Spread(to_pos(size, from), dir, *take, *spreads)
+        }
+    }
+}
+
+/// Returns the lexicographically smallest position string among all symmetric
+/// variants of the board, together with the symmetry that produces it.
+fn canonicalize(board: &Board) -> (String, usize) {
+    let tak_board = board.to_tak_board();
+    (0..SYMMETRY_COUNT)
+        .map(|symmetry| {
+            (
+                position_key(board, &tak_board.transformed(symmetry)),
+                symmetry,
+            )
+        })
+        .min()
+        .expect("There is at least one symmetry")
+}
+
+fn position_key(board: &Board, transformed: &TakBoard) -> String {
+    format!(
+        "{} {} {}",
+        transformed.to_partial_tps(),
+        board.current_player,
+        board.ply_index
+    )
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpeningBook {
+    entries: HashMap<String, Action>,
+}
+
+impl OpeningBook {
+    /// Builds a book from position strings and the move to play in them.
+    /// Entries with unparsable positions are skipped.
+    pub fn from_entries(entries: impl IntoIterator<Item = (String, Action)>) -> Self {
+        let mut book = HashMap::new();
+        for (position, action) in entries {
+            let Some(board) = Board::try_from_pos_str(&position, Settings::new(0)) else {
+                continue;
+            };
+            let (key, symmetry) = canonicalize(&board);
+            book.insert(key, transform_action(board.size, symmetry, &action));
+        }
+        Self { entries: book }
+    }
+
+    /// Opening moves for every board size: start in a corner and answer in the
+    /// opposite one.
+    pub fn standard() -> Self {
+        let mut entries = Vec::new();
+        for size in 3..=8 {
+            let mut board = Board::empty(size, Settings::new(0));
+            let first = Action::Place(0, Board::VARIANT_FLAT);
+            entries.push((board.to_pos_str(), first.clone()));
+            board.make(&first);
+            let reply = Action::Place(size * size - 1, Board::VARIANT_FLAT);
+            entries.push((board.to_pos_str(), reply));
+        }
+        Self::from_entries(entries)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up the book move for a position, mapped back onto the board's
+    /// actual orientation.
+    pub fn lookup(&self, board: &Board) -> Option<Action> {
+        let (key, symmetry) = canonicalize(board);
+        let action = self.entries.get(&key)?;
+        let inverse = inverse_symmetry(board.size, symmetry);
+        Some(transform_action(board.size, inverse, action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tak_core::TakPlayer;
+
+    use crate::{SearchConfig, gen_moves, iterative_deepening_with_config};
+
+    use super::*;
+
+    #[test]
+    fn test_rotated_corners_share_entry() {
+        let book = OpeningBook::from_entries([(
+            "2,x4/x5/x5/x5/x5 2 1".to_string(),
+            Action::Place(24, Board::VARIANT_FLAT),
+        )]);
+        assert_eq!(book.len(), 1);
+
+        let corners = [0, 4, 24, 20];
+        let replies = [24, 20, 0, 4];
+        for (corner, reply) in corners.into_iter().zip(replies) {
+            let mut board = Board::empty(5, Settings::new(0));
+            board.make(&Action::Place(corner, Board::VARIANT_FLAT));
+            let action = book.lookup(&board).expect("Position should be in book");
+            assert_eq!(action, Action::Place(reply, Board::VARIANT_FLAT));
+            assert!(gen_moves(&board).contains(&action));
+        }
+    }
+
+    #[test]
+    fn test_spread_direction_follows_symmetry() {
+        let board = Board::try_from_pos_str("x4/x,1,x2/x,2,x2/x4 1 3", Settings::new(0)).unwrap();
+        let action = Action::Spread(5, Board::DIR_RIGHT, 1, 1);
+        let mut moved = board.clone();
+        moved.make(&action);
+        for symmetry in 0..SYMMETRY_COUNT {
+            let mut transformed = Board::from_tak_board(
+                &board.to_tak_board().transformed(symmetry),
+                TakPlayer::White,
+                board.ply_index,
+                Settings::new(0),
+            );
+            transformed.make(&transform_action(4, symmetry, &action));
+            assert_eq!(
+                transformed.to_tak_board().to_partial_tps(),
+                moved.to_tak_board().transformed(symmetry).to_partial_tps()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_position_misses() {
+        let book = OpeningBook::standard();
+        let board =
+            Board::try_from_pos_str("2,x4/x,1,x3/x5/x5/x4,1 2 2", Settings::new(0)).unwrap();
+        assert!(book.lookup(&board).is_none());
+        assert!(book.lookup(&Board::empty(6, Settings::new(0))).is_some());
+    }
+
+    #[test]
+    fn test_search_consults_book() {
+        let mut board = Board::empty(5, Settings::new(0));
+        let search = |board: &mut Board, opening_book| {
+            let config = SearchConfig {
+                opening_book,
+                ..SearchConfig::default()
+            };
+            iterative_deepening_with_config(board, 1, 10_000, &config, |_| {})
+        };
+        let (depth, best) = search(&mut board, true);
+        assert_eq!(depth, 0);
+        assert_eq!(best.map(|(_, action)| action), STANDARD_BOOK.lookup(&board));
+        assert_eq!(search(&mut board, false).0, 1);
+    }
+}
@@ -0,0 +1,194 @@
+use tak_core::{TakBoard, TakCoord, TakGame, TakPieceVariant, TakPlayer};
+
+use crate::{Board, Settings};
+
+impl Board {
+    /// Converts a tak-core board without going through TPS. Reserves are what is left of the
+    /// default piece counts after the pieces on the board.
+    pub fn from_tak_board(
+        board: &TakBoard,
+        current_player: TakPlayer,
+        ply_index: usize,
+        settings: Settings,
+    ) -> Self {
+        let size = board.size;
+        let (piece_count, capstone_count) = Self::default_pieces(size);
+        let mut result = Self::empty(size, settings);
+        result.ply_index = ply_index;
+        result.current_player = player_index(current_player);
+
+        let mut used = [(0, 0); 2];
+        for (coord, stack) in board.iter_pieces(None) {
+            let pos = (size - 1 - coord.y as usize) * size + coord.x as usize;
+            let pos_mask = 1u64 << pos;
+            let mut bits = 0;
+            for (height, piece) in stack.composition.iter().enumerate() {
+                if piece.player == TakPlayer::Black {
+                    bits |= 1 << height;
+                }
+                used[piece.player.index()].0 += 1;
+            }
+            let owner = stack.player();
+            match stack.variant {
+                TakPieceVariant::Flat => {}
+                TakPieceVariant::Wall => result.walls |= pos_mask,
+                TakPieceVariant::Capstone => {
+                    result.capstones |= pos_mask;
+                    used[owner.index()].0 -= 1;
+                    used[owner.index()].1 += 1;
+                }
+            }
+            if owner == TakPlayer::Black {
+                result.owner |= pos_mask;
+            }
+            result.occupied |= pos_mask;
+            result.stacks[pos] = bits;
+            result.stack_heights[pos] = stack.height() as u64;
+            result.empty_positions -= 1;
+        }
+
+        result.white_pieces = piece_count.saturating_sub(used[0].0);
+        result.white_capstones = capstone_count.saturating_sub(used[0].1);
+        result.black_pieces = piece_count.saturating_sub(used[1].0);
+        result.black_capstones = capstone_count.saturating_sub(used[1].1);
+        result.recompute_zobrist();
+        result.recompute_result();
+        result
+    }
+
+    /// Converts the position back to a tak-core board. Piece IDs are reassigned square by
+    /// square, so they only match the original board if it was built the same way.
+    pub fn to_tak_board(&self) -> TakBoard {
+        let stacks = TakCoord::iter_board(self.size)
+            .map(|coord| {
+                let pos = (self.size - 1 - coord.y as usize) * self.size + coord.x as usize;
+                let height = self.stack_heights[pos];
+                if height == 0 {
+                    return None;
+                }
+                let pos_mask = 1u64 << pos;
+                let variant = if self.capstones & pos_mask != 0 {
+                    TakPieceVariant::Capstone
+                } else if self.walls & pos_mask != 0 {
+                    TakPieceVariant::Wall
+                } else {
+                    TakPieceVariant::Flat
+                };
+                let players = (0..height)
+                    .map(|h| {
+                        if self.stacks[pos] & (1 << h) != 0 {
+                            TakPlayer::Black
+                        } else {
+                            TakPlayer::White
+                        }
+                    })
+                    .collect();
+                Some((variant, players))
+            })
+            .collect();
+        TakBoard::from_stacks(self.size, stacks).expect("Board should have size * size squares")
+    }
+}
+
+impl From<&TakBoard> for Board {
+    /// Takes the board as white's first move without komi, with the default reserves.
+    fn from(board: &TakBoard) -> Self {
+        Board::from_tak_board(board, TakPlayer::White, 0, Settings::new(0))
+    }
+}
+
+impl From<&TakGame> for Board {
+    fn from(game: &TakGame) -> Self {
+        let komi = &game.settings.komi;
+        let mut board = Board::from(&game.board);
+        board.double_komi = komi.amount * 2 + komi.tiebreak as usize;
+        board.ply_index = game.ply_index;
+        board.current_player = player_index(game.current_player);
+        board.white_pieces = game.hands[0].stones as u64;
+        board.white_capstones = game.hands[0].capstones as u64;
+        board.black_pieces = game.hands[1].stones as u64;
+        board.black_capstones = game.hands[1].capstones as u64;
+        board.recompute_zobrist();
+        board.recompute_result();
+        board
+    }
+}
+
+fn player_index(player: TakPlayer) -> u64 {
+    match player {
+        TakPlayer::White => Board::PLAYER_WHITE,
+        TakPlayer::Black => Board::PLAYER_BLACK,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tak_core::{TakAction, TakGameSettings, TakKomi, TakTps};
+
+    use super::*;
+    use crate::{Action, gen_moves};
+
+    fn game_from_tps(tps: &str) -> TakGame {
+        let tps = TakTps::try_from_str(tps).unwrap();
+        let size = TakBoard::try_from_partial_tps(&tps.position).unwrap().size;
+        let settings = TakGameSettings::new_with_position(size, tps, None, TakKomi::none(), None);
+        TakGame::new(settings).unwrap()
+    }
+
+    #[test]
+    fn test_board_roundtrip() {
+        for tps in [
+            "x5/x5/x5/x5/x5 1 1",
+            "112C,11S,x3/x5/1C,x4/x5/x5 2 10",
+            "x2,2,22,2C,1/21221S,1112,x,2211,1,2/x2,111S,x,11S,12S/11S,1S,2S,2,12S,1211C/x,12S,2,122S,x,212S/12,x2,1S,22222S,21121 2 31",
+        ] {
+            let game = game_from_tps(tps);
+            let board = Board::from(&game);
+            assert_eq!(
+                board,
+                Board::try_from_pos_str(tps, Settings::new(0)).unwrap()
+            );
+            assert_eq!(
+                board.to_tak_board().to_partial_tps(),
+                game.board.to_partial_tps()
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_tak_board() {
+        let game = game_from_tps("112C,11S,x3/x5/1C,x4/x5/x5 1 10");
+        let mut board = Board::from(&game.board);
+        assert_eq!(board.ply_index, 0);
+        board.ply_index = game.ply_index;
+        assert_eq!(board, Board::from(&game));
+        assert_eq!(
+            Board::from(&TakBoard::try_from_partial_tps("x5/x5/x5/x5/x5").unwrap()),
+            Board::try_from_pos_str("x5/x5/x5/x5/x5 1 1", Settings::new(0)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_move_generation_matches() {
+        let game = game_from_tps("112C,11S,x3/x5/1C,x4/x5/x5 2 10");
+        let board = Board::from(&game);
+        let memo = tak_core::compute_partition_memo(board.size);
+
+        let mut expected = tak_core::gen_moves(&game, &memo)
+            .iter()
+            .map(|action| format!("{:?}", action))
+            .collect::<Vec<_>>();
+        let mut actual = gen_moves(&board)
+            .iter()
+            .map(|action: &Action| {
+                format!(
+                    "{:?}",
+                    TakAction::from_ptn(&action.to_ptn(board.size)).unwrap()
+                )
+            })
+            .collect::<Vec<_>>();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+}
@@ -7,6 +7,130 @@ pub enum Action {
     Spread(usize, usize, u64, u64), // position, direction, take, spreads
 }
 
+impl Action {
+    /// Formats the action in PTN notation for a board of the given size, so that it can be
+    /// compared with the output of other engines.
+    pub fn to_ptn(&self, size: usize) -> String {
+        let square = |pos: usize| {
+            format!(
+                "{}{}",
+                (b'a' + (pos % size) as u8) as char,
+                size - pos / size
+            )
+        };
+        match *self {
+            Action::Place(pos, variant) => {
+                let prefix = match variant {
+                    Board::VARIANT_WALL => "S",
+                    Board::VARIANT_CAPSTONE => "C",
+                    _ => "",
+                };
+                format!("{}{}", prefix, square(pos))
+            }
+            Action::Spread(pos, dir, take, spreads) => {
+                let dir = match dir {
+                    Board::DIR_RIGHT => '>',
+                    Board::DIR_LEFT => '<',
+                    Board::DIR_DOWN => '-',
+                    _ => '+',
+                };
+                let drops = crate::decode_spread_vec(spreads);
+                let take = if take > 1 {
+                    take.to_string()
+                } else {
+                    String::new()
+                };
+                let drops = if drops.len() > 1 {
+                    drops.iter().map(|drop| drop.to_string()).collect()
+                } else {
+                    String::new()
+                };
+                format!("{}{}{}{}", take, square(pos), dir, drops)
+            }
+        }
+    }
+
+    /// Parses an action in PTN notation for a board of the given size. Only the notation is
+    /// checked, not whether the action is legal in some position.
+    pub fn from_ptn(ptn: &str, size: usize) -> Option<Action> {
+        let mut chars = ptn.chars().peekable();
+
+        let variant = match chars.peek().copied() {
+            Some('F') => Some(Board::VARIANT_FLAT),
+            Some('S') => Some(Board::VARIANT_WALL),
+            Some('C') => Some(Board::VARIANT_CAPSTONE),
+            _ => None,
+        };
+        if variant.is_some() {
+            chars.next();
+        }
+        let take = match chars.peek().copied() {
+            Some(c @ '1'..='9') if variant.is_none() => {
+                chars.next();
+                Some(c as usize - '0' as usize)
+            }
+            _ => None,
+        };
+
+        let file = match chars.next()? {
+            c @ 'a'..='h' => c as usize - 'a' as usize,
+            _ => return None,
+        };
+        let rank = match chars.next()? {
+            c @ '1'..='8' => c as usize - '1' as usize,
+            _ => return None,
+        };
+        if file >= size || rank >= size {
+            return None;
+        }
+        let pos = (size - 1 - rank) * size + file;
+
+        let Some(dir) = chars.next() else {
+            if take.is_some() {
+                return None;
+            }
+            return Some(Action::Place(pos, variant.unwrap_or(Board::VARIANT_FLAT)));
+        };
+        if variant.is_some() {
+            return None;
+        }
+        let (dir, (dx, dy)) = match dir {
+            '>' => (Board::DIR_RIGHT, (1, 0)),
+            '<' => (Board::DIR_LEFT, (-1, 0)),
+            '-' => (Board::DIR_DOWN, (0, -1)),
+            '+' => (Board::DIR_UP, (0, 1)),
+            _ => return None,
+        };
+
+        let take = take.unwrap_or(1);
+        let mut drops = Vec::new();
+        while let Some(c @ '1'..='9') = chars.peek().copied() {
+            drops.push(c as usize - '0' as usize);
+            chars.next();
+        }
+        if drops.is_empty() {
+            drops.push(take);
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+        }
+        if chars.next().is_some() || take > size || drops.iter().sum::<usize>() != take {
+            return None;
+        }
+        let end_file = file as isize + dx * drops.len() as isize;
+        let end_rank = rank as isize + dy * drops.len() as isize;
+        if !(0..size as isize).contains(&end_file) || !(0..size as isize).contains(&end_rank) {
+            return None;
+        }
+
+        let spreads = drops
+            .iter()
+            .rev()
+            .fold(0, |spreads, drop| (spreads << 4) | *drop as u64);
+        Some(Action::Spread(pos, dir, take as u64, spreads))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Settings {
@@ -794,15 +918,30 @@ impl Board {
             zobrist: 0,
         };
         board.recompute_zobrist();
+        board.recompute_result();
 
-        board.check_flat_win();
-        for pos in 0..(size * size) {
-            if board.stack_heights[pos] > 0 {
-                board.check_road_win(board.controlling_player(pos), pos);
+        Some(board)
+    }
+
+    /// Whether the player has a road anywhere on the board.
+    pub(crate) fn has_road(&self, player: u64) -> bool {
+        let mut board = self.clone();
+        (0..self.size * self.size).any(|pos| {
+            self.stack_heights[pos] > 0
+                && self.controlling_player(pos) == player
+                && board.check_road_win(player, pos)
+        })
+    }
+
+    /// Checks the whole board for a finished game, for positions that were set up rather than
+    /// played.
+    pub(crate) fn recompute_result(&mut self) {
+        self.check_flat_win();
+        for pos in 0..(self.size * self.size) {
+            if self.stack_heights[pos] > 0 {
+                self.check_road_win(self.controlling_player(pos), pos);
             }
         }
-
-        Some(board)
     }
 
     pub fn to_pos_str(&self) -> String {
@@ -867,6 +1006,44 @@ impl Board {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_action_to_ptn() {
+        assert_eq!(Action::Place(0, Board::VARIANT_FLAT).to_ptn(5), "a5");
+        assert_eq!(Action::Place(24, Board::VARIANT_WALL).to_ptn(5), "Se1");
+        assert_eq!(Action::Place(7, Board::VARIANT_CAPSTONE).to_ptn(5), "Cc4");
+        assert_eq!(Action::Spread(20, Board::DIR_UP, 1, 0x1).to_ptn(5), "a1+");
+        assert_eq!(Action::Spread(4, Board::DIR_DOWN, 3, 0x3).to_ptn(5), "3e5-");
+        assert_eq!(
+            Action::Spread(12, Board::DIR_LEFT, 3, 0x21).to_ptn(5),
+            "3c3<12"
+        );
+    }
+
+    #[test]
+    fn test_action_ptn_roundtrip() {
+        let mut board =
+            Board::try_from_pos_str("112C,11S,x3/x5/1C,x4/x5/x5 2 10", Settings::new(4)).unwrap();
+        let smash = Action::from_ptn("a5>*", 5).unwrap();
+        assert_eq!(smash, Action::Spread(0, Board::DIR_RIGHT, 1, 0x1));
+        assert!(crate::gen_moves(&board).contains(&smash));
+        assert!(board.make(&smash));
+        assert_eq!(smash.to_ptn(5), "a5>");
+
+        let board = Board::try_from_pos_str("x5/x5/x5/x5/221,x4 1 10", Settings::new(4)).unwrap();
+        let spread = Action::from_ptn("3a1>12", 5).unwrap();
+        assert_eq!(spread, Action::Spread(20, Board::DIR_RIGHT, 3, 0x21));
+        assert!(crate::gen_moves(&board).contains(&spread));
+        assert_eq!(spread.to_ptn(5), "3a1>12");
+
+        for ptn in ["a1", "Sc3", "Ce5", "2b2+11", "5c3<"] {
+            let action = Action::from_ptn(ptn, 5).unwrap();
+            assert_eq!(action.to_ptn(5), ptn);
+        }
+        for ptn in ["f1", "a0", "a1>*x", "3a1>11", "2a1<11", "6a5-", "Sa1+"] {
+            assert_eq!(Action::from_ptn(ptn, 5), None);
+        }
+    }
+
     #[test]
     fn test_empty() {
         let board = Board::empty(5, Settings::new(4));
@@ -0,0 +1,161 @@
+use crate::{
+    Action, Board, Engine, RandomEngine, SearchConfig, gen_moves, iterative_deepening_with_config,
+};
+
+/// The strengths offered when playing against the computer, from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EngineLevel {
+    Beginner,
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+    Max,
+}
+
+/// How a level searches and how often it deliberately misplays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelConfig {
+    pub max_depth: usize,
+    /// Upper bound for the time spent per move in milliseconds. Games with a clock may get
+    /// less.
+    pub max_move_time: u64,
+    /// Chance in percent to play a random other legal move instead of the best one.
+    pub blunder_percent: u64,
+    pub search: SearchConfig,
+}
+
+impl EngineLevel {
+    pub const ALL: [EngineLevel; 5] = [
+        EngineLevel::Beginner,
+        EngineLevel::Easy,
+        EngineLevel::Medium,
+        EngineLevel::Hard,
+        EngineLevel::Max,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineLevel::Beginner => "Beginner",
+            EngineLevel::Easy => "Easy",
+            EngineLevel::Medium => "Medium",
+            EngineLevel::Hard => "Hard",
+            EngineLevel::Max => "Max",
+        }
+    }
+
+    pub fn config(&self) -> LevelConfig {
+        let (max_depth, max_move_time, blunder_percent, quiescence) = match self {
+            EngineLevel::Beginner => (1, 200, 35, false),
+            EngineLevel::Easy => (2, 500, 15, false),
+            EngineLevel::Medium => (4, 1000, 5, false),
+            EngineLevel::Hard => (8, 3000, 0, true),
+            EngineLevel::Max => (12, 10_000, 0, true),
+        };
+        LevelConfig {
+            max_depth,
+            max_move_time,
+            blunder_percent,
+            search: SearchConfig {
+                quiescence,
+                ..SearchConfig::default()
+            },
+        }
+    }
+}
+
+/// With the given chance, swaps the best move for a random other legal move. Positions with
+/// a single legal move always keep it.
+pub fn maybe_blunder(
+    board: &Board,
+    best_move: Action,
+    blunder_percent: u64,
+    rng: &mut RandomEngine,
+) -> Action {
+    if rng.next() % 100 >= blunder_percent {
+        return best_move;
+    }
+    let others = gen_moves(board)
+        .into_iter()
+        .filter(|x| *x != best_move)
+        .collect::<Vec<_>>();
+    if others.is_empty() {
+        return best_move;
+    }
+    others[(rng.next() % others.len() as u64) as usize].clone()
+}
+
+/// Searches with the settings of a level and applies its blunders. Seeded for reproducible
+/// games.
+pub struct LevelEngine {
+    config: LevelConfig,
+    rng: RandomEngine,
+}
+
+impl LevelEngine {
+    pub fn new(level: EngineLevel, seed: u64) -> Self {
+        Self {
+            config: level.config(),
+            rng: RandomEngine::new(seed),
+        }
+    }
+
+    /// Decides whether to play the searched best move or blunder instead.
+    pub fn pick(&mut self, board: &Board, best_move: Action) -> Action {
+        maybe_blunder(board, best_move, self.config.blunder_percent, &mut self.rng)
+    }
+}
+
+impl Engine for LevelEngine {
+    fn choose(&mut self, board: &Board) -> Action {
+        let mut searched = board.clone();
+        let best_move = match iterative_deepening_with_config(
+            &mut searched,
+            self.config.max_depth,
+            self.config.max_move_time,
+            &self.config.search,
+            |_| {},
+        ) {
+            (_, Some((_, action))) => action,
+            (_, None) => gen_moves(board).swap_remove(0),
+        };
+        self.pick(board, best_move)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Settings;
+
+    #[test]
+    fn test_blunders_by_level() {
+        let mut board = Board::empty(5, Settings::new(4));
+        board.make(&gen_moves(&board)[0]);
+        board.make(&gen_moves(&board)[0]);
+        let legal = gen_moves(&board);
+        let best_move = legal[0].clone();
+
+        let mut beginner = LevelEngine::new(EngineLevel::Beginner, 7);
+        let picks = (0..200)
+            .map(|_| beginner.pick(&board, best_move.clone()))
+            .collect::<Vec<_>>();
+        assert!(picks.iter().all(|x| legal.contains(x)));
+        let deviations = picks.iter().filter(|x| **x != best_move).count();
+        assert!(deviations > 0);
+        assert!(deviations < picks.len());
+
+        let mut max = LevelEngine::new(EngineLevel::Max, 7);
+        assert!((0..200).all(|_| max.pick(&board, best_move.clone()) == best_move));
+    }
+
+    #[test]
+    fn test_levels_get_stronger() {
+        let configs = EngineLevel::ALL.map(|x| x.config());
+        for pair in configs.windows(2) {
+            assert!(pair[0].max_depth <= pair[1].max_depth);
+            assert!(pair[0].blunder_percent >= pair[1].blunder_percent);
+        }
+    }
+}
@@ -1,13 +1,24 @@
+mod arena;
+mod book;
+mod bridge;
 mod game;
+mod level;
 mod minimax;
 mod movegen;
+mod ponder;
+mod tablebase;
 mod time_control;
 mod worker;
 mod zobrist;
 
+pub use arena::*;
+pub use book::*;
 pub use game::*;
+pub use level::*;
 pub use minimax::*;
 pub use movegen::*;
+pub use ponder::*;
+pub use tablebase::*;
 pub use time_control::*;
 pub use worker::*;
 pub use zobrist::*;
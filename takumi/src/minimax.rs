@@ -1,6 +1,6 @@
 use crate::{
-    Action, Board, TRANSPOSITION_TABLE, TranspositionEntry, TranspositionNodeType,
-    TranspositionTable, console_log, gen_moves,
+    Action, Board, SEARCH_CONFIG, STANDARD_BOOK, SearchConfig, TRANSPOSITION_TABLE,
+    TranspositionEntry, TranspositionNodeType, TranspositionTable, console_log, gen_moves,
 };
 
 #[cfg(target_arch = "wasm32")]
@@ -25,27 +25,210 @@ struct Stats {
     saved_by_tt: usize,
 }
 
+/// The result of a completed iteration of the search.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnalysisUpdate {
+    pub depth: usize,
+    pub score: i32,
+    pub best_move: Action,
+    /// Nodes visited so far, summed over all completed depths.
+    pub nodes: usize,
+}
+
 pub fn iterative_deepening(
     board: &mut Board,
     max_depth: usize,
     max_duration: u64,
 ) -> (usize, Option<(i32, Action)>) {
+    iterative_deepening_with_updates(board, max_depth, max_duration, |_| {})
+}
+
+/// Like `iterative_deepening`, but reports the best move after every completed depth.
+/// Updates arrive with increasing depth, and the last one matches the returned result.
+pub fn iterative_deepening_with_updates(
+    board: &mut Board,
+    max_depth: usize,
+    max_duration: u64,
+    on_depth: impl FnMut(AnalysisUpdate),
+) -> (usize, Option<(i32, Action)>) {
+    let config = SEARCH_CONFIG.with(|config| config.borrow().clone());
+    iterative_deepening_with_config(board, max_depth, max_duration, &config, on_depth)
+}
+
+pub fn iterative_deepening_with_config(
+    board: &mut Board,
+    max_depth: usize,
+    max_duration: u64,
+    config: &SearchConfig,
+    mut on_depth: impl FnMut(AnalysisUpdate),
+) -> (usize, Option<(i32, Action)>) {
+    if let Some(action) = config
+        .opening_book
+        .then(|| STANDARD_BOOK.lookup(board))
+        .flatten()
+    {
+        on_depth(AnalysisUpdate {
+            depth: 0,
+            score: 0,
+            best_move: action.clone(),
+            nodes: 0,
+        });
+        return (0, Some((0, action)));
+    }
     TRANSPOSITION_TABLE.with(|tt| {
         let mut tt = tt.borrow_mut();
-        iterative_deepening_with_tt(board, max_depth, max_duration, &mut tt)
+        let mut ordering = MoveOrdering::new(true);
+        let (best_depth, best, _) = iterative_deepening_with_ordering(
+            board,
+            max_depth,
+            max_duration,
+            config,
+            &mut tt,
+            &mut ordering,
+            &mut on_depth,
+        );
+        (best_depth, best)
     })
 }
 
 const INF: i32 = 100_000_000;
 
+/// Score of a won game, minus the number of plies it takes to get there, so that the search
+/// prefers the quickest win and the slowest loss.
+const WIN_SCORE: i32 = 1_000_000;
+/// Scores at least this far from zero are wins or losses rather than evaluations.
+const WIN_THRESHOLD: i32 = 900_000;
+/// Evaluations this close to zero count as drawish for contempt.
+const DRAWISH_MARGIN: i32 = 50;
+
+/// Converts a score relative to the root into one relative to the current node, since a
+/// position can be stored at one distance from the root and found again at another.
+fn score_to_tt(score: i32, plies_from_root: usize) -> i32 {
+    if score >= WIN_THRESHOLD {
+        score + plies_from_root as i32
+    } else if score <= -WIN_THRESHOLD {
+        score - plies_from_root as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, plies_from_root: usize) -> i32 {
+    if score >= WIN_THRESHOLD {
+        score - plies_from_root as i32
+    } else if score <= -WIN_THRESHOLD {
+        score + plies_from_root as i32
+    } else {
+        score
+    }
+}
+
+const MAX_KILLER_PLIES: usize = 64;
+const HISTORY_PLACE_OFFSET: usize = 0;
+const HISTORY_SPREAD_OFFSET: usize = 64 * 3;
+const HISTORY_SIZE: usize = HISTORY_SPREAD_OFFSET + 64 * 4;
+
+/// Search-local move ordering on top of the categories produced by `gen_moves`: two killer
+/// moves per ply that recently caused a cutoff, followed by the remaining moves sorted by
+/// how often they caused cutoffs anywhere in the tree.
+struct MoveOrdering {
+    enabled: bool,
+    killers: [[Option<Action>; 2]; MAX_KILLER_PLIES],
+    history: [u32; HISTORY_SIZE],
+}
+
+impl MoveOrdering {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            killers: std::array::from_fn(|_| [None, None]),
+            history: [0; HISTORY_SIZE],
+        }
+    }
+
+    fn history_index(action: &Action) -> usize {
+        match action {
+            Action::Place(pos, variant) => HISTORY_PLACE_OFFSET + pos * 3 + variant,
+            Action::Spread(pos, dir, _, _) => HISTORY_SPREAD_OFFSET + pos * 4 + dir,
+        }
+    }
+
+    fn priority(&self, action: &Action, inv_depth: usize) -> u32 {
+        if let Some(killers) = self.killers.get(inv_depth) {
+            if killers[0].as_ref() == Some(action) {
+                return u32::MAX;
+            }
+            if killers[1].as_ref() == Some(action) {
+                return u32::MAX - 1;
+            }
+        }
+        self.history[Self::history_index(action)]
+    }
+
+    fn order(&self, moves: &mut [Action], inv_depth: usize) {
+        if self.enabled {
+            moves.sort_by_cached_key(|mv| std::cmp::Reverse(self.priority(mv, inv_depth)));
+        }
+    }
+
+    fn on_cutoff(&mut self, action: &Action, depth: usize, inv_depth: usize) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(killers) = self.killers.get_mut(inv_depth)
+            && killers[0].as_ref() != Some(action)
+        {
+            killers[1] = killers[0].replace(action.clone());
+        }
+        let entry = &mut self.history[Self::history_index(action)];
+        *entry = entry.saturating_add((depth * depth) as u32);
+    }
+}
+
+#[cfg(test)]
 fn iterative_deepening_with_tt(
     board: &mut Board,
     max_depth: usize,
     max_duration: u64,
     tt: &mut TranspositionTable,
 ) -> (usize, Option<(i32, Action)>) {
+    let mut ordering = MoveOrdering::new(true);
+    let (best_depth, best, _) = iterative_deepening_with_ordering(
+        board,
+        max_depth,
+        max_duration,
+        &SearchConfig::default(),
+        tt,
+        &mut ordering,
+        &mut |_| {},
+    );
+    (best_depth, best)
+}
+
+/// Orders equally scored moves: the one closest to the center of the board first, then the
+/// shortest PTN, so flats win over capstones and smaller spreads over larger ones, then by PTN.
+fn tie_break_key(size: usize, action: &Action) -> (usize, usize, String) {
+    let (Action::Place(pos, _) | Action::Spread(pos, ..)) = *action;
+    let center_distance = |coord: usize| (2 * coord).abs_diff(size - 1);
+    let distance = center_distance(pos % size) + center_distance(pos / size);
+    let ptn = action.to_ptn(size);
+    (distance, ptn.len(), ptn)
+}
+
+/// Returns the reached depth, the best move with its score, and the number of visited nodes.
+fn iterative_deepening_with_ordering(
+    board: &mut Board,
+    max_depth: usize,
+    max_duration: u64,
+    config: &SearchConfig,
+    tt: &mut TranspositionTable,
+    ordering: &mut MoveOrdering,
+    on_depth: &mut dyn FnMut(AnalysisUpdate),
+) -> (usize, Option<(i32, Action)>, usize) {
     let mut best = None;
     let mut best_depth = 0;
+    let mut total_nodes = 0;
 
     let start_time = now();
     let end_time = start_time + max_duration;
@@ -65,13 +248,19 @@ fn iterative_deepening_with_tt(
             let mut best_move = None;
             for mv in moves.iter() {
                 let smash = board.make(mv);
-                let Some(score) =
-                    alphabeta(board, depth, 0, end_time, -INF, INF, tt, &mut stats).map(|s| -s)
-                else {
+                let Some(score) = alphabeta(
+                    board, depth, 0, end_time, -INF, INF, config, tt, ordering, &mut stats,
+                )
+                .map(|s| -s) else {
                     break 'l None;
                 };
                 board.unmake(mv, smash);
-                if score > best_score {
+                let wins_tie = score == best_score
+                    && config.tie_break
+                    && best_move.as_ref().is_some_and(|best| {
+                        tie_break_key(board.size, mv) < tie_break_key(board.size, best)
+                    });
+                if score > best_score || wins_tie {
                     best_score = score;
                     best_move = Some(mv.clone());
                 }
@@ -79,6 +268,8 @@ fn iterative_deepening_with_tt(
             best_move.map(|m| (best_score, m))
         };
 
+        total_nodes += stats.node_count;
+
         if res.is_none() {
             console_log!("Timeout at {}", depth);
             break;
@@ -97,6 +288,15 @@ fn iterative_deepening_with_tt(
             stats
         );
 
+        if let Some((score, best_move)) = &res {
+            on_depth(AnalysisUpdate {
+                depth,
+                score: *score,
+                best_move: best_move.clone(),
+                nodes: total_nodes,
+            });
+        }
+
         best = res;
         best_depth = depth;
 
@@ -111,13 +311,13 @@ fn iterative_deepening_with_tt(
 
         if best
             .as_ref()
-            .is_some_and(|(score, _)| score.abs() >= 900_000)
+            .is_some_and(|(score, _)| score.abs() >= WIN_THRESHOLD)
         {
             break;
         }
     }
 
-    (best_depth, best)
+    (best_depth, best, total_nodes)
 }
 
 fn alphabeta(
@@ -127,24 +327,28 @@ fn alphabeta(
     end_time: u64,
     mut alpha: i32,
     beta: i32,
+    config: &SearchConfig,
     tt: &mut TranspositionTable,
+    ordering: &mut MoveOrdering,
     stats: &mut Stats,
 ) -> Option<i32> {
     stats.node_count += 1;
 
+    let plies_from_root = inv_depth + 1;
     let is_leaf = depth == 0 || board.result.is_some();
 
     let prev_best_move = if let Some(entry) = tt.get(board.zobrist) {
         stats.found_in_tt += 1;
         if entry.depth >= depth {
             stats.saved_by_tt += 1;
+            let score = score_from_tt(entry.score, plies_from_root);
             match entry.node_type {
-                TranspositionNodeType::Exact => return Some(entry.score),
-                TranspositionNodeType::Alpha if entry.score <= alpha && !is_leaf => {
-                    return Some(entry.score);
+                TranspositionNodeType::Exact => return Some(score),
+                TranspositionNodeType::Alpha if score <= alpha && !is_leaf => {
+                    return Some(score);
                 }
-                TranspositionNodeType::Beta if entry.score >= beta && !is_leaf => {
-                    return Some(entry.score);
+                TranspositionNodeType::Beta if score >= beta && !is_leaf => {
+                    return Some(score);
                 }
                 _ => {}
             }
@@ -158,10 +362,26 @@ fn alphabeta(
     };
 
     if is_leaf {
-        return Some(evaluate_for_active_player(board));
+        if config.quiescence && board.result.is_none() {
+            return Some(quiesce(
+                board,
+                alpha,
+                beta,
+                plies_from_root,
+                config.max_quiescence_depth,
+                config.contempt,
+                stats,
+            ));
+        }
+        return Some(evaluate_for_active_player(
+            board,
+            plies_from_root,
+            config.contempt,
+        ));
     }
 
     let mut moves = gen_moves(board);
+    ordering.order(&mut moves, inv_depth);
     if let Some(prev_move_pos) = prev_best_move.and_then(|m| moves.iter().position(|x| x == m)) {
         moves.swap(0, prev_move_pos);
     }
@@ -178,14 +398,17 @@ fn alphabeta(
             end_time,
             -beta,
             -alpha,
+            config,
             tt,
+            ordering,
             stats,
         )?;
         board.unmake(&mv, smash);
         if score >= beta {
+            ordering.on_cutoff(&mv, depth, inv_depth);
             tt.maybe_insert(TranspositionEntry {
                 zobrist: board.zobrist,
-                score: beta,
+                score: score_to_tt(beta, plies_from_root),
                 depth,
                 ply: board.ply_index,
                 node_type: TranspositionNodeType::Beta,
@@ -213,7 +436,7 @@ fn alphabeta(
         zobrist: board.zobrist,
         depth,
         ply: board.ply_index,
-        score: alpha,
+        score: score_to_tt(alpha, plies_from_root),
         node_type: flag,
         best_move,
     });
@@ -221,22 +444,148 @@ fn alphabeta(
     Some(alpha)
 }
 
-fn evaluate_for_active_player(board: &Board) -> i32 {
-    let white_score = evaluate(board);
-    if board.current_player == 0 {
+/// Searches only capturing spreads and placements that complete a road, letting the side to
+/// move stand pat on the static evaluation if none of them improves it. Spreads that win by
+/// road without capturing are left to the main search to keep leaf nodes cheap.
+fn quiesce(
+    board: &mut Board,
+    mut alpha: i32,
+    beta: i32,
+    plies_from_root: usize,
+    depth_left: usize,
+    contempt: i32,
+    stats: &mut Stats,
+) -> i32 {
+    stats.node_count += 1;
+
+    let stand_pat = evaluate_for_active_player(board, plies_from_root, contempt);
+    if board.result.is_some() || depth_left == 0 {
+        return stand_pat;
+    }
+    if stand_pat >= beta {
+        return beta;
+    }
+    alpha = alpha.max(stand_pat);
+
+    let player = board.current_player;
+    for mv in gen_moves(board) {
+        let is_tactical = match mv {
+            Action::Place(_, Board::VARIANT_WALL) => false,
+            Action::Place(pos, _) => touches_own_stack(board, pos),
+            Action::Spread(..) => is_capture(board, &mv),
+        };
+        if !is_tactical {
+            continue;
+        }
+        let smash = board.make(&mv);
+        if matches!(mv, Action::Place(..)) && board.result != Some(player) {
+            board.unmake(&mv, smash);
+            continue;
+        }
+        let score = -quiesce(
+            board,
+            -beta,
+            -alpha,
+            plies_from_root + 1,
+            depth_left - 1,
+            contempt,
+            stats,
+        );
+        board.unmake(&mv, smash);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+/// Whether a square is next to a stack of the side to move, so that placing there could
+/// complete a road.
+fn touches_own_stack(board: &Board, pos: usize) -> bool {
+    (0..4).any(|dir| {
+        board.offset_by_dir(pos, dir).is_some_and(|neighbor| {
+            let neighbor_mask = 1u64 << neighbor;
+            let black_owned = board.owner & neighbor_mask != 0;
+            board.occupied & neighbor_mask != 0 && black_owned as u64 == board.current_player
+        })
+    })
+}
+
+/// Whether a spread drops onto a stack controlled by the opponent of the side to move.
+fn is_capture(board: &Board, action: &Action) -> bool {
+    let Action::Spread(mut pos, dir, _, mut spreads) = *action else {
+        return false;
+    };
+    while spreads > 0 {
+        let Some(next) = board.offset_by_dir(pos, dir) else {
+            return false;
+        };
+        pos = next;
+        let pos_mask = 1u64 << pos;
+        let black_owned = board.owner & pos_mask != 0;
+        if board.occupied & pos_mask != 0 && black_owned as u64 != board.current_player {
+            return true;
+        }
+        spreads >>= 4;
+    }
+    false
+}
+
+/// Scores the position for the side to move. Contempt lowers drawish scores for the player at
+/// the root, who is to move whenever the distance from the root is even.
+fn evaluate_for_active_player(board: &Board, plies_from_root: usize, contempt: i32) -> i32 {
+    let white_score = match board.result {
+        Some(0) => WIN_SCORE - plies_from_root as i32,
+        Some(1) => -WIN_SCORE + plies_from_root as i32,
+        _ => evaluate(board),
+    };
+    let score = if board.current_player == 0 {
         white_score
     } else {
         -white_score
+    };
+    let is_drawish =
+        board.result == Some(2) || (board.result.is_none() && score.abs() <= DRAWISH_MARGIN);
+    if !is_drawish {
+        score
+    } else if plies_from_root.is_multiple_of(2) {
+        score - contempt
+    } else {
+        score + contempt
+    }
+}
+
+/// The terms of the static evaluation, each weighted and from white's point of view.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvalBreakdown {
+    /// Stacks controlled, whatever the top piece is.
+    pub stack_control: i32,
+    /// Topmost flats, with the komi counted against white.
+    pub flat_diff: i32,
+    /// Square of the reach of the longest connected group towards spanning the board.
+    pub road_progress: i32,
+    /// Penalty for road pieces split into many separate groups.
+    pub fragmentation: i32,
+}
+
+impl EvalBreakdown {
+    pub fn total(&self) -> i32 {
+        self.stack_control + self.flat_diff + self.road_progress + self.fragmentation
     }
 }
 
 fn evaluate(board: &Board) -> i32 {
-    if let Some(result) = board.result {
-        return match result {
-            0 => 1_000_000 - board.ply_index as i32,
-            1 => -1_000_000 + board.ply_index as i32,
-            _ => 0,
-        };
+    evaluate_explained(board).total()
+}
+
+/// Evaluates a position term by term, so it is visible why the engine likes it. The terms sum
+/// up to the score the search uses.
+pub fn evaluate_explained(board: &Board) -> EvalBreakdown {
+    // Wins are scored by the caller, which knows their distance from the root.
+    if board.result.is_some() {
+        return EvalBreakdown::default();
     }
 
     let mut piece_count = 0;
@@ -264,7 +613,12 @@ fn evaluate(board: &Board) -> i32 {
     let longest_road =
         longest_road_white * longest_road_white - longest_road_black * longest_road_black;
     let disjoint_count_diff = disjoint_count_white as i32 - disjoint_count_black as i32;
-    piece_count * 100 + flat_count_diff * 10 + longest_road * 20 - disjoint_count_diff * 5
+    EvalBreakdown {
+        stack_control: piece_count * 100,
+        flat_diff: flat_count_diff * 10,
+        road_progress: longest_road * 20,
+        fragmentation: -disjoint_count_diff * 5,
+    }
 }
 
 fn find_longest_road(board: &Board, player: usize) -> (i32, usize) {
@@ -370,4 +724,279 @@ mod tests {
         println!("Result: {:?}", res);
         assert_eq!(res.0, 100_000);
     }
+
+    #[test]
+    fn test_breakdown_sums_to_evaluation() {
+        for (position, komi) in [
+            ("x5/x5/x5/x5/x5 1 1", 4),
+            ("1,1,1,1,x/2,2,2,x2/x5/x5/x5 1 8", 0),
+            (
+                "1,2,2,1,1/1C,2112112112C,222221S,221,1/x,2,12,21,1/2121S,2,1,x,2/2,x4 2 36",
+                4,
+            ),
+            ("x3,2S,x2/x,1,1C,x3/x2,2,1S,x2/x,12,x,2C,x2/x6/2,x5 1 9", 1),
+        ] {
+            let board = Board::try_from_pos_str(position, Settings::new(komi)).unwrap();
+            let breakdown = evaluate_explained(&board);
+            assert_eq!(breakdown.total(), evaluate(&board), "{position}");
+        }
+
+        for (position, komi, expected) in [
+            // One more stack and flat for white, each side a single group.
+            (
+                "1,1,1,1,x/2,2,2,x2/x5/x5/x5 1 8",
+                0,
+                EvalBreakdown {
+                    stack_control: 100,
+                    flat_diff: 20,
+                    road_progress: 0,
+                    fragmentation: 0,
+                },
+            ),
+            // Black's only piece is a wall, so only white has a road group.
+            (
+                "1,1,x3/x,2S,x3/x5/x5/x5 1 4",
+                0,
+                EvalBreakdown {
+                    stack_control: 100,
+                    flat_diff: 40,
+                    road_progress: 500,
+                    fragmentation: -5,
+                },
+            ),
+            // Walls and capstones count as stacks but not flats, and black's four scattered
+            // road pieces outweigh white's connected pair. Komi costs white half a flat.
+            (
+                "x3,2S,x2/x,1,1C,x3/x2,2,1S,x2/x,12,x,2C,x2/x6/2,x5 1 9",
+                1,
+                EvalBreakdown {
+                    stack_control: -200,
+                    flat_diff: -50,
+                    road_progress: 0,
+                    fragmentation: 15,
+                },
+            ),
+        ] {
+            let board = Board::try_from_pos_str(position, Settings::new(komi)).unwrap();
+            assert_eq!(evaluate_explained(&board), expected, "{position}");
+        }
+    }
+
+    #[test]
+    fn test_small_table_finds_road() {
+        let mut board =
+            Board::try_from_pos_str("1,1,1,1,x/2,2,2,x2/x5/x5/x5 1 8", Settings::new(4)).unwrap();
+        let mut tt = TranspositionTable::with_size_mb(1);
+        let (_, best) = iterative_deepening_with_tt(&mut board, 3, 10_000_000, &mut tt);
+        let (score, action) = best.unwrap();
+        assert!(score >= WIN_THRESHOLD);
+        assert_eq!(action, Action::Place(4, Board::VARIANT_FLAT));
+    }
+
+    #[test]
+    fn test_prefers_quickest_road() {
+        // White threatens roads on rows 5 and 3, so any quiet move still wins two plies later.
+        let position = "1,1,1,1,x/2,2,x3/1,1,1,1,x/2,2,x3/x5 1 10";
+        let mut board = Board::try_from_pos_str(position, Settings::new(4)).unwrap();
+        let mut tt = TranspositionTable::new(16);
+        let mut ordering = MoveOrdering::new(true);
+        let mut stats = Stats {
+            node_count: 0,
+            found_in_tt: 0,
+            saved_by_tt: 0,
+        };
+        let config = SearchConfig::default();
+        let mut score_after = |board: &mut Board, mv: &Action| {
+            let smash = board.make(mv);
+            let score = -alphabeta(
+                board,
+                2,
+                0,
+                u64::MAX,
+                -INF,
+                INF,
+                &config,
+                &mut tt,
+                &mut ordering,
+                &mut stats,
+            )
+            .unwrap();
+            board.unmake(mv, smash);
+            score
+        };
+        let road_in_1 = Action::Place(4, Board::VARIANT_FLAT);
+        let road_in_3 = Action::Place(24, Board::VARIANT_FLAT);
+        assert_eq!(score_after(&mut board, &road_in_3), WIN_SCORE - 3);
+        assert_eq!(score_after(&mut board, &road_in_1), WIN_SCORE - 1);
+
+        let mut tt = TranspositionTable::new(16);
+        let (_, best) = iterative_deepening_with_tt(&mut board, 3, 10_000_000, &mut tt);
+        let (score, action) = best.unwrap();
+        assert_eq!(score, WIN_SCORE - 1);
+        assert!(action == road_in_1 || action == Action::Place(14, Board::VARIANT_FLAT));
+    }
+
+    #[test]
+    fn test_win_scores_survive_tt() {
+        for score in [WIN_SCORE - 5, -WIN_SCORE + 7, 1234, -55] {
+            for plies in [0, 1, 6] {
+                assert_eq!(score_from_tt(score_to_tt(score, plies), plies), score);
+            }
+        }
+        // A win two plies below a node stored three plies from the root is found again one
+        // ply from the root.
+        let stored = score_to_tt(WIN_SCORE - 5, 3);
+        assert_eq!(score_from_tt(stored, 1), WIN_SCORE - 3);
+    }
+
+    #[test]
+    fn test_move_ordering_reduces_nodes() {
+        let position = "x,2,x,1,x/x,1,2,x2/2,12,1C,2,x/x,2,x,1,x/x5 1 10";
+        let count_nodes = |enabled| {
+            let mut board = Board::try_from_pos_str(position, Settings::new(4)).unwrap();
+            let mut tt = TranspositionTable::new(16);
+            let mut ordering = MoveOrdering::new(enabled);
+            let (depth, _, nodes) = iterative_deepening_with_ordering(
+                &mut board,
+                2,
+                10_000_000,
+                &SearchConfig::default(),
+                &mut tt,
+                &mut ordering,
+                &mut |_| {},
+            );
+            assert_eq!(depth, 2);
+            nodes
+        };
+        let with_heuristics = count_nodes(true);
+        let without_heuristics = count_nodes(false);
+        assert!(with_heuristics < without_heuristics);
+    }
+
+    #[test]
+    fn test_analysis_updates_are_ordered() {
+        let mut board = Board::try_from_pos_str(
+            "x,2,x,1,x/x,1,2,x2/2,12,1C,2,x/x,2,x,1,x/x5 1 10",
+            Settings::new(4),
+        )
+        .unwrap();
+        let mut updates = Vec::new();
+        let (depth, best) =
+            iterative_deepening_with_updates(&mut board, 2, 10_000_000, |u| updates.push(u));
+        let (score, best_move) = best.unwrap();
+
+        assert_eq!(updates.len(), depth);
+        assert!(updates.windows(2).all(|w| w[0].depth < w[1].depth));
+        assert!(updates.windows(2).all(|w| w[0].nodes < w[1].nodes));
+        let last = updates.last().unwrap();
+        assert_eq!((last.depth, last.score), (depth, score));
+        assert_eq!(last.best_move, best_move);
+    }
+
+    /// Positions where a capture decides the position, with the move a depth 1 search plays when
+    /// quiescence is on.
+    const CAPTURE_SUITE: [(&str, &str); 4] = [
+        ("x2,21,2,1/x5/2,1,x3/2C,x,2,1C,x1/x,2,x3 1 10", "2c5>"),
+        ("x4,1/2,x,1C,x2/x,2C,1,22,21/x5/1S,1S,x3 2 15", "2d3>"),
+        ("2S,2C,x,2,x1/x5/x2,12,1C,x1/1,x,2,x,1/x5 1 10", "d3<"),
+        ("x4,12/x3,22C,1S/x4,2/2,1,2S,1,x1/1C,x,1,x2 1 13", "e4+"),
+    ];
+
+    fn best_move_at_depth_1(position: &str, config: &SearchConfig) -> String {
+        let mut board = Board::try_from_pos_str(position, Settings::new(0)).unwrap();
+        let mut tt = TranspositionTable::new(16);
+        let mut ordering = MoveOrdering::new(true);
+        let (_, best, _) = iterative_deepening_with_ordering(
+            &mut board,
+            1,
+            10_000_000,
+            config,
+            &mut tt,
+            &mut ordering,
+            &mut |_| {},
+        );
+        best.unwrap().1.to_ptn(board.size)
+    }
+
+    fn with_quiescence(quiescence: bool) -> SearchConfig {
+        SearchConfig {
+            quiescence,
+            ..SearchConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_quiescence_finds_capture() {
+        let (position, capture) = CAPTURE_SUITE[0];
+        assert_ne!(
+            best_move_at_depth_1(position, &with_quiescence(false)),
+            capture
+        );
+        assert_eq!(
+            best_move_at_depth_1(position, &with_quiescence(true)),
+            capture
+        );
+    }
+
+    #[test]
+    fn test_quiescence_tactical_accuracy() {
+        let solved = |quiescence| {
+            CAPTURE_SUITE
+                .iter()
+                .filter(|(position, capture)| {
+                    best_move_at_depth_1(position, &with_quiescence(quiescence)) == *capture
+                })
+                .count()
+        };
+        let (with_quiescence, without_quiescence) = (solved(true), solved(false));
+        assert_eq!(with_quiescence, CAPTURE_SUITE.len());
+        assert!(without_quiescence < with_quiescence);
+    }
+
+    #[test]
+    fn test_contempt_avoids_drawish_move() {
+        let position = "x4/x2,2,1/1S,x,1S,1/2,2S,x,2S 1 7";
+        // Several moves score 0 here, so search order rather than the tie-break picks c4.
+        let with_contempt = |contempt| SearchConfig {
+            contempt,
+            tie_break: false,
+            ..SearchConfig::default()
+        };
+        assert_eq!(best_move_at_depth_1(position, &with_contempt(0)), "c4");
+        assert_ne!(best_move_at_depth_1(position, &with_contempt(150)), "c4");
+        let default_with_contempt = SearchConfig {
+            contempt: 150,
+            ..SearchConfig::default()
+        };
+        assert_ne!(best_move_at_depth_1(position, &default_with_contempt), "c4");
+    }
+
+    #[test]
+    fn test_tie_break_is_reproducible() {
+        let position = "x5/x5/x5/x5/x5 1 3";
+        let mut tt = TranspositionTable::new(16);
+        let mut search = |tie_break, depth| {
+            let mut board = Board::try_from_pos_str(position, Settings::new(4)).unwrap();
+            let config = SearchConfig {
+                tie_break,
+                ..SearchConfig::default()
+            };
+            let mut ordering = MoveOrdering::new(true);
+            let (_, best, _) = iterative_deepening_with_ordering(
+                &mut board,
+                depth,
+                10_000_000,
+                &config,
+                &mut tt,
+                &mut ordering,
+                &mut |_| {},
+            );
+            best.unwrap().1.to_ptn(board.size)
+        };
+
+        assert_eq!(search(true, 2), "c3");
+        assert_eq!(search(true, 1), "c3");
+        assert_eq!(search(true, 2), "c3");
+        assert_ne!(search(false, 2), "c3");
+    }
 }
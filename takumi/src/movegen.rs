@@ -326,6 +326,26 @@ pub fn perft(game: &mut Board, depth: usize) -> usize {
     count
 }
 
+/// Counts the nodes below each root move, sorted by the moves' PTN, to compare move generation
+/// with another engine.
+pub fn perft_divide(game: &mut Board, depth: usize) -> Vec<(Action, usize)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let mut divide = gen_moves(game)
+        .into_iter()
+        .map(|action| {
+            let smashed = game.make(&action);
+            let count = perft(game, depth - 1);
+            game.unmake(&action, smashed);
+            (action, count)
+        })
+        .collect::<Vec<_>>();
+    divide.sort_by_cached_key(|(action, _)| action.to_ptn(game.size));
+    divide
+}
+
 #[cfg(test)]
 mod tests {
     use crate::Settings;
@@ -438,4 +458,23 @@ mod tests {
             assert_eq!(clone, game);
         }
     }
+
+    #[test]
+    fn test_perft_divide() {
+        let mut game =
+            Board::try_from_pos_str("112C,11S,x3/x5/1C,x4/x5/x5 2 10", Settings::new(4)).unwrap();
+        let before = game.clone();
+        let divide = perft_divide(&mut game, 3);
+        assert_eq!(game, before);
+        assert_eq!(divide.len(), gen_moves(&game).len());
+        assert_eq!(
+            divide.iter().map(|(_, count)| count).sum::<usize>(),
+            perft(&mut game, 3)
+        );
+        let moves = divide
+            .iter()
+            .map(|(action, _)| action.to_ptn(game.size))
+            .collect::<Vec<_>>();
+        assert!(moves.is_sorted());
+    }
 }
@@ -0,0 +1,180 @@
+use crate::{
+    Action, AnalysisUpdate, Board, TRANSPOSITION_TABLE, iterative_deepening_with_updates, now,
+};
+
+/// A search done on the opponent's time, in the position after the engine's move and the reply
+/// the engine expects.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PonderResult {
+    pub predicted_reply: Action,
+    pub position: String,
+    pub update: AnalysisUpdate,
+    /// Milliseconds spent pondering.
+    pub millis: u64,
+}
+
+impl PonderResult {
+    /// Returns the pondered analysis if the board is the predicted position, i.e. the opponent
+    /// played the expected reply, and a new search couldn't do better: either pondering
+    /// already reached `max_depth`, or the new search would get no more time than pondering
+    /// had.
+    pub fn reuse_for(
+        &self,
+        board: &Board,
+        max_depth: usize,
+        time_to_use: u64,
+    ) -> Option<&AnalysisUpdate> {
+        (board.to_pos_str() == self.position
+            && (self.update.depth >= max_depth || time_to_use <= self.millis))
+            .then_some(&self.update)
+    }
+}
+
+/// Looks up the opponent's expected reply to `best_move` in the transposition table, which the
+/// search that found `best_move` has just filled.
+pub fn predict_reply(board: &Board, best_move: &Action) -> Option<Action> {
+    let mut board = board.clone();
+    board.make(best_move);
+    if board.result.is_some() {
+        return None;
+    }
+    TRANSPOSITION_TABLE.with(|tt| {
+        tt.borrow()
+            .get(board.zobrist)
+            .and_then(|entry| entry.best_move.clone())
+    })
+}
+
+/// A ponder search in progress. It is deepened one depth at a time, so that it can be stopped
+/// between depths, e.g. when the opponent's move arrives.
+pub struct Pondering {
+    predicted_reply: Action,
+    board: Board,
+    last_update: Option<AnalysisUpdate>,
+    millis: u64,
+}
+
+impl Pondering {
+    /// Plays `best_move` and the predicted reply. Returns `None` if there is no reply to
+    /// expect or the game is over.
+    pub fn start(board: &Board, best_move: &Action) -> Option<Self> {
+        let predicted_reply = predict_reply(board, best_move)?;
+        let mut board = board.clone();
+        board.make(best_move);
+        board.make(&predicted_reply);
+        if board.result.is_some() {
+            return None;
+        }
+        Some(Self {
+            predicted_reply,
+            board,
+            last_update: None,
+            millis: 0,
+        })
+    }
+
+    /// The deepest depth completed so far.
+    pub fn depth(&self) -> usize {
+        self.last_update.as_ref().map_or(0, |update| update.depth)
+    }
+
+    /// Searches one depth deeper than before, for at most `max_duration` milliseconds. The
+    /// transposition table keeps the shallower depths cheap to repeat. Returns whether the
+    /// depth was completed.
+    pub fn deepen(&mut self, max_duration: u64) -> bool {
+        let started = now();
+        let target = self.depth() + 1;
+        let mut board = self.board.clone();
+        let mut last_update = None;
+        iterative_deepening_with_updates(&mut board, target, max_duration, |update| {
+            last_update = Some(update);
+        });
+        self.millis += now() - started;
+        let completed = last_update.as_ref().is_some_and(|x| x.depth >= target);
+        if completed {
+            self.last_update = last_update;
+        }
+        completed
+    }
+
+    pub fn finish(self) -> Option<PonderResult> {
+        Some(PonderResult {
+            predicted_reply: self.predicted_reply,
+            position: self.board.to_pos_str(),
+            update: self.last_update?,
+            millis: self.millis,
+        })
+    }
+}
+
+/// Plays `best_move` and the predicted reply, then searches the resulting position.
+pub fn ponder(
+    board: &Board,
+    best_move: &Action,
+    max_depth: usize,
+    max_duration: u64,
+) -> Option<PonderResult> {
+    let mut pondering = Pondering::start(board, best_move)?;
+    let deadline = now() + max_duration;
+    while pondering.depth() < max_depth {
+        let remaining = deadline.saturating_sub(now());
+        if remaining == 0 || !pondering.deepen(remaining) {
+            break;
+        }
+    }
+    pondering.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Settings, gen_moves, iterative_deepening};
+
+    use super::*;
+
+    #[test]
+    fn test_ponder_hit_reuses_result() {
+        let position = "x,2,x,1,x/x,1,2,x2/2,12,1C,2,x/x,2,x,1,x/x5 1 10";
+        let mut board = Board::try_from_pos_str(position, Settings::new(4)).unwrap();
+        let (_, best) = iterative_deepening(&mut board, 2, 10_000_000);
+        let (_, best_move) = best.unwrap();
+
+        let result = ponder(&board, &best_move, 2, 10_000_000).expect("Should ponder");
+        assert!(result.update.depth > 0);
+
+        let mut hit = board.clone();
+        hit.make(&best_move);
+        let miss_reply = gen_moves(&hit)
+            .into_iter()
+            .find(|mv| *mv != result.predicted_reply)
+            .unwrap();
+        let mut miss = hit.clone();
+        hit.make(&result.predicted_reply);
+        miss.make(&miss_reply);
+
+        let reused = result
+            .reuse_for(&hit, 2, 10_000_000)
+            .expect("Predicted reply should be a hit");
+        assert!(gen_moves(&hit).contains(&reused.best_move));
+        assert!(result.reuse_for(&miss, 2, 10_000_000).is_none());
+    }
+
+    #[test]
+    fn test_shallow_ponder_is_not_reused_with_more_time() {
+        let position = "x,2,x,1,x/x,1,2,x2/2,12,1C,2,x/x,2,x,1,x/x5 1 10";
+        let mut board = Board::try_from_pos_str(position, Settings::new(4)).unwrap();
+        let (_, best) = iterative_deepening(&mut board, 2, 10_000_000);
+        let (_, best_move) = best.unwrap();
+
+        let mut pondering = Pondering::start(&board, &best_move).expect("Should ponder");
+        assert!(pondering.deepen(10_000_000));
+        assert_eq!(pondering.depth(), 1);
+        let result = pondering.finish().unwrap();
+
+        let mut hit = board.clone();
+        hit.make(&best_move);
+        hit.make(&result.predicted_reply);
+        assert!(result.reuse_for(&hit, 1, 10_000_000).is_some());
+        assert!(result.reuse_for(&hit, 3, 10_000_000).is_none());
+        assert!(result.reuse_for(&hit, 3, result.millis).is_some());
+    }
+}
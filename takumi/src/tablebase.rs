@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use crate::{Action, Board, gen_moves};
+
+/// The exact value of a position for the player to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TablebaseValue {
+    /// The player to move forces a win within this many plies.
+    Win(usize),
+    /// The opponent forces a win within this many plies, however the player to move defends.
+    Loss(usize),
+    /// Neither player forces a win within the horizon.
+    Unresolved,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TablebaseConfig {
+    /// Positions with more stones and capstones left in the reserves of both players are
+    /// not solved.
+    pub max_pieces: u64,
+    /// How many plies ahead wins are searched for.
+    pub max_plies: usize,
+}
+
+impl Default for TablebaseConfig {
+    fn default() -> Self {
+        Self {
+            max_pieces: 20,
+            max_plies: 9,
+        }
+    }
+}
+
+/// What is known about a position for some horizon: it holds for every horizon from `proven`
+/// on and for none up to `refuted`.
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    proven: usize,
+    refuted: usize,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            proven: usize::MAX,
+            refuted: 0,
+        }
+    }
+}
+
+/// Solves small positions exactly by searching every line, remembering solved positions by a
+/// hash that is the same for all rotations and reflections of the board.
+pub struct Tablebase {
+    config: TablebaseConfig,
+    /// Bounds for winning and for losing, by canonical hash.
+    known: HashMap<u64, (Bounds, Bounds)>,
+    symmetries: Vec<Vec<usize>>,
+}
+
+impl Tablebase {
+    pub fn new(config: TablebaseConfig) -> Self {
+        Self {
+            config,
+            known: HashMap::new(),
+            symmetries: Vec::new(),
+        }
+    }
+
+    /// Number of positions with something known about them.
+    pub fn len(&self) -> usize {
+        self.known.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value of the position and the move that achieves it: the fastest win, the
+    /// slowest loss, or a move that doesn't lose within the horizon. Returns `None` for finished
+    /// games and positions over the piece budget.
+    pub fn probe(&mut self, board: &Board) -> Option<(TablebaseValue, Action)> {
+        let reserves =
+            board.white_pieces + board.black_pieces + board.white_capstones + board.black_capstones;
+        if board.result.is_some() || reserves > self.config.max_pieces {
+            return None;
+        }
+        if self
+            .symmetries
+            .first()
+            .is_none_or(|symmetry| symmetry.len() != board.size * board.size)
+        {
+            self.known.clear();
+            self.symmetries = symmetries(board.size);
+        }
+        let mut board = board.clone();
+        let mover = board.current_player;
+        let moves = gen_moves(&board);
+        let first = moves.first()?.clone();
+        for plies in 1..=self.config.max_plies {
+            if self.wins(&mut board, plies) {
+                let best = moves.into_iter().find(|action| {
+                    self.after(&mut board, action, |tb, board| match board.result {
+                        Some(result) => result == mover,
+                        None => plies > 2 && tb.loses(board, plies - 1),
+                    })
+                });
+                return Some((TablebaseValue::Win(plies), best.unwrap_or(first)));
+            }
+            if plies > 1 && self.loses(&mut board, plies) {
+                let best = moves.into_iter().max_by_key(|action| {
+                    self.after(&mut board, action, |tb, board| match board.result {
+                        Some(_) => 0,
+                        None => (1..plies)
+                            .find(|&reply| tb.wins(board, reply))
+                            .unwrap_or(plies),
+                    })
+                });
+                return Some((TablebaseValue::Loss(plies), best.unwrap_or(first)));
+            }
+        }
+        let horizon = self.config.max_plies - 1;
+        let best = moves.into_iter().find(|action| {
+            self.after(&mut board, action, |tb, board| match board.result {
+                Some(result) => result != 1 - mover,
+                None => !tb.wins(board, horizon),
+            })
+        });
+        Some((TablebaseValue::Unresolved, best.unwrap_or(first)))
+    }
+
+    fn after<T>(
+        &mut self,
+        board: &mut Board,
+        action: &Action,
+        f: impl FnOnce(&mut Self, &mut Board) -> T,
+    ) -> T {
+        let smash = board.make(action);
+        let value = f(self, board);
+        board.unmake(action, smash);
+        value
+    }
+
+    /// Whether the player to move can force a win within `plies` plies.
+    fn wins(&mut self, board: &mut Board, plies: usize) -> bool {
+        if plies == 0 {
+            return false;
+        }
+        let key = canonical_hash(board, &self.symmetries);
+        let bounds = self.known.get(&key).copied().unwrap_or_default().0;
+        if plies >= bounds.proven {
+            return true;
+        }
+        if plies <= bounds.refuted {
+            return false;
+        }
+        let mover = board.current_player;
+        let won = gen_moves(board).iter().any(|action| {
+            self.after(board, action, |tb, board| match board.result {
+                Some(result) => result == mover,
+                None => plies > 2 && tb.loses(board, plies - 1),
+            })
+        });
+        let bounds = &mut self.known.entry(key).or_default().0;
+        if won {
+            bounds.proven = bounds.proven.min(plies);
+        } else {
+            bounds.refuted = bounds.refuted.max(plies);
+        }
+        won
+    }
+
+    /// Whether every move of the player to move lets the opponent win within `plies` plies.
+    fn loses(&mut self, board: &mut Board, plies: usize) -> bool {
+        let key = canonical_hash(board, &self.symmetries);
+        let bounds = self.known.get(&key).copied().unwrap_or_default().1;
+        if plies >= bounds.proven {
+            return true;
+        }
+        if plies <= bounds.refuted {
+            return false;
+        }
+        let opponent = 1 - board.current_player;
+        let lost = gen_moves(board).iter().all(|action| {
+            self.after(board, action, |tb, board| match board.result {
+                Some(result) => result == opponent,
+                None => tb.wins(board, plies - 1),
+            })
+        });
+        let bounds = &mut self.known.entry(key).or_default().1;
+        if lost {
+            bounds.proven = bounds.proven.min(plies);
+        } else {
+            bounds.refuted = bounds.refuted.max(plies);
+        }
+        lost
+    }
+}
+
+/// For each of the eight symmetries of the square, the square each square is mapped to.
+fn symmetries(size: usize) -> Vec<Vec<usize>> {
+    let last = size - 1;
+    (0..8)
+        .map(|symmetry| {
+            (0..size * size)
+                .map(|pos| {
+                    let (x, y) = (pos % size, pos / size);
+                    let (x, y) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+                    let x = if symmetry & 1 != 0 { last - x } else { x };
+                    let y = if symmetry & 2 != 0 { last - y } else { y };
+                    y * size + x
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Hashes everything that decides how the game goes on, reserves included, taking the smallest
+/// hash over the symmetries of the square.
+fn canonical_hash(board: &Board, symmetries: &[Vec<usize>]) -> u64 {
+    let mut squares = [0u64; 64];
+    for (pos, square) in squares.iter_mut().enumerate().take(board.size * board.size) {
+        let mask = 1u64 << pos;
+        let top = (board.walls & mask != 0) as u64 | ((board.capstones & mask != 0) as u64) << 1;
+        *square = board.stacks[pos] << 8 | board.stack_heights[pos] << 2 | top;
+    }
+    let turn =
+        (board.double_komi as u64) << 2 | board.current_player << 1 | (board.ply_index < 2) as u64;
+    let reserves = board.white_pieces
+        | board.black_pieces << 8
+        | board.white_capstones << 16
+        | board.black_capstones << 24;
+    let turn = turn | reserves << 16;
+    let mut best = u64::MAX;
+    for symmetry in symmetries {
+        let mut hash = turn.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        for &pos in symmetry {
+            hash = (hash.rotate_left(23) ^ squares[pos]).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+        best = best.min(hash);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Settings;
+
+    #[test]
+    fn test_hash_depends_on_reserves() {
+        let board = Board::empty(3, Settings::new(0));
+        let symmetries = symmetries(3);
+        let mut fewer = board.clone();
+        fewer.black_pieces -= 1;
+        assert_ne!(
+            canonical_hash(&board, &symmetries),
+            canonical_hash(&fewer, &symmetries)
+        );
+    }
+
+    #[test]
+    fn test_solves_empty_3x3() {
+        // The first player builds a road on move seven at the latest, however black defends.
+        let mut board = Board::empty(3, Settings::new(0));
+        let mut tablebase = Tablebase::new(TablebaseConfig {
+            max_plies: 13,
+            ..Default::default()
+        });
+        let (value, action) = tablebase.probe(&board).unwrap();
+        assert_eq!(value, TablebaseValue::Win(13));
+
+        board.make(&action);
+        let (value, _) = tablebase.probe(&board).unwrap();
+        assert_eq!(value, TablebaseValue::Loss(12));
+    }
+
+    #[test]
+    fn test_completes_road() {
+        let mut board = Board::try_from_pos_str("x3/1,1,x/2,2,x 1 3", Settings::new(0)).unwrap();
+        let mut tablebase = Tablebase::new(TablebaseConfig::default());
+        let (value, action) = tablebase.probe(&board).unwrap();
+        assert_eq!(value, TablebaseValue::Win(1));
+        board.make(&action);
+        assert_eq!(board.result, Some(0));
+    }
+}
@@ -1,7 +1,13 @@
-use futures::{SinkExt, StreamExt};
+use std::task::{Context, Poll};
+
+use futures::{FutureExt, SinkExt, StreamExt};
 use gloo_worker::reactor::{reactor, ReactorScope};
 
-use crate::{determine_time_to_use, iterative_deepening, Action, Board, Settings};
+use crate::{
+    configure_search, determine_time_to_use, iterative_deepening_with_updates, maybe_blunder, now,
+    Action, AnalysisUpdate, Board, EngineLevel, PonderResult, Pondering,
+    RandomEngine, SearchConfig, Settings,
+};
 
 #[macro_export]
 macro_rules! console_log {
@@ -15,7 +21,7 @@ macro_rules! console_log {
             }
             #[cfg(not(target_arch = "wasm32"))]
             {
-                println!($($t)*);
+                eprintln!($($t)*);
             }
         }
     }
@@ -29,6 +35,8 @@ pub struct TakumiWorkerInput {
     settings: Settings,
     time_remaining: u64,
     increment: u64,
+    ponder: bool,
+    level: Option<EngineLevel>,
 }
 
 impl TakumiWorkerInput {
@@ -45,25 +53,184 @@ impl TakumiWorkerInput {
             settings,
             time_remaining,
             increment,
+            ponder: false,
+            level: None,
         }
     }
+
+    /// Keeps searching after the move is sent, assuming the opponent plays the expected reply.
+    /// The opponent's actual move is fed back through the position of the next request.
+    pub fn with_ponder(mut self, ponder: bool) -> Self {
+        self.ponder = ponder;
+        self
+    }
+
+    /// Plays at the strength of a level, which limits the depth and time and adds blunders.
+    pub fn with_level(mut self, level: EngineLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+}
+
+/// Messages sent back for every request: any number of `AnalysisUpdate`s while searching,
+/// always followed by exactly one `Done` with the move to play.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TakumiWorkerOutput {
+    AnalysisUpdate(AnalysisUpdate),
+    Done(Action),
+}
+
+/// Sends an update from within the synchronous search without waiting. Updates only report
+/// progress, so one is dropped if the output isn't ready to take it right away.
+fn send_update_now(
+    scope: &mut ReactorScope<TakumiWorkerInput, TakumiWorkerOutput>,
+    update: AnalysisUpdate,
+) {
+    let waker = futures::task::noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    if let Poll::Ready(Ok(())) = scope.poll_ready_unpin(&mut cx)
+        && scope
+            .start_send_unpin(TakumiWorkerOutput::AnalysisUpdate(update))
+            .is_ok()
+    {
+        let _ = scope.poll_flush_unpin(&mut cx);
+    }
+}
+
+/// Gives the event loop a chance to deliver queued messages. In a web worker, incoming
+/// messages are macrotasks, so this waits for a timeout rather than a resolved promise.
+async fn yield_to_event_loop() {
+    #[cfg(target_arch = "wasm32")]
+    {
+        use wasm_bindgen::JsCast;
+        use web_sys::js_sys::{Function, Promise, Reflect, global};
+
+        let promise = Promise::new(&mut |resolve, _| {
+            let global = global();
+            let set_timeout = Reflect::get(&global, &"setTimeout".into())
+                .ok()
+                .and_then(|x| x.dyn_into::<Function>().ok());
+            match set_timeout {
+                Some(set_timeout) => {
+                    let _ = set_timeout.call1(&global, &resolve);
+                }
+                None => {
+                    let _ = resolve.call0(&global);
+                }
+            }
+        });
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut yielded = false;
+        futures::future::poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+    }
 }
 
 #[reactor]
-pub async fn TakumiWorker(mut scope: ReactorScope<TakumiWorkerInput, Action>) {
+pub async fn TakumiWorker(mut scope: ReactorScope<TakumiWorkerInput, TakumiWorkerOutput>) {
     console_log!("TestWorker function triggered");
-    while let Some(input) = scope.next().await {
+    let mut pondered: Option<PonderResult> = None;
+    let mut search_config = SearchConfig::default();
+    let mut rng = RandomEngine::new(now());
+    // A request that arrived while pondering, which it interrupted.
+    let mut next_input = None;
+    loop {
+        let input = match next_input.take() {
+            Some(input) => input,
+            None => match scope.next().await {
+                Some(input) => input,
+                None => break,
+            },
+        };
         let mut board = Board::try_from_pos_str(&input.position, input.settings)
             .expect("Failed to create board from TPS");
 
-        let time_to_use = determine_time_to_use(&board, input.time_remaining, input.increment);
+        // Every request configures the search itself, so a level doesn't outlive its request.
+        let level = input.level.map(|level| level.config());
+        let level_search = level.as_ref().map(|level| level.search.clone());
+        if level_search.as_ref().unwrap_or(&SearchConfig::default()) != &search_config {
+            search_config = level_search.unwrap_or_default();
+            configure_search(&search_config);
+        }
+        let mut pick = |board: &Board, action: Action| match &level {
+            Some(level) => maybe_blunder(board, action, level.blunder_percent, &mut rng),
+            None => action,
+        };
+
+        let mut time_to_use = determine_time_to_use(&board, input.time_remaining, input.increment);
+        let mut max_depth = input.max_depth;
+        if let Some(level) = &level {
+            time_to_use = time_to_use.min(level.max_move_time);
+            max_depth = level.max_depth;
+        }
         console_log!("Determined time to use: {} ms", time_to_use);
-        let (depth, best_move) = iterative_deepening(&mut board, input.max_depth, time_to_use);
+
+        if let Some(update) = pondered
+            .take()
+            .and_then(|p| p.reuse_for(&board, max_depth, time_to_use).cloned())
+        {
+            console_log!("Ponder hit, playing pondered move: {:?}", update.best_move);
+            let action = pick(&board, update.best_move.clone());
+            scope
+                .send(TakumiWorkerOutput::AnalysisUpdate(update))
+                .await
+                .expect("Failed to send analysis update");
+            scope
+                .send(TakumiWorkerOutput::Done(action))
+                .await
+                .expect("Failed to send action");
+            continue;
+        }
+
+        let (depth, best_move) =
+            iterative_deepening_with_updates(&mut board, max_depth, time_to_use, |update| {
+                send_update_now(&mut scope, update);
+            });
 
         console_log!("Best move calculated: {:?} at depth {}", best_move, depth);
+        let action = pick(&board, best_move.expect("Should have a best move").1);
         scope
-            .send(best_move.expect("Should have a best move").1)
+            .send(TakumiWorkerOutput::Done(action.clone()))
             .await
             .expect("Failed to send action");
+
+        if !input.ponder {
+            continue;
+        }
+        let Some(mut pondering) = Pondering::start(&board, &action) else {
+            continue;
+        };
+        // Pondering stops as soon as the next request arrives, and otherwise gets the same
+        // budget as the move itself.
+        let deadline = now() + time_to_use;
+        while pondering.depth() < max_depth {
+            let remaining = deadline.saturating_sub(now());
+            if remaining == 0 || !pondering.deepen(remaining) {
+                break;
+            }
+            yield_to_event_loop().await;
+            match scope.next().now_or_never() {
+                Some(Some(input)) => {
+                    next_input = Some(input);
+                    break;
+                }
+                Some(None) => return,
+                None => {}
+            }
+        }
+        pondered = pondering.finish();
+        console_log!("Pondered: {:?}", pondered);
     }
 }
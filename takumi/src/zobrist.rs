@@ -24,11 +24,57 @@ pub struct TranspositionEntry {
     pub best_move: Option<Action>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchConfig {
+    pub tt_size_mb: usize,
+    /// Keeps searching captures and road-completing placements at leaf nodes until the
+    /// position is quiet, so that a big capture just past the depth limit isn't overlooked.
+    pub quiescence: bool,
+    /// The most plies quiescence search may add beyond the nominal depth.
+    pub max_quiescence_depth: usize,
+    /// How much the engine dislikes draws and dead-equal positions, making it prefer unclear
+    /// tries for a win. Zero treats them as even.
+    pub contempt: i32,
+    /// Picks among equally scored root moves by a fixed rule instead of taking the first one
+    /// searched, so the same position always gets the same move.
+    pub tie_break: bool,
+    /// Plays the move from the standard opening book without searching when the position is
+    /// in it.
+    pub opening_book: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            tt_size_mb: 64,
+            quiescence: false,
+            max_quiescence_depth: 4,
+            contempt: 0,
+            tie_break: true,
+            opening_book: true,
+        }
+    }
+}
+
 thread_local! {
     pub static TRANSPOSITION_TABLE: LazyLock<RefCell<TranspositionTable>> =
-    LazyLock::new(|| RefCell::new(TranspositionTable::new(20)));
+    LazyLock::new(|| RefCell::new(TranspositionTable::from_config(&SearchConfig::default())));
+    pub static SEARCH_CONFIG: RefCell<SearchConfig> = RefCell::new(SearchConfig::default());
+}
+
+/// Replaces the search's transposition table with an empty one of the configured size and
+/// uses the remaining options for all following searches.
+pub fn configure_search(config: &SearchConfig) {
+    TRANSPOSITION_TABLE.with(|tt| {
+        *tt.borrow_mut() = TranspositionTable::from_config(config);
+    });
+    SEARCH_CONFIG.with(|search_config| *search_config.borrow_mut() = config.clone());
 }
 
+/// A fixed-capacity table with `2^size` slots, indexed by the low bits of the Zobrist hash.
+/// On collisions, entries searched to a greater depth are kept unless they stem from an
+/// earlier ply of the game.
 #[derive(Debug, Clone, PartialEq)]
 pub struct TranspositionTable {
     pub size: usize,
@@ -43,6 +89,25 @@ impl TranspositionTable {
         }
     }
 
+    /// Creates the largest table whose slots fit into the given number of megabytes.
+    pub fn with_size_mb(size_mb: usize) -> Self {
+        let slot_size = std::mem::size_of::<Option<TranspositionEntry>>();
+        let slots = (size_mb * 1024 * 1024 / slot_size).max(2);
+        Self::new(slots.ilog2() as usize)
+    }
+
+    pub fn from_config(config: &SearchConfig) -> Self {
+        Self::with_size_mb(config.tt_size_mb)
+    }
+
+    pub fn capacity(&self) -> usize {
+        1 << self.size
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.entries.capacity() * std::mem::size_of::<Option<TranspositionEntry>>()
+    }
+
     fn index(&self, zobrist: u64) -> usize {
         (zobrist & ((1 << self.size) - 1)) as usize
     }
@@ -76,3 +141,48 @@ impl TranspositionTable {
         self.entries.iter().filter(|e| e.is_some()).count()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_stays_bounded() {
+        let mut tt = TranspositionTable::with_size_mb(1);
+        let memory_usage = tt.memory_usage();
+        assert!(memory_usage <= 1024 * 1024);
+        assert!(memory_usage > 512 * 1024);
+
+        for i in 0..(4 * tt.capacity() as u64) {
+            tt.maybe_insert(TranspositionEntry {
+                zobrist: i.wrapping_mul(0x9E37_79B9_7F4A_7C15),
+                score: 0,
+                depth: (i % 7) as usize,
+                ply: 0,
+                node_type: TranspositionNodeType::Exact,
+                best_move: None,
+            });
+        }
+        assert!(tt.count() <= tt.capacity());
+        assert_eq!(tt.memory_usage(), memory_usage);
+    }
+
+    #[test]
+    fn test_deeper_entries_are_kept() {
+        let mut tt = TranspositionTable::new(1);
+        let entry = |zobrist, depth| TranspositionEntry {
+            zobrist,
+            score: 0,
+            depth,
+            ply: 0,
+            node_type: TranspositionNodeType::Exact,
+            best_move: None,
+        };
+        tt.maybe_insert(entry(2, 5));
+        tt.maybe_insert(entry(4, 3));
+        assert_eq!(tt.get(2).map(|e| e.depth), Some(5));
+        assert!(tt.get(4).is_none());
+        tt.maybe_insert(entry(4, 6));
+        assert_eq!(tt.get(4).map(|e| e.depth), Some(6));
+    }
+}
@@ -13,7 +13,8 @@ use std::{
 use tokio_tungstenite_wasm::{Message, WebSocketStream};
 
 use crate::{
-    AuthResponse, PublishMessage, ServerFunctions, Topic,
+    AuthResponse, COMPRESSION_THRESHOLD, PublishMessage, ResumeRequest, ServerFunctions, Topic,
+    WsError,
     future::{Service, run_service},
 };
 
@@ -26,8 +27,9 @@ pub struct WsConnector {
     ws_sink: Signal<Option<SplitSink<WebSocketStream, Message>>>,
     pub url: Signal<Option<String>>,
     pub token: Signal<Option<String>>,
-    send_service: Service<serde_json::Value, Result<(), String>>,
+    send_service: Service<serde_json::Value, Result<(), WsError>>,
     handlers: Arc<WsHandlers>,
+    resume: Arc<Mutex<ResumeRequest>>,
 }
 
 pub struct WsHandlers {
@@ -76,6 +78,14 @@ impl WsHandlers {
             dioxus::logger::tracing::warn!("No handlers found for topic: {}", topic);
         }
     }
+
+    pub fn has_handlers(&self, topic: &String) -> bool {
+        self.handlers
+            .lock()
+            .unwrap()
+            .get(topic)
+            .is_some_and(|handlers| !handlers.is_empty())
+    }
 }
 
 impl WsConnector {
@@ -88,6 +98,7 @@ impl WsConnector {
             token: Signal::new(None),
             send_service: Service::new(),
             handlers: Arc::new(WsHandlers::new()),
+            resume: Arc::new(Mutex::new(ResumeRequest::default())),
         }
     }
 
@@ -133,6 +144,24 @@ impl WsConnector {
             }
             _ => None,
         }?;
+        let resume = {
+            let resume = self.resume.lock().unwrap();
+            ResumeRequest {
+                last_seen: resume
+                    .last_seen
+                    .iter()
+                    .filter(|(topic, _)| self.handlers.has_handlers(topic))
+                    .map(|(topic, seq)| (topic.clone(), *seq))
+                    .collect(),
+            }
+        };
+        if !resume.last_seen.is_empty() {
+            let msg = serde_json::to_string(&resume).unwrap();
+            if let Err(e) = stream.send(Message::Text(msg.into())).await {
+                dioxus::logger::tracing::error!("Failed to send resume request: {}", e);
+                return None;
+            }
+        }
         self.close_connection().await;
         let (ws_sink, ws_stream) = stream.split();
         *self.ws_sink.write() = Some(ws_sink);
@@ -192,9 +221,15 @@ fn use_receive(connector: WsConnector) {
                     Ok(Message::Text(text)) => {
                         dioxus::logger::tracing::info!("Received message: {}", text);
                         let parsed = serde_json::from_str::<PublishMessage>(&text);
-                        match parsed {
-                            Ok(PublishMessage { topic, payload }) => {
-                                connector.handlers.send_to_topic(&topic, payload);
+                        match parsed.map(PublishMessage::decompress) {
+                            Ok(Some(msg)) => {
+                                if !connector.resume.lock().unwrap().observe(&msg) {
+                                    continue;
+                                }
+                                connector.handlers.send_to_topic(&msg.topic, msg.payload);
+                            }
+                            Ok(None) => {
+                                dioxus::logger::tracing::error!("Failed to decompress message");
                             }
                             Err(e) => {
                                 dioxus::logger::tracing::error!("Failed to parse message: {}", e);
@@ -232,7 +267,7 @@ fn use_send(connector: WsConnector) {
             dioxus::logger::tracing::info!("WebSocket sink is ready for sending messages");
             run_service(service, sink, move |mut sink: SplitSink<WebSocketStream, Message>, msg: serde_json::Value| async move {
                 if let Err(e) = sink.send(Message::Text(msg.to_string().into())).await {
-                    return (Err(format!("Failed to send message: {}", e)), sink);
+                    return (Err(WsError::from(e)), sink);
                 }
                 (Ok(()), sink)
             })
@@ -290,10 +325,10 @@ fn use_ws_topic_subscription<ServerFut: ServerFunctions>(
                 return;
             };
             dioxus::logger::tracing::info!("Subscribing to topic: {}", topic);
-            if connector.ws_connection.read().is_none() {
+            let Some(connection_id) = connector.ws_connection.read().clone() else {
                 return;
-            }
-            match ServerFut::subscribe(topic).await {
+            };
+            match ServerFut::subscribe(topic, connection_id).await {
                 Ok(subscription_id) => {
                     dioxus::logger::tracing::info!(
                         "Subscribed to topic with ID: {}",
@@ -409,10 +444,10 @@ pub fn use_ws_topic_receive_dynamic<
 
 pub fn use_ws_topic_send<T: Serialize + 'static>(
     topic: impl Into<Topic>,
-) -> Service<T, Result<(), String>> {
+) -> Service<T, Result<(), WsError>> {
     let connector = WS_CLIENT.resolve();
     let topic_clone = topic.into();
-    let service = use_hook(|| crate::future::Service::<T, Result<(), String>>::new());
+    let service = use_hook(|| crate::future::Service::<T, Result<(), WsError>>::new());
     let service_clone = service.clone();
     use_future(move || {
         let topic = topic_clone.clone();
@@ -420,17 +455,21 @@ pub fn use_ws_topic_send<T: Serialize + 'static>(
         let send_service = connector.send_service.clone();
         async move {
             crate::future::run_service(service, (), move |(), msg: T| {
-                let value = serde_json::to_value(&PublishMessage {
-                    topic: topic.clone(),
-                    payload: serde_json::to_value(msg).unwrap(),
-                })
-                .unwrap();
+                let value = serde_json::to_value(msg).and_then(|payload| {
+                    let msg = PublishMessage {
+                        topic: topic.clone(),
+                        payload,
+                        seq: None,
+                        compressed: false,
+                    };
+                    serde_json::to_value(msg.compress(COMPRESSION_THRESHOLD))
+                });
                 let send_service = send_service.clone();
                 async move {
-                    let res = send_service
-                        .send(value)
-                        .await
-                        .unwrap_or(Err("Service not running".to_string()));
+                    let res = match value {
+                        Ok(value) => send_service.send(value).await.and_then(|res| res),
+                        Err(e) => Err(WsError::from(e)),
+                    };
                     (res, ())
                 }
             })
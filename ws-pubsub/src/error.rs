@@ -0,0 +1,97 @@
+/// Failure of sending a message over the pubsub connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsError {
+    /// No connection is open, so nothing is there to send the message with.
+    NotConnected,
+    /// The connection refused the message. Sending it again may succeed.
+    SendFailed(String),
+    /// The message couldn't be turned into JSON. Sending it again fails the same way.
+    SerializationFailed(String),
+    /// The connection closed while the message was being sent.
+    Closed,
+}
+
+impl WsError {
+    /// Whether sending the same message again, e.g. after a reconnect, may succeed.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, WsError::SerializationFailed(_))
+    }
+}
+
+impl std::fmt::Display for WsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WsError::NotConnected => write!(f, "Not connected"),
+            WsError::SendFailed(e) => write!(f, "Failed to send message: {e}"),
+            WsError::SerializationFailed(e) => write!(f, "Failed to serialize message: {e}"),
+            WsError::Closed => write!(f, "Connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for WsError {}
+
+impl From<serde_json::Error> for WsError {
+    fn from(e: serde_json::Error) -> Self {
+        WsError::SerializationFailed(e.to_string())
+    }
+}
+
+#[cfg(feature = "client")]
+impl From<tokio_tungstenite_wasm::Error> for WsError {
+    fn from(e: tokio_tungstenite_wasm::Error) -> Self {
+        match e {
+            tokio_tungstenite_wasm::Error::ConnectionClosed
+            | tokio_tungstenite_wasm::Error::AlreadyClosed => WsError::Closed,
+            e => WsError::SendFailed(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::{StreamExt, executor::block_on, future::join};
+
+    use super::*;
+    use crate::future::Service;
+
+    #[test]
+    fn test_service_not_running() {
+        let service = Service::<u32, Result<(), WsError>>::new();
+        assert_eq!(block_on(service.send(1)), Err(WsError::NotConnected));
+    }
+
+    #[test]
+    fn test_service_dropping_reply() {
+        let service = Service::<u32, Result<(), WsError>>::new();
+        let mut receiver = service.receiver.try_lock().unwrap();
+        let (sent, _) = block_on(join(service.send(1), async {
+            let (_, reply) = receiver.next().await.unwrap();
+            drop(reply);
+        }));
+        assert_eq!(sent, Err(WsError::Closed));
+    }
+
+    #[test]
+    fn test_serialization_failed() {
+        let unserializable = HashMap::from([((1, 2), 3)]);
+        let error = WsError::from(serde_json::to_value(unserializable).unwrap_err());
+        assert!(matches!(error, WsError::SerializationFailed(_)));
+        assert!(!error.is_retryable());
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_socket_errors() {
+        use tokio_tungstenite_wasm::Error;
+
+        assert_eq!(WsError::from(Error::ConnectionClosed), WsError::Closed);
+        assert_eq!(WsError::from(Error::AlreadyClosed), WsError::Closed);
+        let io = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe");
+        let error = WsError::from(Error::Io(io));
+        assert!(matches!(error, WsError::SendFailed(_)));
+        assert!(error.is_retryable());
+    }
+}
@@ -7,6 +7,8 @@ use futures::{
 };
 use futures_intrusive::sync::Mutex;
 
+use crate::WsError;
+
 pub async fn sleep(duration: std::time::Duration) {
     #[cfg(all(feature = "client-wasm", not(feature = "client-native")))]
     gloo::timers::future::sleep(duration).await;
@@ -38,13 +40,16 @@ impl<T, R> Service<T, R> {
         }
     }
 
-    pub async fn send(&self, msg: T) -> Option<R> {
+    /// Hands a message to the running service and waits for its reply.
+    pub async fn send(&self, msg: T) -> Result<R, WsError> {
         if !self.is_running() {
-            return None;
+            return Err(WsError::NotConnected);
         }
         let (tx, rx) = oneshot::channel();
-        self.sender.unbounded_send((msg, tx)).unwrap();
-        Some(rx.await.unwrap())
+        self.sender
+            .unbounded_send((msg, tx))
+            .map_err(|_| WsError::Closed)?;
+        rx.await.map_err(|_| WsError::Closed)
     }
 
     pub fn is_running(&self) -> bool {
@@ -1,5 +1,6 @@
 #[cfg(feature = "client")]
 mod client;
+mod error;
 mod future;
 mod logger;
 mod message;
@@ -13,13 +14,15 @@ pub use server::*;
 
 #[cfg(feature = "client")]
 pub use client::*;
+pub use error::*;
 pub use message::*;
 pub use topic::*;
 
 #[async_trait::async_trait]
 pub trait ServerFunctions {
     type Error: std::fmt::Debug;
-    async fn subscribe(topic: String) -> Result<String, Self::Error>;
+    /// Subscribes the socket with the given connection id to a topic.
+    async fn subscribe(topic: String, connection_id: String) -> Result<String, Self::Error>;
     async fn unsubscribe(subscription_id: String) -> Result<(), Self::Error>;
 }
 
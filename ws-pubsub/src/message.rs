@@ -1,9 +1,76 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 use serde::{Deserialize, Serialize};
 
+use crate::Topic;
+
+/// Payloads whose JSON is longer than this many bytes are sent gzip-compressed.
+pub const COMPRESSION_THRESHOLD: usize = 4096;
+
+/// Compressed payloads that expand to this many bytes or more are rejected, so a small
+/// message can't make the receiver inflate it without bound.
+pub const MAX_DECOMPRESSED_LEN: usize = 1 << 20;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublishMessage {
     pub topic: String,
     pub payload: serde_json::Value,
+    /// Per-topic sequence number assigned by the server. Messages published by
+    /// clients don't carry one.
+    #[serde(default)]
+    pub seq: Option<u64>,
+    /// Whether `payload` is a base64 string holding the gzip-compressed JSON of the
+    /// actual payload.
+    #[serde(default)]
+    pub compressed: bool,
+}
+
+impl PublishMessage {
+    /// Compresses the payload if its JSON is longer than `threshold` bytes.
+    pub fn compress(mut self, threshold: usize) -> Self {
+        if self.compressed {
+            return self;
+        }
+        let json = self.payload.to_string();
+        if json.len() <= threshold {
+            return self;
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let Ok(bytes) = encoder
+            .write_all(json.as_bytes())
+            .and_then(|()| encoder.finish())
+        else {
+            return self;
+        };
+        self.payload = serde_json::Value::String(STANDARD.encode(bytes));
+        self.compressed = true;
+        self
+    }
+
+    /// Restores a compressed payload. Returns `None` if it doesn't hold compressed JSON or
+    /// expands to `MAX_DECOMPRESSED_LEN` bytes or more.
+    pub fn decompress(mut self) -> Option<Self> {
+        if !self.compressed {
+            return Some(self);
+        }
+        let bytes = STANDARD.decode(self.payload.as_str()?).ok()?;
+        let mut json = String::new();
+        GzDecoder::new(bytes.as_slice())
+            .take(MAX_DECOMPRESSED_LEN as u64)
+            .read_to_string(&mut json)
+            .ok()?;
+        if json.len() >= MAX_DECOMPRESSED_LEN {
+            return None;
+        }
+        self.payload = serde_json::from_str(&json).ok()?;
+        self.compressed = false;
+        Some(self)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,3 +78,88 @@ pub enum AuthResponse {
     Success(String),
     Failure,
 }
+
+/// Sent by a client right after authenticating on a reconnect, carrying the
+/// last sequence number it saw on each topic so missed messages can be replayed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResumeRequest {
+    pub last_seen: HashMap<Topic, u64>,
+}
+
+impl ResumeRequest {
+    /// Records the sequence number of a received message. Returns `false` if the
+    /// message was already seen, e.g. when a replay overlaps with live messages.
+    pub fn observe(&mut self, msg: &PublishMessage) -> bool {
+        let Some(seq) = msg.seq else {
+            return true;
+        };
+        let last_seen = self.last_seen.entry(msg.topic.clone()).or_insert(0);
+        if seq <= *last_seen {
+            return false;
+        }
+        *last_seen = seq;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(payload: serde_json::Value) -> PublishMessage {
+        PublishMessage {
+            topic: "matches/1".to_string(),
+            payload,
+            seq: Some(3),
+            compressed: false,
+        }
+    }
+
+    #[test]
+    fn test_large_payload_roundtrip() {
+        let moves = (0..2000)
+            .map(|i| format!("a{}", i % 5 + 1))
+            .collect::<Vec<_>>();
+        let payload = serde_json::json!({ "ptn": moves.join(" "), "moves": moves });
+        let compressed = message(payload.clone()).compress(COMPRESSION_THRESHOLD);
+        assert!(compressed.compressed);
+
+        let text = serde_json::to_string(&compressed).unwrap();
+        assert!(text.len() < payload.to_string().len());
+        let received = serde_json::from_str::<PublishMessage>(&text)
+            .unwrap()
+            .decompress()
+            .unwrap();
+        assert!(!received.compressed);
+        assert_eq!(received.payload, payload);
+        assert_eq!(received.seq, Some(3));
+    }
+
+    #[test]
+    fn test_small_payload_stays_uncompressed() {
+        let payload = serde_json::json!({ "ptn": "a1 e5" });
+        let msg = message(payload.clone()).compress(COMPRESSION_THRESHOLD);
+        assert!(!msg.compressed);
+        assert_eq!(msg.payload, payload);
+
+        let text = r#"{"topic":"matches/1","payload":{"ptn":"a1 e5"},"seq":null}"#;
+        let parsed = serde_json::from_str::<PublishMessage>(text).unwrap();
+        assert_eq!(parsed.decompress().unwrap().payload, payload);
+    }
+
+    #[test]
+    fn test_oversized_payload_is_rejected() {
+        let payload = serde_json::Value::String("a".repeat(MAX_DECOMPRESSED_LEN));
+        let compressed = message(payload).compress(COMPRESSION_THRESHOLD);
+        assert!(compressed.compressed);
+        assert!(compressed.payload.as_str().unwrap().len() < MAX_DECOMPRESSED_LEN / 100);
+        assert!(compressed.decompress().is_none());
+    }
+
+    #[test]
+    fn test_invalid_compressed_payload() {
+        let mut msg = message(serde_json::json!("not base64!"));
+        msg.compressed = true;
+        assert!(msg.decompress().is_none());
+    }
+}
@@ -1,13 +1,19 @@
 use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, LazyLock},
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        Arc, LazyLock, RwLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
 use axum::extract::ws::{Message, WebSocket};
-use dashmap::{DashMap, mapref::one::RefMut};
+use dashmap::DashMap;
 use futures_util::{SinkExt, StreamExt, stream::SplitSink};
 
-use crate::{AuthResponse, PublishMessage, Topic, TopicMatcher};
+use crate::{
+    AuthResponse, COMPRESSION_THRESHOLD, PublishMessage, ResumeRequest, Topic, TopicMatcher,
+};
 use tokio::sync::{
     Mutex,
     mpsc::{UnboundedReceiver, UnboundedSender},
@@ -19,8 +25,109 @@ pub type ConnectionId = String;
 
 pub type ServerHandler = UnboundedSender<(UserId, Topic, serde_json::Value)>;
 
+/// Sent when a user opens their first connection or closes their last one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresenceEvent {
+    Connected(UserId),
+    Disconnected(UserId),
+}
+
+#[derive(Debug, Clone)]
+pub struct PubSubConfig {
+    pub max_subscriptions_per_connection: usize,
+    pub max_total_subscriptions: usize,
+    /// Number of queued outgoing messages after which a connection is
+    /// considered stalled and gets closed.
+    pub max_pending_messages: usize,
+    /// Time between two pings sent to every connection.
+    pub heartbeat_interval: Duration,
+    /// Number of pings in a row a connection may leave unanswered before it is
+    /// considered dead and gets closed.
+    pub max_missed_pongs: usize,
+}
+
+impl Default for PubSubConfig {
+    fn default() -> Self {
+        PubSubConfig {
+            max_subscriptions_per_connection: 500,
+            max_total_subscriptions: 100_000,
+            max_pending_messages: 256,
+            heartbeat_interval: Duration::from_secs(15),
+            max_missed_pongs: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscribeError {
+    NotConnected,
+    ConnectionLimitReached(usize),
+    TotalLimitReached(usize),
+}
+
+impl std::fmt::Display for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubscribeError::NotConnected => write!(f, "Client is not connected"),
+            SubscribeError::ConnectionLimitReached(limit) => {
+                write!(f, "Subscription limit of {limit} per connection reached")
+            }
+            SubscribeError::TotalLimitReached(limit) => {
+                write!(f, "Total subscription limit of {limit} reached")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubscribeError {}
+
+struct Connection {
+    sender: UnboundedSender<Message>,
+    pending: Arc<AtomicUsize>,
+    missed_pongs: AtomicUsize,
+}
+
+impl Connection {
+    /// Spawns a writer task that forwards queued messages to the socket. The
+    /// socket is closed once the connection is dropped.
+    fn spawn(mut socket: SplitSink<WebSocket, Message>) -> Self {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let writer_pending = pending.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                if let Err(e) = socket.send(msg).await {
+                    println!("Failed to write to socket: {e}");
+                    break;
+                }
+                writer_pending.fetch_sub(1, Ordering::Relaxed);
+            }
+            let _ = socket.close().await;
+        });
+        Connection {
+            sender,
+            pending,
+            missed_pongs: AtomicUsize::new(0),
+        }
+    }
+
+    /// Queues a message. Returns false if the connection is closed or has more
+    /// than `max_pending` messages waiting. Rejected messages don't count as
+    /// pending, since the writer task never sees them.
+    fn send(&self, msg: Message, max_pending: usize) -> bool {
+        if self.pending.fetch_add(1, Ordering::Relaxed) >= max_pending
+            || self.sender.send(msg).is_err()
+        {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+}
+
 pub struct ClientInfo {
-    subscriptions: HashMap<SubscriptionId, Topic>,
+    /// The connection each subscription was made on, and its topic.
+    subscriptions: HashMap<SubscriptionId, (ConnectionId, Topic)>,
     topics: HashMap<Topic, HashSet<SubscriptionId>>,
 }
 
@@ -36,22 +143,26 @@ impl Default for ClientInfo {
 impl ClientInfo {
     fn add_subscription(
         &mut self,
+        connection_id: &ConnectionId,
         subscription_id: &SubscriptionId,
         topic: &Topic,
-    ) -> Option<Topic> {
-        if self.subscriptions.len() >= 500 {
-            return None;
+        limit: usize,
+    ) -> Result<Option<Topic>, SubscribeError> {
+        if self.connection_subscriptions(connection_id).len() >= limit {
+            return Err(SubscribeError::ConnectionLimitReached(limit));
         }
-        self.subscriptions
-            .insert(subscription_id.clone(), topic.clone());
+        self.subscriptions.insert(
+            subscription_id.clone(),
+            (connection_id.clone(), topic.clone()),
+        );
         let topics = self.topics.entry(topic.clone()).or_default();
         let was_empty = topics.is_empty();
         topics.insert(subscription_id.clone());
-        if was_empty { Some(topic.clone()) } else { None }
+        Ok(if was_empty { Some(topic.clone()) } else { None })
     }
 
     fn remove_subscription(&mut self, subscription_id: &SubscriptionId) -> Option<Topic> {
-        if let Some(topic) = self.subscriptions.remove(subscription_id) {
+        if let Some((_, topic)) = self.subscriptions.remove(subscription_id) {
             if let Some(topics) = self.topics.get_mut(&topic) {
                 topics.remove(subscription_id);
                 if topics.is_empty() {
@@ -63,6 +174,14 @@ impl ClientInfo {
         None
     }
 
+    fn connection_subscriptions(&self, connection_id: &ConnectionId) -> Vec<SubscriptionId> {
+        self.subscriptions
+            .iter()
+            .filter(|(_, (connection, _))| connection == connection_id)
+            .map(|(subscription_id, _)| subscription_id.clone())
+            .collect()
+    }
+
     fn all_topics(&self) -> impl Iterator<Item = &Topic> {
         self.topics.keys()
     }
@@ -70,14 +189,55 @@ impl ClientInfo {
     fn is_empty(&self) -> bool {
         self.subscriptions.is_empty() && self.topics.is_empty()
     }
+
+    fn subscription_count(&self) -> usize {
+        self.subscriptions.len()
+    }
+}
+
+const BACKLOG_SIZE: usize = 64;
+
+#[derive(Default)]
+struct TopicBacklog {
+    next_seq: u64,
+    messages: VecDeque<PublishMessage>,
+}
+
+impl TopicBacklog {
+    fn push(&mut self, topic: &Topic, payload: serde_json::Value) -> PublishMessage {
+        self.next_seq += 1;
+        let msg = PublishMessage {
+            topic: topic.clone(),
+            payload,
+            seq: Some(self.next_seq),
+            compressed: false,
+        };
+        if self.messages.len() >= BACKLOG_SIZE {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(msg.clone());
+        msg
+    }
+
+    fn messages_after(&self, last_seen: u64) -> Vec<PublishMessage> {
+        self.messages
+            .iter()
+            .filter(|msg| msg.seq.is_some_and(|seq| seq > last_seen))
+            .cloned()
+            .collect()
+    }
 }
 
 struct PubSub {
     topic_to_subscribers: DashMap<Topic, HashSet<UserId>>,
     client_info: DashMap<UserId, ClientInfo>,
+    backlogs: DashMap<Topic, TopicBacklog>,
+    total_subscriptions: AtomicUsize,
+    config: RwLock<PubSubConfig>,
 
-    connections: DashMap<UserId, HashMap<ConnectionId, SplitSink<WebSocket, Message>>>,
+    connections: DashMap<UserId, HashMap<ConnectionId, Connection>>,
     handlers: Arc<Mutex<TopicMatcher<Vec<ServerHandler>>>>,
+    presence_handlers: RwLock<Vec<UnboundedSender<PresenceEvent>>>,
 }
 
 impl PubSub {
@@ -85,27 +245,70 @@ impl PubSub {
         PubSub {
             topic_to_subscribers: DashMap::new(),
             client_info: DashMap::new(),
+            backlogs: DashMap::new(),
+            total_subscriptions: AtomicUsize::new(0),
+            config: RwLock::new(PubSubConfig::default()),
 
             connections: DashMap::new(),
             handlers: Arc::new(Mutex::new(TopicMatcher::new())),
+            presence_handlers: RwLock::new(Vec::new()),
         }
     }
 
-    fn subscribe(&self, user_id: &UserId, subscription_id: &SubscriptionId, topic: &Topic) -> bool {
-        if self.connections.get(user_id).is_none() {
-            return false;
+    fn config(&self) -> PubSubConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    fn subscribe(
+        &self,
+        user_id: &UserId,
+        connection_id: &ConnectionId,
+        subscription_id: &SubscriptionId,
+        topic: &Topic,
+    ) -> Result<(), SubscribeError> {
+        if !self
+            .connections
+            .get(user_id)
+            .is_some_and(|connections| connections.contains_key(connection_id))
+        {
+            return Err(SubscribeError::NotConnected);
+        }
+        let config = self.config();
+        let total = self.total_subscriptions.fetch_add(1, Ordering::SeqCst);
+        if total >= config.max_total_subscriptions {
+            self.total_subscriptions.fetch_sub(1, Ordering::SeqCst);
+            return Err(SubscribeError::TotalLimitReached(
+                config.max_total_subscriptions,
+            ));
         }
         let mut client_info = self.client_info.entry(user_id.clone()).or_default();
-        if let Some(topic) = client_info.add_subscription(subscription_id, topic) {
-            let mut subscribers = self.topic_to_subscribers.entry(topic.clone()).or_default();
-            subscribers.insert(user_id.clone());
+        match client_info.add_subscription(
+            connection_id,
+            subscription_id,
+            topic,
+            config.max_subscriptions_per_connection,
+        ) {
+            Ok(Some(topic)) => {
+                let mut subscribers = self.topic_to_subscribers.entry(topic.clone()).or_default();
+                subscribers.insert(user_id.clone());
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.total_subscriptions.fetch_sub(1, Ordering::SeqCst);
+                return Err(e);
+            }
         }
-        true
+        Ok(())
     }
 
     fn unsubscribe(&self, user_id: &UserId, subscription_id: &SubscriptionId) {
         if let Some(mut client_info) = self.client_info.get_mut(user_id) {
-            if let Some(topic) = client_info.remove_subscription(subscription_id) {
+            let count = client_info.subscription_count();
+            let removed_topic = client_info.remove_subscription(subscription_id);
+            if client_info.subscription_count() < count {
+                self.total_subscriptions.fetch_sub(1, Ordering::SeqCst);
+            }
+            if let Some(topic) = removed_topic {
                 if let Some(mut subscribers) = self.topic_to_subscribers.get_mut(&topic) {
                     subscribers.remove(user_id);
                     if subscribers.is_empty() {
@@ -121,6 +324,18 @@ impl PubSub {
         }
     }
 
+    /// Drops the subscriptions made on one connection of a user.
+    fn remove_connection_subscriptions(&self, user_id: &UserId, connection_id: &ConnectionId) {
+        let subscription_ids = self
+            .client_info
+            .get(user_id)
+            .map(|client_info| client_info.connection_subscriptions(connection_id))
+            .unwrap_or_default();
+        for subscription_id in subscription_ids {
+            self.unsubscribe(user_id, &subscription_id);
+        }
+    }
+
     fn get_subscribers(&self, topic: impl AsRef<str>) -> HashSet<UserId> {
         self.topic_to_subscribers
             .get(topic.as_ref())
@@ -129,6 +344,8 @@ impl PubSub {
 
     fn remove_all_subscriptions(&self, user_id: &UserId) {
         if let Some((_, client_info)) = self.client_info.remove(user_id) {
+            self.total_subscriptions
+                .fetch_sub(client_info.subscription_count(), Ordering::SeqCst);
             for topic in client_info.all_topics() {
                 if let Some(mut subscribers) = self.topic_to_subscribers.get_mut(topic) {
                     subscribers.remove(user_id);
@@ -145,36 +362,156 @@ impl PubSub {
         &self,
         user_id: &UserId,
         connection_id: &ConnectionId,
-        socket: SplitSink<WebSocket, Message>,
+        connection: Connection,
     ) {
         let mut connections = self.connections.entry(user_id.clone()).or_default();
-        connections.insert(connection_id.clone(), socket);
-    }
-
-    fn get_connections(
-        &self,
-        user_id: &UserId,
-    ) -> Option<RefMut<'_, UserId, HashMap<ConnectionId, SplitSink<WebSocket, Message>>>> {
-        self.connections.get_mut(user_id)
+        let is_first = connections.is_empty();
+        connections.insert(connection_id.clone(), connection);
+        drop(connections);
+        if is_first {
+            self.notify_presence(PresenceEvent::Connected(user_id.clone()));
+        }
     }
 
     fn remove_connection(
         &self,
         user_id: &UserId,
         connection_id: &ConnectionId,
-    ) -> Option<SplitSink<WebSocket, Message>> {
+    ) -> Option<Connection> {
         let mut connections = self.connections.get_mut(user_id)?;
         if let Some(socket) = connections.remove(connection_id) {
             if connections.is_empty() {
                 drop(connections);
                 self.connections.remove(user_id);
                 self.remove_all_subscriptions(user_id);
+                self.notify_presence(PresenceEvent::Disconnected(user_id.clone()));
+            } else {
+                drop(connections);
+                self.remove_connection_subscriptions(user_id, connection_id);
             }
             return Some(socket);
         }
         None
     }
 
+    fn is_connected(&self, user_id: &UserId) -> bool {
+        self.connections
+            .get(user_id)
+            .is_some_and(|connections| !connections.is_empty())
+    }
+
+    /// Drops every connection of a user, which closes their sockets.
+    fn disconnect_user(&self, user_id: &UserId) -> bool {
+        if self.connections.remove(user_id).is_none() {
+            return false;
+        }
+        self.remove_all_subscriptions(user_id);
+        self.notify_presence(PresenceEvent::Disconnected(user_id.clone()));
+        true
+    }
+
+    /// Pings every connection and closes the ones that left the last
+    /// `max_missed_pongs` pings unanswered, which happens when the peer vanished
+    /// without closing the socket.
+    fn heartbeat(&self) {
+        let config = self.config();
+        let mut dead = Vec::new();
+        for connections in self.connections.iter() {
+            for (connection_id, connection) in connections.iter() {
+                let missed = connection.missed_pongs.fetch_add(1, Ordering::Relaxed);
+                if missed >= config.max_missed_pongs
+                    || !connection.send(
+                        Message::Ping(Default::default()),
+                        config.max_pending_messages,
+                    )
+                {
+                    dead.push((connections.key().clone(), connection_id.clone()));
+                }
+            }
+        }
+        for (user_id, connection_id) in dead {
+            println!("Dropping unresponsive connection {connection_id} of {user_id}");
+            self.remove_connection(&user_id, &connection_id);
+        }
+    }
+
+    /// Resets the missed pings of a connection that just sent something.
+    fn mark_alive(&self, user_id: &UserId, connection_id: &ConnectionId) {
+        if let Some(connections) = self.connections.get(user_id)
+            && let Some(connection) = connections.get(connection_id)
+        {
+            connection.missed_pongs.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn notify_presence(&self, event: PresenceEvent) {
+        self.presence_handlers
+            .write()
+            .unwrap()
+            .retain(|handler| handler.send(event.clone()).is_ok());
+    }
+
+    fn record_message(&self, topic: &Topic, payload: serde_json::Value) -> PublishMessage {
+        self.backlogs
+            .entry(topic.clone())
+            .or_default()
+            .push(topic, payload)
+    }
+
+    fn missed_messages(&self, resume: &ResumeRequest) -> Vec<PublishMessage> {
+        let mut missed = Vec::new();
+        for (topic, last_seen) in resume.last_seen.iter() {
+            if let Some(backlog) = self.backlogs.get(topic) {
+                missed.extend(backlog.messages_after(*last_seen));
+            }
+        }
+        missed
+    }
+
+    fn replay(&self, user_id: &UserId, connection_id: &ConnectionId, resume: ResumeRequest) {
+        let max_pending = self.config().max_pending_messages;
+        let missed = self.missed_messages(&resume);
+        let stalled = {
+            let Some(connections) = self.connections.get(user_id) else {
+                return;
+            };
+            let Some(connection) = connections.get(connection_id) else {
+                return;
+            };
+            missed.into_iter().any(|msg| {
+                let msg = msg.compress(COMPRESSION_THRESHOLD);
+                !connection.send(
+                    Message::Text(serde_json::to_string(&msg).unwrap().into()),
+                    max_pending,
+                )
+            })
+        };
+        if stalled {
+            println!("Dropping stalled connection {connection_id} of {user_id} during replay");
+            self.remove_connection(user_id, connection_id);
+        }
+    }
+
+    fn publish(&self, topic: &Topic, payload: serde_json::Value) {
+        let max_pending = self.config().max_pending_messages;
+        let msg = self.record_message(topic, payload);
+        let text = serde_json::to_string(&msg.compress(COMPRESSION_THRESHOLD)).unwrap();
+        let mut stalled = Vec::new();
+        for user_id in self.get_subscribers(topic) {
+            if let Some(connections) = self.connections.get(&user_id) {
+                for (connection_id, connection) in connections.iter() {
+                    if !connection.send(Message::Text(text.clone().into()), max_pending) {
+                        stalled.push((user_id.clone(), connection_id.clone()));
+                    }
+                }
+            }
+        }
+        for (user_id, connection_id) in stalled {
+            println!("Dropping stalled connection {connection_id} of subscriber {user_id}");
+            self.remove_connection(&user_id, &connection_id);
+        }
+    }
+
     async fn add_handler(&self, topic: impl AsRef<str>, handler: ServerHandler) {
         let mut lock = self.handlers.lock().await;
         if let Some(existing) = lock.get_mut(topic.as_ref()) {
@@ -242,19 +579,22 @@ pub async fn handle_socket<F: FnOnce(&str) -> Option<String> + Send + Sync>(
 
     println!("New connection established: {}", connection_id);
 
-    SERVER.add_connection(&user_id, &connection_id, tx);
+    SERVER.add_connection(&user_id, &connection_id, Connection::spawn(tx));
 
-    process_socket(rx, &user_id).await;
+    process_socket(rx, &user_id, &connection_id).await;
     println!("Processor ended for client: {}", connection_id);
 
-    if let Some(mut tx) = SERVER.remove_connection(&user_id, &connection_id) {
-        let _ = tx.close().await;
+    if SERVER.remove_connection(&user_id, &connection_id).is_some() {
         println!("Connection closed for client: {}", connection_id);
     }
     println!("Handler ended for client: {}", connection_id);
 }
 
-async fn process_socket(mut rx: futures::stream::SplitStream<WebSocket>, user_id: &UserId) {
+async fn process_socket(
+    mut rx: futures::stream::SplitStream<WebSocket>,
+    user_id: &UserId,
+    connection_id: &ConnectionId,
+) {
     while let Some(msg) = rx.next().await {
         let msg = match msg {
             Ok(msg) => msg,
@@ -263,9 +603,16 @@ async fn process_socket(mut rx: futures::stream::SplitStream<WebSocket>, user_id
                 break;
             }
         };
+        SERVER.mark_alive(user_id, connection_id);
         if let Message::Text(text) = msg {
-            let Ok(PublishMessage { topic, payload }) =
+            if let Ok(resume) = serde_json::from_str::<ResumeRequest>(&text) {
+                SERVER.replay(user_id, connection_id, resume);
+                continue;
+            }
+            let Some(PublishMessage { topic, payload, .. }) =
                 serde_json::from_str::<PublishMessage>(&text)
+                    .ok()
+                    .and_then(PublishMessage::decompress)
             else {
                 println!("Failed to parse message: {text}");
                 continue;
@@ -327,34 +674,315 @@ pub async fn publish_to_topic<T>(topic: impl Into<Topic>, payload: T)
 where
     T: serde::Serialize + Send + 'static,
 {
-    let topic = topic.into();
-    let msg = PublishMessage {
-        topic: topic.clone(),
-        payload: serde_json::to_value(payload).unwrap(),
-    };
-    for user_id in SERVER.get_subscribers(&topic) {
-        if let Some(mut connections) = SERVER.get_connections(&user_id) {
-            for tx in connections.values_mut() {
-                if let Err(e) = tx
-                    .send(Message::Text(serde_json::to_string(&msg).unwrap().into()))
-                    .await
-                {
-                    println!("Failed to send message to subscriber {}: {}", user_id, e);
-                }
-            }
+    SERVER.publish(&topic.into(), serde_json::to_value(payload).unwrap());
+}
+
+/// Returns the users currently subscribed to exactly this topic.
+pub fn get_topic_subscribers(topic: impl AsRef<str>) -> HashSet<UserId> {
+    SERVER.get_subscribers(topic)
+}
+
+/// Returns whether the user has at least one open socket.
+pub fn is_connected(user_id: &UserId) -> bool {
+    SERVER.is_connected(user_id)
+}
+
+/// Closes all sockets of a user. Returns whether they were connected.
+pub fn disconnect_user(user_id: &UserId) -> bool {
+    SERVER.disconnect_user(user_id)
+}
+
+pub fn subscribe_to_presence() -> UnboundedReceiver<PresenceEvent> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    SERVER.presence_handlers.write().unwrap().push(tx);
+    rx
+}
+
+pub fn configure(config: PubSubConfig) {
+    *SERVER.config.write().unwrap() = config;
+}
+
+/// Starts pinging all connections to detect the ones that died silently. Closing
+/// them reports the user as disconnected like a regular close does.
+pub fn spawn_heartbeat() {
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(SERVER.config().heartbeat_interval).await;
+            SERVER.heartbeat();
         }
-    }
+    });
 }
 
-pub async fn client_subscribe(topic: &String, user_id: &UserId) -> Option<String> {
+/// Subscribes the socket `connection_id` of a user to a topic. The subscription
+/// ends when that socket closes.
+pub async fn client_subscribe(
+    topic: &String,
+    user_id: &UserId,
+    connection_id: &ConnectionId,
+) -> Result<SubscriptionId, SubscribeError> {
     let subscription_id = uuid::Uuid::new_v4().to_string();
-    if SERVER.subscribe(user_id, &subscription_id, topic) {
-        Some(subscription_id)
-    } else {
-        None
-    }
+    SERVER.subscribe(user_id, connection_id, &subscription_id, topic)?;
+    Ok(subscription_id)
 }
 
 pub async fn client_unsubscribe(user_id: &UserId, subscription_id: &SubscriptionId) {
     SERVER.unsubscribe(user_id, subscription_id);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detached_connection() -> (Connection, UnboundedReceiver<Message>) {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let connection = Connection {
+            sender,
+            pending: Arc::new(AtomicUsize::new(0)),
+            missed_pongs: AtomicUsize::new(0),
+        };
+        (connection, receiver)
+    }
+
+    fn small_config() -> PubSubConfig {
+        PubSubConfig {
+            max_subscriptions_per_connection: 2,
+            max_total_subscriptions: 3,
+            max_pending_messages: 4,
+            ..PubSubConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_subscription_limits() {
+        let pubsub = PubSub::new();
+        *pubsub.config.write().unwrap() = small_config();
+        let user_a = "a".to_string();
+        let user_b = "b".to_string();
+        let subscribe = |user: &UserId, connection: &str, subscription: &str, topic: &str| {
+            pubsub.subscribe(
+                user,
+                &connection.to_string(),
+                &subscription.to_string(),
+                &topic.to_string(),
+            )
+        };
+
+        assert_eq!(
+            subscribe(&user_a, "c1", "s0", "t"),
+            Err(SubscribeError::NotConnected)
+        );
+
+        let (conn_a1, _rx_a1) = detached_connection();
+        let (conn_a2, _rx_a2) = detached_connection();
+        let (conn_b, _rx_b) = detached_connection();
+        pubsub.add_connection(&user_a, &"c1".to_string(), conn_a1);
+        pubsub.add_connection(&user_a, &"c2".to_string(), conn_a2);
+        pubsub.add_connection(&user_b, &"c3".to_string(), conn_b);
+        assert_eq!(
+            subscribe(&user_b, "c1", "s0", "t"),
+            Err(SubscribeError::NotConnected)
+        );
+
+        assert!(subscribe(&user_a, "c1", "s1", "t1").is_ok());
+        assert!(subscribe(&user_a, "c1", "s2", "t2").is_ok());
+        assert_eq!(
+            subscribe(&user_a, "c1", "s3", "t3"),
+            Err(SubscribeError::ConnectionLimitReached(2))
+        );
+        // The limit is per connection, so another socket of the same user still has room.
+        assert!(subscribe(&user_a, "c2", "s3", "t3").is_ok());
+
+        assert_eq!(
+            subscribe(&user_b, "c3", "s4", "t1"),
+            Err(SubscribeError::TotalLimitReached(3))
+        );
+
+        pubsub.unsubscribe(&user_a, &"s1".to_string());
+        assert!(subscribe(&user_b, "c3", "s4", "t1").is_ok());
+
+        pubsub.remove_connection(&user_a, &"c1".to_string());
+        assert!(!pubsub.get_subscribers("t2").contains(&user_a));
+        assert!(pubsub.get_subscribers("t3").contains(&user_a));
+        assert_eq!(pubsub.total_subscriptions.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_rejected_messages_are_not_pending() {
+        let (connection, mut rx) = detached_connection();
+        for _ in 0..10 {
+            connection.send(Message::Ping(Default::default()), 4);
+        }
+        assert_eq!(connection.pending.load(Ordering::Relaxed), 4);
+        while rx.try_recv().is_ok() {
+            connection.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+        assert!(connection.send(Message::Ping(Default::default()), 4));
+    }
+
+    #[test]
+    fn test_stalled_subscriber_is_evicted() {
+        let pubsub = PubSub::new();
+        *pubsub.config.write().unwrap() = small_config();
+        let topic = "matches/1".to_string();
+        let fast_user = "fast".to_string();
+        let slow_user = "slow".to_string();
+
+        let (fast_conn, mut fast_rx) = detached_connection();
+        let fast_pending = fast_conn.pending.clone();
+        let (slow_conn, _slow_rx) = detached_connection();
+        pubsub.add_connection(&fast_user, &"c1".to_string(), fast_conn);
+        pubsub.add_connection(&slow_user, &"c2".to_string(), slow_conn);
+        pubsub
+            .subscribe(&fast_user, &"c1".to_string(), &"s1".to_string(), &topic)
+            .unwrap();
+        pubsub
+            .subscribe(&slow_user, &"c2".to_string(), &"s2".to_string(), &topic)
+            .unwrap();
+
+        for i in 0..10 {
+            pubsub.publish(&topic, serde_json::json!(i));
+            while fast_rx.try_recv().is_ok() {
+                fast_pending.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        assert!(pubsub.connections.get(&slow_user).is_none());
+        assert!(!pubsub.get_subscribers(&topic).contains(&slow_user));
+        assert!(pubsub.connections.get(&fast_user).is_some());
+        assert_eq!(pubsub.total_subscriptions.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_replay_after_disconnect() {
+        let pubsub = PubSub::new();
+        let topic = "matches/1".to_string();
+        let mut client = ResumeRequest::default();
+
+        for i in 0..2 {
+            let msg = pubsub.record_message(&topic, serde_json::json!(i));
+            assert!(client.observe(&msg));
+        }
+
+        // The client is disconnected while these are published.
+        for i in 2..5 {
+            pubsub.record_message(&topic, serde_json::json!(i));
+        }
+
+        let missed = pubsub.missed_messages(&client);
+        let payloads = missed.iter().map(|m| m.payload.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            payloads,
+            vec![
+                serde_json::json!(2),
+                serde_json::json!(3),
+                serde_json::json!(4)
+            ]
+        );
+        for msg in missed.iter() {
+            assert!(client.observe(msg));
+        }
+        assert!(!client.observe(&missed[2]));
+        assert!(pubsub.missed_messages(&client).is_empty());
+    }
+
+    #[test]
+    fn test_backlog_is_bounded() {
+        let pubsub = PubSub::new();
+        let topic = "seeks".to_string();
+        for i in 0..BACKLOG_SIZE + 10 {
+            pubsub.record_message(&topic, serde_json::json!(i));
+        }
+        let mut resume = ResumeRequest::default();
+        resume.last_seen.insert(topic.clone(), 0);
+        let missed = pubsub.missed_messages(&resume);
+        assert_eq!(missed.len(), BACKLOG_SIZE);
+        assert_eq!(missed[0].seq, Some(11));
+    }
+
+    #[test]
+    fn test_presence_follows_first_and_last_connection() {
+        let pubsub = PubSub::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        pubsub.presence_handlers.write().unwrap().push(tx);
+        let user = "a".to_string();
+
+        let (conn1, _rx1) = detached_connection();
+        let (conn2, _rx2) = detached_connection();
+        pubsub.add_connection(&user, &"c1".to_string(), conn1);
+        pubsub.add_connection(&user, &"c2".to_string(), conn2);
+        pubsub.remove_connection(&user, &"c1".to_string());
+        assert_eq!(rx.try_recv(), Ok(PresenceEvent::Connected(user.clone())));
+        assert!(rx.try_recv().is_err());
+
+        pubsub.remove_connection(&user, &"c2".to_string());
+        assert_eq!(rx.try_recv(), Ok(PresenceEvent::Disconnected(user.clone())));
+    }
+
+    #[test]
+    fn test_is_connected_until_last_socket_closes() {
+        let pubsub = PubSub::new();
+        let user = "a".to_string();
+        assert!(!pubsub.is_connected(&user));
+
+        let (conn1, _rx1) = detached_connection();
+        let (conn2, _rx2) = detached_connection();
+        pubsub.add_connection(&user, &"c1".to_string(), conn1);
+        pubsub.add_connection(&user, &"c2".to_string(), conn2);
+        pubsub.remove_connection(&user, &"c1".to_string());
+        assert!(pubsub.is_connected(&user));
+
+        pubsub.remove_connection(&user, &"c2".to_string());
+        assert!(!pubsub.is_connected(&user));
+    }
+
+    #[test]
+    fn test_unresponsive_connection_is_evicted() {
+        let pubsub = PubSub::new();
+        *pubsub.config.write().unwrap() = small_config();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        pubsub.presence_handlers.write().unwrap().push(tx);
+        let alive = "alive".to_string();
+        let dead = "dead".to_string();
+        let (conn_alive, mut rx_alive) = detached_connection();
+        let (conn_dead, mut rx_dead) = detached_connection();
+        pubsub.add_connection(&alive, &"c1".to_string(), conn_alive);
+        pubsub.add_connection(&dead, &"c2".to_string(), conn_dead);
+        while rx.try_recv().is_ok() {}
+
+        for _ in 0..2 {
+            pubsub.heartbeat();
+            pubsub.mark_alive(&alive, &"c1".to_string());
+        }
+        assert!(matches!(rx_alive.try_recv(), Ok(Message::Ping(_))));
+        assert!(matches!(rx_dead.try_recv(), Ok(Message::Ping(_))));
+        assert!(rx.try_recv().is_err());
+
+        pubsub.heartbeat();
+        assert_eq!(rx.try_recv(), Ok(PresenceEvent::Disconnected(dead.clone())));
+        assert!(!pubsub.connections.contains_key(&dead));
+        assert!(pubsub.connections.contains_key(&alive));
+    }
+
+    #[test]
+    fn test_disconnect_user_closes_all_connections() {
+        let pubsub = PubSub::new();
+        let user = "a".to_string();
+        let topic = "matches/1".to_string();
+
+        let (conn1, mut rx1) = detached_connection();
+        let (conn2, mut rx2) = detached_connection();
+        pubsub.add_connection(&user, &"c1".to_string(), conn1);
+        pubsub.add_connection(&user, &"c2".to_string(), conn2);
+        pubsub
+            .subscribe(&user, &"c1".to_string(), &"s1".to_string(), &topic)
+            .unwrap();
+
+        assert!(pubsub.disconnect_user(&user));
+        assert!(pubsub.connections.get(&user).is_none());
+        assert!(pubsub.get_subscribers(&topic).is_empty());
+        assert_eq!(pubsub.total_subscriptions.load(Ordering::SeqCst), 0);
+        assert!(rx1.try_recv().is_err() && rx1.is_closed());
+        assert!(rx2.try_recv().is_err() && rx2.is_closed());
+        assert!(!pubsub.disconnect_user(&user));
+    }
+}